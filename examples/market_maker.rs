@@ -0,0 +1,93 @@
+///
+/// Market maker example: quotes a bid and an ask around the mid price of a
+/// market, replacing them at every tick.
+///
+/// Runs in dry-run mode unless `--live` is given:
+///
+///     CRYPTOMKT_API_KEY=... CRYPTOMKT_API_SECRET=... \
+///         cargo run --example market_maker --features strategy -- [--live]
+///
+use std::env;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cryptomkt::strategy::{Context, Runner, Strategy};
+use cryptomkt::{Client, CryptoMktResult, Decimal, Side};
+
+const MARKET: &str = "ETHCLP";
+
+struct MarketMaker {
+    /// Amount of each quote
+    amount: Decimal,
+    /// Distance of each quote to the mid price, as a fraction of it
+    half_spread: Decimal,
+    /// Identifiers of the quotes placed in the last tick
+    quotes: Vec<String>,
+}
+
+impl MarketMaker {
+    async fn cancel_quotes(&mut self, ctx: &Context) {
+        let market = ctx.client().create_market(MARKET);
+        let ids: Vec<&str> = self.quotes.iter().map(String::as_str).collect();
+        for (id, result) in ids.iter().zip(market.cancel_orders(&ids).await) {
+            if let Err(e) = result {
+                println!("Could not cancel {}: {}", id, e);
+            }
+        }
+        self.quotes.clear();
+    }
+}
+
+#[async_trait]
+impl Strategy for MarketMaker {
+    async fn on_tick(&mut self, ctx: &Context) -> CryptoMktResult<()> {
+        let market = ctx.client().create_market(MARKET);
+        let limits = market.get_limits().await?;
+        let ticker = market.get_current_ticker().await?;
+        let mid = (ticker.bid + ticker.ask) / Decimal::TWO;
+
+        self.cancel_quotes(ctx).await;
+        let bid = limits.round_price(mid * (Decimal::ONE - self.half_spread));
+        let ask = limits.round_price(mid * (Decimal::ONE + self.half_spread));
+        for (side, price) in [(Side::Buy, bid), (Side::Sell, ask)] {
+            let order = market.create_order(side, self.amount, price).await?;
+            println!("#{} {:?} {} at {}: {}", ctx.tick(), side, self.amount, price, order.id);
+            self.quotes.push(order.id);
+        }
+        Ok(())
+    }
+
+    async fn on_shutdown(&mut self, ctx: &Context) -> CryptoMktResult<()> {
+        self.cancel_quotes(ctx).await;
+        println!("Stopped after {} ticks", ctx.tick());
+        Ok(())
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let live = env::args().any(|arg| arg == "--live");
+    let builder = Client::builder().credentials(
+        &env::var("CRYPTOMKT_API_KEY").unwrap_or_default(),
+        &env::var("CRYPTOMKT_API_SECRET").unwrap_or_default(),
+    );
+    let runner = Runner::new(builder)
+        .interval(Duration::from_secs(30))
+        .dry_run(!live);
+
+    let shutdown = runner.shutdown_token();
+    tokio::spawn(async move {
+        tokio::signal::ctrl_c().await.ok();
+        println!("Shutting down...");
+        shutdown.cancel();
+    });
+
+    let strategy = MarketMaker {
+        amount: Decimal::new(1, 2),
+        half_spread: Decimal::new(5, 3),
+        quotes: Vec::new(),
+    };
+    if let Err(e) = runner.run(strategy).await {
+        println!("{:?}", e);
+    }
+}
@@ -1,9 +1,9 @@
 ///
 /// Example
 ///
-use cryptomkt::{Client, OrderType};
-const API_KEY: &'static str = "<API_KEY>";
-const API_SECRET: &'static str = "<API SECRET>";
+use cryptomkt::{BookSide, Client, NaiveDate, Page};
+const API_KEY: &str = "<API_KEY>";
+const API_SECRET: &str = "<API SECRET>";
 
 
 #[tokio::main]
@@ -11,7 +11,13 @@ async fn main() {
     let client = Client::new(API_KEY, API_SECRET);
 
     // Get all markets available
-    let markets = client.get_markets().await;
+    let markets = match client.get_markets().await {
+        Ok(markets) => markets,
+        Err(e) => {
+            println!("{:?}", e);
+            return;
+        }
+    };
     for m in markets.iter() {
         println!("{}", m.get_name());
 
@@ -26,7 +32,7 @@ async fn main() {
         }
 
         println!("------- Orders ------");
-        match m.get_orders_book(OrderType::Buy, 0, 20).await {
+        match m.get_orders_book(BookSide::Bids, Page::default().limit(20)).await {
             Ok(orders) => {
                 println!("{:?}", orders);
             }
@@ -36,7 +42,9 @@ async fn main() {
         }
 
         println!("------- Trades ------");
-        match m.get_trades("2018-05-15", "2018-05-16", 0, 20).await {
+        let start = NaiveDate::from_ymd_opt(2018, 5, 15).unwrap();
+        let end = NaiveDate::from_ymd_opt(2018, 5, 16).unwrap();
+        match m.get_trades(start, end, Page::default().limit(20)).await {
             Ok(trades) => {
                 println!("{:?}", trades);
             }
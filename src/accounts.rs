@@ -0,0 +1,162 @@
+//!
+//! ## Accounts
+//!
+//! Registry of clients for several accounts of the exchange, e.g. one key to
+//! trade and another one to move funds, each with a label to pick it by. The
+//! clients share one transport, and therefore one connection pool, as well as
+//! the response cache and the public rate limit of the template builder. Each
+//! account gets its own quota for authenticated endpoints.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::accounts::Accounts;
+//! use cryptomkt::{Client, CryptoMktResult, RateLimit};
+//!
+//! # async fn run() -> CryptoMktResult<()> {
+//! let accounts = Accounts::builder(Client::builder().timeout(Duration::from_secs(10)))
+//!     .account("trading", "<API_KEY>", "<API SECRET>")
+//!     .account("treasury", "<API_KEY>", "<API SECRET>")
+//!     .rate_limit("trading", RateLimit::new(10, Duration::from_secs(1)))
+//!     .build()?;
+//!
+//! let balance = accounts.get("treasury")?.get_balance().await?;
+//! let market = accounts.get("trading")?.create_market("ETHCLP");
+//! # Ok(())
+//! # }
+//! ```
+//!
+
+use std::collections::BTreeMap;
+
+use crate::client::{Client, ClientBuilder};
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::ratelimit::RateLimit;
+use crate::internal::secret::SecretString;
+
+///
+/// Clients of several accounts, by label. Cheap to clone, see the module docs
+///
+#[derive(Debug, Clone)]
+pub struct Accounts {
+    clients: BTreeMap<String, Client>,
+}
+
+impl Accounts {
+    ///
+    /// Builder of a registry whose clients take every option but the
+    /// credentials from `template`
+    ///
+    pub fn builder(template: ClientBuilder) -> AccountsBuilder {
+        AccountsBuilder {
+            template,
+            accounts: Vec::new(),
+            rate_limits: Vec::new(),
+        }
+    }
+
+    ///
+    /// Client of the account registered as `label`. Fails with
+    /// `UnknownAccount` when there is none
+    ///
+    pub fn get(&self, label: &str) -> CryptoMktResult<&Client> {
+        self.clients
+            .get(label)
+            .ok_or_else(|| CryptoMktErrorType::UnknownAccount(label.to_string()).into())
+    }
+
+    ///
+    /// Labels of the registered accounts, in alphabetical order
+    ///
+    pub fn labels(&self) -> impl Iterator<Item = &str> {
+        self.clients.keys().map(String::as_str)
+    }
+
+    ///
+    /// Every account with its client, in alphabetical order of the labels
+    ///
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &Client)> {
+        self.clients.iter().map(|(label, client)| (label.as_str(), client))
+    }
+}
+
+///
+/// Builder of an `Accounts` registry, see `Accounts::builder`
+///
+#[derive(Debug)]
+pub struct AccountsBuilder {
+    template: ClientBuilder,
+    /// Etiqueta y credenciales de cada cuenta, en orden de registro
+    accounts: Vec<(String, SecretString, SecretString)>,
+    /// Cuotas de endpoints autenticados por etiqueta
+    rate_limits: Vec<(String, RateLimit)>,
+}
+
+impl AccountsBuilder {
+    ///
+    /// Register an account under `label`. `build` fails with
+    /// `InvalidConfiguration` when a label is used twice
+    ///
+    pub fn account(mut self, label: &str, api_key: &str, secret_key: &str) -> Self {
+        self.accounts.push((
+            label.to_string(),
+            SecretString::new(api_key),
+            SecretString::new(secret_key),
+        ));
+        self
+    }
+
+    ///
+    /// Throttle the requests of the account `label` to authenticated endpoints
+    /// to `limit`. Accounts without one get their own quota with the
+    /// `private_rate_limit` of the template, if any. `build` fails with
+    /// `InvalidConfiguration` when no account has that label
+    ///
+    pub fn rate_limit(mut self, label: &str, limit: RateLimit) -> Self {
+        self.rate_limits.push((label.to_string(), limit));
+        self
+    }
+
+    ///
+    /// Create the clients of every account. Fails as `ClientBuilder::build`
+    /// does, or with `InvalidConfiguration` on a repeated or unknown label
+    ///
+    pub fn build(self) -> CryptoMktResult<Accounts> {
+        let mut labels = Vec::with_capacity(self.accounts.len());
+        let mut credentials = Vec::with_capacity(self.accounts.len());
+        for (label, api_key, secret_key) in self.accounts {
+            if labels.contains(&label) {
+                return Err(invalid_label("repeated", &label));
+            }
+            let limit = self
+                .rate_limits
+                .iter()
+                .rev()
+                .find(|(name, _)| *name == label)
+                .map(|(_, limit)| *limit);
+            credentials.push((api_key, secret_key, limit));
+            labels.push(label);
+        }
+        let unknown = self.rate_limits.iter().find(|(name, _)| !labels.contains(name));
+        if let Some((label, _)) = unknown {
+            return Err(invalid_label("unknown", label));
+        }
+
+        let clients = self.template.build_clients(credentials)?;
+        Ok(Accounts {
+            clients: labels.into_iter().zip(clients).collect(),
+        })
+    }
+}
+
+///
+/// Error de una etiqueta repetida o desconocida
+///
+/// Argumentos
+///     problem: `repeated` o `unknown`
+///     label: Etiqueta de la cuenta
+///
+fn invalid_label(problem: &str, label: &str) -> CryptoMktError {
+    CryptoMktErrorType::InvalidConfiguration(format!("{} account label {:?}", problem, label))
+        .into()
+}
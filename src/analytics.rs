@@ -0,0 +1,271 @@
+//!
+//! ## Analytics
+//!
+//! Candles of arbitrary resolution built from raw trades, for the intervals
+//! `Market::get_prices` doesn't offer.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::analytics::aggregate_candles;
+//! use cryptomkt::history::download_trades;
+//! use cryptomkt::{Client, NaiveDate};
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let market = Client::public().create_market("ETHCLP");
+//! let from = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+//! let to = NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+//! let trades = download_trades(&market, from, to).await?;
+//! for candle in aggregate_candles(&trades, Duration::from_secs(30 * 60)) {
+//!     println!("{} {} {}", candle.candle_date, candle.close, candle.volume);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Order book signals, computed from a snapshot such as the one of
+//! `Market::get_order_book_snapshot`: the imbalance between bids and asks,
+//! the amount resting near the mid price, and the average price a market
+//! order of a given size would get.
+//!
+//! ```no_run
+//! use cryptomkt::{Client, Decimal, Side};
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let market = Client::public().create_market("ETHCLP");
+//! let book = market.get_order_book_snapshot(50).await?;
+//! println!("imbalance {:?}", book.imbalance());
+//! println!("within 0.5%: {:?}", book.liquidity_within(50));
+//! println!("buying 2 ETH costs {:?} each", book.vwap_for_size(Side::Buy, Decimal::new(2, 0)));
+//! # Ok(())
+//! # }
+//! ```
+//!
+
+use std::time::Duration;
+
+use chrono::{DateTime, TimeZone, Utc};
+use rust_decimal::Decimal;
+
+use crate::internal::models::{Candle, OrderBook, PriceLevel, Trade};
+use crate::market::Side;
+
+/// Puntos básicos en una unidad
+const BPS: i64 = 10_000;
+
+///
+/// Aggregate `trades` into OHLCV candles of `resolution`, oldest first.
+///
+/// Candles are aligned to the Unix epoch, so a one hour candle opens at the
+/// start of an hour, and `candle_date` is its opening date. The trades may be
+/// in any order; intervals without trades have no candle. The volume is the
+/// traded amount, in the base currency of the market.
+///
+/// # Panics
+///
+/// If `resolution` is shorter than a millisecond
+///
+pub fn aggregate_candles(trades: &[Trade], resolution: Duration) -> Vec<Candle> {
+    let resolution = resolution.as_millis() as i64;
+    assert!(resolution > 0, "candle resolution must be at least 1ms");
+
+    let mut trades: Vec<&Trade> = trades.iter().collect();
+    trades.sort_by_key(|trade| trade.timestamp);
+
+    let mut candles: Vec<Candle> = Vec::new();
+    for trade in trades {
+        let start = bucket_start(trade.timestamp, resolution);
+        match candles.last_mut() {
+            Some(candle) if candle.candle_date == start => {
+                candle.high = candle.high.max(trade.price);
+                candle.low = candle.low.min(trade.price);
+                candle.close = trade.price;
+                candle.volume += trade.amount;
+            }
+            _ => candles.push(Candle {
+                open: trade.price,
+                high: trade.price,
+                low: trade.price,
+                close: trade.price,
+                volume: trade.amount,
+                candle_date: start,
+            }),
+        }
+    }
+    candles
+}
+
+///
+/// Fecha de apertura de la vela que contiene `date`
+///
+/// Argumentos
+///     date: Fecha del trade
+///     resolution: Duración de las velas en milisegundos
+///
+fn bucket_start(date: DateTime<Utc>, resolution: i64) -> DateTime<Utc> {
+    let millis = date.timestamp_millis();
+    Utc.timestamp_millis_opt(millis - millis.rem_euclid(resolution)).unwrap()
+}
+
+///
+/// Amounts resting on each side of a book near its mid price, in the base
+/// currency of the market, see `OrderBook::liquidity_within`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BookDepth {
+    /// Amount of the bids within the range
+    pub bids: Decimal,
+    /// Amount of the asks within the range
+    pub asks: Decimal,
+}
+
+impl OrderBook {
+    ///
+    /// Imbalance between the amounts of the bids and the asks of the book,
+    /// `(bids - asks) / (bids + asks)`: from `-1`, only asks, to `1`, only
+    /// bids. `None` when the book is empty
+    ///
+    pub fn imbalance(&self) -> Option<Decimal> {
+        let bids = total(&self.bids);
+        let asks = total(&self.asks);
+        let amount = bids + asks;
+        if amount.is_zero() {
+            return None;
+        }
+        Some((bids - asks) / amount)
+    }
+
+    ///
+    /// Amount resting on each side at most `bps` basis points away from the
+    /// mid price, e.g. 50 for 0.5%. `None` when a side is empty
+    ///
+    pub fn liquidity_within(&self, bps: u32) -> Option<BookDepth> {
+        let mid = (self.best_bid()?.price + self.best_ask()?.price) / Decimal::TWO;
+        let range = mid * Decimal::from(bps) / Decimal::from(BPS);
+        let bids = self.bids.iter().take_while(|level| level.price >= mid - range);
+        let asks = self.asks.iter().take_while(|level| level.price <= mid + range);
+        Some(BookDepth {
+            bids: bids.map(|level| level.amount).sum(),
+            asks: asks.map(|level| level.amount).sum(),
+        })
+    }
+
+    ///
+    /// Average price of a market order of `side` for `amount`, walking the
+    /// asks for a purchase and the bids for a sale from the best price. `None`
+    /// when `amount` isn't positive or the book is too shallow to fill it.
+    /// Fees are not included, see the `cost` module
+    ///
+    pub fn vwap_for_size(&self, side: Side, amount: Decimal) -> Option<Decimal> {
+        if amount <= Decimal::ZERO {
+            return None;
+        }
+        let levels = match side {
+            Side::Buy => &self.asks,
+            Side::Sell => &self.bids,
+        };
+        let mut remaining = amount;
+        let mut cost = Decimal::ZERO;
+        for level in levels {
+            let filled = remaining.min(level.amount);
+            cost += filled * level.price;
+            remaining -= filled;
+            if remaining.is_zero() {
+                return Some(cost / amount);
+            }
+        }
+        None
+    }
+}
+
+///
+/// Cantidad total de un lado del libro
+///
+fn total(levels: &[PriceLevel]) -> Decimal {
+    levels.iter().map(|level| level.amount).sum()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn trade(timestamp: &str, price: i64, amount: i64) -> Trade {
+        serde_json::from_value(serde_json::json!({
+            "market_taker": "buy",
+            "timestamp": timestamp,
+            "price": price.to_string(),
+            "amount": amount.to_string(),
+            "market": "ETHCLP",
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn aggregate_candles_buckets_unordered_trades() {
+        // Como los entrega el exchange: del más nuevo al más antiguo
+        let trades = vec![
+            trade("2017-05-29T10:31:00", 90, 1),
+            trade("2017-05-29T10:14:00", 120, 3),
+            trade("2017-05-29T10:10:00", 80, 1),
+            trade("2017-05-29T10:03:00", 100, 2),
+        ];
+        let candles = aggregate_candles(&trades, Duration::from_secs(15 * 60));
+
+        assert_eq!(candles.len(), 2);
+        let first = &candles[0];
+        assert_eq!(first.candle_date.to_rfc3339(), "2017-05-29T10:00:00+00:00");
+        assert_eq!(first.open, Decimal::new(100, 0));
+        assert_eq!(first.high, Decimal::new(120, 0));
+        assert_eq!(first.low, Decimal::new(80, 0));
+        assert_eq!(first.close, Decimal::new(120, 0));
+        assert_eq!(first.volume, Decimal::new(6, 0));
+        // Sin velas para el intervalo 10:15-10:30, que no tuvo trades
+        assert_eq!(candles[1].candle_date.to_rfc3339(), "2017-05-29T10:30:00+00:00");
+        assert_eq!(candles[1].open, candles[1].close);
+
+        assert!(aggregate_candles(&[], Duration::from_secs(60)).is_empty());
+    }
+
+    fn order_book(bids: &[(i64, i64)], asks: &[(i64, i64)]) -> OrderBook {
+        let levels = |levels: &[(i64, i64)]| {
+            levels
+                .iter()
+                .map(|&(price, amount)| PriceLevel {
+                    price: Decimal::new(price, 0),
+                    amount: Decimal::new(amount, 0),
+                })
+                .collect()
+        };
+        OrderBook {
+            market: "ETHCLP".to_string(),
+            bids: levels(bids),
+            asks: levels(asks),
+            ..OrderBook::default()
+        }
+    }
+
+    #[test]
+    fn order_book_signals() {
+        let book = order_book(&[(9950, 3), (9900, 1), (9000, 4)], &[(10050, 1), (10100, 1)]);
+
+        assert_eq!(book.imbalance(), Some(Decimal::new(6, 1)));
+        assert_eq!(OrderBook::default().imbalance(), None);
+
+        // Mid 10000: 1% llega hasta 9900 y 10100, 0.5% solo al mejor nivel
+        let depth = book.liquidity_within(100).unwrap();
+        assert_eq!((depth.bids, depth.asks), (Decimal::new(4, 0), Decimal::new(2, 0)));
+        let depth = book.liquidity_within(50).unwrap();
+        assert_eq!((depth.bids, depth.asks), (Decimal::new(3, 0), Decimal::new(1, 0)));
+        assert_eq!(order_book(&[], &[(10050, 1)]).liquidity_within(100), None);
+
+        assert_eq!(book.vwap_for_size(Side::Buy, Decimal::ONE), Some(Decimal::new(10050, 0)));
+        assert_eq!(book.vwap_for_size(Side::Buy, Decimal::TWO), Some(Decimal::new(10075, 0)));
+        // 3 a 9950 y 1 a 9900
+        assert_eq!(
+            book.vwap_for_size(Side::Sell, Decimal::new(4, 0)),
+            Some(Decimal::new(99375, 1))
+        );
+        assert_eq!(book.vwap_for_size(Side::Buy, Decimal::new(3, 0)), None);
+        assert_eq!(book.vwap_for_size(Side::Sell, Decimal::ZERO), None);
+    }
+}
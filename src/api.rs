@@ -1,8 +1,25 @@
 use crate::internal::api::Api;
-use crate::internal::errors::CryptoMktErrorType;
-use crate::internal::request::CryptoMktRequest;
+use crate::internal::cache::ResponseCache;
+use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::ratelimit::{RateLimitBehavior, RateLimiter};
+use crate::internal::request::{
+    ConnectionStatus, CryptoMktRequest, ResponseMeta, Transport, TransportStats,
+};
+use crate::internal::response::{CryptoMktResponse, MarketResponse};
+use crate::internal::rt::{self, Instant};
+use crate::internal::streaming::PageStream;
+use crate::market::OrderRequest;
+use chrono::{DateTime, Utc};
+use reqwest::header::HeaderMap;
+use reqwest::Url;
 use serde::de::DeserializeOwned;
-use std::collections::HashMap;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::future::Future;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 ///
 /// HTTP methods supported by the API
@@ -15,6 +32,58 @@ pub enum RequestMethod {
     Get(bool),
 }
 
+///
+/// Bounds of a request made through `CryptoMktApi::call_with`, on top of the
+/// timeout of the HTTP client.
+///
+/// A POST that runs out of time or is cancelled may still have reached the
+/// exchange: see `Market::create_order_with_id` to retry orders safely.
+///
+#[derive(Debug, Clone, Default)]
+pub struct RequestOptions {
+    /// Maximum time of each attempt; attempts are repeated after a
+    /// `429 Too Many Requests`
+    pub timeout: Option<Duration>,
+    /// Instant by which the whole call must finish, waits and retries included
+    pub deadline: Option<Instant>,
+    /// Token that aborts the call, failing it with `Cancelled`
+    pub cancel: Option<CancellationToken>,
+}
+
+///
+/// Run `call` until it finishes or `cancel` fires, whichever comes first. When
+/// cancelled, `call` is dropped, aborting its in-flight request, and
+/// `Cancelled` is returned.
+///
+/// Every call of the crate can be aborted this way: the futures don't spawn
+/// tasks and dropping them at any `.await` leaves the client usable, so a
+/// strategy engine can stop its requests cleanly on shutdown. Like any
+/// timeout, a cancelled order creation may still have reached the exchange.
+///
+/// ```no_run
+/// use cryptomkt::{with_cancellation, Client};
+/// use tokio_util::sync::CancellationToken;
+///
+/// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+/// let client = Client::new("<API_KEY>", "<API SECRET>");
+/// let shutdown = CancellationToken::new();
+/// let market = client.create_market("ETHCLP");
+/// let ticker = with_cancellation(&shutdown, market.get_current_ticker()).await?;
+/// # Ok(())
+/// # }
+/// ```
+///
+pub async fn with_cancellation<F, T>(cancel: &CancellationToken, call: F) -> CryptoMktResult<T>
+where
+    F: Future<Output = CryptoMktResult<T>>,
+{
+    tokio::select! {
+        biased;
+        _ = cancel.cancelled() => Err(CryptoMktErrorType::Cancelled.into()),
+        result = call => result,
+    }
+}
+
 ///
 /// # Cryptomkt API
 ///
@@ -31,11 +100,57 @@ pub enum RequestMethod {
 /// println!("API domain: {}", api.domain());
 /// ```
 ///
+/// Clones are cheap: they share the transport, the rate limits, the clock
+/// offset and the cached market list.
+///
 #[derive(Debug, Clone)]
 pub struct CryptoMktApi {
-    i_api: Box<Api<CryptoMktRequest>>,
+    inner: Arc<ApiState>,
 }
 
+///
+/// Estado compartido por todos los clones de `CryptoMktApi`
+///
+#[derive(Debug)]
+struct ApiState {
+    i_api: Api<Transport>,
+    markets: Mutex<Option<Arc<HashSet<String>>>>,
+    concurrency: AtomicUsize,
+    submissions: Mutex<HashMap<String, Submission>>,
+}
+
+///
+/// Orden enviada con un identificador del cliente, ver
+/// `Market::create_order_with_id`
+///
+#[derive(Debug, Clone)]
+pub(crate) struct Submission {
+    pub market: String,
+    pub request: OrderRequest,
+    /// Fecha del primer envío, según el reloj del exchange
+    pub submitted_at: DateTime<Utc>,
+    /// ID asignado por el exchange, `None` mientras no se confirme
+    pub order_id: Option<String>,
+    /// Hay una llamada enviando o reconciliando la orden
+    pub in_flight: bool,
+}
+
+///
+/// Qué se sabe de un identificador del cliente al enviar su orden
+///
+#[derive(Debug)]
+pub(crate) enum PriorSubmission {
+    /// Nunca se envió
+    None,
+    /// Se envió sin saber si llegó al exchange, el primer envío fue en esa fecha
+    Unknown(DateTime<Utc>),
+    /// El exchange la registró con ese ID
+    Placed(String),
+}
+
+/// Peticiones simultáneas por defecto de las consultas a varios mercados
+const DEFAULT_CONCURRENCY: usize = 4;
+
 impl CryptoMktApi {
     ///
     /// Create the new API instance
@@ -45,12 +160,58 @@ impl CryptoMktApi {
     ///     secret_key: Secret Key as string
     ///
     pub fn new<'a>(api_key: &'a str, secret_key: &'a str) -> Self {
+        CryptoMktApi::from_api(Api::<Transport>::new(
+            api_key,
+            secret_key,
+            Box::new(Transport::new(CryptoMktRequest::new())),
+        ))
+    }
+
+    ///
+    /// Create an API instance over an already configured transport
+    ///
+    /// Arguments:
+    ///     api_key: API Key as string
+    ///     secret_key: Secret Key as string
+    ///     domain: Base URL, `None` for CryptoMarket's
+    ///     api_version: Version segment of the path, `None` for `v1`
+    ///     transport: HTTP transport, `CryptoMktRequest` or a mock
+    ///     rate_limits: Request quotas applied before sending each request
+    ///     cache: Responses of public endpoints kept for a while
+    ///     headers: Headers added to every request
+    ///
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn with_transport(
+        api_key: &str,
+        secret_key: &str,
+        domain: Option<&str>,
+        api_version: Option<&str>,
+        transport: Transport,
+        rate_limits: RateLimiter,
+        cache: Arc<ResponseCache>,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<Self> {
+        let mut api = Api::<Transport>::new(api_key, secret_key, Box::new(transport));
+        if let Some(domain) = domain {
+            api.set_domain(domain)?;
+        }
+        if let Some(api_version) = api_version {
+            api.set_api_version(api_version)?;
+        }
+        api.set_rate_limits(rate_limits);
+        api.set_cache(cache);
+        api.set_default_headers(headers);
+        Ok(CryptoMktApi::from_api(api))
+    }
+
+    fn from_api(api: Api<Transport>) -> Self {
         CryptoMktApi {
-            i_api: Box::new(Api::<CryptoMktRequest>::new(
-                api_key,
-                secret_key,
-                Box::new(CryptoMktRequest::new()),
-            )),
+            inner: Arc::new(ApiState {
+                i_api: api,
+                markets: Mutex::new(None),
+                concurrency: AtomicUsize::new(DEFAULT_CONCURRENCY),
+                submissions: Mutex::new(HashMap::new()),
+            }),
         }
     }
 
@@ -58,14 +219,123 @@ impl CryptoMktApi {
     /// Get the domain
     ///
     pub fn domain(&self) -> String {
-        self.i_api.domain()
+        self.inner.i_api.domain()
+    }
+
+    ///
+    /// Send the requests to `domain` instead, e.g. a staging environment or a
+    /// regional endpoint. Applies to every clone of this API. Fails with
+    /// `InvalidConfiguration` when `domain` isn't a valid URL
+    ///
+    pub fn set_domain(&self, domain: &str) -> CryptoMktResult<()> {
+        self.inner.i_api.set_domain(domain)
+    }
+
+    ///
+    /// Base URL of the v3 API, where the resources the v1 API doesn't publish
+    /// are read from, like `Market::get_limits`. Without `set_v3_domain` it is
+    /// `cryptomkt::v3::DEFAULT_URL`, as long as the domain and version are the
+    /// default ones; otherwise it fails with `InvalidConfiguration`
+    ///
+    pub fn v3_domain(&self) -> CryptoMktResult<String> {
+        self.inner.i_api.v3_domain()
+    }
+
+    ///
+    /// Read the v3 resources from `domain` instead, e.g. the v3 API of a
+    /// sandbox. Applies to every clone of this API. Fails with
+    /// `InvalidConfiguration` when `domain` isn't a valid URL
+    ///
+    pub fn set_v3_domain(&self, domain: &str) -> CryptoMktResult<()> {
+        self.inner.i_api.set_v3_domain(domain)
     }
 
     ///
     /// Get the API Version
     ///
     pub fn version(&self) -> String {
-        self.i_api.api_version()
+        self.inner.i_api.api_version()
+    }
+
+    ///
+    /// Change the version segment of the endpoints (`v1` by default), which is
+    /// part of the signature too. Applies to every clone of this API. Fails with
+    /// `InvalidConfiguration` when `version` is empty or has a `/`
+    ///
+    pub fn set_version(&self, version: &str) -> CryptoMktResult<()> {
+        self.inner.i_api.set_api_version(version)
+    }
+
+    ///
+    /// Maximum number of requests sent at once by the calls that query several
+    /// markets, like `Client::get_tickers`. 4 by default
+    ///
+    pub fn concurrency(&self) -> usize {
+        self.inner.concurrency.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Change the maximum number of simultaneous requests of the calls that
+    /// query several markets. Applies to every clone of this API; `0` is taken
+    /// as `1`
+    ///
+    pub fn set_concurrency(&self, limit: usize) {
+        self.inner.concurrency.store(limit.max(1), Ordering::Relaxed);
+    }
+
+    ///
+    /// Health of the connection with the exchange.
+    ///
+    /// After a few transport failures in a row (connect errors, DNS failures,
+    /// timeouts) the underlying HTTP client and its connection pool are rebuilt,
+    /// so a long-running process recovers from network blips without being
+    /// restarted. Useful as a readiness probe.
+    ///
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.inner.i_api.connection_status()
+    }
+
+    ///
+    /// Requests sent by the HTTP transport, its failures and mean latency, to
+    /// tune the connection pool options of `ClientBuilder`. Transports that
+    /// don't keep count, like `MockTransport`, report zeros
+    ///
+    pub fn transport_stats(&self) -> TransportStats {
+        self.inner.i_api.transport_stats()
+    }
+
+    ///
+    /// Respuesta configurada ante un 429 del exchange
+    ///
+    pub(crate) fn rate_limit_behavior(&self) -> RateLimitBehavior {
+        self.inner.i_api.rate_limit_behavior()
+    }
+
+    ///
+    /// Keep signature timestamps aligned with the exchange clock.
+    ///
+    /// The offset between the local clock and the server is measured from the
+    /// `Date` header of the exchange and added to every signature timestamp.
+    /// Signed requests measure it again once it is older than `interval`, so
+    /// drift on long-running processes is corrected too. Pass `None` to stop
+    /// refreshing; the last measured offset keeps being applied.
+    ///
+    pub fn enable_clock_sync(&self, interval: Option<Duration>) {
+        self.inner.i_api.set_clock_sync_interval(interval);
+    }
+
+    ///
+    /// Measure the clock offset against the exchange right away
+    ///
+    pub async fn sync_clock(&self) -> CryptoMktResult<()> {
+        self.inner.i_api.sync_clock().await
+    }
+
+    ///
+    /// Offset in milliseconds applied to signature timestamps (server - local)
+    ///
+    pub fn clock_offset(&self) -> i64 {
+        self.inner.i_api.clock_offset()
     }
 
     ///
@@ -100,18 +370,375 @@ impl CryptoMktApi {
     ///     `endpoint`: Endpoint
     ///     `payload`: Payload
     ///
-    pub async fn call<'a, T>(
+    pub async fn call<T>(
         &self,
         method: RequestMethod,
-        endpoint: &'a str,
+        endpoint: &str,
         payload: HashMap<String, String>,
-    ) -> Result<T, CryptoMktErrorType>
+    ) -> CryptoMktResult<T>
     where
         T: DeserializeOwned,
     {
         match method {
-            RequestMethod::Get(is_public) => self.i_api.get_edge(endpoint, payload, is_public).await,
-            RequestMethod::Post => self.i_api.post_edge(endpoint, payload).await,
+            RequestMethod::Get(is_public) => {
+                self.inner.i_api.get_edge(endpoint, payload, is_public).await
+            }
+            RequestMethod::Post => self.inner.i_api.post_edge(endpoint, payload).await,
         }
     }
+
+    ///
+    /// Like `call`, bounded by `options`: each attempt by `options.timeout` and
+    /// the whole call, including rate limit waits and retries, by
+    /// `options.deadline`. When either runs out the call fails with `Timeout`;
+    /// when `options.cancel` fires, with `Cancelled`
+    ///
+    /// ```no_run
+    /// use cryptomkt::response::TickerResponse;
+    /// use cryptomkt::{CryptoMktApi, RequestMethod, RequestOptions};
+    /// use std::collections::HashMap;
+    /// use std::time::Duration;
+    ///
+    /// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// let api = CryptoMktApi::new("<API Key>", "<Secret Key>");
+    /// let options = RequestOptions {
+    ///     timeout: Some(Duration::from_millis(300)),
+    ///     ..Default::default()
+    /// };
+    /// let ticker: TickerResponse = api
+    ///     .call_with(RequestMethod::Get(true), "ticker", HashMap::new(), options)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn call_with<T>(
+        &self,
+        method: RequestMethod,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+        options: RequestOptions,
+    ) -> CryptoMktResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let api = &self.inner.i_api;
+        let request = async {
+            match method {
+                RequestMethod::Get(is_public) => {
+                    api.get_edge_with(endpoint, payload, is_public, options.timeout)
+                        .await
+                }
+                RequestMethod::Post => api.post_edge_with(endpoint, payload, options.timeout).await,
+            }
+        };
+        let request = async {
+            match options.deadline {
+                Some(deadline) => rt::timeout_at(deadline, request)
+                    .await
+                    .unwrap_or_else(|_| Err(CryptoMktErrorType::Timeout.into())),
+                None => request.await,
+            }
+        };
+        match &options.cancel {
+            Some(cancel) => with_cancellation(cancel, request).await,
+            None => request.await,
+        }
+    }
+
+    ///
+    /// Like `call`, also returning the status, headers and latency of the
+    /// response, e.g. to throttle on the rate limit headers of the exchange or
+    /// to follow its clock. Public endpoints are always requested, the cache
+    /// of `ClientBuilder::cache_ttl` is refreshed but not read
+    ///
+    /// ```no_run
+    /// use cryptomkt::response::TickerResponse;
+    /// use cryptomkt::{CryptoMktApi, RequestMethod};
+    /// use std::collections::HashMap;
+    ///
+    /// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// let api = CryptoMktApi::new("<API Key>", "<Secret Key>");
+    /// let (ticker, meta): (TickerResponse, _) = api
+    ///     .call_with_meta(RequestMethod::Get(true), "ticker", HashMap::new())
+    ///     .await?;
+    /// println!("{:?} in {:?}", meta.header("x-ratelimit-remaining"), meta.latency);
+    /// println!("exchange time: {:?}", meta.server_time());
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn call_with_meta<T>(
+        &self,
+        method: RequestMethod,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<(T, ResponseMeta)>
+    where
+        T: DeserializeOwned,
+    {
+        let api = &self.inner.i_api;
+        match method {
+            RequestMethod::Get(is_public) => {
+                api.get_edge_meta(endpoint, payload, is_public, None).await
+            }
+            RequestMethod::Post => api.post_edge_meta(endpoint, payload, None).await,
+        }
+    }
+
+    ///
+    /// Like `call`, but returns the body as untyped JSON, for responses the
+    /// crate's models don't describe yet
+    ///
+    /// ```no_run
+    /// use cryptomkt::{CryptoMktApi, RequestMethod};
+    /// use std::collections::HashMap;
+    ///
+    /// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// let api = CryptoMktApi::new("<API Key>", "<Secret Key>");
+    /// let body = api.call_raw(RequestMethod::Get(true), "market", HashMap::new()).await?;
+    /// println!("{}", body["data"]);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn call_raw(
+        &self,
+        method: RequestMethod,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<Value> {
+        self.call(method, endpoint, payload).await
+    }
+
+    ///
+    /// Like `call`, but deserializes only the `data` field of the response,
+    /// so `T` can be any type of the caller without the response envelope
+    ///
+    /// ```no_run
+    /// use cryptomkt::{CryptoMktApi, RequestMethod};
+    /// use serde::Deserialize;
+    /// use std::collections::HashMap;
+    ///
+    /// #[derive(Deserialize)]
+    /// struct MyTicker {
+    ///     market: String,
+    ///     last_price: String,
+    /// }
+    ///
+    /// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// let api = CryptoMktApi::new("<API Key>", "<Secret Key>");
+    /// let mut payload = HashMap::new();
+    /// payload.insert("market".to_string(), "ETHCLP".to_string());
+    /// let tickers: Vec<MyTicker> = api
+    ///     .call_as(RequestMethod::Get(true), "ticker", payload)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn call_as<T>(
+        &self,
+        method: RequestMethod,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let resp: CryptoMktResponse<T> = self.call(method, endpoint, payload).await?;
+        Ok(resp.data)
+    }
+
+    ///
+    /// Fetch a public resource outside the configured API, e.g. from the v3 API
+    ///
+    pub(crate) async fn get_external<T: DeserializeOwned>(
+        &self,
+        url: Url,
+        endpoint: &str,
+    ) -> CryptoMktResult<T> {
+        self.inner.i_api.get_external(url, endpoint).await
+    }
+
+    ///
+    /// Request a page of a public list endpoint, handing out its items as the
+    /// body arrives instead of buffering it. The cache is not used
+    ///
+    pub(crate) async fn get_page_stream<T: DeserializeOwned>(
+        &self,
+        endpoint: &str,
+        payload: &HashMap<String, String>,
+    ) -> CryptoMktResult<PageStream<T>> {
+        self.inner.i_api.get_edge_stream(endpoint, payload).await
+    }
+
+    ///
+    /// Drop the responses kept by the cache of public endpoints, see
+    /// `ClientBuilder::cache_ttl`
+    ///
+    pub fn clear_cache(&self) {
+        self.inner.i_api.clear_cache();
+    }
+
+    ///
+    /// Registra un envío de `client_order_id` hecho antes de crear este
+    /// cliente, p. ej. por el proceso anterior a un reinicio, sin saber si
+    /// llegó al exchange. El siguiente `Market::create_order_with_id` busca la
+    /// orden antes de enviarla. No cambia nada si el identificador ya se conoce
+    ///
+    /// Argumentos
+    ///     client_order_id: Identificador del cliente
+    ///     market: Mercado de la orden
+    ///     request: Orden enviada
+    ///     submitted_at: Fecha local del primer envío
+    ///
+    pub(crate) fn restore_submission(
+        &self,
+        client_order_id: &str,
+        market: &str,
+        request: &OrderRequest,
+        submitted_at: DateTime<Utc>,
+    ) {
+        let submitted_at = submitted_at + chrono::Duration::milliseconds(self.clock_offset());
+        let mut submissions = self.inner.submissions.lock().unwrap();
+        submissions
+            .entry(client_order_id.to_string())
+            .or_insert_with(|| Submission {
+                market: market.to_string(),
+                request: request.clone(),
+                submitted_at,
+                order_id: None,
+                in_flight: false,
+            });
+    }
+
+    ///
+    /// Registra el envío de la orden `client_order_id`, marcándola en curso.
+    /// Falla si el identificador ya se usó para otra orden o si otra llamada
+    /// la está enviando
+    ///
+    pub(crate) fn begin_submission(
+        &self,
+        client_order_id: &str,
+        market: &str,
+        request: &OrderRequest,
+    ) -> CryptoMktResult<PriorSubmission> {
+        let now = Utc::now() + chrono::Duration::milliseconds(self.clock_offset());
+        let mut submissions = self.inner.submissions.lock().unwrap();
+        let submission = match submissions.get_mut(client_order_id) {
+            Some(submission) => submission,
+            None => {
+                submissions.insert(
+                    client_order_id.to_string(),
+                    Submission {
+                        market: market.to_string(),
+                        request: request.clone(),
+                        submitted_at: now,
+                        order_id: None,
+                        in_flight: true,
+                    },
+                );
+                return Ok(PriorSubmission::None);
+            }
+        };
+        let same = submission.market.eq_ignore_ascii_case(market)
+            && submission.request.order_type == request.order_type
+            && submission.request.amount == request.amount
+            && submission.request.price == request.price;
+        if !same {
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "client order id {} was used for another order",
+                client_order_id
+            ))
+            .into());
+        }
+        if submission.in_flight {
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "order {} is already being submitted",
+                client_order_id
+            ))
+            .into());
+        }
+        if let Some(order_id) = &submission.order_id {
+            return Ok(PriorSubmission::Placed(order_id.clone()));
+        }
+        submission.in_flight = true;
+        Ok(PriorSubmission::Unknown(submission.submitted_at))
+    }
+
+    ///
+    /// Termina el envío en curso de `client_order_id`
+    ///
+    /// Argumentos
+    ///     client_order_id: Identificador del cliente
+    ///     order_id: ID asignado por el exchange, `None` si no se sabe si llegó
+    ///
+    pub(crate) fn settle_submission(&self, client_order_id: &str, order_id: Option<&str>) {
+        let mut submissions = self.inner.submissions.lock().unwrap();
+        if let Some(submission) = submissions.get_mut(client_order_id) {
+            submission.in_flight = false;
+            if let Some(order_id) = order_id {
+                submission.order_id = Some(order_id.to_string());
+            }
+        }
+    }
+
+    ///
+    /// IDs del exchange ya asignados a algún identificador del cliente
+    ///
+    pub(crate) fn submitted_order_ids(&self) -> HashSet<String> {
+        let submissions = self.inner.submissions.lock().unwrap();
+        submissions
+            .values()
+            .filter_map(|submission| submission.order_id.clone())
+            .collect()
+    }
+
+    ///
+    /// Forget the order submitted as `client_order_id` by
+    /// `Market::create_order_with_id`, so the identifier can be reused and its
+    /// entry doesn't take memory anymore. Returns whether it was known
+    ///
+    pub fn forget_submission(&self, client_order_id: &str) -> bool {
+        self.inner
+            .submissions
+            .lock()
+            .unwrap()
+            .remove(client_order_id)
+            .is_some()
+    }
+
+    ///
+    /// Checks `market` against the markets listed by the exchange, failing with
+    /// `UnknownMarket` when it isn't one of them. The list is fetched on first use
+    /// and shared by every clone of this API.
+    ///
+    pub async fn validate_market(&self, market: &str) -> CryptoMktResult<()> {
+        let cached = self.inner.markets.lock().unwrap().clone();
+        let markets = match cached {
+            Some(markets) => markets,
+            None => {
+                let resp = self
+                    .call::<MarketResponse>(RequestMethod::Get(true), "market", HashMap::new())
+                    .await?;
+                self.remember_markets(&resp.data)
+            }
+        };
+
+        if markets.contains(&market.to_uppercase()) {
+            Ok(())
+        } else {
+            Err(CryptoMktErrorType::UnknownMarket(market.to_string()).into())
+        }
+    }
+
+    ///
+    /// Replaces the cached market list used by `validate_market`
+    ///
+    pub(crate) fn remember_markets(&self, names: &[String]) -> Arc<HashSet<String>> {
+        let markets = Arc::new(names.iter().map(|m| m.to_uppercase()).collect::<HashSet<_>>());
+        *self.inner.markets.lock().unwrap() = Some(markets.clone());
+        markets
+    }
 }
@@ -0,0 +1,124 @@
+//!
+//! ## cryptomkt
+//!
+//! Command line client (feature `cli`) for quick checks and manual trades.
+//! Private commands read the credentials from `CRYPTOMKT_API_KEY` and
+//! `CRYPTOMKT_API_SECRET`; answers are printed as JSON.
+//!
+//! ```text
+//! cryptomkt ticker ETHCLP
+//! cryptomkt book ETHCLP --depth 10
+//! cryptomkt balance
+//! cryptomkt order create ETHCLP buy 0.5 170000
+//! cryptomkt order cancel ETHCLP O000001
+//! ```
+//!
+
+use std::env;
+use std::error::Error;
+use std::process;
+use std::str::FromStr;
+
+use cryptomkt::{Client, Decimal, Side};
+use serde::Serialize;
+
+const USAGE: &str = "\
+Usage: cryptomkt <command>
+
+Commands:
+    ticker <market>                                 Current ticker
+    book <market> [--depth <n>]                     Order book, 20 levels by default
+    balance                                         Balance of every wallet
+    order create <market> <buy|sell> <amount> <price>
+                                                    Place a limit order
+    order cancel <market> <order id>                Cancel an order
+
+Private commands read CRYPTOMKT_API_KEY and CRYPTOMKT_API_SECRET";
+
+const DEFAULT_DEPTH: u32 = 20;
+
+type CliResult<T> = Result<T, Box<dyn Error>>;
+
+///
+/// Error de uso de la línea de comandos
+///
+fn usage_error(msg: &str) -> Box<dyn Error> {
+    format!("{}\n\n{}", msg, USAGE).into()
+}
+
+///
+/// Argumento posicional obligatorio
+///
+fn arg<'a>(args: &'a [String], i: usize, name: &str) -> CliResult<&'a str> {
+    args.get(i)
+        .map(String::as_str)
+        .ok_or_else(|| usage_error(&format!("missing <{}>", name)))
+}
+
+///
+/// Argumento posicional obligatorio convertido con `FromStr`
+///
+fn parse<T: FromStr>(args: &[String], i: usize, name: &str) -> CliResult<T> {
+    let value = arg(args, i, name)?;
+    value
+        .parse()
+        .map_err(|_| usage_error(&format!("invalid <{}>: {:?}", name, value)))
+}
+
+///
+/// Valor de la opción `flag`, si está presente
+///
+fn option<T: FromStr>(args: &[String], flag: &str) -> CliResult<Option<T>> {
+    match args.iter().position(|arg| arg == flag) {
+        Some(i) => parse(args, i + 1, &flag[2..]).map(Some),
+        None => Ok(None),
+    }
+}
+
+fn print<T: Serialize>(value: &T) -> CliResult<()> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn run(args: &[String]) -> CliResult<()> {
+    match args.first().map(String::as_str) {
+        Some("ticker") => {
+            let market = Client::public().create_market(arg(args, 1, "market")?);
+            print(&market.get_current_ticker().await?)
+        }
+        Some("book") => {
+            let market = Client::public().create_market(arg(args, 1, "market")?);
+            let depth = option(args, "--depth")?.unwrap_or(DEFAULT_DEPTH);
+            print(&market.get_order_book_snapshot(depth).await?)
+        }
+        Some("balance") => print(&Client::from_env()?.get_balance().await?),
+        Some("order") => {
+            let market = Client::from_env()?.create_market(arg(args, 2, "market")?);
+            match arg(args, 1, "create|cancel")? {
+                "create" => {
+                    let side: Side = parse(args, 3, "buy|sell")?;
+                    let amount: Decimal = parse(args, 4, "amount")?;
+                    let price: Decimal = parse(args, 5, "price")?;
+                    print(&market.create_order(side, amount, price).await?)
+                }
+                "cancel" => print(&market.cancel_order(arg(args, 3, "order id")?).await?),
+                other => Err(usage_error(&format!("unknown order command {:?}", other))),
+            }
+        }
+        Some("help") | Some("--help") | Some("-h") => {
+            println!("{}", USAGE);
+            Ok(())
+        }
+        Some(other) => Err(usage_error(&format!("unknown command {:?}", other))),
+        None => Err(usage_error("missing command")),
+    }
+}
+
+#[tokio::main(flavor = "current_thread")]
+async fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    if let Err(e) = run(&args).await {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
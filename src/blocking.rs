@@ -0,0 +1,501 @@
+//!
+//! ## Blocking client
+//!
+//! `cryptomkt::blocking::Client` offers the methods of `cryptomkt::Client`
+//! without `async`, for scripts and CLIs. Available with the `blocking` feature.
+//!
+//! Like `reqwest::blocking`, every call runs the asynchronous client on a
+//! runtime owned by the blocking client, so the signing, rate limiting and
+//! response parsing are the same. The methods must not be called from inside
+//! an asynchronous runtime.
+//!
+//! ```no_run
+//! use cryptomkt::blocking::Client;
+//!
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! for market in client.get_markets().unwrap() {
+//!     println!("{:?}", market.get_current_ticker());
+//! }
+//! ```
+//!
+//! Options of `ClientBuilder` are available by converting the built client:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! let client: cryptomkt::blocking::Client = cryptomkt::Client::builder()
+//!     .credentials("<API_KEY>", "<API SECRET>")
+//!     .timeout(Duration::from_secs(10))
+//!     .build()
+//!     .unwrap()
+//!     .into();
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::fmt::Display;
+use std::future::Future;
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use rust_decimal::Decimal;
+use tokio::runtime::Runtime;
+
+use crate::client::WithdrawalDestination;
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::pagination::Page;
+use crate::internal::models::{
+    Account, Balance, Book, Conversion, Currency, KeyPermissions, MarketLimits, MarketPair, Order,
+    OrderBook, OrdersInstant, Payment, PriceLevel, Prices, Ticker, Trade, Transaction,
+};
+use crate::internal::request::{ConnectionStatus, TransportStats};
+use crate::internal::response::Paginated;
+use crate::market::{BookSide, OrderRequest, Side, Timeframe};
+
+///
+/// Blocking version of `cryptomkt::Client`. Clones share the runtime
+///
+#[derive(Clone)]
+pub struct Client {
+    inner: crate::Client,
+    runtime: Arc<Runtime>,
+}
+
+impl Client {
+    ///
+    /// Create the new Client instance
+    ///
+    pub fn new(api_key: &str, secret_key: &str) -> Self {
+        crate::Client::new(api_key, secret_key).into()
+    }
+
+    ///
+    /// Asynchronous client the calls are made with
+    ///
+    pub fn inner(&self) -> &crate::Client {
+        &self.inner
+    }
+
+    ///
+    /// Run a future of the asynchronous client, for the calls without a
+    /// blocking version, e.g.
+    /// `client.block_on(client.inner().payment_order(amount, "CLP", email).send())`
+    ///
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    ///
+    /// Get Market List
+    ///
+    pub fn get_markets(&self) -> CryptoMktResult<Vec<Market>> {
+        let markets = self.block_on(self.inner.get_markets())?;
+        Ok(markets
+            .into_iter()
+            .map(|inner| Market {
+                inner,
+                runtime: self.runtime.clone(),
+            })
+            .collect())
+    }
+
+    ///
+    /// Return a new market from its name or its pair
+    ///
+    pub fn create_market<M: Display>(&self, market: M) -> Market {
+        Market {
+            inner: self.inner.create_market(market),
+            runtime: self.runtime.clone(),
+        }
+    }
+
+    ///
+    /// Health of the connection with the exchange
+    ///
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.inner.connection_status()
+    }
+
+    ///
+    /// Statistics of the HTTP transport
+    ///
+    pub fn transport_stats(&self) -> TransportStats {
+        self.inner.transport_stats()
+    }
+
+    ///
+    /// Get the ticker of every market, see `cryptomkt::Client::get_all_tickers`
+    ///
+    pub fn get_all_tickers(&self) -> CryptoMktResult<HashMap<String, Ticker>> {
+        self.block_on(self.inner.get_all_tickers())
+    }
+
+    ///
+    /// Get the ticker of each of `markets`, see `cryptomkt::Client::get_tickers`
+    ///
+    pub fn get_tickers(&self, markets: &[&str]) -> CryptoMktResult<HashMap<String, Ticker>> {
+        self.block_on(self.inner.get_tickers(markets))
+    }
+
+    ///
+    /// Estimate a currency conversion, see `cryptomkt::Client::convert`
+    ///
+    pub fn convert<F, T>(&self, amount: Decimal, from: F, to: T) -> CryptoMktResult<Conversion>
+    where
+        F: Into<Currency>,
+        T: Into<Currency>,
+    {
+        self.block_on(self.inner.convert(amount, from, to))
+    }
+
+    ///
+    /// Get the account of the owner of the API key
+    ///
+    pub fn get_account(&self) -> CryptoMktResult<Account> {
+        self.block_on(self.inner.get_account())
+    }
+
+    ///
+    /// Get the balance of every wallet
+    ///
+    pub fn get_balance(&self) -> CryptoMktResult<Vec<Balance>> {
+        self.block_on(self.inner.get_balance())
+    }
+
+    ///
+    /// Get the deposits of `currency`
+    ///
+    pub fn get_deposits(
+        &self,
+        currency: &str,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Transaction>> {
+        self.block_on(self.inner.get_deposits(currency, page))
+    }
+
+    ///
+    /// Get the withdrawals of `currency`
+    ///
+    pub fn get_withdrawals(
+        &self,
+        currency: &str,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Transaction>> {
+        self.block_on(self.inner.get_withdrawals(currency, page))
+    }
+
+    ///
+    /// Withdraw `amount` of `currency` to `destination`
+    ///
+    pub fn request_withdrawal(
+        &self,
+        amount: Decimal,
+        currency: &str,
+        destination: WithdrawalDestination,
+    ) -> CryptoMktResult<Transaction> {
+        self.block_on(self.inner.request_withdrawal(amount, currency, destination))
+    }
+
+    ///
+    /// Get Order status
+    ///
+    pub fn get_order_status(&self, order_id: &str) -> CryptoMktResult<Order> {
+        self.block_on(self.inner.get_order_status(order_id))
+    }
+
+    ///
+    /// Scopes granted to the API key, see `cryptomkt::Client::key_permissions`
+    ///
+    pub fn key_permissions(&self) -> CryptoMktResult<KeyPermissions> {
+        self.block_on(self.inner.key_permissions())
+    }
+
+    ///
+    /// Returns the status of a payment order
+    ///
+    pub fn payment_order_status(&self, id: &str) -> CryptoMktResult<Payment> {
+        self.block_on(self.inner.payment_order_status(id))
+    }
+
+    ///
+    /// Void an unpaid payment order, see `cryptomkt::Client::cancel_payment_order`
+    ///
+    pub fn cancel_payment_order(&self, id: &str) -> CryptoMktResult<Payment> {
+        self.block_on(self.inner.cancel_payment_order(id))
+    }
+
+    ///
+    /// Wait for a payment order to reach a final state, see
+    /// `cryptomkt::Client::wait_for_payment`
+    ///
+    pub fn wait_for_payment(
+        &self,
+        id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> CryptoMktResult<Payment> {
+        self.block_on(self.inner.wait_for_payment(id, poll_interval, timeout))
+    }
+
+    ///
+    /// Returns the list of generated payment orders
+    ///
+    pub fn get_payment_orders(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Payment>> {
+        self.block_on(self.inner.get_payment_orders(start_date, end_date, page))
+    }
+
+    ///
+    /// Get the trades of `market`, see `cryptomkt::Client::get_trades`
+    ///
+    pub fn get_trades(
+        &self,
+        market: &str,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Trade>> {
+        self.block_on(self.inner.get_trades(market, start, end, page))
+    }
+}
+
+impl From<crate::Client> for Client {
+    fn from(inner: crate::Client) -> Self {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .expect("failed to start the runtime of the blocking client");
+        Client {
+            inner,
+            runtime: Arc::new(runtime),
+        }
+    }
+}
+
+///
+/// Blocking version of `cryptomkt::Market`
+///
+#[derive(Clone)]
+pub struct Market {
+    inner: crate::Market,
+    runtime: Arc<Runtime>,
+}
+
+impl Market {
+    fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.runtime.block_on(future)
+    }
+
+    ///
+    /// Get the market name (Ej ETHCLP)
+    ///
+    pub fn get_name(&self) -> String {
+        self.inner.get_name()
+    }
+
+    ///
+    /// Base and quote currencies of the market, see `cryptomkt::Market::pair`
+    ///
+    pub fn pair(&self) -> CryptoMktResult<MarketPair> {
+        self.inner.pair()
+    }
+
+    ///
+    /// Trading rules of the market, see `cryptomkt::Market::get_limits`
+    ///
+    pub fn get_limits(&self) -> CryptoMktResult<MarketLimits> {
+        self.block_on(self.inner.get_limits())
+    }
+
+    ///
+    /// Get the current ticker
+    ///
+    pub fn get_current_ticker(&self) -> CryptoMktResult<Ticker> {
+        self.block_on(self.inner.get_current_ticker())
+    }
+
+    ///
+    /// Get the order books
+    ///
+    pub fn get_orders_book<B: Into<BookSide>>(
+        &self,
+        book_side: B,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Book>> {
+        self.block_on(self.inner.get_orders_book(book_side, page))
+    }
+
+    ///
+    /// Get both sides of the order book at once, see
+    /// `cryptomkt::Market::get_order_book_snapshot`
+    ///
+    pub fn get_order_book_snapshot(&self, depth: u32) -> CryptoMktResult<OrderBook> {
+        self.block_on(self.inner.get_order_book_snapshot(depth))
+    }
+
+    ///
+    /// Highest purchase price, see `cryptomkt::Market::best_bid`
+    ///
+    pub fn best_bid(&self) -> CryptoMktResult<Option<PriceLevel>> {
+        self.block_on(self.inner.best_bid())
+    }
+
+    ///
+    /// Lowest sale price, see `cryptomkt::Market::best_ask`
+    ///
+    pub fn best_ask(&self) -> CryptoMktResult<Option<PriceLevel>> {
+        self.block_on(self.inner.best_ask())
+    }
+
+    ///
+    /// Difference between the best prices, see `cryptomkt::Market::spread`
+    ///
+    pub fn spread(&self) -> CryptoMktResult<Option<Decimal>> {
+        self.block_on(self.inner.spread())
+    }
+
+    ///
+    /// Get the price history as candles
+    ///
+    pub fn get_prices(&self, timeframe: Timeframe, page: Page) -> CryptoMktResult<Prices> {
+        self.block_on(self.inner.get_prices(timeframe, page))
+    }
+
+    ///
+    /// Get Trades
+    ///
+    pub fn get_trades(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Trade>> {
+        self.block_on(self.inner.get_trades(start, end, page))
+    }
+
+    ///
+    /// Get my active orders in this market
+    ///
+    pub fn get_active_orders(&self, page: Page) -> CryptoMktResult<Paginated<Order>> {
+        self.block_on(self.inner.get_active_orders(page))
+    }
+
+    ///
+    /// Get my executed orders in this market
+    ///
+    pub fn get_executed_orders(&self, page: Page) -> CryptoMktResult<Paginated<Order>> {
+        self.block_on(self.inner.get_executed_orders(page))
+    }
+
+    ///
+    /// Create a limit order
+    ///
+    pub fn create_order(
+        &self,
+        order_type: Side,
+        amount: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        self.block_on(self.inner.create_order(order_type, amount, price))
+    }
+
+    ///
+    /// Create a limit order that is safe to retry, see
+    /// `cryptomkt::Market::create_order_with_id`
+    ///
+    pub fn create_order_with_id(
+        &self,
+        client_order_id: &str,
+        order_type: Side,
+        amount: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        self.block_on(
+            self.inner
+                .create_order_with_id(client_order_id, order_type, amount, price),
+        )
+    }
+
+    ///
+    /// Create several orders at once, see `cryptomkt::Market::create_orders`
+    ///
+    pub fn create_orders(&self, orders: &[OrderRequest]) -> Vec<CryptoMktResult<Order>> {
+        self.block_on(self.inner.create_orders(orders))
+    }
+
+    ///
+    /// Get Order status
+    ///
+    pub fn get_order_status(&self, order_id: &str) -> CryptoMktResult<Order> {
+        self.block_on(self.inner.get_order_status(order_id))
+    }
+
+    ///
+    /// Cancel Order
+    ///
+    pub fn cancel_order(&self, order_id: &str) -> CryptoMktResult<Order> {
+        self.block_on(self.inner.cancel_order(order_id))
+    }
+
+    ///
+    /// Cancel several orders at once, see `cryptomkt::Market::cancel_orders`
+    ///
+    pub fn cancel_orders(&self, order_ids: &[&str]) -> Vec<CryptoMktResult<Order>> {
+        self.block_on(self.inner.cancel_orders(order_ids))
+    }
+
+    ///
+    /// Quote a purchase or sale in the Instant Exchange
+    ///
+    pub fn quote_instant(&self, side: Side, amount: Decimal) -> CryptoMktResult<OrdersInstant> {
+        self.block_on(self.inner.quote_instant(side, amount))
+    }
+
+    ///
+    /// Buy or sell `amount` right now through the Instant Exchange
+    ///
+    pub fn execute_instant(&self, side: Side, amount: Decimal) -> CryptoMktResult<String> {
+        self.block_on(self.inner.execute_instant(side, amount))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock::MockTransport;
+
+    fn client(transport: &MockTransport) -> Client {
+        crate::Client::builder()
+            .credentials("APK", "SK")
+            .http_transport(transport.clone())
+            .build()
+            .unwrap()
+            .into()
+    }
+
+    #[test]
+    fn calls_without_a_runtime() {
+        let transport = MockTransport::with_fixtures();
+        let client = client(&transport);
+
+        let balance = client.get_balance().unwrap();
+        assert_eq!(balance[0].wallet, "CLP");
+
+        let markets = client.get_markets().unwrap();
+        assert_eq!(markets[0].get_name(), "ETHCLP");
+        let ticker = markets[0].get_current_ticker().unwrap();
+        assert_eq!(ticker.market, "ETHCLP");
+
+        let orders = client
+            .create_market("ETHCLP")
+            .get_active_orders(Page::default().limit(20))
+            .unwrap();
+        assert_eq!(orders.len(), 2);
+        assert_eq!(transport.requests().len(), 4);
+    }
+}
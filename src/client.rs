@@ -12,7 +12,13 @@
 //! #[tokio::main]
 //! async fn main() {
 //!     let client = Client::new(API_KEY, API_SECRET);
-//!     let markets = client.get_markets().await;
+//!     let markets = match client.get_markets().await {
+//!         Ok(markets) => markets,
+//!         Err(e) => {
+//!             println!("{:?}", e);
+//!             return;
+//!         }
+//!     };
 //!     for m in markets.iter() {
 //!         println!("{}", m.get_name());
 //!
@@ -31,19 +37,68 @@
 //!
 
 use crate::api::{CryptoMktApi, RequestMethod};
-use crate::market::Market;
+use crate::market::{Market, Side};
+use crate::{v2, v3};
 
-use crate::internal::errors::CryptoMktResult;
-use crate::internal::errors::CryptoMktErrorType;
-use crate::internal::models::{Balance, Payment, Trade};
+use crate::internal::cache::ResponseCache;
+use crate::internal::config::Profile;
+use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::{
+    Account, Balance, Conversion, ConversionStep, Currency, KeyPermissions, MarketPair, Order,
+    Payment, Ticker, Trade, Transaction,
+};
+use crate::internal::middleware::Middleware;
+use crate::internal::pagination::{collect_pages, stream_incremental_pages, stream_pages, Page};
+use crate::internal::paper::PaperTransport;
+use crate::internal::ratelimit::{RateLimit, RateLimitBehavior, RateLimiter, TokenBucket};
+use crate::internal::rt::{self, Instant};
+use crate::internal::timestamp;
+use crate::internal::request::{
+    ConnectionStatus, CryptoMktRequest, HttpRequest, Transport, TransportConfig, TransportStats,
+};
+use crate::internal::secret::SecretString;
 use crate::internal::response::{
-    BalanceResponse, MarketResponse, PaymentListResponse, PaymentResponse,
+    AccountResponse, BalanceResponse, MarketResponse, Paginated, PaymentListResponse,
+    PaymentResponse, SimpleOrderResponse, SimpleTransactionResponse, TickerResponse,
+    TransactionResponse,
 };
+use chrono::NaiveDate;
+use futures::stream::{self, Stream, StreamExt, TryStreamExt};
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use rust_decimal::Decimal;
 use std::collections::HashMap;
+use std::fmt::Display;
+#[cfg(feature = "config")]
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+///
+/// Where the funds of a withdrawal are sent
+///
+#[derive(Debug, Clone, PartialEq)]
+pub enum WithdrawalDestination {
+    /// Cryptocurrency wallet
+    Address {
+        /// Wallet address
+        address: String,
+        /// Memo or destination tag, for the currencies that require one
+        memo: Option<String>,
+    },
+    /// Bank account registered in CryptoMarket, see `Client::get_account`
+    BankAccount(i64),
+}
 
 ///
 /// CryptoMkt Client
 ///
+/// `Client` is `Send + Sync` and cloning it is cheap: clones share the HTTP
+/// connection pool, the rate limits, the clock offset and the cached market
+/// list, so a single client can be cloned into as many tokio tasks as needed.
+/// The `Market`s it creates share the same state.
+///
+#[derive(Debug, Clone)]
 pub struct Client {
     pub api: CryptoMktApi,
 }
@@ -57,32 +112,238 @@ impl Client {
             api: CryptoMktApi::new(api_key, secret_key),
         }
     }
+
+    ///
+    /// Create a Client without credentials, for market data only. Public
+    /// endpoints work as usual; private ones fail with `MissingCredentials`
+    /// without reaching the exchange
+    ///
+    /// ```no_run
+    /// # #[tokio::main]
+    /// # async fn main() {
+    /// let client = cryptomkt::Client::public();
+    /// let ticker = client.create_market("ETHCLP").get_current_ticker().await.unwrap();
+    /// println!("{}", ticker.last_price);
+    /// # }
+    /// ```
+    ///
+    pub fn public() -> Self {
+        Client::new("", "")
+    }
+
+    ///
+    /// Create a Client in paper trading mode, see `ClientBuilder::paper_trading`
+    ///
+    pub fn new_paper<'a>(api_key: &'a str, secret_key: &'a str) -> Self {
+        Client {
+            api: CryptoMktApi::with_transport(
+                api_key,
+                secret_key,
+                None,
+                None,
+                Transport::new(PaperTransport::new(Transport::new(CryptoMktRequest::new()))),
+                RateLimiter::default(),
+                Arc::new(ResponseCache::default()),
+                HeaderMap::new(),
+            )
+            .expect("the default configuration is valid"),
+        }
+    }
+
+    ///
+    /// Configure a Client before creating it
+    ///
+    /// ```
+    /// use cryptomkt::Client;
+    /// use std::time::Duration;
+    ///
+    /// let client = Client::builder()
+    ///     .credentials("<API_KEY>", "<API SECRET>")
+    ///     .timeout(Duration::from_secs(10))
+    ///     .user_agent("my-bot/1.0")
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    ///
+    pub fn builder() -> ClientBuilder {
+        ClientBuilder::default()
+    }
+
+    ///
+    /// Create a Client with the credentials in the `CRYPTOMKT_API_KEY` and
+    /// `CRYPTOMKT_API_SECRET` environment variables. Fails with
+    /// `InvalidConfiguration` when either is missing or empty
+    ///
+    pub fn from_env() -> CryptoMktResult<Self> {
+        Client::builder().profile(Profile::from_env()?).build()
+    }
+
+    ///
+    /// Create a Client for a test environment, so private endpoints can be
+    /// exercised without risking real funds. CryptoMarket doesn't publish a
+    /// fixed sandbox address, so it's read from the `CRYPTOMKT_SANDBOX_URL`
+    /// environment variable: the test environment given to your account, or a
+    /// mock server in CI. Fails with `InvalidConfiguration` when it's missing
+    /// or isn't a valid URL.
+    ///
+    /// To simulate orders against the live market instead, see
+    /// `ClientBuilder::paper_trading`
+    ///
+    /// ```no_run
+    /// # fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// // CRYPTOMKT_SANDBOX_URL=http://localhost:8080/
+    /// let client = cryptomkt::Client::sandbox("<API_KEY>", "<API SECRET>")?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn sandbox(api_key: &str, secret_key: &str) -> CryptoMktResult<Self> {
+        Client::builder()
+            .profile(Profile::sandbox(api_key, secret_key)?)
+            .build()
+    }
+
+    ///
+    /// Create a Client from the profile `name` of a TOML file with one table
+    /// per account (feature `config`). `base_url` and `api_version` are optional
+    ///
+    /// ```toml
+    /// [default]
+    /// api_key = "<API_KEY>"
+    /// api_secret = "<API SECRET>"
+    ///
+    /// [staging]
+    /// api_key = "<API_KEY>"
+    /// api_secret = "<API SECRET>"
+    /// base_url = "https://staging.example.com/"
+    /// ```
+    ///
+    /// Fails with `InvalidConfiguration` when the file can't be read or
+    /// parsed, or doesn't have the profile
+    ///
+    #[cfg(feature = "config")]
+    pub fn from_config<P: AsRef<Path>>(path: P, name: &str) -> CryptoMktResult<Self> {
+        Client::builder()
+            .profile(Profile::from_file(path.as_ref(), name)?)
+            .build()
+    }
     ///
     /// Get Market List
     ///
-    pub async fn get_markets(&self) -> Vec<Market> {
+    pub async fn get_markets(&self) -> CryptoMktResult<Vec<Market>> {
         let resp =
             self.api
                 .call::<MarketResponse>(RequestMethod::Get(true), "market", HashMap::new());
-        match resp.await {
-            Ok(value) => {
-                let mut market_list = Vec::new();
-                for it in value.data {
-                    market_list.push(Market::new(self.api.clone(), it.clone().as_str()));
-                }
-                market_list
-            }
-            Err(e) => {
-                println!("{:?}", e);
-                Vec::new()
-            }
+        let value = resp.await?;
+        self.api.remember_markets(&value.data);
+        Ok(value
+            .data
+            .iter()
+            .map(|it| Market::new(self.api.clone(), it))
+            .collect())
+    }
+    ///
+    /// Health of the connection with the exchange, see `CryptoMktApi::connection_status`
+    ///
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.api.connection_status()
+    }
+
+    ///
+    /// Statistics of the HTTP transport, see `CryptoMktApi::transport_stats`
+    ///
+    pub fn transport_stats(&self) -> TransportStats {
+        self.api.transport_stats()
+    }
+
+    ///
+    /// Return a new market from its name (`"ETHCLP"`) or its pair
+    /// (`MarketPair::ETHCLP`)
+    ///
+    pub fn create_market<M: Display>(&self, market: M) -> Market {
+        Market::new(self.api.clone(), &market.to_string())
+    }
+
+    ///
+    /// Get the current ticker of every market in a single request, keyed by
+    /// market name
+    ///
+    pub async fn get_all_tickers(&self) -> CryptoMktResult<HashMap<String, Ticker>> {
+        let resp =
+            self.api
+                .call::<TickerResponse>(RequestMethod::Get(true), "ticker", HashMap::new());
+        let value = resp.await?;
+        Ok(value
+            .data
+            .into_iter()
+            .map(|ticker| (ticker.market.clone(), ticker))
+            .collect())
+    }
+
+    ///
+    /// Get the current ticker of each of `markets`, keyed by the given names.
+    /// The requests are sent concurrently, at most `CryptoMktApi::concurrency`
+    /// at once; the first failure is returned, e.g. `UnknownMarket`
+    ///
+    pub async fn get_tickers(&self, markets: &[&str]) -> CryptoMktResult<HashMap<String, Ticker>> {
+        stream::iter(markets)
+            .map(|name| async move {
+                let ticker = self.create_market(name).get_current_ticker().await?;
+                Ok((name.to_string(), ticker))
+            })
+            .buffer_unordered(self.api.concurrency())
+            .try_collect()
+            .await
+    }
+
+    ///
+    /// Estimate how much `amount` of `from` is worth in `to` at the current
+    /// best prices: through a market trading both currencies or, when there
+    /// is none, through the intermediate currency that yields the most. Fees
+    /// and the depth of the books are ignored, so it's meant for display, e.g.
+    /// of every balance in a single fiat currency. Fails with `UnknownMarket`
+    /// when no route of at most two markets exists
+    ///
+    /// ```no_run
+    /// use cryptomkt::{Client, Currency, Decimal};
+    ///
+    /// # async fn run() -> cryptomkt::CryptoMktResult<()> {
+    /// let client = Client::public();
+    /// let conversion = client.convert(Decimal::new(5, 1), Currency::Eth, Currency::Btc).await?;
+    /// println!("{} BTC through {:?}", conversion.amount, conversion.route);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub async fn convert<F, T>(&self, amount: Decimal, from: F, to: T) -> CryptoMktResult<Conversion>
+    where
+        F: Into<Currency>,
+        T: Into<Currency>,
+    {
+        let (from, to) = (from.into(), to.into());
+        if from == to {
+            return Ok(Conversion {
+                amount,
+                route: Vec::new(),
+            });
         }
+        let tickers = self.get_all_tickers().await?;
+        conversion_route(&tickers, amount, &from, &to)
+            .ok_or_else(|| CryptoMktErrorType::UnknownMarket(format!("{}{}", from, to)).into())
     }
+
     ///
-    /// Return a new market from NAME
+    /// Account of the owner of the API key: name, email, trading fees and
+    /// registered bank accounts
     ///
-    pub fn create_market<'a>(&self, name: &'a str) -> Market {
-        Market::new(self.api.clone(), name)
+    pub async fn get_account(&self) -> CryptoMktResult<Account> {
+        let resp =
+            self.api
+                .call::<AccountResponse>(RequestMethod::Get(false), "account", HashMap::new());
+        match resp.await {
+            Ok(value) => Ok(value.data),
+            Err(e) => Err(e),
+        }
     }
 
     ///
@@ -99,12 +360,177 @@ impl Client {
         }
     }
 
+    ///
+    /// Deposits of `currency` into my wallets, newest first
+    ///
+    pub async fn get_deposits(
+        &self,
+        currency: &str,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Transaction>> {
+        self.transactions("deposits", currency, page).await
+    }
+
+    ///
+    /// Withdrawals of `currency` from my wallets, newest first
+    ///
+    pub async fn get_withdrawals(
+        &self,
+        currency: &str,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Transaction>> {
+        self.transactions("withdrawals", currency, page).await
+    }
+
+    ///
+    /// Request a withdrawal of `amount` of `currency` to `destination`.
+    ///
+    /// Every parameter is part of the request signature, so the exchange
+    /// rejects the withdrawal if any of them is altered on the way. The API key
+    /// needs withdrawal permissions.
+    ///
+    pub async fn request_withdrawal(
+        &self,
+        amount: Decimal,
+        currency: &str,
+        destination: WithdrawalDestination,
+    ) -> CryptoMktResult<Transaction> {
+        let mut params = HashMap::new();
+        params.insert("amount".to_string(), format!("{}", amount));
+        params.insert("currency".to_string(), currency.to_uppercase());
+        match destination {
+            WithdrawalDestination::Address { address, memo } => {
+                params.insert("address".to_string(), address);
+                if let Some(memo) = memo {
+                    params.insert("memo".to_string(), memo);
+                }
+            }
+            WithdrawalDestination::BankAccount(id) => {
+                params.insert("bank_account".to_string(), id.to_string());
+            }
+        }
+
+        let resp =
+            self.api
+                .call::<SimpleTransactionResponse>(RequestMethod::Post, "withdrawal", params);
+        match resp.await {
+            Ok(value) => Ok(value.data),
+            Err(e) => Err(e),
+        }
+    }
+
+    async fn transactions(
+        &self,
+        endpoint: &str,
+        currency: &str,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Transaction>> {
+        let mut params = HashMap::new();
+        params.insert("currency".to_string(), currency.to_uppercase());
+        page.add_to(&mut params);
+
+        let resp = self
+            .api
+            .call::<TransactionResponse>(RequestMethod::Get(false), endpoint, params);
+        match resp.await {
+            Ok(value) => Ok(value.into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///
+    /// Status of one of my orders, whatever its market. `amount.remaining` and
+    /// `amount.executed` tell how much of it has been filled
+    ///
+    pub async fn get_order_status(&self, order_id: &str) -> CryptoMktResult<Order> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), order_id.to_string());
+
+        let resp = self.api.call::<SimpleOrderResponse>(
+            RequestMethod::Get(false),
+            "orders/status",
+            params,
+        );
+        match resp.await {
+            Ok(value) => Ok(value.data),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///
+    /// Reports what the configured API key is allowed to do.
+    ///
+    /// CryptoMarket doesn't publish the scopes of a key, so they are inferred
+    /// from two harmless probes: reading the balance checks the read scope, and
+    /// cancelling a non-existent order checks the trade scope. A `RequestForbidden`
+    /// answer marks the scope as missing, while any other answer from the exchange
+    /// means the key got past the permission check. An invalid key fails with
+    /// `RequestUnauthorized`, and probes that never got an answer, or got one
+    /// that couldn't be read, fail with their error. Withdrawal permissions
+    /// can't be probed without moving funds, so they are not reported.
+    ///
+    pub async fn key_permissions(&self) -> CryptoMktResult<KeyPermissions> {
+        let mut cancel_params = HashMap::new();
+        cancel_params.insert("id".to_string(), "M0".to_string());
+
+        let (read, trade) = tokio::join!(
+            self.api
+                .call::<BalanceResponse>(RequestMethod::Get(false), "balance", HashMap::new()),
+            self.api
+                .call::<SimpleOrderResponse>(RequestMethod::Post, "orders/cancel", cancel_params),
+        );
+        Ok(KeyPermissions {
+            read: scope_granted(read)?,
+            trade: scope_granted(trade)?,
+        })
+    }
+
+    ///
+    /// Start a payment order of `to_receive` `to_receive_currency` for
+    /// `payment_receiver`. The optional fields are set on the returned builder
+    /// and the order is created by `PaymentOrderBuilder::send`
+    ///
+    /// ```no_run
+    /// # use cryptomkt::{Client, Currency, Decimal};
+    /// # async fn run(client: Client) -> cryptomkt::CryptoMktResult<()> {
+    /// let payment = client
+    ///     .payment_order(Decimal::new(5000, 0), Currency::Clp, "merchant@example.com")
+    ///     .external_id("ORD-1")
+    ///     .callback_url("https://example.com/callback")
+    ///     .send()
+    ///     .await?;
+    /// println!("{}", payment.payment_url);
+    /// # Ok(())
+    /// # }
+    /// ```
+    ///
+    pub fn payment_order<C: Into<Currency>>(
+        &self,
+        to_receive: Decimal,
+        to_receive_currency: C,
+        payment_receiver: &str,
+    ) -> PaymentOrderBuilder<'_> {
+        let mut params = HashMap::new();
+        params.insert("to_receive".to_string(), format!("{}", to_receive));
+        params.insert(
+            "to_receive_currency".to_string(),
+            to_receive_currency.into().to_string(),
+        );
+        params.insert("payment_receiver".to_string(), payment_receiver.to_string());
+        PaymentOrderBuilder {
+            client: self,
+            params,
+        }
+    }
+
     ///
     /// It allows you to create a payment order, delivering QRs and urls to pay.
     ///
+    #[deprecated(note = "use `Client::payment_order`")]
+    #[allow(clippy::too_many_arguments)]
     pub async fn create_payment_order<'a>(
         &self,
-        to_receive: f32,
+        to_receive: Decimal,
         to_receive_currency: &'a str,
         payment_receiver: &'a str,
         external_id: Option<String>,
@@ -113,33 +539,36 @@ impl Client {
         success_url: Option<String>,
         refund_email: Option<String>,
     ) -> CryptoMktResult<Payment> {
-        let mut params = HashMap::new();
-        params.insert("to_receive".to_string(), format!("{}", to_receive));
-        params.insert(
-            "to_receive_currency".to_string(),
-            to_receive_currency.to_string(),
-        );
-        params.insert("payment_receiver".to_string(), payment_receiver.to_string());
-
+        let mut order = self.payment_order(to_receive, to_receive_currency, payment_receiver);
         if let Some(external_id) = external_id {
-            params.insert("external_id".to_string(), external_id);
+            order = order.external_id(&external_id);
         }
         if let Some(callback_url) = callback_url {
-            params.insert("callback_url".to_string(), callback_url);
+            order = order.callback_url(&callback_url);
         }
         if let Some(error_url) = error_url {
-            params.insert("error_url".to_string(), error_url);
+            order = order.error_url(&error_url);
         }
         if let Some(success_url) = success_url {
-            params.insert("success_url".to_string(), success_url);
+            order = order.success_url(&success_url);
         }
         if let Some(refund_email) = refund_email {
-            params.insert("refund_email".to_string(), refund_email);
+            order = order.refund_email(&refund_email);
         }
+        order.send().await
+    }
+
+    ///
+    /// Returns the status of a payment order
+    ///
+    pub async fn payment_order_status(&self, id: &str) -> CryptoMktResult<Payment> {
+        let mut params = HashMap::new();
+        params.insert("id".to_string(), id.to_string());
 
         let resp =
             self.api
-                .call::<PaymentResponse>(RequestMethod::Post, "payment/new_order", params);
+                .call::<PaymentResponse>(RequestMethod::Get(false), "payment/status", params);
+
         match resp.await {
             Ok(value) => Ok(value.data),
             Err(e) => Err(e),
@@ -147,53 +576,751 @@ impl Client {
     }
 
     ///
-    /// Returns the status of a payment order
+    /// Void the payment order `id` while it's still unpaid, and return it with
+    /// the final status it was left in, see `PaymentStatus::is_final`. Orders
+    /// that already received a payment can't be cancelled and the exchange
+    /// answers with an error
     ///
-    pub async fn  payment_order_status<'a>(&self, id: &'a str) -> CryptoMktResult<Payment> {
+    pub async fn cancel_payment_order(&self, id: &str) -> CryptoMktResult<Payment> {
         let mut params = HashMap::new();
         params.insert("id".to_string(), id.to_string());
 
-        let resp =
-            self.api
-                .call::<PaymentResponse>(RequestMethod::Get(false), "payment/status", params);
+        let resp = self
+            .api
+            .call::<PaymentResponse>(RequestMethod::Post, "payment/cancel", params)
+            .await?;
+        Ok(resp.data)
+    }
+
+    ///
+    /// Poll the status of a payment order every `poll_interval` until it reaches
+    /// a final state (see `PaymentStatus::is_final`) and return it. Fails with
+    /// `Timeout` when the order is still pending after `timeout`; errors of the
+    /// status requests are returned right away.
+    ///
+    pub async fn wait_for_payment(
+        &self,
+        id: &str,
+        poll_interval: Duration,
+        timeout: Duration,
+    ) -> CryptoMktResult<Payment> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            let payment = self.payment_order_status(id).await?;
+            if payment.is_final() {
+                return Ok(payment);
+            }
+            let now = Instant::now();
+            if now >= deadline {
+                return Err(CryptoMktErrorType::Timeout.into());
+            }
+            // La última espera se acorta para consultar justo al vencer el plazo
+            rt::sleep(poll_interval.min(deadline - now)).await;
+        }
+    }
 
+    ///
+    /// Returns the list of generated payment orders
+    ///
+    pub async fn get_payment_orders(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Payment>> {
+        let resp = self.payment_orders_page(start_date, end_date, page);
         match resp.await {
-            Ok(value) => Ok(value.data),
+            Ok(value) => Ok(value.into()),
             Err(e) => Err(e),
         }
     }
 
     ///
-    /// Returns the list of generated payment orders
+    /// Returns every payment order generated between `start_date` and `end_date`,
+    /// fetching page after page until the last one.
+    ///
+    /// `cancel` is checked between pages and raced against the in-flight request.
+    /// When it fires, the orders collected so far are returned as `Ok`, so a
+    /// cancelled call yields a possibly empty prefix of the full list rather than
+    /// an error. Dropping the returned future also stops any further fetches.
     ///
-    pub async fn get_payment_orders<'a>(
+    pub async fn get_all_payment_orders(
         &self,
-        start_date: &'a str,
-        end_date: &'a str,
-        page: Option<i32>,
-        limit: Option<i32>,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        cancel: &CancellationToken,
     ) -> CryptoMktResult<Vec<Payment>> {
+        collect_pages(cancel, |page| {
+            self.payment_orders_page(start_date, end_date, Page::default().number(page))
+        })
+        .await
+    }
+
+    ///
+    /// Every payment order generated between `start_date` and `end_date` as a
+    /// stream, see `Market::stream_trades`
+    ///
+    pub fn stream_payment_orders(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+    ) -> impl Stream<Item = CryptoMktResult<Payment>> + '_ {
+        stream_pages(move |page| {
+            self.payment_orders_page(start_date, end_date, Page::default().number(page))
+        })
+    }
+
+    ///
+    /// Get the trades of `market`, with the pagination info to request the
+    /// next pages.
+    ///
+    /// Every parameter but `market` is optional and left to the exchange
+    /// defaults when `None` or unset: `start` and `end` bound the dates of the
+    /// trades.
+    ///
+    pub async fn get_trades(
+        &self,
+        market: &str,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Trade>> {
+        let resp = self
+            .create_market(market)
+            .trades_query(start, end, page)
+            .await?;
+        Ok(resp.into())
+    }
+
+    ///
+    /// Every trade of `market` between `start` and `end` as a stream, see
+    /// `Market::stream_trades`
+    ///
+    pub fn stream_trades(
+        &self,
+        market: &str,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> impl Stream<Item = CryptoMktResult<Trade>> + '_ {
+        let market = market.to_string();
+        stream_incremental_pages(move |page| {
+            let market = self.create_market(&market);
+            async move { market.trades_page_stream(start, end, page).await }
+        })
+    }
+
+    async fn payment_orders_page(
+        &self,
+        start_date: NaiveDate,
+        end_date: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<PaymentListResponse> {
         let mut params = HashMap::new();
-        params.insert("start_date".to_string(), start_date.to_string());
-        params.insert("end_date".to_string(), end_date.to_string());
+        params.insert("start_date".to_string(), timestamp::format_date(start_date));
+        params.insert("end_date".to_string(), timestamp::format_date(end_date));
+        page.add_to(&mut params);
+
+        self.api
+            .call::<PaymentListResponse>(RequestMethod::Get(false), "payment/orders", params)
+            .await
+    }
+}
+
+///
+/// Payment order waiting to be sent, see `Client::payment_order`
+///
+pub struct PaymentOrderBuilder<'a> {
+    client: &'a Client,
+    params: HashMap<String, String>,
+}
+
+impl<'a> PaymentOrderBuilder<'a> {
+    ///
+    /// Identifier of the order in the merchant's system
+    ///
+    pub fn external_id(self, external_id: &str) -> Self {
+        self.param("external_id", external_id)
+    }
+
+    ///
+    /// URL notified on every state change, see the `webhooks` module
+    ///
+    pub fn callback_url(self, url: &str) -> Self {
+        self.param("callback_url", url)
+    }
+
+    ///
+    /// URL the payer is sent to when the payment fails
+    ///
+    pub fn error_url(self, url: &str) -> Self {
+        self.param("error_url", url)
+    }
+
+    ///
+    /// URL the payer is sent to when the payment succeeds
+    ///
+    pub fn success_url(self, url: &str) -> Self {
+        self.param("success_url", url)
+    }
+
+    ///
+    /// Contact email to coordinate refunds
+    ///
+    pub fn refund_email(self, email: &str) -> Self {
+        self.param("refund_email", email)
+    }
+
+    fn param(mut self, name: &str, value: &str) -> Self {
+        self.params.insert(name.to_string(), value.to_string());
+        self
+    }
+
+    ///
+    /// Create the payment order
+    ///
+    pub async fn send(self) -> CryptoMktResult<Payment> {
+        let resp = self.client.api.call::<PaymentResponse>(
+            RequestMethod::Post,
+            "payment/new_order",
+            self.params,
+        );
+        Ok(resp.await?.data)
+    }
+}
+
+///
+/// Builder of a `Client`, see `Client::builder`
+///
+#[derive(Debug, Default)]
+pub struct ClientBuilder {
+    api_key: SecretString,
+    secret_key: SecretString,
+    base_url: Option<String>,
+    api_version: Option<String>,
+    v3_base_url: Option<String>,
+    transport: TransportConfig,
+    http_transport: Option<Transport>,
+    rate_limits: RateLimiter,
+    paper_trading: bool,
+    clock_sync: Option<Duration>,
+    concurrency: Option<usize>,
+    cache: ResponseCache,
+    headers: Vec<(String, String)>,
+}
+
+impl ClientBuilder {
+    ///
+    /// API credentials used to sign private requests
+    ///
+    pub fn credentials(mut self, api_key: &str, secret_key: &str) -> Self {
+        self.api_key = SecretString::new(api_key);
+        self.secret_key = SecretString::new(secret_key);
+        self
+    }
+
+    ///
+    /// Credentials and endpoint of a loaded profile
+    ///
+    fn profile(mut self, profile: Profile) -> Self {
+        self = self.credentials(&profile.api_key, profile.api_secret.expose());
+        self.base_url = profile.base_url.or(self.base_url);
+        self.api_version = profile.api_version.or(self.api_version);
+        self
+    }
+
+    ///
+    /// Send the requests to `url` instead of CryptoMarket, e.g. a sandbox or a
+    /// mock server. The API version is appended to it: `<url>/v1/market`
+    ///
+    pub fn base_url(mut self, url: &str) -> Self {
+        self.base_url = Some(url.to_string());
+        self
+    }
+
+    ///
+    /// Base URL of the v3 API, where `Market::get_limits` reads the trading
+    /// rules the v1 API doesn't publish. `cryptomkt::v3::DEFAULT_URL` by
+    /// default, which is only used with the default `base_url` and
+    /// `api_version`: with a sandbox or another base URL `get_limits` fails
+    /// with `InvalidConfiguration` until this is set
+    ///
+    pub fn v3_base_url(mut self, url: &str) -> Self {
+        self.v3_base_url = Some(url.to_string());
+        self
+    }
+
+    ///
+    /// Version segment of the endpoints, `v1` by default: `<url>/<version>/market`
+    ///
+    pub fn api_version(mut self, version: &str) -> Self {
+        self.api_version = Some(version.to_string());
+        self
+    }
+
+    ///
+    /// Maximum time for each request, from connecting to reading the body
+    ///
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.transport.timeout = Some(timeout);
+        self
+    }
+
+    ///
+    /// Send every request through the proxy at `url`
+    ///
+    pub fn proxy(mut self, url: &str) -> Self {
+        self.transport.proxy = Some(url.to_string());
+        self
+    }
+
+    ///
+    /// `User-Agent` header sent with every request
+    ///
+    pub fn user_agent(mut self, user_agent: &str) -> Self {
+        self.transport.user_agent = Some(user_agent.to_string());
+        self
+    }
+
+    ///
+    /// Header sent with every request, e.g. to identify the application to the
+    /// exchange or to a proxy. Unlike `user_agent` it applies to any transport.
+    /// Can be called several times; `build` fails with `InvalidConfiguration`
+    /// when a name or value isn't a valid header
+    ///
+    pub fn default_header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    ///
+    /// Close the pooled connections that stay unused for `timeout`. reqwest
+    /// closes them after 90 seconds by default
+    ///
+    pub fn pool_idle_timeout(mut self, timeout: Duration) -> Self {
+        self.transport.pool_idle_timeout = Some(timeout);
+        self
+    }
+
+    ///
+    /// Keep at most `max` unused connections to the exchange in the pool
+    ///
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.transport.pool_max_idle_per_host = Some(max);
+        self
+    }
+
+    ///
+    /// Speak HTTP/2 right away (`true`) or only HTTP/1.1 (`false`), instead of
+    /// negotiating the version during the TLS handshake
+    ///
+    pub fn http2(mut self, enabled: bool) -> Self {
+        self.transport.http2 = Some(enabled);
+        self
+    }
+
+    ///
+    /// Whether to ask for gzip or brotli compressed responses, which are
+    /// decompressed transparently. Enabled by default with the `compression`
+    /// feature; large `trades` and `prices` pages shrink several times
+    ///
+    #[cfg(feature = "compression")]
+    pub fn compression(mut self, enabled: bool) -> Self {
+        self.transport.compression = Some(enabled);
+        self
+    }
+
+    ///
+    /// Send the requests with a pre-configured `reqwest::Client`, e.g. to tune
+    /// its connection pool or trust custom TLS roots. The `timeout`, `proxy`,
+    /// `user_agent` and pool options are ignored in that case, and the client
+    /// isn't rebuilt after repeated transport failures
+    ///
+    pub fn http_client(mut self, client: reqwest::Client) -> Self {
+        self.transport.client = Some(client);
+        self
+    }
+
+    ///
+    /// Register a `Middleware` on the HTTP requests. Can be called several
+    /// times, middlewares run in the order they were registered
+    ///
+    pub fn middleware<M>(mut self, middleware: M) -> Self
+    where
+        M: Middleware + 'static,
+    {
+        self.transport.middleware.push(Arc::new(middleware));
+        self
+    }
+
+    ///
+    /// Send the requests through `transport` instead of HTTP, e.g. a
+    /// `cryptomkt::mock::MockTransport` to test without network access. The
+    /// `timeout`, `proxy`, `user_agent`, pool, `http_client` and `middleware`
+    /// options don't apply to it
+    ///
+    pub fn http_transport<R>(mut self, transport: R) -> Self
+    where
+        R: HttpRequest<Result = CryptoMktResult<String>> + 'static,
+    {
+        self.http_transport = Some(Transport::new(transport));
+        self
+    }
+
+    ///
+    /// Simulate orders instead of sending them to the exchange.
+    ///
+    /// Limit orders are filled right away against the live order book, taking
+    /// the levels that cross their price; whatever isn't filled stays active
+    /// and is matched again against a fresh book whenever the active, executed
+    /// or status queries ask for it, which answer with the simulated orders.
+    /// No fees are charged. Every other request that would change something on
+    /// the exchange, like withdrawals, instant orders or payment orders, fails
+    /// with `InvalidConfiguration` without being sent; reads such as balances
+    /// and tickers still reach the exchange. `build_v2` and `build_v3` refuse
+    /// to build a client in this mode
+    ///
+    pub fn paper_trading(mut self, enabled: bool) -> Self {
+        self.paper_trading = enabled;
+        self
+    }
 
-        if let Some(page) = page {
-            params.insert("page".to_string(), format!("{}", page));
+    ///
+    /// Correct signature timestamps with the exchange clock, measuring the
+    /// offset again once it is older than `interval`, see
+    /// `CryptoMktApi::enable_clock_sync`
+    ///
+    pub fn clock_sync(mut self, interval: Duration) -> Self {
+        self.clock_sync = Some(interval);
+        self
+    }
+
+    ///
+    /// Maximum number of requests sent at once by the calls that query several
+    /// markets, see `CryptoMktApi::set_concurrency`
+    ///
+    pub fn concurrency(mut self, limit: usize) -> Self {
+        self.concurrency = Some(limit);
+        self
+    }
+
+    ///
+    /// Keep the responses of the public `endpoint` for `ttl`, so repeated calls
+    /// within that window don't reach the exchange. Endpoints are named as in
+    /// the v1 paths (`market`, `ticker`, `book`, `trades`, `prices`), plus
+    /// `symbol` for `Market::get_limits`. Responses are kept per URL, so per
+    /// market and page; errors aren't kept. Nothing is cached by default, see
+    /// `CryptoMktApi::clear_cache` to drop the kept responses
+    ///
+    /// ```
+    /// use cryptomkt::Client;
+    /// use std::time::Duration;
+    ///
+    /// let client = Client::builder()
+    ///     .cache_ttl("market", Duration::from_secs(300))
+    ///     .cache_ttl("symbol", Duration::from_secs(3600))
+    ///     .build()
+    ///     .unwrap();
+    /// ```
+    ///
+    pub fn cache_ttl(mut self, endpoint: &str, ttl: Duration) -> Self {
+        self.cache.set_ttl(endpoint, ttl);
+        self
+    }
+
+    ///
+    /// Throttle requests to public endpoints (ticker, book, trades, ...) to
+    /// `limit`. Requests over the quota wait for their turn instead of failing
+    ///
+    pub fn public_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limits.public = Some(Arc::new(TokenBucket::new(limit)));
+        self
+    }
+
+    ///
+    /// Throttle requests to authenticated endpoints (balance, orders,
+    /// payments, ...) to `limit`. Requests over the quota wait for their turn
+    /// instead of failing
+    ///
+    pub fn private_rate_limit(mut self, limit: RateLimit) -> Self {
+        self.rate_limits.private = Some(Arc::new(TokenBucket::new(limit)));
+        self
+    }
+
+    ///
+    /// What to do when the exchange answers `429 Too Many Requests`. By default
+    /// (`RateLimitBehavior::Fail`) `RequestTooManyRequests` is returned right away
+    ///
+    pub fn rate_limit_behavior(mut self, behavior: RateLimitBehavior) -> Self {
+        self.rate_limits.behavior = behavior;
+        self
+    }
+
+    ///
+    /// Create the Client. Fails with `InvalidConfiguration` when the base URL,
+    /// the API version or the proxy can't be parsed
+    ///
+    pub fn build(self) -> CryptoMktResult<Client> {
+        let credentials = (self.api_key.clone(), self.secret_key.clone(), None);
+        let mut clients = self.build_clients(vec![credentials])?;
+        Ok(clients.remove(0))
+    }
+
+    ///
+    /// Crea un cliente por cada par de credenciales. Comparten el transporte
+    /// (y su pool de conexiones), la caché y la cuota de los endpoints
+    /// públicos; cada uno tiene su propia cuota de endpoints autenticados
+    ///
+    /// Argumentos
+    ///     accounts: API key, secret y cuota de endpoints autenticados de cada
+    ///         cliente, `None` para una cuota como la de `private_rate_limit`
+    ///
+    pub(crate) fn build_clients(
+        self,
+        accounts: Vec<(SecretString, SecretString, Option<RateLimit>)>,
+    ) -> CryptoMktResult<Vec<Client>> {
+        let headers = default_headers(&self.headers)?;
+        let transport = match self.http_transport {
+            Some(transport) => transport,
+            None => Transport::new(CryptoMktRequest::with_config(self.transport)?),
+        };
+        let cache = Arc::new(self.cache);
+        let private_limit = self.rate_limits.private.as_ref().map(|bucket| bucket.limit());
+
+        let mut clients = Vec::with_capacity(accounts.len());
+        for (api_key, secret_key, limit) in accounts {
+            // Cada cuenta simulada lleva sus propias órdenes
+            let transport = if self.paper_trading {
+                Transport::new(PaperTransport::new(transport.clone()))
+            } else {
+                transport.clone()
+            };
+            let rate_limits = RateLimiter {
+                private: limit.or(private_limit).map(|limit| Arc::new(TokenBucket::new(limit))),
+                ..self.rate_limits.clone()
+            };
+            let api = CryptoMktApi::with_transport(
+                api_key.expose(),
+                secret_key.expose(),
+                self.base_url.as_deref(),
+                self.api_version.as_deref(),
+                transport,
+                rate_limits,
+                cache.clone(),
+                headers.clone(),
+            )?;
+            if let Some(url) = &self.v3_base_url {
+                api.set_v3_domain(url)?;
+            }
+            if let Some(interval) = self.clock_sync {
+                api.enable_clock_sync(Some(interval));
+            }
+            if let Some(limit) = self.concurrency {
+                api.set_concurrency(limit);
+            }
+            clients.push(Client { api });
         }
-        if let Some(limit) = limit {
-            params.insert("limit".to_string(), format!("{}", limit));
+        Ok(clients)
+    }
+
+    ///
+    /// Create a client of the v2 API, see `cryptomkt::v2`. `base_url` replaces
+    /// `cryptomkt::v2::DEFAULT_URL`; rate limits and clock sync don't apply to
+    /// it. Fails with `InvalidConfiguration` in paper trading mode, which the
+    /// v2 API can't simulate
+    ///
+    pub fn build_v2(self) -> CryptoMktResult<v2::Client> {
+        self.reject_paper_trading("v2")?;
+        let headers = default_headers(&self.headers)?;
+        let transport = match self.http_transport {
+            Some(transport) => transport,
+            None => Transport::new(CryptoMktRequest::with_config(self.transport)?),
+        };
+        v2::Client::with_transport(
+            self.api_key.expose(),
+            self.secret_key.expose(),
+            self.base_url.as_deref(),
+            transport,
+            headers,
+        )
+    }
+
+    ///
+    /// Create a client of the v3 API, see `cryptomkt::v3`. `base_url` replaces
+    /// `cryptomkt::v3::DEFAULT_URL`; rate limits and clock sync don't apply to
+    /// it. Fails with `InvalidConfiguration` in paper trading mode, which the
+    /// v3 API can't simulate
+    ///
+    pub fn build_v3(self) -> CryptoMktResult<v3::Client> {
+        self.reject_paper_trading("v3")?;
+        let headers = default_headers(&self.headers)?;
+        let transport = match self.http_transport {
+            Some(transport) => transport,
+            None => Transport::new(CryptoMktRequest::with_config(self.transport)?),
+        };
+        v3::Client::with_transport(
+            self.api_key.expose(),
+            self.secret_key.expose(),
+            self.base_url.as_deref(),
+            transport,
+            headers,
+        )
+    }
+
+    ///
+    /// Falla si se pidió paper trading para un cliente que no lo simula
+    ///
+    /// Argumentos
+    ///     api: Versión del API del cliente, p. ej. `v3`
+    ///
+    fn reject_paper_trading(&self, api: &str) -> CryptoMktResult<()> {
+        if self.paper_trading {
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "paper trading isn't available for the {} API",
+                api
+            ))
+            .into());
         }
+        Ok(())
+    }
+}
 
-        let resp = self.api.call::<PaymentListResponse>(
-            RequestMethod::Get(false),
-            "payment/status",
-            params,
-        );
+///
+/// Paso de una conversión de `from` a `to` por el mercado `pair`, si lo cruza
+///
+/// Argumentos
+///     pair: Mercado
+///     ticker: Precios actuales del mercado
+///     from: Moneda de origen
+///     to: Moneda de destino
+///
+fn conversion_step(
+    pair: &MarketPair,
+    ticker: &Ticker,
+    from: &Currency,
+    to: &Currency,
+) -> Option<ConversionStep> {
+    let (side, price) = if pair.base == *from && pair.quote == *to {
+        (Side::Sell, ticker.bid)
+    } else if pair.base == *to && pair.quote == *from {
+        (Side::Buy, ticker.ask)
+    } else {
+        return None;
+    };
+    (!price.is_zero()).then(|| ConversionStep {
+        market: pair.clone(),
+        side,
+        price,
+    })
+}
 
-        match resp.await {
-            Ok(value) => Ok(value.data),
-            Err(e) => Err(e),
+///
+/// Cantidad obtenida al convertir `amount` en un paso, `None` si desborda
+///
+fn step_amount(step: &ConversionStep, amount: Decimal) -> Option<Decimal> {
+    match step.side {
+        Side::Sell => amount.checked_mul(step.price),
+        Side::Buy => amount.checked_div(step.price),
+    }
+}
+
+///
+/// Ruta de conversión directa o, si no la hay, la de dos mercados que más
+/// rinde
+///
+/// Argumentos
+///     tickers: Precios actuales por nombre de mercado
+///     amount: Cantidad a convertir
+///     from: Moneda de origen
+///     to: Moneda de destino
+///
+fn conversion_route(
+    tickers: &HashMap<String, Ticker>,
+    amount: Decimal,
+    from: &Currency,
+    to: &Currency,
+) -> Option<Conversion> {
+    // Ordenados por nombre para que los empates se resuelvan siempre igual
+    let mut markets: Vec<(&String, &Ticker)> = tickers.iter().collect();
+    markets.sort_by_key(|(name, _)| *name);
+    let markets: Vec<(MarketPair, &Ticker)> = markets
+        .into_iter()
+        .filter_map(|(name, ticker)| Some((name.parse().ok()?, ticker)))
+        .collect();
+    let step = |from: &Currency, to: &Currency| {
+        markets
+            .iter()
+            .find_map(|(pair, ticker)| conversion_step(pair, ticker, from, to))
+    };
+
+    if let Some(direct) = step(from, to) {
+        return Some(Conversion {
+            amount: step_amount(&direct, amount)?,
+            route: vec![direct],
+        });
+    }
+    let mut intermediates: Vec<&Currency> = Vec::new();
+    for (pair, _) in &markets {
+        for currency in [&pair.base, &pair.quote] {
+            if currency != from && currency != to && !intermediates.contains(&currency) {
+                intermediates.push(currency);
+            }
         }
     }
+    let mut best: Option<Conversion> = None;
+    for via in intermediates {
+        let (first, second) = match (step(from, via), step(via, to)) {
+            (Some(first), Some(second)) => (first, second),
+            _ => continue,
+        };
+        let converted = step_amount(&first, amount).and_then(|a| step_amount(&second, a));
+        if let Some(converted) = converted {
+            if best.as_ref().is_none_or(|best| converted > best.amount) {
+                best = Some(Conversion {
+                    amount: converted,
+                    route: vec![first, second],
+                });
+            }
+        }
+    }
+    best
+}
 
+///
+/// Cabeceras configuradas con `ClientBuilder::default_header`
+///
+/// Argumentos
+///     headers: Pares de nombre y valor
+///
+fn default_headers(headers: &[(String, String)]) -> CryptoMktResult<HeaderMap> {
+    let mut map = HeaderMap::new();
+    for (name, value) in headers {
+        let invalid = |e: &dyn Display| {
+            CryptoMktErrorType::InvalidConfiguration(format!("header {}: {}", name, e))
+        };
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(|e| invalid(&e))?;
+        let value = HeaderValue::from_str(value).map_err(|e| invalid(&e))?;
+        map.insert(name, value);
+    }
+    Ok(map)
+}
 
+///
+/// Interprets the outcome of a permission probe. Only errors carrying a 4xx
+/// status or an error envelope are answers of the exchange; transport failures
+/// have no status and are returned as they are
+///
+fn scope_granted<T>(resp: CryptoMktResult<T>) -> CryptoMktResult<bool> {
+    let e = match resp {
+        Ok(_) => return Ok(true),
+        Err(e) => e,
+    };
+    let answered = e.status().is_some_and(|status| (400..500).contains(&status));
+    match e.kind() {
+        CryptoMktErrorType::RequestForbidden if answered => Ok(false),
+        CryptoMktErrorType::RequestRejected if e.status().is_some() => Ok(true),
+        CryptoMktErrorType::BadRequest | CryptoMktErrorType::RequestNotFound if answered => {
+            Ok(true)
+        }
+        _ => Err(e),
+    }
+}
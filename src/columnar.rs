@@ -0,0 +1,101 @@
+//!
+//! ## Columnar output
+//!
+//! Available with the `arrow` feature. Trades are converted to an Arrow
+//! `RecordBatch` in one pass, so large histories can be handed to Arrow or
+//! Polars without building a row per trade.
+//!
+//! The schema returned by [`trades_schema`] is stable:
+//!
+//! | Column         | Type      | Nullable | Notes                                  |
+//! |----------------|-----------|----------|----------------------------------------|
+//! | `market_taker` | `Utf8`    | no       | `buy` or `sell`                        |
+//! | `price`        | `Float64` | yes      | null when the value doesn't fit a `Float64` |
+//! | `amount`       | `Float64` | yes      | null when the value doesn't fit a `Float64` |
+//! | `tid`          | `Utf8`    | no       | empty when the exchange omits it       |
+//! | `timestamp`    | `Utf8`    | no       | RFC 3339, in UTC                       |
+//! | `market`       | `Utf8`    | no       | market pair, e.g. `ETHCLP`             |
+//!
+
+use std::sync::Arc;
+
+use arrow_array::{ArrayRef, Float64Array, RecordBatch, StringArray};
+use arrow_schema::{DataType, Field, Schema, SchemaRef};
+
+use rust_decimal::prelude::ToPrimitive;
+use rust_decimal::Decimal;
+
+use crate::internal::models::Trade;
+
+///
+/// Schema of the batches built by `trades_to_record_batch`
+///
+pub fn trades_schema() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("market_taker", DataType::Utf8, false),
+        Field::new("price", DataType::Float64, true),
+        Field::new("amount", DataType::Float64, true),
+        Field::new("tid", DataType::Utf8, false),
+        Field::new("timestamp", DataType::Utf8, false),
+        Field::new("market", DataType::Utf8, false),
+    ]))
+}
+
+///
+/// Convert trades to a `RecordBatch` following `trades_schema`
+///
+pub fn trades_to_record_batch(trades: &[Trade]) -> RecordBatch {
+    let utf8 = |field: fn(&Trade) -> &str| -> ArrayRef {
+        Arc::new(trades.iter().map(|t| Some(field(t))).collect::<StringArray>())
+    };
+    let float = |field: fn(&Trade) -> &Decimal| -> ArrayRef {
+        Arc::new(
+            trades
+                .iter()
+                .map(|t| field(t).to_f64())
+                .collect::<Float64Array>(),
+        )
+    };
+
+    let columns = vec![
+        utf8(|t| &t.market_taker),
+        float(|t| &t.price),
+        float(|t| &t.amount),
+        utf8(|t| &t.tid),
+        Arc::new(
+            trades
+                .iter()
+                .map(|t| Some(t.timestamp.to_rfc3339()))
+                .collect::<StringArray>(),
+        ),
+        utf8(|t| &t.market),
+    ];
+    RecordBatch::try_new(trades_schema(), columns).expect("columns match the trades schema")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arrow_array::Array;
+
+    #[test]
+    fn trades_to_record_batch_follows_schema() {
+        let trades: Vec<Trade> = serde_json::from_str(
+            "[{\"market_taker\":\"buy\",\"timestamp\":\"2017-05-29T22:14:00.419466\",\"price\":\"155000\",\"amount\":\"0.129\",\"market\":\"ETHCLP\"},{\"market_taker\":\"sell\",\"timestamp\":\"2017-05-29T22:13:52.168265\",\"price\":\"154999.5\",\"amount\":\"0.6451\",\"market\":\"ETHCLP\"}]",
+        )
+        .unwrap();
+
+        let batch = trades_to_record_batch(&trades);
+        assert_eq!(batch.schema(), trades_schema());
+        assert_eq!(batch.num_rows(), 2);
+
+        let price = batch
+            .column(1)
+            .as_any()
+            .downcast_ref::<Float64Array>()
+            .unwrap();
+        assert_eq!(price.value(0), 155000.0);
+        assert_eq!(price.value(1), 154999.5);
+        assert_eq!(price.null_count(), 0);
+    }
+}
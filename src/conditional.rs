@@ -0,0 +1,251 @@
+//!
+//! ## Conditional orders
+//!
+//! CryptoMarket only takes limit orders, so stop-loss and take-profit orders
+//! are emulated: `ConditionalOrders` keeps the triggers registered for a
+//! market and, when the price crosses one of them, places its limit order
+//! with `Market::create_order`.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::conditional::{ConditionalOrder, ConditionalOrders};
+//! use cryptomkt::{Client, Decimal, Side};
+//! use futures::StreamExt;
+//!
+//! # async fn run() {
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! let conditional = ConditionalOrders::new(client.create_market("ETHCLP"));
+//! // Sell 0.5 ETH at 170000 or more once the price falls to 175000
+//! conditional.register(ConditionalOrder::stop_loss(
+//!     Side::Sell,
+//!     Decimal::new(5, 1),
+//!     Decimal::new(175000, 0),
+//!     Decimal::new(170000, 0),
+//! ));
+//!
+//! let mut triggered = Box::pin(conditional.watch(Duration::from_secs(5)));
+//! while let Some(triggered) = triggered.next().await {
+//!     println!("{:?}", triggered);
+//! }
+//! # }
+//! ```
+//!
+//! The price is only checked when `on_price` is called, or at each poll of
+//! `watch`, so nothing is placed while the process isn't running, and the
+//! price may have moved past the trigger by then. Prices pushed by
+//! `cryptomkt::ws` can be fed to `on_price` for a faster reaction.
+//!
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::stream::{self, Stream, StreamExt};
+use rust_decimal::Decimal;
+
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::models::Order;
+use crate::internal::rt;
+use crate::market::{Market, OrderRequest, Side};
+
+///
+/// Price condition of a conditional order
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Trigger {
+    /// The price rises to this value or above
+    Above(Decimal),
+    /// The price falls to this value or below
+    Below(Decimal),
+}
+
+impl Trigger {
+    ///
+    /// Whether `price` meets the condition
+    ///
+    pub fn is_met(&self, price: Decimal) -> bool {
+        match *self {
+            Trigger::Above(trigger) => price >= trigger,
+            Trigger::Below(trigger) => price <= trigger,
+        }
+    }
+}
+
+///
+/// Limit order placed once its trigger is met
+///
+#[derive(Debug, Clone)]
+pub struct ConditionalOrder {
+    /// Condition on the last price of the market
+    pub trigger: Trigger,
+    /// Order placed when the condition is met
+    pub order: OrderRequest,
+}
+
+impl ConditionalOrder {
+    ///
+    /// Order closing a position when the price moves against it: a sale when
+    /// the price falls to `stop`, or a purchase when it rises to `stop`. The
+    /// order is placed at the `limit` price
+    ///
+    pub fn stop_loss(side: Side, amount: Decimal, stop: Decimal, limit: Decimal) -> Self {
+        let trigger = match side {
+            Side::Sell => Trigger::Below(stop),
+            Side::Buy => Trigger::Above(stop),
+        };
+        ConditionalOrder::new(trigger, side, amount, limit)
+    }
+
+    ///
+    /// Order closing a position when the price moves in its favour: a sale
+    /// when the price rises to `target`, or a purchase when it falls to
+    /// `target`. The order is placed at the `limit` price
+    ///
+    pub fn take_profit(side: Side, amount: Decimal, target: Decimal, limit: Decimal) -> Self {
+        let trigger = match side {
+            Side::Sell => Trigger::Above(target),
+            Side::Buy => Trigger::Below(target),
+        };
+        ConditionalOrder::new(trigger, side, amount, limit)
+    }
+
+    fn new(trigger: Trigger, side: Side, amount: Decimal, price: Decimal) -> Self {
+        ConditionalOrder {
+            trigger,
+            order: OrderRequest {
+                order_type: side,
+                amount,
+                price,
+            },
+        }
+    }
+}
+
+///
+/// Identifier of a registered conditional order
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct TriggerId(u64);
+
+///
+/// Conditional order whose trigger was met, with the outcome of its placement
+///
+#[derive(Debug)]
+pub struct Triggered {
+    /// Identifier returned by `ConditionalOrders::register`
+    pub id: TriggerId,
+    /// The conditional order
+    pub conditional: ConditionalOrder,
+    /// Price that met the trigger
+    pub price: Decimal,
+    /// Order placed in the exchange, or why it couldn't be placed
+    pub result: CryptoMktResult<Order>,
+}
+
+///
+/// Conditional orders of a market, see the module docs. Clones share the
+/// registered orders
+///
+#[derive(Debug, Clone)]
+pub struct ConditionalOrders {
+    market: Market,
+    pending: Arc<Mutex<Vec<(TriggerId, ConditionalOrder)>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl ConditionalOrders {
+    ///
+    /// Conditional orders placed in `market`
+    ///
+    pub fn new(market: Market) -> Self {
+        ConditionalOrders {
+            market,
+            pending: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    ///
+    /// Place `conditional` once its trigger is met
+    ///
+    pub fn register(&self, conditional: ConditionalOrder) -> TriggerId {
+        let id = TriggerId(self.next_id.fetch_add(1, Ordering::Relaxed));
+        self.pending.lock().unwrap().push((id, conditional));
+        id
+    }
+
+    ///
+    /// Drop a conditional order that wasn't triggered yet, returning it
+    ///
+    pub fn cancel(&self, id: TriggerId) -> Option<ConditionalOrder> {
+        let mut pending = self.pending.lock().unwrap();
+        let position = pending
+            .iter()
+            .position(|(pending_id, _)| *pending_id == id)?;
+        Some(pending.remove(position).1)
+    }
+
+    ///
+    /// Conditional orders waiting for their trigger, in registration order
+    ///
+    pub fn pending(&self) -> Vec<(TriggerId, ConditionalOrder)> {
+        self.pending.lock().unwrap().clone()
+    }
+
+    ///
+    /// Check the triggers against `price`, placing the orders whose trigger is
+    /// met, in registration order. Triggered orders are removed before being
+    /// placed, so each one is placed at most once: a failed placement is
+    /// reported in `Triggered::result` and isn't retried
+    ///
+    pub async fn on_price(&self, price: Decimal) -> Vec<Triggered> {
+        let triggered: Vec<_> = {
+            let mut pending = self.pending.lock().unwrap();
+            let (met, waiting) = pending
+                .drain(..)
+                .partition(|(_, conditional)| conditional.trigger.is_met(price));
+            *pending = waiting;
+            met
+        };
+        let mut placed = Vec::with_capacity(triggered.len());
+        for (id, conditional) in triggered {
+            let order = &conditional.order;
+            let result = self
+                .market
+                .create_order(order.order_type, order.amount, order.price)
+                .await;
+            placed.push(Triggered {
+                id,
+                conditional,
+                price,
+                result,
+            });
+        }
+        placed
+    }
+
+    ///
+    /// Check the triggers against the last price of the market every
+    /// `interval`, yielding the triggered orders. The first check happens
+    /// right away. Failed ticker requests yield their error and the stream
+    /// goes on; drop it to stop watching
+    ///
+    pub fn watch(&self, interval: Duration) -> impl Stream<Item = CryptoMktResult<Triggered>> + '_ {
+        let ticks = rt::interval(interval);
+        stream::unfold(ticks, move |mut ticks| async move {
+            ticks.tick().await;
+            let triggered: Vec<_> = match self.market.get_current_ticker().await {
+                Ok(ticker) => self
+                    .on_price(ticker.last_price)
+                    .await
+                    .into_iter()
+                    .map(Ok)
+                    .collect(),
+                Err(e) => vec![Err(e)],
+            };
+            Some((stream::iter(triggered), ticks))
+        })
+        .flatten()
+    }
+}
@@ -0,0 +1,265 @@
+//!
+//! ## Trade costs
+//!
+//! `TradeCostCalculator` tells what a prospective order would cost, or
+//! yield, once the exchange fees are applied, using the fee tier of the
+//! account:
+//!
+//! ```no_run
+//! use cryptomkt::cost::{Liquidity, TradeCostCalculator};
+//! use cryptomkt::{Client, Decimal, Side};
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! let calculator = TradeCostCalculator::from_account(&client)
+//!     .await?
+//!     .tick_size(Decimal::new(10, 0))
+//!     .lot_size(Decimal::new(1, 4));
+//! let estimate = calculator.estimate(
+//!     Side::Buy,
+//!     Decimal::new(5, 1),
+//!     Decimal::new(180005, 0),
+//!     Liquidity::Taker,
+//! );
+//! println!("{} CLP, {} of them in fees", estimate.net, estimate.fee);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! For a single estimate, `Market::estimate_cost` fetches the fees and the
+//! ticker of the market itself.
+//!
+
+use rust_decimal::{Decimal, RoundingStrategy};
+
+use crate::client::Client;
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::models::{round_to_step, MarketLimits, Rate};
+use crate::market::Side;
+
+///
+/// Whether an order rests on the book or executes against it, which decides
+/// the fee charged
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Liquidity {
+    /// The order rests on the book until another order takes it
+    Maker,
+    /// The order executes right away against the book
+    Taker,
+}
+
+impl Liquidity {
+    ///
+    /// Liquidity of a limit order at `price`, given the best prices of the
+    /// book: orders crossing the spread are takers
+    ///
+    pub fn of(side: Side, price: Decimal, bid: Decimal, ask: Decimal) -> Self {
+        let crosses = match side {
+            Side::Buy => price >= ask,
+            Side::Sell => price <= bid,
+        };
+        if crosses {
+            Liquidity::Taker
+        } else {
+            Liquidity::Maker
+        }
+    }
+}
+
+///
+/// Cost of a prospective order, in the quote currency of the market
+///
+#[derive(Debug, Clone, PartialEq)]
+pub struct CostEstimate {
+    /// Side of the order
+    pub side: Side,
+    /// Quantity, rounded down to the lot size
+    pub amount: Decimal,
+    /// Limit price, rounded to the tick size
+    pub price: Decimal,
+    /// Whether the order was taken as maker or taker
+    pub liquidity: Liquidity,
+    /// Fee rate applied
+    pub fee_rate: Decimal,
+    /// `amount * price`
+    pub gross: Decimal,
+    /// Fee charged
+    pub fee: Decimal,
+    /// What a purchase costs (`gross + fee`) or a sale yields (`gross - fee`)
+    pub net: Decimal,
+}
+
+///
+/// Computes the cost of orders with the fees of an account, see the module
+/// docs
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TradeCostCalculator {
+    rate: Rate,
+    tick_size: Option<Decimal>,
+    lot_size: Option<Decimal>,
+}
+
+impl TradeCostCalculator {
+    ///
+    /// Calculator with the given fees, without rounding
+    ///
+    pub fn new(rate: Rate) -> Self {
+        TradeCostCalculator {
+            rate,
+            tick_size: None,
+            lot_size: None,
+        }
+    }
+
+    ///
+    /// Calculator with the fees of the account of `client`
+    ///
+    pub async fn from_account(client: &Client) -> CryptoMktResult<Self> {
+        Ok(TradeCostCalculator::new(client.get_account().await?.rate))
+    }
+
+    ///
+    /// Round prices to multiples of `tick`: purchases down and sales up, so
+    /// the order is never more aggressive than asked
+    ///
+    pub fn tick_size(mut self, tick: Decimal) -> Self {
+        self.tick_size = Some(tick).filter(|tick| *tick > Decimal::ZERO);
+        self
+    }
+
+    ///
+    /// Round quantities down to multiples of `lot`
+    ///
+    pub fn lot_size(mut self, lot: Decimal) -> Self {
+        self.lot_size = Some(lot).filter(|lot| *lot > Decimal::ZERO);
+        self
+    }
+
+    ///
+    /// Round prices and quantities with the tick and lot sizes of a market,
+    /// see `Market::get_limits`
+    ///
+    pub fn limits(self, limits: &MarketLimits) -> Self {
+        self.tick_size(limits.tick_size).lot_size(limits.lot_size)
+    }
+
+    ///
+    /// Fees used by the calculator
+    ///
+    pub fn rate(&self) -> Rate {
+        self.rate
+    }
+
+    ///
+    /// Cost of an order of `amount` at `price`
+    ///
+    pub fn estimate(
+        &self,
+        side: Side,
+        amount: Decimal,
+        price: Decimal,
+        liquidity: Liquidity,
+    ) -> CostEstimate {
+        let price = match (self.tick_size, side) {
+            (Some(tick), Side::Buy) => round_to_step(price, tick, RoundingStrategy::ToZero),
+            (Some(tick), Side::Sell) => round_to_step(price, tick, RoundingStrategy::AwayFromZero),
+            (None, _) => price,
+        };
+        let amount = match self.lot_size {
+            Some(lot) => round_to_step(amount, lot, RoundingStrategy::ToZero),
+            None => amount,
+        };
+        let fee_rate = match liquidity {
+            Liquidity::Maker => self.rate.market_maker,
+            Liquidity::Taker => self.rate.market_taker,
+        };
+        let gross = amount * price;
+        let fee = gross * fee_rate;
+        let net = match side {
+            Side::Buy => gross + fee,
+            Side::Sell => gross - fee,
+        };
+        CostEstimate {
+            side,
+            amount,
+            price,
+            liquidity,
+            fee_rate,
+            gross,
+            fee,
+            net,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn calculator() -> TradeCostCalculator {
+        TradeCostCalculator::new(Rate {
+            market_maker: Decimal::new(39, 4),
+            market_taker: Decimal::new(68, 4),
+        })
+    }
+
+    #[test]
+    fn estimate_applies_fee_of_liquidity() {
+        let buy = calculator().estimate(
+            Side::Buy,
+            Decimal::new(5, 1),
+            Decimal::new(180000, 0),
+            Liquidity::Taker,
+        );
+        assert_eq!(buy.gross, Decimal::new(90000, 0));
+        assert_eq!(buy.fee, Decimal::new(612, 0));
+        assert_eq!(buy.net, Decimal::new(90612, 0));
+
+        let sell = calculator().estimate(
+            Side::Sell,
+            Decimal::new(5, 1),
+            Decimal::new(180000, 0),
+            Liquidity::Maker,
+        );
+        assert_eq!(sell.fee, Decimal::new(351, 0));
+        assert_eq!(sell.net, Decimal::new(89649, 0));
+    }
+
+    #[test]
+    fn estimate_rounds_to_tick_and_lot() {
+        let calculator = calculator()
+            .tick_size(Decimal::new(10, 0))
+            .lot_size(Decimal::new(1, 2));
+        let buy = calculator.estimate(
+            Side::Buy,
+            Decimal::new(1239, 3),
+            Decimal::new(180005, 0),
+            Liquidity::Maker,
+        );
+        assert_eq!(buy.amount, Decimal::new(123, 2));
+        assert_eq!(buy.price, Decimal::new(180000, 0));
+        let sell = calculator.estimate(
+            Side::Sell,
+            Decimal::new(1239, 3),
+            Decimal::new(180005, 0),
+            Liquidity::Maker,
+        );
+        assert_eq!(sell.price, Decimal::new(180010, 0));
+    }
+
+    #[test]
+    fn crossing_orders_are_takers() {
+        let (bid, ask) = (Decimal::new(100, 0), Decimal::new(110, 0));
+        assert_eq!(Liquidity::of(Side::Buy, ask, bid, ask), Liquidity::Taker);
+        assert_eq!(
+            Liquidity::of(Side::Buy, Decimal::new(105, 0), bid, ask),
+            Liquidity::Maker
+        );
+        assert_eq!(
+            Liquidity::of(Side::Sell, Decimal::new(90, 0), bid, ask),
+            Liquidity::Taker
+        );
+    }
+}
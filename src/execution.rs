@@ -0,0 +1,447 @@
+//!
+//! ## Execution algorithms
+//!
+//! `Twap` splits a large order into child orders spread evenly over a period
+//! of time (time-weighted average price), so a single order doesn't move the
+//! book. Each child is a limit order at the best price of the other side of
+//! the book, taken from the ticker, so it executes right away when there is
+//! enough liquidity.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::execution::execute_twap;
+//! use cryptomkt::{Client, Decimal, Side};
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! let market = client.create_market("ETHCLP");
+//! // Buy 10 ETH in 20 orders over the next hour
+//! let hour = Duration::from_secs(3600);
+//! let state = execute_twap(&market, Side::Buy, Decimal::new(10, 0), hour, 20).await?;
+//! println!("filled {} of {}", state.filled(), state.total_amount);
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The progress is kept in a `TwapState`, which can be serialized. Persist it
+//! from the callback of `Twap::run` and, after a restart, continue with
+//! `Twap::resume`: the slices already placed aren't placed again, and the ones
+//! whose time has passed are placed right away.
+//!
+//! Each child is placed with `Market::create_order_with_id`, under a client
+//! order ID made of the execution ID and the slice number. A slice whose
+//! placement failed without a clear rejection, e.g. on a timeout, is kept in
+//! the state as pending and sent again with the same ID, amount and price, so
+//! an order that did reach the exchange is found instead of placed twice. This
+//! holds across restarts too, as long as the state saved by `Twap::run` is
+//! resumed.
+//!
+//! Amounts are rounded down to the lot size of the market, see
+//! `Market::get_limits`. A slice below the minimum amount is skipped and its
+//! quantity spread over the following ones; a remainder below the minimum at
+//! the last slice is left unplaced.
+//!
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::{Deserialize, Serialize};
+
+use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::MarketLimits;
+use crate::internal::rt;
+use crate::market::{is_rejection, Market, OrderRequest, Side};
+use crate::tracker::filled_amount;
+
+/// Decimales de la cantidad de cada orden hija
+const AMOUNT_DECIMALS: u32 = 8;
+
+/// Ejecuciones creadas por este proceso, para distinguir sus IDs
+static EXECUTIONS: AtomicU64 = AtomicU64::new(0);
+
+///
+/// Child order placed by a `Twap`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ChildOrder {
+    /// Slice number, from 0
+    pub slice: u32,
+    /// Order ID
+    pub order_id: String,
+    /// Order quantity
+    pub amount: Decimal,
+    /// Order limit price
+    pub price: Decimal,
+    /// Executed quantity, as of the last `Twap::refresh_fills`
+    pub filled: Decimal,
+    /// Date the order was placed
+    pub placed_at: DateTime<Utc>,
+}
+
+///
+/// Child order sent without a known outcome, placed again with the same
+/// client order ID, amount and price by the next `Twap::step`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PendingSlice {
+    /// Slice number, from 0
+    pub slice: u32,
+    /// Order quantity
+    pub amount: Decimal,
+    /// Order limit price
+    pub price: Decimal,
+    /// Date of the first attempt
+    pub submitted_at: DateTime<Utc>,
+}
+
+///
+/// Progress of a `Twap`, serializable so the execution survives restarts
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct TwapState {
+    /// Market pair
+    pub market: String,
+    /// Side of the child orders
+    pub side: Side,
+    /// Quantity to execute in total
+    pub total_amount: Decimal,
+    /// Period over which the slices are spread
+    pub duration: Duration,
+    /// Number of child orders
+    pub slices: u32,
+    /// Date of the first slice
+    pub started_at: DateTime<Utc>,
+    /// ID of the execution, prefix of the client order IDs of its children
+    #[serde(default)]
+    pub execution_id: String,
+    /// Child orders placed so far
+    pub children: Vec<ChildOrder>,
+    /// Slices skipped for being below the minimum amount of the market
+    #[serde(default)]
+    pub skipped: u32,
+    /// Slice being placed, whose outcome isn't known yet
+    #[serde(default)]
+    pub pending: Option<PendingSlice>,
+}
+
+impl TwapState {
+    ///
+    /// Quantity of the child orders placed so far
+    ///
+    pub fn placed(&self) -> Decimal {
+        self.children.iter().map(|child| child.amount).sum()
+    }
+
+    ///
+    /// Quantity executed, as of the last `Twap::refresh_fills`
+    ///
+    pub fn filled(&self) -> Decimal {
+        self.children.iter().map(|child| child.filled).sum()
+    }
+
+    ///
+    /// Whether every slice was placed or skipped
+    ///
+    pub fn is_complete(&self) -> bool {
+        self.next_slice() >= self.slices
+    }
+
+    ///
+    /// Date the next slice is due, `None` once every slice was placed
+    ///
+    pub fn next_slice_at(&self) -> Option<DateTime<Utc>> {
+        if self.is_complete() {
+            return None;
+        }
+        let offset = self.duration * self.next_slice() / self.slices;
+        Some(self.started_at + chrono::Duration::from_std(offset).ok()?)
+    }
+
+    ///
+    /// Client order ID of the child order of `slice`
+    ///
+    pub fn client_order_id(&self, slice: u32) -> String {
+        format!("{}-{}", self.execution_id, slice)
+    }
+
+    ///
+    /// Número de la siguiente porción
+    ///
+    fn next_slice(&self) -> u32 {
+        self.children.len() as u32 + self.skipped
+    }
+
+    ///
+    /// Cantidad de la siguiente orden hija: lo que falta repartido en partes
+    /// iguales, y todo el resto en la última, redondeada al lote del mercado
+    ///
+    /// Argumentos
+    ///     limits: Reglas del mercado
+    ///
+    fn next_amount(&self, limits: &MarketLimits) -> Decimal {
+        let remaining = self.total_amount - self.placed();
+        let slices_left = self.slices - self.next_slice();
+        let amount = if slices_left <= 1 {
+            remaining
+        } else {
+            (remaining / Decimal::from(slices_left))
+                .round_dp_with_strategy(AMOUNT_DECIMALS, RoundingStrategy::ToZero)
+        };
+        limits.round_amount(amount).normalize()
+    }
+}
+
+///
+/// ID de una ejecución nueva, único entre las de este proceso y, por la fecha,
+/// entre procesos
+///
+/// Argumentos
+///     market: Mercado de la ejecución
+///
+fn new_execution_id(market: &str) -> String {
+    let now = Utc::now();
+    format!(
+        "twap-{}-{}{:09}-{}",
+        market.to_lowercase(),
+        now.timestamp(),
+        now.timestamp_subsec_nanos(),
+        EXECUTIONS.fetch_add(1, Ordering::Relaxed)
+    )
+}
+
+///
+/// Time-weighted execution of a large order, see the module docs
+///
+#[derive(Debug, Clone)]
+pub struct Twap {
+    market: Market,
+    state: TwapState,
+    /// Reglas del mercado, pedidas antes de la primera porción
+    limits: Option<MarketLimits>,
+}
+
+impl Twap {
+    ///
+    /// Execution of `total_amount` in `slices` child orders spread over
+    /// `duration`, starting now. Fails with `InvalidConfiguration` when
+    /// `slices` is 0 or `total_amount` isn't positive
+    ///
+    pub fn new(
+        market: &Market,
+        side: Side,
+        total_amount: Decimal,
+        duration: Duration,
+        slices: u32,
+    ) -> CryptoMktResult<Self> {
+        if slices == 0 || total_amount <= Decimal::ZERO {
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "TWAP of {} in {} slices",
+                total_amount, slices
+            ))
+            .into());
+        }
+        Ok(Twap {
+            market: market.clone(),
+            state: TwapState {
+                market: market.get_name(),
+                side,
+                total_amount,
+                duration,
+                slices,
+                started_at: Utc::now(),
+                execution_id: new_execution_id(&market.get_name()),
+                children: Vec::new(),
+                skipped: 0,
+                pending: None,
+            },
+            limits: None,
+        })
+    }
+
+    ///
+    /// Continue an execution from its saved state. A pending slice is looked
+    /// up among the orders of the market before it's placed again. Fails with
+    /// `InvalidConfiguration` when the state belongs to another market
+    ///
+    pub fn resume(market: &Market, mut state: TwapState) -> CryptoMktResult<Self> {
+        if !state.market.eq_ignore_ascii_case(&market.get_name()) {
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "TWAP state of {} resumed on {}",
+                state.market,
+                market.get_name()
+            ))
+            .into());
+        }
+        // Estados guardados antes de que existieran los IDs de ejecución
+        if state.execution_id.is_empty() {
+            state.execution_id = new_execution_id(&state.market);
+        }
+        if let Some(pending) = &state.pending {
+            let request = OrderRequest {
+                order_type: state.side,
+                amount: pending.amount,
+                price: pending.price,
+            };
+            let client_order_id = state.client_order_id(pending.slice);
+            market.restore_submission(&client_order_id, &request, pending.submitted_at);
+        }
+        Ok(Twap {
+            market: market.clone(),
+            state,
+            limits: None,
+        })
+    }
+
+    ///
+    /// Progress of the execution
+    ///
+    pub fn state(&self) -> &TwapState {
+        &self.state
+    }
+
+    ///
+    /// Wait until the next slice is due and place it, returning the child
+    /// order, or `None` if every slice was already placed. Slices below the
+    /// minimum amount of the market are skipped on the way. If the exchange
+    /// rejects the order the slice is priced again by the next call; if the
+    /// outcome is unknown, e.g. on a timeout, the slice stays pending and the
+    /// next call looks it up before placing it again
+    ///
+    pub async fn step(&mut self) -> CryptoMktResult<Option<&ChildOrder>> {
+        if !self.prepare().await? {
+            return Ok(None);
+        }
+        self.place().await.map(Some)
+    }
+
+    ///
+    /// Espera a la siguiente porción y la deja pendiente con su cantidad y
+    /// precio, saltando las que no llegan al mínimo. Devuelve `false` si no
+    /// quedan porciones
+    ///
+    async fn prepare(&mut self) -> CryptoMktResult<bool> {
+        if self.state.pending.is_some() {
+            return Ok(true);
+        }
+        loop {
+            let due = match self.state.next_slice_at() {
+                Some(due) => due,
+                None => return Ok(false),
+            };
+            if let Ok(wait) = (due - Utc::now()).to_std() {
+                rt::sleep(wait).await;
+            }
+            let limits = match &self.limits {
+                Some(limits) => limits,
+                None => self.limits.insert(self.market.get_limits().await?),
+            };
+            let amount = self.state.next_amount(limits);
+            if amount.is_zero() || amount < limits.min_amount {
+                // Su cantidad pasa a las siguientes porciones
+                self.state.skipped += 1;
+                continue;
+            }
+            let ticker = self.market.get_current_ticker().await?;
+            let price = match self.state.side {
+                Side::Buy => ticker.ask,
+                Side::Sell => ticker.bid,
+            };
+            self.state.pending = Some(PendingSlice {
+                slice: self.state.next_slice(),
+                amount,
+                price,
+                submitted_at: Utc::now(),
+            });
+            return Ok(true);
+        }
+    }
+
+    ///
+    /// Envía la porción pendiente con su identificador del cliente
+    ///
+    async fn place(&mut self) -> CryptoMktResult<&ChildOrder> {
+        let pending = match self.state.pending.clone() {
+            Some(pending) => pending,
+            None => unreachable!("place sin porción pendiente"),
+        };
+        let client_order_id = self.state.client_order_id(pending.slice);
+        let result = self
+            .market
+            .create_order_with_id(&client_order_id, self.state.side, pending.amount, pending.price)
+            .await;
+        let order = match result {
+            Ok(order) => order,
+            Err(e) => {
+                if is_rejection(&e) {
+                    self.state.pending = None;
+                }
+                return Err(e);
+            }
+        };
+        self.state.pending = None;
+        self.state.children.push(ChildOrder {
+            slice: pending.slice,
+            order_id: order.id,
+            amount: pending.amount,
+            price: pending.price,
+            filled: Decimal::ZERO,
+            placed_at: Utc::now(),
+        });
+        Ok(&self.state.children[self.state.children.len() - 1])
+    }
+
+    ///
+    /// Update the executed quantity of the child orders that aren't fully
+    /// executed yet
+    ///
+    pub async fn refresh_fills(&mut self) -> CryptoMktResult<()> {
+        for child in &mut self.state.children {
+            if child.filled < child.amount {
+                let order = self.market.get_order_status(&child.order_id).await?;
+                child.filled = filled_amount(&order);
+            }
+        }
+        Ok(())
+    }
+
+    ///
+    /// Place the remaining slices, each one when it's due, then update the
+    /// fills. `persist` is called with the state before and after each
+    /// placement, e.g. to save it to a file. The state saved before a
+    /// placement has the slice as pending, so if the process stops before the
+    /// answer arrives, the resumed execution looks the order up instead of
+    /// placing it twice
+    ///
+    pub async fn run<F>(&mut self, mut persist: F) -> CryptoMktResult<&TwapState>
+    where
+        F: FnMut(&TwapState),
+    {
+        while self.prepare().await? {
+            persist(&self.state);
+            self.place().await?;
+            persist(&self.state);
+        }
+        self.refresh_fills().await?;
+        persist(&self.state);
+        Ok(&self.state)
+    }
+}
+
+///
+/// Execute `total_amount` in `slices` child orders spread over `duration`,
+/// see `Twap`. Returns the final state, with the fills of the child orders
+///
+pub async fn execute_twap(
+    market: &Market,
+    side: Side,
+    total_amount: Decimal,
+    duration: Duration,
+    slices: u32,
+) -> CryptoMktResult<TwapState> {
+    let mut twap = Twap::new(market, side, total_amount, duration, slices)?;
+    twap.run(|_| ()).await?;
+    Ok(twap.state)
+}
@@ -0,0 +1,227 @@
+//!
+//! ## CSV export
+//!
+//! Available with the `csv` feature. Trades, orders, payments and balances
+//! are written as CSV with a header row, ready to be opened in a spreadsheet.
+//!
+//! ```no_run
+//! use std::fs::File;
+//!
+//! use cryptomkt::export::to_csv;
+//! use cryptomkt::{Client, NaiveDate};
+//! use tokio_util::sync::CancellationToken;
+//!
+//! # async fn run() -> Result<(), Box<dyn std::error::Error>> {
+//! let client = Client::public();
+//! let market = client.create_market("ETHCLP");
+//! let start = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+//! let end = NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+//! let trades = market.get_all_trades(start, end, &CancellationToken::new()).await?;
+//! to_csv(&trades, File::create("trades.csv")?)?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! The columns of each type are stable, in this order:
+//!
+//! | Type      | Columns |
+//! |-----------|---------|
+//! | `Trade`   | `market_taker`, `price`, `amount`, `tid`, `timestamp`, `market` |
+//! | `Order`   | `id`, `status`, `type`, `price`, `amount_original`, `amount_remaining`, `amount_executed`, `execution_price`, `avg_execution_price`, `fee`, `market`, `created_at`, `updated_at`, `executed_at` |
+//! | `Payment` | `id`, `external_id`, `status`, `to_receive`, `to_receive_currency`, `expected_amount`, `expected_currency`, `deposit_address`, `refund_email`, `qr`, `obs`, `callback_url`, `error_url`, `success_url`, `payment_url`, `created_at`, `updated_at` |
+//! | `Balance` | `wallet`, `available`, `balance` |
+//!
+//! Amounts are written as exact decimals and dates in RFC 3339, in UTC.
+//! Missing optional values are empty cells, and the status of a payment is
+//! its numeric code.
+//!
+
+use std::io::{self, Write};
+
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+use crate::internal::models::{Balance, Order, Payment, Trade};
+
+///
+/// Type that can be written as a CSV row, see the module docs for the columns
+///
+pub trait CsvRecord {
+    /// Names of the columns, in order
+    const HEADERS: &'static [&'static str];
+
+    /// Values of the columns, in the order of `HEADERS`
+    fn fields(&self) -> Vec<String>;
+}
+
+///
+/// Write `records` to `writer` as CSV, preceded by a header row
+///
+pub fn to_csv<T: CsvRecord, W: Write>(records: &[T], writer: W) -> io::Result<()> {
+    let mut csv = csv::Writer::from_writer(writer);
+    csv.write_record(T::HEADERS)?;
+    for record in records {
+        csv.write_record(record.fields())?;
+    }
+    csv.flush()
+}
+
+fn date(date: &DateTime<Utc>) -> String {
+    date.to_rfc3339()
+}
+
+fn optional_date(value: &Option<DateTime<Utc>>) -> String {
+    value.as_ref().map(date).unwrap_or_default()
+}
+
+fn optional_decimal(value: &Option<Decimal>) -> String {
+    value.map(|value| value.to_string()).unwrap_or_default()
+}
+
+impl CsvRecord for Trade {
+    const HEADERS: &'static [&'static str] =
+        &["market_taker", "price", "amount", "tid", "timestamp", "market"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.market_taker.clone(),
+            self.price.to_string(),
+            self.amount.to_string(),
+            self.tid.clone(),
+            date(&self.timestamp),
+            self.market.clone(),
+        ]
+    }
+}
+
+impl CsvRecord for Order {
+    const HEADERS: &'static [&'static str] = &[
+        "id",
+        "status",
+        "type",
+        "price",
+        "amount_original",
+        "amount_remaining",
+        "amount_executed",
+        "execution_price",
+        "avg_execution_price",
+        "fee",
+        "market",
+        "created_at",
+        "updated_at",
+        "executed_at",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.clone(),
+            self.status.clone(),
+            self.order_type.clone(),
+            self.price.to_string(),
+            self.amount.original.to_string(),
+            self.amount.remaining.to_string(),
+            self.amount.executed.to_string(),
+            optional_decimal(&self.execution_price),
+            self.avg_execution_price.to_string(),
+            self.fee.to_string(),
+            self.market.clone(),
+            date(&self.created_at),
+            optional_date(&self.updated_at),
+            optional_date(&self.executed_at),
+        ]
+    }
+}
+
+impl CsvRecord for Payment {
+    const HEADERS: &'static [&'static str] = &[
+        "id",
+        "external_id",
+        "status",
+        "to_receive",
+        "to_receive_currency",
+        "expected_amount",
+        "expected_currency",
+        "deposit_address",
+        "refund_email",
+        "qr",
+        "obs",
+        "callback_url",
+        "error_url",
+        "success_url",
+        "payment_url",
+        "created_at",
+        "updated_at",
+    ];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.id.to_string(),
+            self.external_id.clone(),
+            self.status.code().to_string(),
+            self.to_receive.to_string(),
+            self.to_receive_currency.clone(),
+            self.expected_amount.to_string(),
+            self.expected_currency.clone(),
+            self.deposit_address.clone(),
+            self.refund_email.clone(),
+            self.qr.clone(),
+            self.obs.clone(),
+            self.callback_url.clone(),
+            self.error_url.clone(),
+            self.success_url.clone(),
+            self.payment_url.clone(),
+            date(&self.created_at),
+            date(&self.updated_at),
+        ]
+    }
+}
+
+impl CsvRecord for Balance {
+    const HEADERS: &'static [&'static str] = &["wallet", "available", "balance"];
+
+    fn fields(&self) -> Vec<String> {
+        vec![
+            self.wallet.clone(),
+            self.available.to_string(),
+            self.balance.to_string(),
+        ]
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn to_csv_writes_header_and_rows() {
+        let trades: Vec<Trade> = serde_json::from_str(
+            "[{\"market_taker\":\"buy\",\"timestamp\":\"2017-05-29T22:14:00.419466\",\"price\":\"155000\",\"amount\":\"0.129\",\"market\":\"ETHCLP\"}]",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        to_csv(&trades, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "market_taker,price,amount,tid,timestamp,market\n\
+             buy,155000,0.129,,2017-05-29T22:14:00.419466+00:00,ETHCLP\n"
+        );
+    }
+
+    #[test]
+    fn to_csv_flattens_orders() {
+        let orders: Vec<Order> = serde_json::from_str(
+            "[{\"id\":\"M103975\",\"status\":\"active\",\"type\":\"sell\",\"price\":\"3000\",\"amount\":{\"original\":\"1.5\",\"remaining\":\"1.5\"},\"market\":\"ETHCLP\",\"created_at\":\"2017-08-31T21:37:42.282102\"}]",
+        )
+        .unwrap();
+        let mut out = Vec::new();
+        to_csv(&orders, &mut out).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut lines = out.lines();
+        assert_eq!(lines.next().unwrap().split(',').count(), Order::HEADERS.len());
+        assert_eq!(
+            lines.next().unwrap(),
+            "M103975,active,sell,3000,1.5,1.5,0,,0,0,ETHCLP,2017-08-31T21:37:42.282102+00:00,,"
+        );
+        assert_eq!(lines.next(), None);
+    }
+}
@@ -0,0 +1,137 @@
+//!
+//! ## Trade history
+//!
+//! Download the full trade history of a market for a range of dates, e.g. to
+//! build research datasets.
+//!
+//! ```no_run
+//! use cryptomkt::history::download_trades;
+//! use cryptomkt::{Client, NaiveDate};
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let client = Client::public();
+//! let market = client.create_market("ETHCLP");
+//! let from = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+//! let to = NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+//! let trades = download_trades(&market, from, to).await?;
+//! println!("{} trades", trades.len());
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Unlike `Market::get_all_trades`, the downloader:
+//!
+//! - Parses each page as its body arrives, see `Market::stream_trades`.
+//! - Skips the trades already seen. New trades shift the pages while they are
+//!   walked, so the last trades of a page show up again at the start of the next.
+//! - Waits and asks again for a page answered with `429 Too Many Requests`, as
+//!   long as the `Retry-After` header asks (one second when it's missing), up to
+//!   `MAX_RETRIES` times per page and `MAX_RETRY_WAIT` each time. When the
+//!   client already retries them, see `RateLimitBehavior`, its limits apply
+//!   instead. The quotas configured with `ClientBuilder::public_rate_limit`
+//!   apply too.
+//!
+
+use std::collections::HashSet;
+use std::time::Duration;
+
+use chrono::NaiveDate;
+use futures::future;
+use futures::stream::{Stream, TryStreamExt};
+
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::models::Trade;
+use crate::internal::pagination::stream_incremental_pages;
+use crate::internal::ratelimit::RateLimitBehavior;
+use crate::internal::rt;
+use crate::internal::streaming::PageStream;
+use crate::market::Market;
+
+/// Times a page answered with `429 Too Many Requests` is asked again
+pub const MAX_RETRIES: u32 = 5;
+
+/// Longest `Retry-After` waited for; a longer one ends the download with the
+/// error
+pub const MAX_RETRY_WAIT: Duration = Duration::from_secs(60);
+
+///
+/// Get every trade of `market` between `from` and `to`, in the order the
+/// exchange lists them and without repetitions
+///
+pub async fn download_trades(
+    market: &Market,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> CryptoMktResult<Vec<Trade>> {
+    stream_trades(market, from, to).try_collect().await
+}
+
+///
+/// Stream every trade of `market` between `from` and `to`, without
+/// repetitions. Each page is requested when the trades of the previous one
+/// are consumed; if a request fails the error is yielded and the stream ends
+///
+pub fn stream_trades(
+    market: &Market,
+    from: NaiveDate,
+    to: NaiveDate,
+) -> impl Stream<Item = CryptoMktResult<Trade>> + '_ {
+    let mut seen = HashSet::new();
+    stream_incremental_pages(move |page| fetch_page(market, from, to, page))
+        .try_filter(move |trade| future::ready(seen.insert(trade_key(trade))))
+}
+
+///
+/// Abre una página de trades, reintentando mientras el exchange responda 429
+/// si el cliente no lo hace ya. Sus trades se leen a medida que llega la
+/// respuesta
+///
+/// Argumentos
+///     market: Mercado
+///     from: Fecha inicial
+///     to: Fecha final
+///     page: Número de página
+///
+async fn fetch_page(
+    market: &Market,
+    from: NaiveDate,
+    to: NaiveDate,
+    page: u32,
+) -> CryptoMktResult<PageStream<Trade>> {
+    // Reintentar lo que el cliente ya reintentó multiplicaría las esperas
+    let behavior = match market.rate_limit_behavior() {
+        RateLimitBehavior::Fail => RateLimitBehavior::Retry {
+            max_retries: MAX_RETRIES,
+            max_wait: MAX_RETRY_WAIT,
+        },
+        _ => RateLimitBehavior::Fail,
+    };
+    let mut retries = 0;
+    loop {
+        match market.trades_page_stream(from, to, page).await {
+            Err(e) => match behavior.retry_delay(&e, retries) {
+                Some(wait) => {
+                    retries += 1;
+                    rt::sleep(wait).await;
+                }
+                None => return Err(e),
+            },
+            resp => return resp,
+        }
+    }
+}
+
+///
+/// Identifica un trade entre páginas: su id, o sus datos si el exchange no
+/// lo envía
+///
+fn trade_key(trade: &Trade) -> String {
+    if trade.tid.is_empty() {
+        format!(
+            "{}|{}|{}|{}",
+            trade.timestamp, trade.price, trade.amount, trade.market_taker
+        )
+    } else {
+        trade.tid.clone()
+    }
+}
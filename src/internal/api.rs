@@ -1,37 +1,103 @@
 use std::collections::HashMap;
-use std::fmt::Write;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::fmt::{self, Write};
+use std::future::Future;
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
+use base64::engine::general_purpose::STANDARD as BASE64;
+use base64::Engine;
 use reqwest::{header::{HeaderMap, HeaderValue}, Url};
-use ring::hmac::{sign, Key, HMAC_SHA384};
 
 use serde::de::DeserializeOwned;
 use serde_json;
 // use log::Level;
 
+/// Dominio de CryptoMarket usado por defecto
+pub(crate) const DEFAULT_DOMAIN: &str = "https://api.cryptomkt.com/";
+/// Versión del API usada por defecto
+pub(crate) const DEFAULT_API_VERSION: &str = "v1";
+
 /// X-MKT-APIKEY: La API key como un string
-const X_MKT_APIKEY: &'static str = "X-MKT-APIKEY";
+const X_MKT_APIKEY: &str = "X-MKT-APIKEY";
 /// X-MKT-SIGNATURE: El mensaje firmado generado por el usuario (ver abajo)
-const X_MKT_SIGNATURE: &'static str = "X-MKT-SIGNATURE";
+const X_MKT_SIGNATURE: &str = "X-MKT-SIGNATURE";
 /// X-MKT-TIMESTAMP: Un timestamp para tu llamada
-const X_MKT_TIMESTAMP: &'static str = "X-MKT-TIMESTAMP";
+const X_MKT_TIMESTAMP: &str = "X-MKT-TIMESTAMP";
 
-use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
-use crate::internal::request::HttpRequest;
+use crate::internal::errors::{error_envelope, CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::cache::ResponseCache;
+use crate::internal::hmac::hmac_sha256;
+use crate::internal::ratelimit::{RateLimitBehavior, RateLimiter};
+use crate::internal::request::{ConnectionStatus, HttpRequest, ResponseMeta, TransportStats};
+use crate::internal::response::parse_bare;
+use crate::internal::rt::{self, Instant};
+use crate::internal::secret::SecretString;
+use crate::internal::signing;
+use crate::internal::streaming::PageStream;
+use crate::internal::trace::{error, instrument, warn};
+use crate::v3;
 
 ///
 /// API Interna
 ///
-#[derive(Debug, Clone)]
+#[derive(Clone)]
 pub struct Api<R>
 where
     R: HttpRequest<Result=CryptoMktResult<String>>
 {
-    api_key: String,
-    secret_key: String,
+    api_key: SecretString,
+    secret_key: SecretString,
+    endpoint: Arc<RwLock<Endpoint>>,
+    req: Box<R>,
+    clock: Arc<ClockSync>,
+    rate_limits: Arc<RateLimiter>,
+    cache: Arc<ResponseCache>,
+    /// Cabeceras añadidas a todas las peticiones
+    default_headers: Arc<HeaderMap>,
+}
+
+/// Las llaves no se muestran, para que no terminen en los logs
+impl<R> fmt::Debug for Api<R>
+where
+    R: HttpRequest<Result=CryptoMktResult<String>>
+{
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Api")
+            .field("api_key", &self.api_key)
+            .field("secret_key", &self.secret_key)
+            .field("endpoint", &self.endpoint)
+            .field("clock", &self.clock)
+            .field("rate_limits", &self.rate_limits)
+            .field("cache", &self.cache)
+            .finish()
+    }
+}
+
+///
+/// Dominio y versión a los que se envían las peticiones, compartidos entre clones
+///
+#[derive(Debug)]
+struct Endpoint {
     domain: String,
     api_version: String,
-    req: Box<R>,
+    /// URL base de la API v3, `None` si no se configuró
+    v3_domain: Option<String>,
+}
+
+///
+/// Desfase entre el reloj local y el del servidor, compartido entre clones
+///
+#[derive(Debug, Default)]
+struct ClockSync {
+    /// Milisegundos que hay que sumar al reloj local (servidor - local)
+    offset_ms: AtomicI64,
+    /// Cada cuánto se vuelve a medir el desfase, `None` si está desactivado
+    interval: Mutex<Option<Duration>>,
+    /// Momento de la última medición
+    last_sync: Mutex<Option<Instant>>,
+    /// Último timestamp firmado, en milisegundos
+    last_nonce: AtomicI64,
 }
 
 impl<R> Api<R>
@@ -48,21 +114,250 @@ where
     ///
     pub fn new<'a>(api_key: &'a str, secret_key: &'a str, http_transport: Box<R>) -> Self {
         Api {
-            api_key: api_key.to_string(),
-            secret_key: secret_key.to_string(),
-            domain: "https://api.cryptomkt.com/".to_string(),
-            api_version: "v1".to_string(),
+            api_key: SecretString::new(api_key),
+            secret_key: SecretString::new(secret_key),
+            endpoint: Arc::new(RwLock::new(Endpoint {
+                domain: DEFAULT_DOMAIN.to_string(),
+                api_version: DEFAULT_API_VERSION.to_string(),
+                v3_domain: None,
+            })),
             req: http_transport,
+            clock: Arc::new(ClockSync::default()),
+            rate_limits: Arc::new(RateLimiter::default()),
+            cache: Arc::new(ResponseCache::default()),
+            default_headers: Arc::new(HeaderMap::new()),
         }
     }
     /// Devuelve el dominio
     pub fn domain(&self) -> String {
-        self.domain.clone()
+        self.endpoint.read().unwrap().domain.clone()
+    }
+
+    ///
+    /// Cambia el dominio al que se envían las peticiones, también en los clones
+    ///
+    /// Argumentos
+    ///     domain: URL base, p. ej. la de un servidor simulado
+    ///
+    pub fn set_domain(&self, domain: &str) -> CryptoMktResult<()> {
+        let mut domain = domain.to_string();
+        if !domain.ends_with('/') {
+            domain.push('/');
+        }
+        if let Err(e) = Url::parse(&domain) {
+            error!(target: "cryptomkt", "Invalid domain {}: {:?}", domain, e);
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!("{}: {}", domain, e)).into());
+        }
+        self.endpoint.write().unwrap().domain = domain;
+        Ok(())
+    }
+
+    ///
+    /// URL base de la API v3 para los recursos que la v1 no publica. Sin
+    /// configurar solo se conoce la de CryptoMarket, y solo mientras se use el
+    /// dominio y la versión por defecto
+    ///
+    pub fn v3_domain(&self) -> CryptoMktResult<String> {
+        let endpoint = self.endpoint.read().unwrap();
+        match &endpoint.v3_domain {
+            Some(domain) => Ok(domain.clone()),
+            None if endpoint.domain == DEFAULT_DOMAIN
+                && endpoint.api_version == DEFAULT_API_VERSION =>
+            {
+                Ok(v3::DEFAULT_URL.to_string())
+            }
+            None => Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "no v3 base URL for {}{}/, see ClientBuilder::v3_base_url",
+                endpoint.domain, endpoint.api_version
+            ))
+            .into()),
+        }
+    }
+
+    ///
+    /// Cambia la URL base de la API v3, también en los clones
+    ///
+    /// Argumentos
+    ///     domain: URL base de la v3, p. ej. `https://api.exchange.cryptomkt.com/api/3/`
+    ///
+    pub fn set_v3_domain(&self, domain: &str) -> CryptoMktResult<()> {
+        let mut domain = domain.to_string();
+        if !domain.ends_with('/') {
+            domain.push('/');
+        }
+        if let Err(e) = Url::parse(&domain) {
+            error!(target: "cryptomkt", "Invalid v3 domain {}: {:?}", domain, e);
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!("{}: {}", domain, e)).into());
+        }
+        self.endpoint.write().unwrap().v3_domain = Some(domain);
+        Ok(())
+    }
+
+    ///
+    /// Cambia los límites de peticiones por tipo de endpoint. Los clones
+    /// creados antes del cambio conservan los límites anteriores
+    ///
+    /// Argumentos
+    ///     rate_limits: Límites para los endpoints públicos y autenticados
+    ///
+    pub(crate) fn set_rate_limits(&mut self, rate_limits: RateLimiter) {
+        self.rate_limits = Arc::new(rate_limits);
+    }
+
+    ///
+    /// Respuesta configurada ante un 429 del exchange
+    ///
+    pub(crate) fn rate_limit_behavior(&self) -> RateLimitBehavior {
+        self.rate_limits.behavior
+    }
+
+    ///
+    /// Reemplaza la caché de respuestas públicas, que puede compartirse entre
+    /// clientes de distintas cuentas
+    ///
+    pub(crate) fn set_cache(&mut self, cache: Arc<ResponseCache>) {
+        self.cache = cache;
+    }
+
+    ///
+    /// Reemplaza las cabeceras que se añaden a todas las peticiones
+    ///
+    /// Argumentos
+    ///     headers: Cabeceras, p. ej. para identificar la aplicación
+    ///
+    pub(crate) fn set_default_headers(&mut self, headers: HeaderMap) {
+        self.default_headers = Arc::new(headers);
+    }
+
+    ///
+    /// Descarta las respuestas guardadas en la caché
+    ///
+    pub(crate) fn clear_cache(&self) {
+        self.cache.clear();
     }
 
     /// Devuelve la version del API
     pub fn api_version(&self) -> String {
-        self.api_version.clone()
+        self.endpoint.read().unwrap().api_version.clone()
+    }
+
+    ///
+    /// Cambia la versión del API, también en los clones
+    ///
+    /// Argumentos
+    ///     api_version: Segmento de la ruta con la versión, p. ej. `v1`
+    ///
+    pub fn set_api_version(&self, api_version: &str) -> CryptoMktResult<()> {
+        let api_version = api_version.trim_matches('/');
+        if api_version.is_empty() || api_version.contains(['/', '?', '#']) {
+            error!(target: "cryptomkt", "Invalid API version {:?}", api_version);
+            return Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "invalid API version: {:?}",
+                api_version
+            ))
+            .into());
+        }
+        self.endpoint.write().unwrap().api_version = api_version.to_string();
+        Ok(())
+    }
+
+    /// Devuelve el estado de la conexión del transporte
+    pub fn connection_status(&self) -> ConnectionStatus {
+        self.req.connection_status()
+    }
+
+    /// Devuelve las estadísticas de las peticiones del transporte
+    pub fn transport_stats(&self) -> TransportStats {
+        self.req.stats()
+    }
+
+    /// Devuelve el desfase aplicado a los timestamps en milisegundos (servidor - local)
+    pub fn clock_offset(&self) -> i64 {
+        self.clock.offset_ms.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Activa o desactiva la medición periódica del desfase del reloj. Con un
+    /// intervalo activo, las peticiones privadas vuelven a medirlo cuando la
+    /// última medición es más antigua que el intervalo
+    ///
+    /// Argumentos
+    ///     interval: Periodo entre mediciones, `None` para desactivarla
+    ///
+    pub fn set_clock_sync_interval(&self, interval: Option<Duration>) {
+        *self.clock.interval.lock().unwrap() = interval;
+    }
+
+    ///
+    /// Mide el desfase entre el reloj local y el del servidor a partir de la
+    /// cabecera `Date`, tomando como referencia local el punto medio de la petición
+    ///
+    pub async fn sync_clock(&self) -> CryptoMktResult<()> {
+        let url = Url::parse(&self.domain()).unwrap();
+        let before = rt::system_now();
+        let server_time = self.req.server_time(url).await?;
+        let elapsed = before.elapsed().unwrap_or_default();
+
+        if let Some(server_time) = server_time {
+            let local = unix_millis(before + elapsed / 2);
+            let offset = unix_millis(server_time) - local;
+            self.clock.offset_ms.store(offset, Ordering::Relaxed);
+        }
+        *self.clock.last_sync.lock().unwrap() = Some(Instant::now());
+        Ok(())
+    }
+
+    ///
+    /// Vuelve a medir el desfase si la medición periódica está activa y vencida.
+    /// Un fallo en la medición no impide la petición, se mantiene el desfase anterior
+    ///
+    async fn refresh_clock(&self) {
+        let interval = *self.clock.interval.lock().unwrap();
+        let last_sync = *self.clock.last_sync.lock().unwrap();
+        let due = match (interval, last_sync) {
+            (Some(interval), Some(last_sync)) => last_sync.elapsed() >= interval,
+            (Some(_), None) => true,
+            (None, _) => false,
+        };
+        if due {
+            if let Err(e) = self.sync_clock().await {
+                error!(target: "cryptomkt", "Clock sync: {:?}", e);
+            }
+        }
+    }
+
+    ///
+    /// Falla con `MissingCredentials` si la instancia no tiene API key, para no
+    /// enviar al exchange una petición privada que va a ser rechazada
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint privado que se quiere llamar
+    ///
+    fn require_credentials(&self, endpoint: &str) -> CryptoMktResult<()> {
+        if self.api_key.is_empty() {
+            error!(target: "cryptomkt", "{}: private endpoint called without credentials", endpoint);
+            return Err(CryptoMktErrorType::MissingCredentials.into());
+        }
+        Ok(())
+    }
+
+    ///
+    /// Timestamp en milisegundos usado para firmar, corregido con el desfase
+    /// del servidor. Es estrictamente creciente entre este API y sus clones,
+    /// de modo que dos peticiones firmadas en el mismo milisegundo no llevan
+    /// la misma firma: la segunda toma el milisegundo siguiente
+    ///
+    fn timestamp(&self) -> i64 {
+        let now = unix_millis(rt::system_now()) + self.clock_offset();
+        let nonce = &self.clock.last_nonce;
+        let mut last = nonce.load(Ordering::Relaxed);
+        loop {
+            let next = now.max(last + 1);
+            match nonce.compare_exchange_weak(last, next, Ordering::Relaxed, Ordering::Relaxed) {
+                Ok(_) => return next,
+                Err(current) => last = current,
+            }
+        }
     }
 
     ///
@@ -72,15 +367,23 @@ where
     ///     endpoint: Endpoint desde donde se va a extraer los datos
     ///     params: Parámetros de la url
     ///
-    pub fn build_url<'a>(&self, endpoint: &'a str, params: &HashMap<String, String>) -> Url {
-        let mut api_url = Url::parse(&self.domain).unwrap();
+    pub fn build_url(&self, endpoint: &str, params: &HashMap<String, String>) -> Url {
+        let (domain, api_version) = {
+            let endpoint = self.endpoint.read().unwrap();
+            (endpoint.domain.clone(), endpoint.api_version.clone())
+        };
+        let mut api_url = Url::parse(&domain).unwrap();
         // Adiciona la version de la API
         api_url = api_url
-            .join(format!("{}/", &self.api_version).as_str())
+            .join(format!("{}/", &api_version).as_str())
             .unwrap();
         // Adiciona el endpoint
         api_url = api_url.join(endpoint).unwrap();
 
+        // En orden, para que la misma consulta dé siempre la misma URL y la
+        // misma entrada en la caché
+        let mut params: Vec<_> = params.iter().collect();
+        params.sort();
         for (key, value) in params {
             api_url
                 .query_pairs_mut()
@@ -89,6 +392,32 @@ where
         api_url
     }
 
+    ///
+    /// Pide una URL pública fuera de la API configurada, sin firma ni el
+    /// envoltorio `{"status", "data"}`, aplicando las cuotas de los endpoints
+    /// públicos
+    ///
+    /// Argumentos
+    ///     url: URL completa del recurso
+    ///     endpoint: Nombre del recurso para la caché, p. ej. `symbol`
+    ///
+    pub(crate) async fn get_external<T: DeserializeOwned>(
+        &self,
+        url: Url,
+        endpoint: &str,
+    ) -> CryptoMktResult<T> {
+        if let Some(body) = self.cache.get(endpoint, url.as_str()) {
+            return parse_bare(url.path(), body);
+        }
+        self.rate_limits.acquire(true).await;
+        let path = url.path().to_string();
+        let body = instrument("GET", &path, self.req.get(url.clone(), self.headers())).await?;
+        let parsed = serde_json::from_str(&body)
+            .map_err(|e| CryptoMktError::malformed(&path, body.clone(), &e))?;
+        self.cache.insert(endpoint, url.as_str(), &body);
+        Ok(parsed)
+    }
+
     ///
     ///
     /// Argumentos
@@ -96,23 +425,142 @@ where
     ///     params: Parámetros de la url
     ///     is_public: indica si el endpoint es public
     ///
-    pub async fn get_edge<'a, T>(
+    pub async fn get_edge<T>(
         &self,
-        endpoint: &'a str,
+        endpoint: &str,
         params: HashMap<String, String>,
         is_public: bool,
     ) -> CryptoMktResult<T>
     where
         T: DeserializeOwned,
     {
+        self.get_edge_with(endpoint, params, is_public, None).await
+    }
+
+    ///
+    /// Como `get_edge`, limitando cada intento a `timeout`
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint desde donde se va a extraer los datos
+    ///     params: Parámetros de la url
+    ///     is_public: indica si el endpoint es public
+    ///     timeout: Tiempo máximo de cada intento, `None` sin límite
+    ///
+    pub async fn get_edge_with<T>(
+        &self,
+        endpoint: &str,
+        params: HashMap<String, String>,
+        is_public: bool,
+        timeout: Option<Duration>,
+    ) -> CryptoMktResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        if is_public {
+            let api_url = self.build_url(endpoint, &params);
+            if let Some(body) = self.cache.get(endpoint, api_url.as_str()) {
+                return parse_edge(api_url.path(), &body);
+            }
+        }
+        let (parsed, _) = self.get_edge_meta(endpoint, params, is_public, timeout).await?;
+        Ok(parsed)
+    }
+
+    ///
+    /// Como `get_edge_with`, devolviendo además los metadatos de la respuesta.
+    /// No responde desde la caché, aunque guarda en ella las respuestas de los
+    /// endpoints públicos
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint desde donde se va a extraer los datos
+    ///     params: Parámetros de la url
+    ///     is_public: indica si el endpoint es public
+    ///     timeout: Tiempo máximo de cada intento, `None` sin límite
+    ///
+    pub async fn get_edge_meta<T>(
+        &self,
+        endpoint: &str,
+        params: HashMap<String, String>,
+        is_public: bool,
+        timeout: Option<Duration>,
+    ) -> CryptoMktResult<(T, ResponseMeta)>
+    where
+        T: DeserializeOwned,
+    {
+        if !is_public {
+            self.require_credentials(endpoint)?;
+        }
         let api_url = self.build_url(endpoint, &params);
-        let headers = self.build_headers(endpoint, &params, is_public, true);
-        let result = self.req.get(api_url, headers).await?;
-        match serde_json::from_str(&result) {
-            Ok(sr) => Ok(sr),
-            Err(e) => {
-                println!("{:?}", e);
-                Err(CryptoMktErrorType::MalformedResource)
+        let path = api_url.path().to_string();
+        let mut retries = 0;
+        let (result, meta) = loop {
+            self.rate_limits.acquire(is_public).await;
+            if !is_public {
+                self.refresh_clock().await;
+            }
+            // Las cabeceras se firman de nuevo en cada intento
+            let headers = self.build_headers(endpoint, &params, is_public, true);
+            let request = bounded(timeout, self.send_get(api_url.clone(), headers));
+            match instrument("GET", &path, request).await {
+                Err(e) => match self.rate_limits.retry_delay(&e, retries) {
+                    Some(wait) => {
+                        warn!(target: "cryptomkt", "GET {}: rate limited, retrying in {:?}", path, wait);
+                        rt::sleep(wait).await;
+                        retries += 1;
+                    }
+                    None => return Err(e),
+                },
+                Ok(answer) => break answer,
+            }
+        };
+        let parsed = parse_edge(&path, &result)?;
+        if is_public {
+            self.cache.insert(endpoint, api_url.as_str(), &result);
+        }
+        Ok((parsed, meta))
+    }
+
+    ///
+    /// Pide una página de un endpoint público y entrega sus elementos a medida
+    /// que llega el cuerpo, sin pasar por la caché. Si el transporte no puede
+    /// leer el cuerpo por fragmentos, la página se pide y se interpreta entera
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint desde donde se va a extraer los datos
+    ///     params: Parámetros de la url
+    ///
+    pub(crate) async fn get_edge_stream<T>(
+        &self,
+        endpoint: &str,
+        params: &HashMap<String, String>,
+    ) -> CryptoMktResult<PageStream<T>>
+    where
+        T: DeserializeOwned,
+    {
+        let api_url = self.build_url(endpoint, params);
+        let path = api_url.path().to_string();
+        let mut retries = 0;
+        loop {
+            self.rate_limits.acquire(true).await;
+            let request = async {
+                match self.req.get_stream(api_url.clone(), self.headers()).await? {
+                    Some(bytes) => Ok(PageStream::incremental(&path, bytes)),
+                    None => {
+                        let (body, _) = self.send_get(api_url.clone(), self.headers()).await?;
+                        parse_edge(&path, &body).map(PageStream::buffered)
+                    }
+                }
+            };
+            match instrument("GET", &path, request).await {
+                Err(e) => match self.rate_limits.retry_delay(&e, retries) {
+                    Some(wait) => {
+                        warn!(target: "cryptomkt", "GET {}: rate limited, retrying in {:?}", path, wait);
+                        rt::sleep(wait).await;
+                        retries += 1;
+                    }
+                    None => return Err(e),
+                },
+                Ok(page) => return Ok(page),
             }
         }
     }
@@ -123,24 +571,127 @@ where
     ///     params: Parámetros de la url
     ///     is_public: indica si el endpoint es public
     ///
-    pub async fn post_edge<'a, T>(
+    pub async fn post_edge<T>(
         &self,
-        endpoint: &'a str,
+        endpoint: &str,
         payload: HashMap<String, String>,
     ) -> CryptoMktResult<T>
     where
         T: DeserializeOwned,
     {
+        self.post_edge_with(endpoint, payload, None).await
+    }
+
+    ///
+    /// Como `post_edge`, limitando cada intento a `timeout`
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint al que se envían los datos
+    ///     payload: Cuerpo de la petición
+    ///     timeout: Tiempo máximo de cada intento, `None` sin límite
+    ///
+    pub async fn post_edge_with<T>(
+        &self,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+        timeout: Option<Duration>,
+    ) -> CryptoMktResult<T>
+    where
+        T: DeserializeOwned,
+    {
+        let (parsed, _) = self.post_edge_meta(endpoint, payload, timeout).await?;
+        Ok(parsed)
+    }
+
+    ///
+    /// Como `post_edge_with`, devolviendo además los metadatos de la respuesta
+    ///
+    /// Argumentos
+    ///     endpoint: Endpoint al que se envían los datos
+    ///     payload: Cuerpo de la petición
+    ///     timeout: Tiempo máximo de cada intento, `None` sin límite
+    ///
+    pub async fn post_edge_meta<T>(
+        &self,
+        endpoint: &str,
+        payload: HashMap<String, String>,
+        timeout: Option<Duration>,
+    ) -> CryptoMktResult<(T, ResponseMeta)>
+    where
+        T: DeserializeOwned,
+    {
+        self.require_credentials(endpoint)?;
         let api_url = self.build_url(endpoint, &HashMap::new());
-        let headers = self.build_headers(endpoint, &payload, false, false);
-        let result = self.req.post(api_url, headers, payload).await?;
-        match serde_json::from_str(&result) {
-            Ok(sr) => Ok(sr),
-            Err(e) => {
-                println!("{:?}", e);
-                Err(CryptoMktErrorType::MalformedResource)
+        let path = api_url.path().to_string();
+        let mut retries = 0;
+        let (result, meta) = loop {
+            self.rate_limits.acquire(false).await;
+            self.refresh_clock().await;
+            // Las cabeceras se firman de nuevo en cada intento
+            let headers = self.build_headers(endpoint, &payload, false, false);
+            let request = self.send_post(api_url.clone(), headers, payload.clone());
+            let request = bounded(timeout, request);
+            match instrument("POST", &path, request).await {
+                Err(e) => match self.rate_limits.retry_delay(&e, retries) {
+                    Some(wait) => {
+                        warn!(target: "cryptomkt", "POST {}: rate limited, retrying in {:?}", path, wait);
+                        rt::sleep(wait).await;
+                        retries += 1;
+                    }
+                    None => return Err(e),
+                },
+                Ok(answer) => break answer,
             }
+        };
+        Ok((parse_edge(&path, &result)?, meta))
+    }
+
+    ///
+    /// Envía un GET con sus metadatos, o con `get` si el transporte no los
+    /// conoce, en cuyo caso se da por `200 OK` sin cabeceras
+    ///
+    /// Argumentos
+    ///     url: URL de la petición
+    ///     headers: Cabeceras de la petición
+    ///
+    async fn send_get(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<(String, ResponseMeta)> {
+        let started = Instant::now();
+        if let Some(answer) = self.req.get_with_meta(url.clone(), headers.clone()).await? {
+            return Ok(answer);
+        }
+        let body = self.req.get(url, headers).await?;
+        Ok((body, ResponseMeta::unknown(started.elapsed())))
+    }
+
+    ///
+    /// Envía un POST con sus metadatos, o con `post` si el transporte no los
+    /// conoce, en cuyo caso se da por `200 OK` sin cabeceras
+    ///
+    /// Argumentos
+    ///     url: URL de la petición
+    ///     headers: Cabeceras de la petición
+    ///     payload: Cuerpo de la petición
+    ///
+    async fn send_post(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<(String, ResponseMeta)> {
+        let started = Instant::now();
+        let answer = self
+            .req
+            .post_with_meta(url.clone(), headers.clone(), payload.clone())
+            .await?;
+        if let Some(answer) = answer {
+            return Ok(answer);
         }
+        let body = self.req.post(url, headers, payload).await?;
+        Ok((body, ResponseMeta::unknown(started.elapsed())))
     }
 
     ///
@@ -151,29 +702,14 @@ where
     ///     payload: Parámetros de la URL
     ///     is_get: Define si el método de encuesta es GET
     ///
-    pub fn build_signature_format<'a>(
+    #[cfg(test)]
+    pub fn build_signature_format(
         &self,
-        endpoint: &'a str,
+        endpoint: &str,
         payload: &HashMap<String, String>,
         is_get: bool,
     ) -> String {
-        // body = str(timestamp)+'/v1/orders/create' + '0.3' + 'ethclp' + '10000' + 'buy'
-        let mut signature: String = match SystemTime::now().duration_since(UNIX_EPOCH) {
-            Ok(n) => n.as_secs().to_string(),
-            Err(_) => "".to_string(),
-        };
-        // Adiciona /api_version/endpoint
-        signature += format!("/{}/{}", &self.api_version, &endpoint).as_str();
-        // si es POST se adicionan los valores de las llaves
-        if !is_get {
-            let mut keys = payload.keys().collect::<Vec<_>>();
-            // Ordena las llaves alfabéticamente
-            keys.sort();
-            for k in keys {
-                signature += payload.get(k).unwrap();
-            }
-        }
-        signature
+        signing::message(self.timestamp(), &self.api_version(), endpoint, payload, is_get)
     }
 
     ///
@@ -182,16 +718,14 @@ where
     /// Argumentos
     ///     msg: cadena de texto que se requiere firmar
     ///
-    pub fn sign_msg<'a>(&self, msg: &'a str) -> String {
-        let s_key = Key::new(HMAC_SHA384, self.secret_key.as_bytes());
-        let sign = sign(&s_key, msg.as_bytes());
-
-        let mut output = String::new();
-        for byte in sign.as_ref() {
-            write!(output, "{:02x}", byte).unwrap();
-        }
-
-        output
+    pub fn sign_msg(&self, msg: &str) -> String {
+        signing::sign(self.secret_key.expose(), msg)
+    }
+    ///
+    /// Copia de las cabeceras que llevan todas las peticiones
+    ///
+    fn headers(&self) -> HeaderMap {
+        (*self.default_headers).clone()
     }
     ///
     /// Conforma los headers para realizar la petición al servidor, en caso de no ser publica
@@ -203,30 +737,138 @@ where
     ///     is_public: indica si el endpoint es public
     ///     is_get: Define si el método de encuesta es GET
     ///
-    fn build_headers<'a>(
+    fn build_headers(
         &self,
-        endpoint: &'a str,
+        endpoint: &str,
         payload: &HashMap<String, String>,
         is_public: bool,
         is_get: bool,
     ) -> HeaderMap {
-        let mut headers = HeaderMap::new();
+        let mut headers = self.headers();
         if !is_public {
-            let msg_to_sign = self.build_signature_format(endpoint, &payload, is_get);
-            let timestamp = msg_to_sign.split("/").collect::<Vec<&str>>();
-            headers.insert(
-                X_MKT_APIKEY,
-                HeaderValue::from_str(self.api_key.as_str()).unwrap(),
-            );
-            headers.insert(
-                X_MKT_SIGNATURE,
-                HeaderValue::from_str(self.sign_msg(msg_to_sign.as_str()).as_str()).unwrap(),
-            );
-            headers.insert(
-                X_MKT_TIMESTAMP,
-                HeaderValue::from_str(timestamp.first().unwrap()).unwrap(),
-            );
+            let timestamp = self.timestamp();
+            let msg_to_sign =
+                signing::message(timestamp, &self.api_version(), endpoint, payload, is_get);
+            headers.insert(X_MKT_APIKEY, sensitive(self.api_key.expose()));
+            headers.insert(X_MKT_SIGNATURE, sensitive(&self.sign_msg(msg_to_sign.as_str())));
+            headers.insert(X_MKT_TIMESTAMP, HeaderValue::from(timestamp));
         }
         headers
     }
 }
+
+///
+/// Interpreta la respuesta de un endpoint de la versión 1, con el sobre de
+/// error del exchange
+///
+/// Argumentos
+///     path: Ruta de la petición
+///     body: Cuerpo de la respuesta
+///
+fn parse_edge<T: DeserializeOwned>(path: &str, body: &str) -> CryptoMktResult<T> {
+    if let Some(err) = error_envelope(path, body) {
+        return Err(err);
+    }
+    serde_json::from_str(body).map_err(|e| {
+        let err = CryptoMktError::malformed(path, body.to_string(), &e);
+        error!(target: "cryptomkt", "Malformed response: {}", err);
+        err
+    })
+}
+
+///
+/// Espera `request` como máximo `timeout`, fallando con `Timeout` al agotarse.
+/// Al fallar se descarta la petición en curso
+///
+async fn bounded<F, T>(timeout: Option<Duration>, request: F) -> CryptoMktResult<T>
+where
+    F: Future<Output = CryptoMktResult<T>>,
+{
+    match timeout {
+        Some(timeout) => rt::timeout(timeout, request)
+            .await
+            .unwrap_or_else(|_| Err(CryptoMktErrorType::Timeout.into())),
+        None => request.await,
+    }
+}
+
+///
+/// Milisegundos desde UNIX_EPOCH, negativos para fechas anteriores
+///
+fn unix_millis(time: SystemTime) -> i64 {
+    match time.duration_since(UNIX_EPOCH) {
+        Ok(n) => n.as_millis() as i64,
+        Err(e) => -(e.duration().as_millis() as i64),
+    }
+}
+
+///
+/// Cabecera con la API key o la firma, marcada como sensible para que no
+/// aparezca en los `Debug` de las cabeceras, p. ej. desde un middleware
+///
+/// Argumentos
+///     value: Valor de la cabecera
+///
+fn sensitive(value: &str) -> HeaderValue {
+    let mut value =
+        HeaderValue::from_str(value).expect("keys and signatures are valid header values");
+    value.set_sensitive(true);
+    value
+}
+
+///
+/// Cabecera `Authorization` de las versiones 2 y 3 del API: autenticación
+/// básica con la API key como usuario y la secret key como contraseña. Se marca
+/// como sensible para que no aparezca en los `Debug`
+///
+/// Argumentos
+///     api_key: API Key
+///     secret_key: Secret Key
+///
+pub(crate) fn basic_auth(api_key: &str, secret_key: &str) -> HeaderValue {
+    let credentials = BASE64.encode(format!("{}:{}", api_key, secret_key));
+    let mut value = HeaderValue::from_str(&format!("Basic {}", credentials))
+        .expect("base64 is a valid header value");
+    value.set_sensitive(true);
+    value
+}
+
+///
+/// Cabecera `Authorization` del API v3 con firma HS256:
+/// `HS256 base64(api_key:firma:timestamp)`, donde la firma es el HMAC-SHA256 en
+/// hexadecimal del método, la ruta, la query (con `?`), el cuerpo y el timestamp
+///
+/// Argumentos
+///     api_key: API Key
+///     secret_key: Secret Key
+///     method: `GET`, `POST` o `DELETE`
+///     url: URL de la petición
+///     body: Cuerpo enviado, vacío si no hay
+///     timestamp: Milisegundos desde epoch
+///
+pub(crate) fn hs256_auth(
+    api_key: &str,
+    secret_key: &str,
+    method: &str,
+    url: &Url,
+    body: &str,
+    timestamp: i64,
+) -> HeaderValue {
+    let mut msg = format!("{}{}", method, url.path());
+    if let Some(query) = url.query() {
+        msg += "?";
+        msg += query;
+    }
+    msg += body;
+    msg += &timestamp.to_string();
+
+    let mut signature = String::new();
+    for byte in hmac_sha256(secret_key.as_bytes(), msg.as_bytes()) {
+        write!(signature, "{:02x}", byte).unwrap();
+    }
+    let credentials = BASE64.encode(format!("{}:{}:{}", api_key, signature, timestamp));
+    let mut value = HeaderValue::from_str(&format!("HS256 {}", credentials))
+        .expect("base64 is a valid header value");
+    value.set_sensitive(true);
+    value
+}
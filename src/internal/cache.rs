@@ -0,0 +1,71 @@
+//!
+//! Caché opcional de las respuestas de endpoints públicos que cambian poco,
+//! como el listado de mercados
+//!
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::internal::rt::Instant;
+
+///
+/// Respuestas guardadas por URL, cada endpoint con su propio vencimiento. Los
+/// endpoints sin vencimiento configurado no se guardan
+///
+#[derive(Debug, Default)]
+pub(crate) struct ResponseCache {
+    ttls: HashMap<String, Duration>,
+    /// Respuesta y momento en que vence, por URL
+    entries: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl ResponseCache {
+    ///
+    /// Guarda las respuestas de `endpoint` durante `ttl`
+    ///
+    /// Argumentos
+    ///     endpoint: Nombre del endpoint, p. ej. `market`
+    ///     ttl: Vigencia de cada respuesta
+    ///
+    pub fn set_ttl(&mut self, endpoint: &str, ttl: Duration) {
+        self.ttls.insert(endpoint.to_string(), ttl);
+    }
+
+    ///
+    /// Respuesta vigente de `endpoint` para la URL `key`
+    ///
+    pub fn get(&self, endpoint: &str, key: &str) -> Option<String> {
+        self.ttls.get(endpoint)?;
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(key) {
+            Some((expires, body)) if Instant::now() < *expires => Some(body.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    ///
+    /// Guarda la respuesta de `endpoint` para la URL `key`, si el endpoint
+    /// tiene vencimiento configurado, descartando las que ya vencieron
+    ///
+    pub fn insert(&self, endpoint: &str, key: &str, body: &str) {
+        if let Some(ttl) = self.ttls.get(endpoint) {
+            let now = Instant::now();
+            let mut entries = self.entries.lock().unwrap();
+            // Las URLs que no se vuelven a pedir no se quedan para siempre
+            entries.retain(|_, (expires, _)| now < *expires);
+            entries.insert(key.to_string(), (now + *ttl, body.to_string()));
+        }
+    }
+
+    ///
+    /// Descarta todas las respuestas guardadas
+    ///
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+}
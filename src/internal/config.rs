@@ -0,0 +1,121 @@
+//!
+//! Carga de credenciales desde variables de entorno y archivos de perfiles
+//!
+use std::env;
+#[cfg(feature = "config")]
+use std::collections::HashMap;
+#[cfg(feature = "config")]
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::secret::SecretString;
+
+/// Variable de entorno con la API key
+pub(crate) const API_KEY_VAR: &str = "CRYPTOMKT_API_KEY";
+/// Variable de entorno con el secret
+pub(crate) const API_SECRET_VAR: &str = "CRYPTOMKT_API_SECRET";
+/// Variable de entorno con la URL del entorno de pruebas
+pub(crate) const SANDBOX_URL_VAR: &str = "CRYPTOMKT_SANDBOX_URL";
+
+///
+/// Credenciales y opciones de conexión de una cuenta
+///
+#[derive(Deserialize, Clone, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub(crate) struct Profile {
+    pub api_key: String,
+    pub api_secret: SecretString,
+    #[serde(default)]
+    pub base_url: Option<String>,
+    #[serde(default)]
+    pub api_version: Option<String>,
+}
+
+/// El secret no se muestra, para que no termine en los logs
+impl std::fmt::Debug for Profile {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Profile")
+            .field("api_key", &self.api_key)
+            .field("api_secret", &self.api_secret)
+            .field("base_url", &self.base_url)
+            .field("api_version", &self.api_version)
+            .finish()
+    }
+}
+
+impl Profile {
+    ///
+    /// Cuenta del entorno de pruebas cuya URL está en `CRYPTOMKT_SANDBOX_URL`
+    ///
+    /// Argumentos
+    ///     api_key: API key del entorno de pruebas
+    ///     api_secret: Secret del entorno de pruebas
+    ///
+    pub(crate) fn sandbox(api_key: &str, api_secret: &str) -> CryptoMktResult<Self> {
+        Ok(Profile {
+            api_key: api_key.to_string(),
+            api_secret: SecretString::new(api_secret),
+            base_url: Some(var(SANDBOX_URL_VAR)?),
+            api_version: None,
+        })
+    }
+
+    ///
+    /// Lee las credenciales de `CRYPTOMKT_API_KEY` y `CRYPTOMKT_API_SECRET`
+    ///
+    pub(crate) fn from_env() -> CryptoMktResult<Self> {
+        Ok(Profile {
+            api_key: var(API_KEY_VAR)?,
+            api_secret: SecretString::new(&var(API_SECRET_VAR)?),
+            base_url: None,
+            api_version: None,
+        })
+    }
+
+    ///
+    /// Lee un perfil de un archivo TOML con una tabla por cuenta
+    ///
+    /// Argumentos
+    ///     path: Ruta del archivo
+    ///     name: Nombre de la tabla del perfil
+    ///
+    #[cfg(feature = "config")]
+    pub(crate) fn from_file(path: &Path, name: &str) -> CryptoMktResult<Self> {
+        let contents = std::fs::read_to_string(path).map_err(|e| {
+            CryptoMktErrorType::InvalidConfiguration(format!("{}: {}", path.display(), e))
+        })?;
+        Profile::from_toml(&contents, name, &path.display().to_string())
+    }
+
+    ///
+    /// Interpreta el contenido de un archivo de perfiles
+    ///
+    /// Argumentos
+    ///     contents: Contenido TOML
+    ///     name: Nombre de la tabla del perfil
+    ///     source: Origen del contenido, para los mensajes de error
+    ///
+    #[cfg(feature = "config")]
+    pub(crate) fn from_toml(contents: &str, name: &str, source: &str) -> CryptoMktResult<Self> {
+        let invalid = |msg: String| {
+            CryptoMktErrorType::InvalidConfiguration(format!("{}: {}", source, msg))
+        };
+        let mut profiles: HashMap<String, Profile> =
+            toml::from_str(contents).map_err(|e| invalid(e.message().to_string()))?;
+        profiles
+            .remove(name)
+            .ok_or_else(|| invalid(format!("no profile named {:?}", name)).into())
+    }
+}
+
+///
+/// Lee una variable de entorno obligatoria
+///
+fn var(name: &str) -> CryptoMktResult<String> {
+    match env::var(name) {
+        Ok(value) if !value.is_empty() => Ok(value),
+        _ => Err(CryptoMktErrorType::InvalidConfiguration(format!("{} is not set", name)).into()),
+    }
+}
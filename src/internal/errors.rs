@@ -1,4 +1,10 @@
-#[derive(Debug)]
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::Duration;
+use thiserror::Error;
+
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
 pub enum CryptoMktErrorType {
     // 401 => Tu API key es errónea
     RequestUnauthorized,
@@ -24,7 +30,300 @@ pub enum CryptoMktErrorType {
     BadRequest,
     //
     MalformedResource,
+    // El mercado no está en el listado de mercados del exchange
+    UnknownMarket(String),
+    // Ninguna cuenta del registro tiene esa etiqueta
+    UnknownAccount(String),
+    // Fallo en la conexión WebSocket o suscripción rechazada
+    WebSocket(String),
+    // Configuración inválida del cliente (URL, proxy, ...)
+    InvalidConfiguration(String),
+    // La firma de una notificación del exchange no corresponde al secret
+    InvalidSignature,
+    // Se agotó el plazo de espera sin que la operación terminara
+    Timeout,
+    // Endpoint privado llamado desde un cliente sin credenciales
+    MissingCredentials,
+    // 200 con {"status": "error"}: el exchange rechazó la operación
+    RequestRejected,
+    // La llamada se canceló con su CancellationToken
+    Cancelled,
+    // No se pudo generar el código QR de una orden de pago
+    QrCode(String),
+}
+
+///
+/// Error returned by every call of the crate
+///
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum CryptoMktError {
+    ///
+    /// The exchange answered, but with an error status or with a body that
+    /// couldn't be understood
+    ///
+    #[error("{endpoint}: {kind:?} (HTTP {status}): {}", message.as_deref().unwrap_or(body))]
+    Http {
+        /// Kind of error
+        kind: CryptoMktErrorType,
+        /// HTTP status code
+        status: u16,
+        /// Path of the request, e.g. `/v1/market`
+        endpoint: String,
+        /// Raw response body
+        body: String,
+        /// `message` of the `{"status": "error", "message": ...}` payload, when
+        /// present. Otherwise, for `MalformedResource`, why the body couldn't be
+        /// deserialized, followed by its first bytes
+        message: Option<String>,
+        /// Wait requested by the `Retry-After` header of the response
+        retry_after: Option<Duration>,
+    },
+    ///
+    /// The exchange answered with HTTP 200 but an error envelope,
+    /// `{"status": "error", "message": ...}`, e.g. for an order below the
+    /// minimum amount. Its kind is `RequestRejected`
+    ///
+    #[error(
+        "{endpoint}: {message}{}",
+        code.as_ref().map(|code| format!(" (code {})", code)).unwrap_or_default()
+    )]
+    Api {
+        /// Path of the request, e.g. `/v1/orders/create`
+        endpoint: String,
+        /// Reason given by the exchange
+        message: String,
+        /// `code` of the envelope, when present
+        code: Option<String>,
+    },
+    ///
+    /// The request didn't get an answer from the exchange, or was rejected
+    /// before being sent
+    ///
+    #[error("{0:?}")]
+    Other(CryptoMktErrorType),
+}
+
+impl CryptoMktError {
+    ///
+    /// Crea el error de una respuesta del servidor, extrayendo el mensaje del
+    /// cuerpo si viene en el formato de error del exchange
+    ///
+    /// Argumentos
+    ///     kind: Tipo de error
+    ///     status: Código HTTP
+    ///     endpoint: Ruta de la petición
+    ///     body: Cuerpo de la respuesta
+    ///
+    pub(crate) fn http(kind: CryptoMktErrorType, status: u16, endpoint: &str, body: String) -> Self {
+        CryptoMktError::Http {
+            kind,
+            status,
+            endpoint: endpoint.to_string(),
+            message: error_message(&body),
+            body,
+            retry_after: None,
+        }
+    }
+
+    ///
+    /// Crea el error de una respuesta que no se pudo deserializar. Si el
+    /// exchange no envió un mensaje, el mensaje es el error de serde junto con
+    /// el comienzo del cuerpo
+    ///
+    /// Argumentos
+    ///     endpoint: Ruta de la petición
+    ///     body: Cuerpo de la respuesta
+    ///     error: Error de serde_json
+    ///
+    pub(crate) fn malformed(endpoint: &str, body: String, error: &serde_json::Error) -> Self {
+        let detail = format!("{} in {:?}", error, snippet(&body));
+        let kind = CryptoMktErrorType::MalformedResource;
+        let mut err = CryptoMktError::http(kind, 200, endpoint, body);
+        if let CryptoMktError::Http { message, .. } = &mut err {
+            message.get_or_insert(detail);
+        }
+        err
+    }
+
+    ///
+    /// Añade la espera indicada por la cabecera `Retry-After`
+    ///
+    pub(crate) fn with_retry_after(mut self, wait: Option<Duration>) -> Self {
+        if let CryptoMktError::Http { retry_after, .. } = &mut self {
+            *retry_after = wait;
+        }
+        self
+    }
+
+    ///
+    /// Kind of error
+    ///
+    pub fn kind(&self) -> &CryptoMktErrorType {
+        match self {
+            CryptoMktError::Http { kind, .. } => kind,
+            CryptoMktError::Api { .. } => &CryptoMktErrorType::RequestRejected,
+            CryptoMktError::Other(kind) => kind,
+        }
+    }
+
+    ///
+    /// HTTP status code of the response, if there was one
+    ///
+    pub fn status(&self) -> Option<u16> {
+        match self {
+            CryptoMktError::Http { status, .. } => Some(*status),
+            CryptoMktError::Api { .. } => Some(200),
+            CryptoMktError::Other(_) => None,
+        }
+    }
+
+    ///
+    /// Path of the request that failed, if it reached the exchange
+    ///
+    pub fn endpoint(&self) -> Option<&str> {
+        match self {
+            CryptoMktError::Http { endpoint, .. } => Some(endpoint),
+            CryptoMktError::Api { endpoint, .. } => Some(endpoint),
+            CryptoMktError::Other(_) => None,
+        }
+    }
+
+    ///
+    /// Raw body of the response, if there was one
+    ///
+    pub fn body(&self) -> Option<&str> {
+        match self {
+            CryptoMktError::Http { body, .. } => Some(body),
+            CryptoMktError::Api { .. } => None,
+            CryptoMktError::Other(_) => None,
+        }
+    }
+
+    ///
+    /// Message sent by the exchange explaining the error, if any. When a
+    /// response couldn't be deserialized and the exchange sent no message,
+    /// the serde error followed by the first bytes of the body, e.g.
+    /// ``missing field `bid` at line 1 column 42 in "{\"status\":...``
+    ///
+    pub fn message(&self) -> Option<&str> {
+        match self {
+            CryptoMktError::Http { message, .. } => message.as_deref(),
+            CryptoMktError::Api { message, .. } => Some(message),
+            CryptoMktError::Other(_) => None,
+        }
+    }
+
+    ///
+    /// `code` of the error envelope of a `RequestRejected` error, if any
+    ///
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            CryptoMktError::Api { code, .. } => code.as_deref(),
+            _ => None,
+        }
+    }
+
+    ///
+    /// Time the exchange asked to wait before retrying, from the `Retry-After`
+    /// header of a `RequestTooManyRequests` response
+    ///
+    pub fn retry_after(&self) -> Option<Duration> {
+        match self {
+            CryptoMktError::Http { retry_after, .. } => *retry_after,
+            CryptoMktError::Api { .. } => None,
+            CryptoMktError::Other(_) => None,
+        }
+    }
+}
+
+impl From<CryptoMktErrorType> for CryptoMktError {
+    fn from(kind: CryptoMktErrorType) -> Self {
+        CryptoMktError::Other(kind)
+    }
+}
+
+///
+/// Sobre de error de la versión 1 del API
+///
+#[derive(Deserialize)]
+struct ErrorEnvelope {
+    status: String,
+    #[serde(default)]
+    message: Value,
+    #[serde(default)]
+    code: Value,
+}
+
+///
+/// Devuelve el error si `body` es el sobre `{"status": "error", ...}` con el que
+/// el exchange rechaza una operación, aunque responda con código 200
+///
+/// Argumentos
+///     endpoint: Ruta de la petición
+///     body: Cuerpo de la respuesta
+///
+pub(crate) fn error_envelope(endpoint: &str, body: &str) -> Option<CryptoMktError> {
+    let envelope: ErrorEnvelope = serde_json::from_str(body).ok()?;
+    if envelope.status != "error" {
+        return None;
+    }
+    Some(CryptoMktError::Api {
+        endpoint: endpoint.to_string(),
+        message: match envelope.message {
+            Value::String(message) => message,
+            Value::Null => String::from("unknown error"),
+            message => message.to_string(),
+        },
+        code: match envelope.code {
+            Value::String(code) => Some(code),
+            Value::Null => None,
+            code => Some(code.to_string()),
+        },
+    })
+}
+
+/// Bytes del cuerpo incluidos en el detalle de un `MalformedResource`
+const BODY_SNIPPET_LEN: usize = 256;
+
+///
+/// Comienzo del cuerpo, cortado en un límite de carácter
+///
+fn snippet(body: &str) -> String {
+    if body.len() <= BODY_SNIPPET_LEN {
+        return body.to_string();
+    }
+    let mut end = BODY_SNIPPET_LEN;
+    while !body.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!("{}...", &body[..end])
+}
+
+///
+/// Extrae `message` de un cuerpo `{"status": "error", "message": ...}` o, en
+/// las versiones 2 y 3 del API, `{"error": {"message": ..., "description": ...}}`
+///
+fn error_message(body: &str) -> Option<String> {
+    let payload: Value = serde_json::from_str(body).ok()?;
+    if let Value::Object(error) = &payload["error"] {
+        let message = error.get("message").and_then(Value::as_str)?;
+        return match error.get("description").and_then(Value::as_str) {
+            Some(description) if !description.is_empty() => {
+                Some(format!("{}: {}", message, description))
+            }
+            _ => Some(message.to_string()),
+        };
+    }
+    if payload["status"] != "error" {
+        return None;
+    }
+    match &payload["message"] {
+        Value::String(message) => Some(message.clone()),
+        Value::Null => None,
+        message => Some(message.to_string()),
+    }
 }
 
-// Define alea generico al Result para  CryptoMktErrorType
-pub type CryptoMktResult<T> = Result<T, CryptoMktErrorType>;
+// Define alea generico al Result para  CryptoMktError
+pub type CryptoMktResult<T> = Result<T, CryptoMktError>;
@@ -0,0 +1,88 @@
+//!
+//! Backends de HMAC-SHA384 (y HMAC-SHA256 para el socket privado y el API v3) usados para
+//! firmar las peticiones
+//!
+//! Por defecto se usa `ring`. Con la feature `rust-hmac` (y sin `ring`) se usa
+//! la implementación en Rust puro de `hmac` + `sha2`. Ambos producen el mismo digest.
+//! En wasm32 `ring` no compila, así que siempre se usa `hmac` + `sha2`
+//!
+
+#[cfg(not(any(feature = "ring", feature = "rust-hmac", target_arch = "wasm32")))]
+compile_error!("cryptomkt needs a signing backend: enable the `ring` or `rust-hmac` feature");
+
+///
+/// Calcula HMAC-SHA384 con el backend activo
+///
+/// Argumentos
+///     key: Llave secreta
+///     msg: Mensaje a firmar
+///
+#[cfg(all(feature = "ring", not(target_arch = "wasm32")))]
+pub fn hmac_sha384(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    ring_hmac_sha384(key, msg)
+}
+
+///
+/// Calcula HMAC-SHA384 con el backend activo
+///
+/// Argumentos
+///     key: Llave secreta
+///     msg: Mensaje a firmar
+///
+#[cfg(not(all(feature = "ring", not(target_arch = "wasm32"))))]
+pub fn hmac_sha384(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    rust_hmac_sha384(key, msg)
+}
+
+///
+/// Calcula HMAC-SHA256 con el backend activo
+///
+/// Argumentos
+///     key: Llave secreta
+///     msg: Mensaje a firmar
+///
+#[cfg(all(feature = "ring", not(target_arch = "wasm32")))]
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    use ring::hmac::{sign, Key, HMAC_SHA256};
+
+    let s_key = Key::new(HMAC_SHA256, key);
+    sign(&s_key, msg).as_ref().to_vec()
+}
+
+///
+/// Calcula HMAC-SHA256 con el backend activo
+///
+/// Argumentos
+///     key: Llave secreta
+///     msg: Mensaje a firmar
+///
+#[cfg(not(all(feature = "ring", not(target_arch = "wasm32"))))]
+pub fn hmac_sha256(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha256;
+
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC acepta llaves de cualquier largo");
+    mac.update(msg);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// HMAC-SHA384 sobre `ring`
+#[cfg(all(feature = "ring", not(target_arch = "wasm32")))]
+pub fn ring_hmac_sha384(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    use ring::hmac::{sign, Key, HMAC_SHA384};
+
+    let s_key = Key::new(HMAC_SHA384, key);
+    sign(&s_key, msg).as_ref().to_vec()
+}
+
+/// HMAC-SHA384 sobre `hmac` + `sha2`. Con ambas features activas solo se usa en las pruebas
+#[cfg(any(feature = "rust-hmac", target_arch = "wasm32"))]
+#[cfg_attr(all(feature = "ring", not(target_arch = "wasm32")), allow(dead_code))]
+pub fn rust_hmac_sha384(key: &[u8], msg: &[u8]) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+    use sha2::Sha384;
+
+    let mut mac = Hmac::<Sha384>::new_from_slice(key).expect("HMAC acepta llaves de cualquier largo");
+    mac.update(msg);
+    mac.finalize().into_bytes().to_vec()
+}
@@ -0,0 +1,76 @@
+//!
+//! Métricas de la actividad del cliente
+//!
+//! Con la feature `metrics` cada petición al exchange se registra con la fachada
+//! `metrics`, que las entrega al exportador que instale la aplicación (p. ej.
+//! Prometheus). Sin la feature no se registra nada. Las etiquetas son el método
+//! y la ruta sin parámetros, para acotar su cardinalidad
+//!
+
+use std::time::Duration;
+
+use crate::internal::errors::CryptoMktResult;
+
+/// Peticiones terminadas, con la etiqueta `outcome` en `ok` o `error`
+#[cfg(feature = "metrics")]
+pub(crate) const REQUESTS: &str = "cryptomkt_requests_total";
+/// Peticiones fallidas, con la etiqueta `kind` del error
+#[cfg(feature = "metrics")]
+pub(crate) const ERRORS: &str = "cryptomkt_request_errors_total";
+/// Duración de las peticiones en segundos
+#[cfg(feature = "metrics")]
+pub(crate) const DURATION: &str = "cryptomkt_request_duration_seconds";
+/// Respuestas 429 del exchange, se reintenten o no
+#[cfg(feature = "metrics")]
+pub(crate) const RATE_LIMITED: &str = "cryptomkt_rate_limit_hits_total";
+
+///
+/// Registra el resultado de una petición
+///
+/// Argumentos
+///     method: `GET`, `POST` o `DELETE`
+///     endpoint: Ruta de la petición, sin parámetros
+///     result: Resultado del transporte
+///     elapsed: Duración de la petición
+///
+#[cfg(feature = "metrics")]
+pub(crate) fn record<T>(
+    method: &'static str,
+    endpoint: &str,
+    result: &CryptoMktResult<T>,
+    elapsed: Duration,
+) {
+    use crate::internal::errors::CryptoMktErrorType;
+    use metrics::{counter, histogram};
+
+    let endpoint = endpoint.to_string();
+    let outcome = if result.is_ok() { "ok" } else { "error" };
+    counter!(REQUESTS, "method" => method, "endpoint" => endpoint.clone(), "outcome" => outcome)
+        .increment(1);
+    histogram!(DURATION, "method" => method, "endpoint" => endpoint.clone())
+        .record(elapsed.as_secs_f64());
+
+    let e = match result {
+        Ok(_) => return,
+        Err(e) => e,
+    };
+    if *e.kind() == CryptoMktErrorType::RequestTooManyRequests {
+        counter!(RATE_LIMITED, "method" => method, "endpoint" => endpoint.clone()).increment(1);
+    }
+    // Los tipos con datos (`UnknownMarket(..)`) se agrupan por su nombre
+    let kind = format!("{:?}", e.kind());
+    let kind = kind.split('(').next().unwrap_or_default().to_string();
+    counter!(ERRORS, "method" => method, "endpoint" => endpoint, "kind" => kind).increment(1);
+}
+
+///
+/// Sin la feature `metrics` no se registra nada
+///
+#[cfg(not(feature = "metrics"))]
+pub(crate) fn record<T>(
+    _method: &'static str,
+    _endpoint: &str,
+    _result: &CryptoMktResult<T>,
+    _elapsed: Duration,
+) {
+}
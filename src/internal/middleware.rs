@@ -0,0 +1,316 @@
+use std::fmt::{self, Debug, Formatter};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use reqwest::header::HeaderMap;
+use reqwest::{Request, StatusCode, Url};
+use serde_json::Value;
+
+use crate::internal::secret::REDACTED;
+use crate::internal::trace::debug;
+
+/// Cabeceras con credenciales, además de las marcadas como sensibles
+const SECRET_HEADERS: [&str; 3] = ["x-mkt-apikey", "x-mkt-signature", "authorization"];
+/// Campos de formularios, parámetros y respuestas JSON que se enmascaran.
+/// `payment_id` es el memo de los depósitos de la v3
+const SECRET_FIELDS: [&str; 7] = [
+    "address",
+    "deposit_address",
+    "memo",
+    "payment_id",
+    "bank_account",
+    "api_key",
+    "signature",
+];
+
+///
+/// Hook on the HTTP requests sent to the exchange, registered with
+/// `ClientBuilder::middleware`. Useful for custom logging, metrics or extra
+/// headers such as request IDs.
+///
+/// `on_request` sees the request right before it's sent, after it has been
+/// signed, and `on_response` sees every response, successful or not, before
+/// the client interprets it. Both can modify what they get. Middlewares run in
+/// the order they were registered. They don't run for transports given to
+/// `ClientBuilder::http_transport`.
+///
+pub trait Middleware: Send + Sync {
+    ///
+    /// Called before `request` is sent
+    ///
+    fn on_request(&self, _request: &mut Request) {}
+
+    ///
+    /// Called when the response to a request to `url` arrives
+    ///
+    fn on_response(&self, _url: &Url, _response: &mut HttpResponse) {}
+}
+
+///
+/// Response of the exchange, as seen by a `Middleware`
+///
+#[derive(Debug, Clone)]
+pub struct HttpResponse {
+    /// Status code
+    pub status: StatusCode,
+    /// Headers
+    pub headers: HeaderMap,
+    /// Body
+    pub body: String,
+}
+
+///
+/// Middleware logging every request and response, headers and bodies
+/// included, at debug level with the `cryptomkt` target. Meant for
+/// troubleshooting: it can be left registered and switched off and on while
+/// the client runs.
+///
+/// API keys, signatures and `Authorization` headers are masked, as well as
+/// wallet addresses, memos (`payment_id` in the v3 API) and bank accounts in
+/// parameters, forms and JSON bodies, unless redaction is turned off. Clones share the switches, so a clone kept after
+/// registering it controls the logger of the client:
+///
+/// ```no_run
+/// use cryptomkt::{Client, DebugLogger};
+///
+/// let logger = DebugLogger::new();
+/// let client = Client::builder()
+///     .credentials("<API_KEY>", "<API SECRET>")
+///     .middleware(logger.clone())
+///     .build()
+///     .unwrap();
+///
+/// // ... once the problem is understood
+/// logger.set_enabled(false);
+/// ```
+///
+#[derive(Debug, Clone)]
+pub struct DebugLogger {
+    enabled: Arc<AtomicBool>,
+    redact: Arc<AtomicBool>,
+}
+
+impl DebugLogger {
+    ///
+    /// Logger switched on and masking secrets
+    ///
+    pub fn new() -> Self {
+        DebugLogger {
+            enabled: Arc::new(AtomicBool::new(true)),
+            redact: Arc::new(AtomicBool::new(true)),
+        }
+    }
+
+    ///
+    /// Start or stop logging, also for the clones
+    ///
+    pub fn set_enabled(&self, enabled: bool) {
+        self.enabled.store(enabled, Ordering::Relaxed);
+    }
+
+    ///
+    /// Whether requests and responses are being logged
+    ///
+    pub fn is_enabled(&self) -> bool {
+        self.enabled.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Mask credentials and wallet addresses or log them verbatim. Turning it
+    /// off writes secrets to the logs, only do it on a test account
+    ///
+    pub fn set_redaction(&self, redact: bool) {
+        self.redact.store(redact, Ordering::Relaxed);
+    }
+
+    ///
+    /// Whether credentials and wallet addresses are masked
+    ///
+    pub fn is_redacting(&self) -> bool {
+        self.redact.load(Ordering::Relaxed)
+    }
+
+    ///
+    /// Línea del log de una petición
+    ///
+    /// Argumentos
+    ///     request: Petición a enviar
+    ///
+    pub(crate) fn describe_request(&self, request: &Request) -> String {
+        let url = request.url();
+        let body = request
+            .body()
+            .and_then(|body| body.as_bytes())
+            .map(|bytes| self.form(&String::from_utf8_lossy(bytes)))
+            .unwrap_or_default();
+        format!(
+            "--> {} {}{} [{}] {}",
+            request.method(),
+            url.path(),
+            self.query(url),
+            self.headers(request.headers()),
+            body
+        )
+    }
+
+    ///
+    /// Línea del log de una respuesta
+    ///
+    /// Argumentos
+    ///     url: URL de la petición
+    ///     response: Respuesta recibida
+    ///
+    pub(crate) fn describe_response(&self, url: &Url, response: &HttpResponse) -> String {
+        format!(
+            "<-- {} {}{} [{}] {}",
+            response.status.as_u16(),
+            url.path(),
+            self.query(url),
+            self.headers(&response.headers),
+            self.json(&response.body)
+        )
+    }
+
+    ///
+    /// Cabeceras como `nombre: valor`, sin las credenciales si se enmascaran
+    ///
+    fn headers(&self, headers: &HeaderMap) -> String {
+        headers
+            .iter()
+            .map(|(name, value)| {
+                let secret = value.is_sensitive() || SECRET_HEADERS.contains(&name.as_str());
+                let value = match value.to_str() {
+                    _ if secret && self.is_redacting() => REDACTED,
+                    Ok(value) => value,
+                    Err(_) => "<binary>",
+                };
+                format!("{}: {}", name, value)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    ///
+    /// Parámetros de la URL decodificados, con `?` delante si hay
+    ///
+    fn query(&self, url: &Url) -> String {
+        match url.query() {
+            Some(query) => format!("?{}", self.form(query)),
+            None => String::new(),
+        }
+    }
+
+    ///
+    /// Formulario decodificado como `llave=valor&...`, enmascarando sus campos
+    /// secretos
+    ///
+    /// Argumentos
+    ///     encoded: Formulario codificado como URL
+    ///
+    fn form(&self, encoded: &str) -> String {
+        let pairs: Vec<(String, String)> = match serde_urlencoded::from_str(encoded) {
+            Ok(pairs) => pairs,
+            Err(_) => return encoded.to_string(),
+        };
+        pairs
+            .into_iter()
+            .map(|(key, value)| {
+                let value = if self.is_secret(&key) { REDACTED } else { &value };
+                format!("{}={}", key, value)
+            })
+            .collect::<Vec<_>>()
+            .join("&")
+    }
+
+    ///
+    /// Cuerpo de una respuesta, enmascarando los campos secretos si es JSON
+    ///
+    fn json(&self, body: &str) -> String {
+        if !self.is_redacting() {
+            return body.to_string();
+        }
+        match serde_json::from_str::<Value>(body) {
+            Ok(mut value) => {
+                mask(&mut value);
+                value.to_string()
+            }
+            Err(_) => body.to_string(),
+        }
+    }
+
+    fn is_secret(&self, field: &str) -> bool {
+        self.is_redacting() && SECRET_FIELDS.contains(&field)
+    }
+}
+
+impl Default for DebugLogger {
+    fn default() -> Self {
+        DebugLogger::new()
+    }
+}
+
+impl Middleware for DebugLogger {
+    fn on_request(&self, request: &mut Request) {
+        if self.is_enabled() {
+            debug!(target: "cryptomkt", "{}", self.describe_request(request));
+        }
+    }
+
+    fn on_response(&self, url: &Url, response: &mut HttpResponse) {
+        if self.is_enabled() {
+            debug!(target: "cryptomkt", "{}", self.describe_response(url, response));
+        }
+    }
+}
+
+///
+/// Reemplaza los valores de los campos secretos de `value`, a cualquier
+/// profundidad
+///
+fn mask(value: &mut Value) {
+    match value {
+        Value::Object(fields) => {
+            for (key, field) in fields.iter_mut() {
+                if SECRET_FIELDS.contains(&key.as_str()) && !field.is_null() {
+                    *field = Value::from(REDACTED);
+                } else {
+                    mask(field);
+                }
+            }
+        }
+        Value::Array(items) => items.iter_mut().for_each(mask),
+        _ => {}
+    }
+}
+
+///
+/// Middlewares registrados, en orden
+///
+#[derive(Clone, Default)]
+pub(crate) struct MiddlewareStack(Vec<Arc<dyn Middleware>>);
+
+impl MiddlewareStack {
+    pub(crate) fn push(&mut self, middleware: Arc<dyn Middleware>) {
+        self.0.push(middleware);
+    }
+
+    pub(crate) fn on_request(&self, request: &mut Request) {
+        for middleware in &self.0 {
+            middleware.on_request(request);
+        }
+    }
+
+    pub(crate) fn on_response(&self, url: &Url, response: &mut HttpResponse) {
+        for middleware in &self.0 {
+            middleware.on_response(url, response);
+        }
+    }
+}
+
+impl Debug for MiddlewareStack {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("MiddlewareStack")
+            .field("len", &self.0.len())
+            .finish()
+    }
+}
@@ -5,28 +5,64 @@
 //! al API junto con sus respectivas pruebas
 //!
 pub mod api;
+pub(crate) mod cache;
+pub(crate) mod config;
 pub mod errors;
+pub mod hmac;
+pub(crate) mod metrics;
+pub mod middleware;
 pub mod models;
+pub mod pagination;
+pub mod paper;
+#[cfg(feature = "qr")]
+pub(crate) mod qr;
+pub mod ratelimit;
 pub mod request;
 pub mod response;
+pub(crate) mod rt;
+pub(crate) mod secret;
+pub(crate) mod signing;
+pub(crate) mod streaming;
+pub mod timestamp;
+pub(crate) mod trace;
 
 #[cfg(test)]
 mod tests {
+    use crate::api::RequestMethod;
+    use crate::client::Client;
     use crate::internal::api::Api;
-    use crate::internal::errors::CryptoMktResult;
-    use crate::internal::request::HttpRequest;
+    use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+    use crate::internal::middleware::{DebugLogger, HttpResponse, Middleware};
+    use crate::internal::models::{PaymentStatus, Trade};
+    use crate::internal::pagination::{collect_pages, stream_pages};
+    use crate::internal::ratelimit::{RateLimit, RateLimitBehavior, RateLimiter, TokenBucket};
+    use crate::internal::secret::SecretString;
+    use crate::internal::signing;
+    use crate::internal::streaming::DataSplitter;
+    use crate::internal::timestamp;
+    use crate::internal::request::{
+        ConnectionStatus, CryptoMktRequest, HttpRequest, Transport, TransportStats,
+    };
     use async_trait::async_trait;
+    use futures::StreamExt;
     use reqwest::header::HeaderMap;
     use reqwest::Url;
     use serde_json::json;
-    use std::collections::HashMap;
+    use std::collections::{HashMap, VecDeque};
+    use std::sync::Arc;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+    use tokio::sync::oneshot;
+    use tokio_util::sync::CancellationToken;
 
     use crate::response::{
-        BalanceResponse, BookResponse, EmptyResponse, MarketResponse, OrderResponse,
-        OrdersInstantResponse, SimpleOrderResponse, TickerResponse, TradeResponse,
+        AccountResponse, BalanceResponse, BookResponse, EmptyResponse, MarketResponse,
+        OrderResponse, OrdersInstantResponse, PricesResponse, SimpleOrderResponse,
+        TickerResponse, TradeResponse,
     };
-    const API_KEY: &'static str = "FS24FJ7";
-    const SECRET_KEY: &'static str = "SFT23GSD";
+    const API_KEY: &str = "FS24FJ7";
+    const SECRET_KEY: &str = "SFT23GSD";
 
     ///
     ///
@@ -35,6 +71,7 @@ mod tests {
     struct MockRequest {
         resp_for_get: String,
         resp_for_post: String,
+        server_time: Option<SystemTime>,
     }
 
     impl MockRequest {
@@ -45,8 +82,17 @@ mod tests {
             MockRequest {
                 resp_for_get: resp_for_get.to_string(),
                 resp_for_post: resp_for_post.to_string(),
+                server_time: None,
             }
         }
+
+        ///
+        /// Hora que reporta el servidor simulado
+        ///
+        pub fn with_server_time(mut self, server_time: SystemTime) -> Self {
+            self.server_time = Some(server_time);
+            self
+        }
     }
 
     #[async_trait]
@@ -75,6 +121,10 @@ mod tests {
         ) -> CryptoMktResult<String> {
             Ok(self.resp_for_post.clone())
         }
+
+        async fn server_time(&self, _url: Url) -> CryptoMktResult<Option<SystemTime>> {
+            Ok(self.server_time)
+        }
     }
     ///
     /// Configura la instancia de API para los diferentes Test que no requieren de
@@ -96,6 +146,178 @@ mod tests {
         assert_eq!(api.api_version(), "v1");
     }
 
+    #[test]
+    fn client_is_shareable_across_tasks() {
+        fn assert_shareable<T: Clone + Send + Sync + 'static>() {}
+        assert_shareable::<Client>();
+        assert_shareable::<crate::market::Market>();
+        assert_shareable::<crate::api::CryptoMktApi>();
+    }
+
+    #[test]
+    fn debug_hides_credentials() {
+        let debug = format!("{:?}", setup_test());
+        assert!(!debug.contains(API_KEY));
+        assert!(!debug.contains(SECRET_KEY));
+        assert!(debug.contains("api.cryptomkt.com"));
+    }
+
+    #[test]
+    fn credentials_from_env() {
+        use crate::internal::config::{Profile, API_KEY_VAR, API_SECRET_VAR};
+
+        std::env::set_var(API_KEY_VAR, API_KEY);
+        std::env::remove_var(API_SECRET_VAR);
+        let err = Profile::from_env().unwrap_err();
+        assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(msg) if msg.contains(API_SECRET_VAR)));
+
+        std::env::set_var(API_SECRET_VAR, SECRET_KEY);
+        let profile = Profile::from_env().unwrap();
+        assert_eq!((profile.api_key.as_str(), profile.api_secret.expose()), (API_KEY, SECRET_KEY));
+        assert!(!format!("{:?}", profile).contains(SECRET_KEY));
+    }
+
+    #[test]
+    fn sandbox_url_from_env() {
+        use crate::internal::config::SANDBOX_URL_VAR;
+
+        std::env::remove_var(SANDBOX_URL_VAR);
+        let err = Client::sandbox(API_KEY, SECRET_KEY).unwrap_err();
+        assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(msg) if msg.contains(SANDBOX_URL_VAR)));
+
+        std::env::set_var(SANDBOX_URL_VAR, "http://localhost:8080");
+        let client = Client::sandbox(API_KEY, SECRET_KEY).unwrap();
+        assert_eq!(client.api.domain(), "http://localhost:8080/");
+    }
+
+    #[cfg(feature = "config")]
+    #[test]
+    fn credentials_from_profile_file() {
+        use crate::internal::config::Profile;
+
+        let contents = r#"
+            [default]
+            api_key = "FS24FJ7"
+            api_secret = "SFT23GSD"
+
+            [staging]
+            api_key = "STG"
+            api_secret = "STGSECRET"
+            base_url = "https://staging.example.com/"
+        "#;
+        let profile = Profile::from_toml(contents, "staging", "profiles.toml").unwrap();
+        assert_eq!(profile.api_key, "STG");
+        assert_eq!(profile.base_url.as_deref(), Some("https://staging.example.com/"));
+        assert_eq!(Profile::from_toml(contents, "default", "").unwrap().base_url, None);
+
+        let err = Profile::from_toml(contents, "prod", "profiles.toml").unwrap_err();
+        assert!(format!("{}", err).contains("profiles.toml"));
+
+        let path = std::env::temp_dir().join(format!("cryptomkt-profiles-{}.toml", std::process::id()));
+        std::fs::write(&path, contents).unwrap();
+        let client = Client::from_config(&path, "default");
+        std::fs::remove_file(&path).unwrap();
+        assert!(client.is_ok());
+        assert!(Client::from_config(&path, "default").is_err());
+    }
+
+    #[cfg(feature = "metrics")]
+    #[test]
+    fn requests_are_recorded_as_metrics() {
+        use metrics::{Counter, Gauge, Histogram, Key, KeyName, Metadata, Recorder, SharedString, Unit};
+        use std::sync::Mutex;
+
+        /// Guarda el nombre y las etiquetas de cada métrica registrada
+        #[derive(Default)]
+        struct KeyRecorder(Mutex<Vec<String>>);
+
+        impl KeyRecorder {
+            fn push(&self, key: &Key) {
+                let labels: Vec<_> = key.labels().map(|l| format!("{}={}", l.key(), l.value())).collect();
+                self.0.lock().unwrap().push(format!("{}{{{}}}", key.name(), labels.join(",")));
+            }
+        }
+
+        impl Recorder for KeyRecorder {
+            fn describe_counter(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_gauge(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn describe_histogram(&self, _: KeyName, _: Option<Unit>, _: SharedString) {}
+            fn register_counter(&self, key: &Key, _: &Metadata<'_>) -> Counter {
+                self.push(key);
+                Counter::noop()
+            }
+            fn register_gauge(&self, key: &Key, _: &Metadata<'_>) -> Gauge {
+                self.push(key);
+                Gauge::noop()
+            }
+            fn register_histogram(&self, key: &Key, _: &Metadata<'_>) -> Histogram {
+                self.push(key);
+                Histogram::noop()
+            }
+        }
+
+        let recorder = KeyRecorder::default();
+        metrics::with_local_recorder(&recorder, || {
+            let elapsed = Duration::from_millis(20);
+            crate::internal::metrics::record("GET", "/v1/market", &Ok(String::new()), elapsed);
+            let limited = CryptoMktError::http(CryptoMktErrorType::RequestTooManyRequests, 429, "/v1/book", String::new());
+            crate::internal::metrics::record("GET", "/v1/book", &Err::<String, _>(limited), elapsed);
+        });
+        let keys = recorder.0.into_inner().unwrap();
+        assert!(keys.contains(&"cryptomkt_requests_total{method=GET,endpoint=/v1/market,outcome=ok}".to_string()));
+        assert!(keys.contains(&"cryptomkt_request_duration_seconds{method=GET,endpoint=/v1/market}".to_string()));
+        assert!(keys.contains(&"cryptomkt_rate_limit_hits_total{method=GET,endpoint=/v1/book}".to_string()));
+        assert!(keys.contains(
+            &"cryptomkt_request_errors_total{method=GET,endpoint=/v1/book,kind=RequestTooManyRequests}".to_string()
+        ));
+    }
+
+    #[test]
+    fn order_and_book_sides() {
+        use crate::market::{BookSide, ReportedSide, Side};
+
+        assert_eq!("SELL".parse::<Side>().unwrap(), Side::Sell);
+        assert!("hold".parse::<Side>().is_err());
+        assert_eq!(Side::Buy.to_string(), "buy");
+        assert_eq!(Side::Buy.opposite(), Side::Sell);
+        assert_eq!(serde_json::to_string(&Side::Sell).unwrap(), r#""sell""#);
+        assert_eq!(serde_json::from_str::<Side>(r#""buy""#).unwrap(), Side::Buy);
+
+        assert_eq!(BookSide::from(Side::Sell), BookSide::Asks);
+        assert_eq!(BookSide::Bids.side(), Side::Buy);
+        assert_eq!("bids".parse::<BookSide>().unwrap().to_string(), "bids");
+        assert_eq!(serde_json::to_string(&BookSide::Asks).unwrap(), r#""asks""#);
+
+        // Un lado desconocido no impide leer la respuesta
+        let side: ReportedSide = serde_json::from_str(r#""BUY""#).unwrap();
+        assert_eq!(side, Side::Buy);
+        let side: ReportedSide = serde_json::from_str(r#""short""#).unwrap();
+        assert_eq!(side, ReportedSide::Unknown("short".to_string()));
+        assert_eq!(side.side(), None);
+        assert_eq!(serde_json::to_string(&side).unwrap(), r#""short""#);
+    }
+
+    #[test]
+    fn currencies_and_market_pairs() {
+        use crate::internal::models::{Currency, MarketPair};
+
+        assert_eq!(Currency::from("clp"), Currency::Clp);
+        assert_eq!(Currency::from("usdt"), Currency::Other("USDT".to_string()));
+        assert!(Currency::Eur.is_fiat() && !Currency::Eth.is_fiat());
+
+        assert_eq!("ethclp".parse::<MarketPair>().unwrap(), MarketPair::ETHCLP);
+        assert_eq!("ETHBTC".parse::<MarketPair>().unwrap(), MarketPair::ETHBTC);
+        let pair: MarketPair = "USDTCLP".parse().unwrap();
+        assert_eq!(pair.base, Currency::Other("USDT".to_string()));
+        assert_eq!(pair.to_string(), "USDTCLP");
+        assert_eq!("BTCUSDT".parse::<MarketPair>().unwrap().quote.code(), "USDT");
+        let err = "FOOBAR".parse::<MarketPair>().unwrap_err();
+        assert_eq!(*err.kind(), CryptoMktErrorType::UnknownMarket("FOOBAR".to_string()));
+
+        assert_eq!(serde_json::to_string(&MarketPair::BTCEUR).unwrap(), r#""BTCEUR""#);
+        assert_eq!(serde_json::from_str::<Currency>(r#""eth""#).unwrap(), Currency::Eth);
+    }
+
     #[tokio::test]
     async fn build_url() {
         let api = setup_test();
@@ -105,11 +327,38 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn custom_domain_and_version() {
+        let api = setup_test();
+        api.set_domain("https://staging.example.com").unwrap();
+        api.set_api_version("/v2/").unwrap();
+        assert_eq!(
+            api.build_url("market", &HashMap::new()).as_str(),
+            "https://staging.example.com/v2/market"
+        );
+        let resp = api.build_signature_format("orders/active", &HashMap::new(), true);
+        assert!(resp.ends_with("/v2/orders/active"));
+
+        assert!(api.set_api_version("").is_err());
+        assert!(api.set_api_version("v1/orders").is_err());
+        assert_eq!(api.api_version(), "v2");
+    }
+
+    #[test]
+    fn domain_and_version_are_shared_by_clones() {
+        let api = crate::CryptoMktApi::new(API_KEY, SECRET_KEY);
+        let clone = api.clone();
+        api.set_domain("https://staging.example.com/").unwrap();
+        api.set_version("v2").unwrap();
+        assert_eq!(clone.domain(), "https://staging.example.com/");
+        assert_eq!(clone.version(), "v2");
+    }
+
     #[tokio::test]
     async fn build_api_signature_format() {
         let api = setup_test();
         let resp = api.build_signature_format("orders/active", &HashMap::new(), true);
-        assert_eq!(resp.ends_with("/v1/orders/active"), true);
+        assert!(resp.ends_with("/v1/orders/active"));
     }
 
     #[tokio::test]
@@ -122,7 +371,7 @@ mod tests {
         params.insert("type".to_string(), "buy".to_string());
         let resp = api.build_signature_format("orders/create", &params, true);
 
-        assert_eq!(resp.ends_with("/v1/orders/create"), true);
+        assert!(resp.ends_with("/v1/orders/create"));
     }
 
     #[tokio::test]
@@ -137,6 +386,111 @@ mod tests {
         assert_ne!(resp.find("/v1/orders/create0.3ethclp10000buy"), None);
     }
 
+    #[tokio::test]
+    async fn build_api_signature_format_applies_clock_offset() {
+        let server_time = SystemTime::now() + Duration::from_secs(3600);
+        let mock_transport = MockRequest::new("", "").with_server_time(server_time);
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(mock_transport));
+
+        api.sync_clock().await.unwrap();
+        let resp = api.build_signature_format("orders/active", &HashMap::new(), true);
+        let timestamp: u64 = resp.split('/').next().unwrap().parse().unwrap();
+        let expected = server_time.duration_since(UNIX_EPOCH).unwrap().as_millis() as u64;
+
+        assert!(
+            timestamp.abs_diff(expected) <= 1000,
+            "El timestamp no está alineado con el servidor: {} esperado: {}",
+            timestamp,
+            expected
+        );
+    }
+
+    #[test]
+    fn signature_timestamps_are_unique_in_bursts() {
+        let transport = Transport::new(CryptoMktRequest::new());
+        let api = Api::<Transport>::new(API_KEY, SECRET_KEY, Box::new(transport));
+        let clone = api.clone();
+        let timestamps: Vec<u64> = (0..100)
+            .map(|i| {
+                let api = if i % 2 == 0 { &api } else { &clone };
+                let resp = api.build_signature_format("orders/active", &HashMap::new(), true);
+                resp.split('/').next().unwrap().parse().unwrap()
+            })
+            .collect();
+
+        assert!(timestamps.windows(2).all(|pair| pair[0] < pair[1]), "{:?}", timestamps);
+    }
+
+    #[tokio::test]
+    async fn transport_reports_reconnection_after_repeated_failures() {
+        let transport = CryptoMktRequest::new();
+        // Puerto cerrado: la conexión se rechaza sin salir de la máquina
+        let url = Url::parse("http://127.0.0.1:1/v1/market").unwrap();
+
+        assert_eq!(transport.connection_status(), ConnectionStatus::Healthy);
+        transport.get(url.clone(), HeaderMap::new()).await.unwrap_err();
+        assert_eq!(
+            transport.connection_status(),
+            ConnectionStatus::Degraded {
+                consecutive_failures: 1
+            }
+        );
+        transport.get(url.clone(), HeaderMap::new()).await.unwrap_err();
+        transport.get(url, HeaderMap::new()).await.unwrap_err();
+        assert_eq!(
+            transport.connection_status(),
+            ConnectionStatus::Reconnecting {
+                consecutive_failures: 3
+            }
+        );
+    }
+
+    #[tokio::test]
+    async fn transport_stats_count_failures_and_pool_rebuilds() {
+        let transport = CryptoMktRequest::new();
+        let url = Url::parse("http://127.0.0.1:1/v1/market").unwrap();
+
+        assert_eq!(transport.stats(), TransportStats::default());
+        for _ in 0..3 {
+            transport.get(url.clone(), HeaderMap::new()).await.unwrap_err();
+        }
+        let stats = transport.stats();
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.failures, 3);
+        assert_eq!(stats.pool_rebuilds, 1);
+        assert_eq!(stats.average_latency, None);
+    }
+
+    #[tokio::test]
+    async fn transport_stats_tell_new_connections_from_reused_ones() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = Url::parse(&format!("http://{}/v1/market", listener.local_addr().unwrap()))
+            .unwrap();
+        let body = r#"{"status":"success","data":["ETHCLP"]}"#;
+        tokio::spawn(async move {
+            // Una sola conexión, que queda abierta entre peticiones
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 8192];
+            while socket.read(&mut buf).await.unwrap_or(0) > 0 {
+                let response = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\n\r\n{}",
+                    body.len(),
+                    body
+                );
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        let transport = CryptoMktRequest::new();
+
+        for _ in 0..3 {
+            transport.get(url.clone(), HeaderMap::new()).await.unwrap();
+        }
+        let stats = transport.stats();
+        assert_eq!(stats.requests, 3);
+        assert_eq!(stats.connections_opened, 1);
+        assert_eq!(stats.connections_reused, 2);
+    }
+
     #[tokio::test]
     async fn sign_msg() {
         let api = setup_test();
@@ -151,6 +505,46 @@ mod tests {
         assert_eq!(resp, "08ec7ce100a196d36970a77f7eee46c2e319e03edb953b0c05e5e9605b5c0d95cc7759f1a074f817f54527f618e90a1e".to_string());
     }
 
+    #[test]
+    fn secret_string_is_redacted() {
+        let secret = SecretString::new(SECRET_KEY);
+        assert_eq!(secret.expose(), SECRET_KEY);
+        assert_eq!(format!("{:?} {}", secret, secret), "<redacted> <redacted>");
+
+        let api = setup_test();
+        let debug = format!("{:?}", api);
+        assert!(!debug.contains(API_KEY) && !debug.contains(SECRET_KEY), "{}", debug);
+    }
+
+    #[test]
+    fn signing_sign_matches_api_sign_msg() {
+        let api = setup_test();
+        let msg = "1525053829/v1/orders/active";
+        assert_eq!(signing::sign(SECRET_KEY, msg), api.sign_msg(msg));
+    }
+
+    #[cfg(all(feature = "ring", feature = "rust-hmac"))]
+    #[test]
+    fn hmac_backends_produce_the_same_digest() {
+        use crate::internal::hmac::{ring_hmac_sha384, rust_hmac_sha384};
+
+        let msg = b"1525055728/v1/orders/create0.3ethclp10000buy";
+        assert_eq!(
+            ring_hmac_sha384(SECRET_KEY.as_bytes(), msg),
+            rust_hmac_sha384(SECRET_KEY.as_bytes(), msg)
+        );
+    }
+
+    #[test]
+    fn hmac_sha256_matches_rfc_4231() {
+        let resp = crate::internal::hmac::hmac_sha256(b"Jefe", b"what do ya want for nothing?");
+        let hex = resp.iter().map(|b| format!("{:02x}", b)).collect::<String>();
+        assert_eq!(
+            hex,
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
     #[tokio::test]
     async fn test_response_for_market_list() {
         let mock_transport = MockRequest::new(
@@ -206,7 +600,7 @@ mod tests {
 
         for it in 1..resp.data.len() {
             assert_eq!(
-                resp.data[it].high, expected["data"][it]["high"],
+                resp.data[it].high.to_string(), expected["data"][it]["high"],
                 "Los mercados no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -246,7 +640,7 @@ mod tests {
 
         for it in 1..resp.data.len() {
             assert_eq!(
-                resp.data[it].timestamp, expected["data"][it]["timestamp"],
+                Some(resp.data[it].timestamp), timestamp::parse(&expected["data"][it]["timestamp"]),
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -298,7 +692,7 @@ mod tests {
                 resp, expected
             );
             assert_eq!(
-                resp.data[it].timestamp, expected["data"][it]["timestamp"],
+                Some(resp.data[it].timestamp), timestamp::parse(&expected["data"][it]["timestamp"]),
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -349,7 +743,7 @@ mod tests {
 
         for it in 1..resp.data.len() {
             assert_eq!(
-                resp.data[it].created_at, expected["data"][it]["created_at"],
+                Some(resp.data[it].created_at), timestamp::parse(&expected["data"][it]["created_at"]),
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -395,7 +789,7 @@ mod tests {
 
         for it in 1..resp.data.len() {
             assert_eq!(
-                resp.data[it].created_at, expected["data"][it]["created_at"],
+                Some(resp.data[it].created_at), timestamp::parse(&expected["data"][it]["created_at"]),
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -405,7 +799,7 @@ mod tests {
                 resp, expected
             );
             assert_eq!(
-                resp.data[it].execution_price, expected["data"][it]["execution_price"],
+                json!(resp.data[it].execution_price), expected["data"][it]["execution_price"],
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -418,6 +812,29 @@ mod tests {
         );
     }
 
+    #[tokio::test]
+    async fn executed_orders_include_fee_and_execution_price() {
+        let mock_transport = MockRequest::new(
+            r#"{"status":"success","pagination":{"previous":"null","limit":20,"page":0,"next":"null"},"data":[{"status":"executed","created_at":"2017-08-31T21:37:42.282102","amount":{"executed":"0.6","original":"3.75"},"execution_price":"8000","avg_execution_price":"8000","fee":"9.6","executed_at":"2017-08-31T22:01:19.481403","price":"8000","type":"buy","id":"M103959","market":"ETHCLP"}]}"#,
+            "",
+        );
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(mock_transport));
+
+        let resp = api
+            .get_edge::<OrderResponse>("orders/executed", HashMap::new(), false)
+            .await
+            .unwrap();
+
+        let order = &resp.data[0];
+        assert_eq!(order.amount.executed.to_string(), "0.6");
+        assert_eq!(order.fee.to_string(), "9.6");
+        assert_eq!(order.execution_price.map(|p| p.to_string()), Some("8000".to_string()));
+        assert_eq!(
+            order.executed_at.map(|t| t.naive_utc().to_string()),
+            Some("2017-08-31 22:01:19.481403".to_string())
+        );
+    }
+
     #[tokio::test]
     async fn test_response_for_orders_create() {
         let mock_transport = MockRequest::new(
@@ -454,7 +871,7 @@ mod tests {
         );
 
         assert_eq!(
-            resp.data.created_at, expected["data"]["created_at"],
+            Some(resp.data.created_at), timestamp::parse(&expected["data"]["created_at"]),
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -464,7 +881,7 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.execution_price, expected["data"]["execution_price"],
+            json!(resp.data.execution_price), expected["data"]["execution_price"],
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -496,7 +913,7 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.created_at, expected["data"]["created_at"],
+            Some(resp.data.created_at), timestamp::parse(&expected["data"]["created_at"]),
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -506,7 +923,7 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.execution_price, expected["data"]["execution_price"],
+            json!(resp.data.execution_price), expected["data"]["execution_price"],
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -541,7 +958,7 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.created_at, expected["data"]["created_at"],
+            Some(resp.data.created_at), timestamp::parse(&expected["data"]["created_at"]),
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -551,7 +968,7 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.execution_price, expected["data"]["execution_price"],
+            json!(resp.data.execution_price), expected["data"]["execution_price"],
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -585,12 +1002,12 @@ mod tests {
             resp, expected
         );
         assert_eq!(
-            resp.data.obtained, expected["data"]["obtained"],
+            resp.data.obtained.to_string(), expected["data"]["obtained"],
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
         assert_eq!(
-            resp.data.required, expected["data"]["required"],
+            resp.data.required.to_string(), expected["data"]["required"],
             "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
             resp, expected
         );
@@ -646,7 +1063,7 @@ mod tests {
 
         for it in 1..resp.data.len() {
             assert_eq!(
-                resp.data[it].available, expected["data"][it]["available"],
+                resp.data[it].available.to_string(), expected["data"][it]["available"],
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
@@ -656,10 +1073,652 @@ mod tests {
                 resp, expected
             );
             assert_eq!(
-                resp.data[it].balance, expected["data"][it]["balance"],
+                resp.data[it].balance.to_string(), expected["data"][it]["balance"],
                 "Los datos no coinciden: Valor respondido {:?} Valor esperado: {:?}",
                 resp, expected
             );
         }
     }
+
+    fn trades_page(page: u32, next: &str) -> TradeResponse {
+        serde_json::from_str(&format!(
+            "{{\"status\":\"success\",\"pagination\":{{\"previous\":\"null\",\"limit\":1,\"page\":{},\"next\":{}}},\"data\":[{{\"market_taker\":\"buy\",\"timestamp\":\"2017-05-29T22:14:00.419466\",\"price\":\"155000\",\"amount\":\"0.129\",\"market\":\"ETHCLP\"}}]}}",
+            page, next
+        ))
+        .unwrap()
+    }
+
+    #[tokio::test]
+    async fn collect_pages_follows_pagination() {
+        let cancel = CancellationToken::new();
+        let resp = collect_pages(&cancel, |page| async move {
+            let next = if page < 2 { format!("{}", page + 1) } else { "\"null\"".to_string() };
+            Ok(trades_page(page, &next))
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(resp.len(), 3, "Se esperaban los trades de las 3 páginas: {:?}", resp);
+    }
+
+    #[tokio::test]
+    async fn collect_pages_returns_partial_result_when_cancelled() {
+        let cancel = CancellationToken::new();
+        let resp = collect_pages(&cancel, |page| {
+            if page == 1 {
+                cancel.cancel();
+            }
+            async move { Ok(trades_page(page, &format!("{}", page + 1))) }
+        })
+        .await
+        .unwrap();
+
+        assert_eq!(resp.len(), 1, "Solo se esperaba la primera página: {:?}", resp);
+    }
+
+    #[tokio::test]
+    async fn stream_pages_fetches_pages_on_demand() {
+        let fetched = std::cell::Cell::new(0);
+        let stream = stream_pages(|page| {
+            fetched.set(fetched.get() + 1);
+            let next = if page < 2 { format!("{}", page + 1) } else { "\"null\"".to_string() };
+            async move { Ok(trades_page(page, &next)) }
+        });
+        futures::pin_mut!(stream);
+
+        assert!(stream.next().await.unwrap().is_ok());
+        assert_eq!(fetched.get(), 1, "Solo se esperaba la primera página");
+        assert_eq!(stream.count().await, 2);
+        assert_eq!(fetched.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn stream_pages_ends_after_an_error() {
+        let stream = stream_pages(|page| async move {
+            if page == 1 {
+                Err(CryptoMktErrorType::BadRequest.into())
+            } else {
+                Ok(trades_page(page, &format!("{}", page + 1)))
+            }
+        });
+        let items: Vec<_> = stream.collect().await;
+
+        assert_eq!(items.len(), 2, "{:?}", items);
+        assert!(items[0].is_ok());
+        assert!(items[1].is_err());
+    }
+
+    #[tokio::test]
+    async fn error_envelope_is_surfaced_as_api_error() {
+        let body = r#"{"status": "error", "message": "invalid market"}"#;
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(MockRequest::new(body, "")));
+        let mut params = HashMap::new();
+        params.insert("market".to_string(), "NOPE".to_string());
+        let err = api.get_edge::<TickerResponse>("ticker", params, true).await.unwrap_err();
+
+        assert_eq!(err.kind(), &CryptoMktErrorType::RequestRejected);
+        assert_eq!(err.status(), Some(200));
+        assert_eq!(err.endpoint(), Some("/v1/ticker"));
+        assert_eq!(err.message(), Some("invalid market"));
+        assert_eq!(err.code(), None);
+        assert_eq!(err.to_string(), "/v1/ticker: invalid market");
+
+        // Aunque el tipo pedido acepte cualquier JSON
+        let body = r#"{"status": "error", "message": "amount too low", "code": 4001}"#;
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(MockRequest::new("", body)));
+        let err = api
+            .post_edge::<serde_json::Value>("orders/create", HashMap::new())
+            .await
+            .unwrap_err();
+        match err {
+            CryptoMktError::Api { endpoint, message, code } => {
+                assert_eq!(endpoint, "/v1/orders/create");
+                assert_eq!(message, "amount too low");
+                assert_eq!(code.as_deref(), Some("4001"));
+            }
+            e => panic!("unexpected error {:?}", e),
+        }
+    }
+
+    #[tokio::test]
+    async fn malformed_response_keeps_serde_error_and_snippet() {
+        let padding = "x".repeat(1000);
+        let body = format!(r#"{{"status": "success", "data": [{{"market": "ETHCLP"}}], "_": "{}"}}"#, padding);
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(MockRequest::new(&body, "")));
+        let err = api.get_edge::<TickerResponse>("ticker", HashMap::new(), true).await.unwrap_err();
+
+        assert_eq!(err.kind(), &CryptoMktErrorType::MalformedResource);
+        assert_eq!(err.body(), Some(body.as_str()));
+        let detail = err.message().unwrap();
+        assert!(detail.starts_with("missing field"), "{}", detail);
+        assert!(detail.contains(r#"{\"status\": \"success\""#), "{}", detail);
+        assert!(detail.len() < 400, "{}", detail);
+        assert!(err.to_string().contains(detail));
+    }
+
+    #[test]
+    fn error_payload_of_newer_api_versions_is_kept_in_the_error() {
+        let body = r#"{"error": {"code": 20001, "message": "Insufficient funds", "description": "Check that the funds are sufficient"}}"#;
+        let err = CryptoMktError::http(CryptoMktErrorType::BadRequest, 400, "/api/2/order", body.to_string());
+        assert_eq!(
+            err.message(),
+            Some("Insufficient funds: Check that the funds are sufficient")
+        );
+
+        let body = r#"{"error": {"code": 2001, "message": "Symbol not found"}}"#;
+        let err = CryptoMktError::http(CryptoMktErrorType::BadRequest, 400, "/api/2/order", body.to_string());
+        assert_eq!(err.message(), Some("Symbol not found"));
+    }
+
+    #[test]
+    fn timestamps_accept_the_formats_of_the_exchange() {
+        let expected = timestamp::parse(&json!("2021-05-20T11:36:02.131Z")).unwrap();
+
+        assert_eq!(timestamp::parse(&json!("2021-05-20T11:36:02.131")), Some(expected));
+        assert_eq!(timestamp::parse(&json!("2021-05-20 11:36:02.131")), Some(expected));
+        assert_eq!(timestamp::parse(&json!("2021-05-2011:36:02.131")), Some(expected));
+        assert_eq!(timestamp::parse(&json!(1621510562131u64)), Some(expected));
+        assert_eq!(timestamp::parse(&json!("1621510562131")), Some(expected));
+        assert_eq!(timestamp::parse(&json!("yesterday")), None);
+    }
+
+    ///
+    /// Servidor HTTP simulado: responde `body` a la primera petición y entrega
+    /// el texto de esa petición
+    ///
+    async fn http_server(body: &'static str) -> (String, oneshot::Receiver<String>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (tx, rx) = oneshot::channel();
+        tokio::spawn(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = vec![0; 8192];
+            let n = socket.read(&mut buf).await.unwrap();
+            let _ = tx.send(String::from_utf8_lossy(&buf[..n]).to_string());
+            let response = format!(
+                "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                body.len(),
+                body
+            );
+            socket.write_all(response.as_bytes()).await.unwrap();
+        });
+        (url, rx)
+    }
+
+    #[tokio::test]
+    async fn client_builder_sends_requests_to_base_url() {
+        let (url, request) =
+            http_server(r#"{"status":"success","data":["ETHCLP","BTCCLP"]}"#).await;
+        let client = Client::builder()
+            .base_url(&url)
+            .user_agent("my-bot/1.0")
+            .timeout(Duration::from_secs(5))
+            .build()
+            .unwrap();
+
+        let markets = client.get_markets().await.unwrap();
+        let request = request.await.unwrap().to_lowercase();
+
+        assert_eq!(markets.len(), 2);
+        assert!(request.starts_with("get /v1/market "), "{}", request);
+        assert!(request.contains("user-agent: my-bot/1.0"), "{}", request);
+    }
+
+    /// Añade una cabecera a las peticiones y guarda el código de las respuestas
+    #[derive(Default)]
+    struct RequestIdMiddleware {
+        statuses: std::sync::Arc<std::sync::Mutex<Vec<u16>>>,
+    }
+
+    impl Middleware for RequestIdMiddleware {
+        fn on_request(&self, request: &mut reqwest::Request) {
+            request
+                .headers_mut()
+                .insert("x-request-id", "42".parse().unwrap());
+        }
+
+        fn on_response(&self, url: &Url, response: &mut HttpResponse) {
+            assert_eq!(url.path(), "/v1/market");
+            self.statuses.lock().unwrap().push(response.status.as_u16());
+            response.body = response.body.replace("ETHCLP", "BTCCLP");
+        }
+    }
+
+    #[tokio::test]
+    async fn client_builder_runs_middleware() {
+        let (url, request) = http_server(r#"{"status":"success","data":["ETHCLP"]}"#).await;
+        let middleware = RequestIdMiddleware::default();
+        let statuses = middleware.statuses.clone();
+        let client = Client::builder()
+            .base_url(&url)
+            .middleware(middleware)
+            .build()
+            .unwrap();
+
+        let markets = client.get_markets().await.unwrap();
+        let request = request.await.unwrap().to_lowercase();
+
+        assert_eq!(markets[0].get_name(), "BTCCLP");
+        assert!(request.contains("x-request-id: 42"), "{}", request);
+        assert_eq!(*statuses.lock().unwrap(), vec![200]);
+    }
+
+    #[cfg(feature = "qr")]
+    #[test]
+    fn payment_qr_codes() {
+        let payment: crate::internal::models::Payment = serde_json::from_value(json!({
+            "id": 52, "external_id": "ORD-1", "status": 0, "to_receive": "5000",
+            "to_receive_currency": "CLP", "expected_amount": "0.0013",
+            "expected_currency": "ETH", "deposit_address": "0xabc", "refund_email": "",
+            "qr": "", "obs": "", "callback_url": "", "error_url": "", "success_url": "",
+            "payment_url": "https://www.cryptomkt.com/payment/P13565",
+            "created_at": "2017-11-15T12:00:00.000000",
+            "updated_at": "2017-11-15T12:00:00.000000"
+        }))
+        .unwrap();
+
+        let png = payment.qr_png(200).unwrap();
+        let mut reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        let mut pixels = vec![0; reader.output_buffer_size()];
+        let frame = reader.next_frame(&mut pixels).unwrap();
+        assert_eq!(frame.width, frame.height);
+        assert!(frame.width <= 200 && frame.width > 100, "{}", frame.width);
+        // El margen es claro y el patrón de posición de la esquina, oscuro
+        let modules = qrcode::QrCode::new(payment.payment_url.as_bytes()).unwrap().width() + 8;
+        let scale = frame.width as usize / modules;
+        assert_eq!(pixels[0], 255);
+        assert_eq!(pixels[(4 * scale) * frame.width as usize + 4 * scale], 0);
+
+        // Con un tamaño muy chico se usa un pixel por módulo
+        let png = payment.qr_png(1).unwrap();
+        let reader = png::Decoder::new(png.as_slice()).read_info().unwrap();
+        assert!(reader.info().width > 20);
+        let err = payment.qr_png(u32::MAX).unwrap_err();
+        assert!(matches!(err.kind(), CryptoMktErrorType::QrCode(_)));
+
+        let svg = payment.qr_svg(200).unwrap();
+        assert!(svg.contains("<svg"), "{}", svg);
+    }
+
+    #[test]
+    fn debug_logger_masks_credentials_and_addresses() {
+        let logger = DebugLogger::new();
+        let request = reqwest::Client::new()
+            .post("https://api.cryptomkt.com/v1/withdrawal?currency=ETH")
+            .header("x-mkt-apikey", API_KEY)
+            .header("x-mkt-signature", "abcdef")
+            .form(&[("address", "0xd3adb33f"), ("amount", "0.5")])
+            .build()
+            .unwrap();
+
+        let line = logger.describe_request(&request);
+        assert!(line.starts_with("--> POST /v1/withdrawal?currency=ETH"), "{}", line);
+        assert!(line.contains("x-mkt-apikey: <redacted>"), "{}", line);
+        assert!(line.contains("x-mkt-signature: <redacted>"), "{}", line);
+        assert!(line.contains("address=<redacted>&amount=0.5"), "{}", line);
+        assert!(!line.contains(API_KEY) && !line.contains("0xd3adb33f"), "{}", line);
+
+        let response = HttpResponse {
+            status: reqwest::StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: r#"{"data":[{"deposit_address":"0xd3adb33f","amount":"2"}]}"#.to_string(),
+        };
+        let url = Url::parse("https://api.cryptomkt.com/v1/payment/status").unwrap();
+        let line = logger.describe_response(&url, &response);
+        assert!(line.starts_with("<-- 200 /v1/payment/status"), "{}", line);
+        assert!(line.contains(r#""deposit_address":"<redacted>""#), "{}", line);
+
+        // Los clones comparten los interruptores
+        logger.clone().set_redaction(false);
+        assert!(logger.describe_request(&request).contains(API_KEY));
+        assert!(logger.describe_response(&url, &response).contains("0xd3adb33f"));
+        logger.clone().set_enabled(false);
+        assert!(!logger.is_enabled());
+    }
+
+    #[test]
+    fn debug_logger_masks_bank_accounts_and_deposit_memos() {
+        let logger = DebugLogger::new();
+        let request = reqwest::Client::new()
+            .post("https://api.cryptomkt.com/v1/withdrawal")
+            .form(&[("amount", "5000"), ("bank_account", "1234567"), ("currency", "CLP")])
+            .build()
+            .unwrap();
+        let line = logger.describe_request(&request);
+        assert!(line.contains("amount=5000&bank_account=<redacted>&currency=CLP"), "{}", line);
+
+        let response = HttpResponse {
+            status: reqwest::StatusCode::OK,
+            headers: HeaderMap::new(),
+            body: r#"{"currency":"XRP","address":"rK9x","payment_id":"3920145"}"#.to_string(),
+        };
+        let url = Url::parse("https://api.exchange.cryptomkt.com/api/3/wallet/crypto/address").unwrap();
+        let line = logger.describe_response(&url, &response);
+        assert!(line.contains(r#""payment_id":"<redacted>""#), "{}", line);
+        assert!(!line.contains("3920145") && !line.contains("rK9x"), "{}", line);
+    }
+
+    #[tokio::test]
+    async fn client_builder_uses_the_given_http_client() {
+        let (url, request) = http_server(r#"{"status":"success","data":["ETHCLP"]}"#).await;
+        let http_client = reqwest::Client::builder()
+            .user_agent("pooled/2.0")
+            .pool_max_idle_per_host(1)
+            .build()
+            .unwrap();
+        let client = Client::builder()
+            .base_url(&url)
+            .user_agent("ignored/1.0")
+            .http_client(http_client)
+            .build()
+            .unwrap();
+
+        client.get_markets().await.unwrap();
+        let request = request.await.unwrap().to_lowercase();
+
+        assert!(request.contains("user-agent: pooled/2.0"), "{}", request);
+    }
+
+    #[tokio::test]
+    async fn call_with_meta_returns_status_and_headers() {
+        let (url, _) = http_server(r#"{"status":"success","data":["ETHCLP"]}"#).await;
+        let client = Client::builder().base_url(&url).build().unwrap();
+
+        let (markets, meta): (MarketResponse, _) = client
+            .api
+            .call_with_meta(RequestMethod::Get(true), "market", HashMap::new())
+            .await
+            .unwrap();
+
+        assert_eq!(markets.data, vec!["ETHCLP".to_string()]);
+        assert_eq!(meta.status, reqwest::StatusCode::OK);
+        assert_eq!(meta.header("content-type"), Some("application/json"));
+        assert_eq!(meta.server_time(), None);
+        assert!(meta.latency > Duration::from_secs(0));
+    }
+
+    #[test]
+    fn client_builder_rejects_invalid_configuration() {
+        let err = Client::builder().base_url("not a url").build().err().unwrap();
+        assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+
+        let err = Client::builder().proxy("::").build().err().unwrap();
+        assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+    }
+
+    #[tokio::test]
+    async fn rate_limit_delays_requests_over_the_quota() {
+        let mock_transport = MockRequest::new(
+            "{\"status\": \"success\",\"data\": [\"ETHARS\",\"ETHCLP\"]}",
+            "",
+        );
+        let mut api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(mock_transport));
+        api.set_rate_limits(RateLimiter {
+            public: Some(Arc::new(TokenBucket::new(RateLimit::new(2, Duration::from_millis(200))))),
+            ..RateLimiter::default()
+        });
+
+        // Las dos primeras salen de inmediato, las otras esperan 100ms cada una
+        let start = Instant::now();
+        for _ in 0..4 {
+            api.get_edge::<MarketResponse>("market", HashMap::new(), true)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() >= Duration::from_millis(190), "{:?}", start.elapsed());
+
+        // Los endpoints autenticados no comparten el límite de los públicos
+        let start = Instant::now();
+        for _ in 0..4 {
+            api.get_edge::<MarketResponse>("market", HashMap::new(), false)
+                .await
+                .unwrap();
+        }
+        assert!(start.elapsed() < Duration::from_millis(100), "{:?}", start.elapsed());
+    }
+
+    ///
+    /// Servidor HTTP que rechaza la primera petición con un 429 y responde
+    /// `body` a la siguiente
+    ///
+    async fn throttling_server(retry_after: &'static str, body: &'static str) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            let responses = [
+                format!(
+                    "HTTP/1.1 429 Too Many Requests\r\nretry-after: {}\r\ncontent-length: 0\r\nconnection: close\r\n\r\n",
+                    retry_after
+                ),
+                format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n{}",
+                    body.len(),
+                    body
+                ),
+            ];
+            for response in responses.iter() {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 8192];
+                let _ = socket.read(&mut buf).await.unwrap();
+                socket.write_all(response.as_bytes()).await.unwrap();
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_reports_retry_after() {
+        let url = throttling_server("7", r#"{"status":"success","data":["ETHCLP"]}"#).await;
+        let client = Client::builder().base_url(&url).build().unwrap();
+
+        let err = client.get_markets().await.err().unwrap();
+        assert_eq!(*err.kind(), CryptoMktErrorType::RequestTooManyRequests);
+        assert_eq!(err.status(), Some(429));
+        assert_eq!(err.retry_after(), Some(Duration::from_secs(7)));
+    }
+
+    #[tokio::test]
+    async fn too_many_requests_is_retried_when_enabled() {
+        let url = throttling_server("0", r#"{"status":"success","data":["ETHCLP"]}"#).await;
+        let client = Client::builder()
+            .base_url(&url)
+            .rate_limit_behavior(RateLimitBehavior::Retry {
+                max_retries: 1,
+                max_wait: Duration::from_secs(1),
+            })
+            .build()
+            .unwrap();
+
+        let markets = client.get_markets().await.unwrap();
+        assert_eq!(markets.len(), 1);
+    }
+
+    #[test]
+    fn retry_is_skipped_when_the_wait_is_too_long() {
+        let limiter = RateLimiter {
+            behavior: RateLimitBehavior::Retry {
+                max_retries: 2,
+                max_wait: Duration::from_secs(5),
+            },
+            ..RateLimiter::default()
+        };
+        let throttled = |wait| {
+            CryptoMktError::http(
+                CryptoMktErrorType::RequestTooManyRequests,
+                429,
+                "/v1/ticker",
+                String::new(),
+            )
+            .with_retry_after(wait)
+        };
+
+        assert_eq!(limiter.retry_delay(&throttled(None), 0), Some(Duration::from_secs(1)));
+        assert_eq!(
+            limiter.retry_delay(&throttled(Some(Duration::from_secs(3))), 1),
+            Some(Duration::from_secs(3))
+        );
+        assert_eq!(limiter.retry_delay(&throttled(Some(Duration::from_secs(3))), 2), None);
+        assert_eq!(limiter.retry_delay(&throttled(Some(Duration::from_secs(60))), 0), None);
+        assert_eq!(
+            limiter.retry_delay(&CryptoMktErrorType::BadRequest.into(), 0),
+            None
+        );
+    }
+
+    #[tokio::test]
+    async fn test_response_for_prices() {
+        let mock_transport = MockRequest::new(
+            r#"{"status":"success","pagination":{"previous":"null","limit":2,"page":0,"next":1},"data":{"ask":[{"candle_id":2194424,"open_price":"252050","hight_price":"252300","close_price":"252210","low_price":"252000","volume_sum":"3.2","candle_date":"2017-08-31T12:00:00","tick_count":"4"}],"bid":[{"candle_id":2194425,"open_price":"250000","hight_price":"251500","close_price":"251000","low_price":"249800","volume_sum":"1.5","candle_date":"2017-08-31T12:00:00","tick_count":"2"}]}}"#,
+            "",
+        );
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(mock_transport));
+        let mut params = HashMap::new();
+        params.insert("market".to_string(), "ETHCLP".to_string());
+        params.insert("timeframe".to_string(), "60".to_string());
+
+        let resp = api
+            .get_edge::<PricesResponse>("prices", params, true)
+            .await
+            .unwrap();
+
+        let ask = &resp.data.ask[0];
+        assert_eq!(ask.open.to_string(), "252050");
+        assert_eq!(ask.high.to_string(), "252300");
+        assert_eq!(ask.low.to_string(), "252000");
+        assert_eq!(ask.close.to_string(), "252210");
+        assert_eq!(ask.volume.to_string(), "3.2");
+        assert_eq!(
+            Some(ask.candle_date),
+            timestamp::parse(&json!("2017-08-31T12:00:00"))
+        );
+        assert_eq!(resp.data.bid[0].close.to_string(), "251000");
+        assert_eq!(resp.pagination.next_page(), Some(1));
+    }
+
+    #[tokio::test]
+    async fn test_response_for_account() {
+        let mock_transport = MockRequest::new(
+            r#"{"status":"success","data":{"name":"John Doe","email":"john.doe@gmail.com","rate":{"market_maker":"0.0039","market_taker":"0.0068"},"bank_accounts":[{"id":1,"bank":"Banco Estado","description":"","country":"CL","number":"123456","dv":"8","agency":null,"clabe":""}]}}"#,
+            "",
+        );
+        let api = Api::<MockRequest>::new(API_KEY, SECRET_KEY, Box::new(mock_transport));
+
+        let resp = api
+            .get_edge::<AccountResponse>("account", HashMap::new(), false)
+            .await
+            .unwrap();
+
+        assert_eq!(resp.data.name, "John Doe");
+        assert_eq!(resp.data.email, "john.doe@gmail.com");
+        assert_eq!(resp.data.rate.market_maker.to_string(), "0.0039");
+        assert_eq!(resp.data.rate.market_taker.to_string(), "0.0068");
+        let bank = &resp.data.bank_accounts[0];
+        assert_eq!(bank.id, 1);
+        assert_eq!(bank.bank, "Banco Estado");
+        assert_eq!(bank.dv.as_deref(), Some("8"));
+        assert_eq!(bank.agency, None);
+    }
+
+    #[test]
+    fn payment_status_from_raw_codes() {
+        let codes: Vec<PaymentStatus> = serde_json::from_str(r#"[3, "-1", " 0", 7]"#).unwrap();
+        assert_eq!(
+            codes,
+            vec![
+                PaymentStatus::Paid,
+                PaymentStatus::Expired,
+                PaymentStatus::WaitingPayment,
+                PaymentStatus::Unknown(7),
+            ]
+        );
+        assert!(codes[0].is_final() && !codes[0].is_failed());
+        assert!(codes[1].is_final() && codes[1].is_failed());
+        assert!(!codes[2].is_final());
+        assert!(!codes[3].is_final());
+        assert_eq!(serde_json::to_string(&PaymentStatus::AmountMismatch).unwrap(), "-3");
+        assert!(serde_json::from_str::<PaymentStatus>(r#""paid""#).is_err());
+    }
+
+    /// Página de trades con una coma, un corchete y comillas escapadas en una
+    /// cadena, y la paginación después de `data`
+    const TRADES_PAGE: &str = r#"{"status":"success","data":[ {"market_taker":"buy","timestamp":"2017-05-29T22:14:00.419466","price":"155000","amount":"0.129","tid":"a,]\"}","market":"ETHCLP"} , {"market_taker":"sell","timestamp":"2017-05-29T22:15:00","price":"154000","amount":"1","tid":"2","market":"ETHCLP"}],"pagination":{"previous":"null","limit":2,"page":0,"next":"null"}}"#;
+
+    #[test]
+    fn data_splitter_yields_items_across_any_chunk_boundary() {
+        for size in 1..=TRADES_PAGE.len() {
+            let mut splitter = DataSplitter::default();
+            let mut ready = VecDeque::new();
+            for chunk in TRADES_PAGE.as_bytes().chunks(size) {
+                splitter.feed(chunk, &mut ready);
+            }
+            let trades: Vec<Trade> =
+                ready.iter().map(|raw| serde_json::from_slice(raw).unwrap()).collect();
+            assert_eq!(trades.len(), 2, "chunks of {}", size);
+            assert_eq!(trades[0].tid, "a,]\"}");
+            assert_eq!(trades[1].price.to_string(), "154000");
+            let pagination = splitter.finish("/v1/trades").unwrap();
+            assert_eq!((pagination.limit, pagination.next_page()), (2, None));
+        }
+    }
+
+    #[test]
+    fn data_splitter_reports_error_envelopes() {
+        let mut splitter = DataSplitter::default();
+        let mut ready = VecDeque::new();
+        splitter.feed(br#"{"status":"error","message":"invalid market"}"#, &mut ready);
+
+        assert!(ready.is_empty());
+        let err = splitter.finish("/v1/trades").unwrap_err();
+        assert!(matches!(err, CryptoMktError::Api { .. }), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn stream_trades_yields_trades_before_the_page_is_complete() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let (resume, resumed) = oneshot::channel::<()>();
+        tokio::spawn(async move {
+            let mut resumed = Some(resumed);
+            loop {
+                let (mut socket, _) = listener.accept().await.unwrap();
+                let mut buf = vec![0; 8192];
+                let n = socket.read(&mut buf).await.unwrap();
+                let request = String::from_utf8_lossy(&buf[..n]).to_string();
+                let body = if request.starts_with("GET /v1/market ") {
+                    r#"{"status":"success","data":["ETHCLP"]}"#
+                } else {
+                    TRADES_PAGE
+                };
+                let head = format!(
+                    "HTTP/1.1 200 OK\r\ncontent-type: application/json\r\ncontent-length: {}\r\nconnection: close\r\n\r\n",
+                    body.len()
+                );
+                socket.write_all(head.as_bytes()).await.unwrap();
+                if body == TRADES_PAGE {
+                    // El resto de la página se envía después de leer el primer trade
+                    let split = TRADES_PAGE.find(" , ").unwrap() + 2;
+                    socket.write_all(&body.as_bytes()[..split]).await.unwrap();
+                    socket.flush().await.unwrap();
+                    let _ = resumed.take().unwrap().await;
+                    socket.write_all(&body.as_bytes()[split..]).await.unwrap();
+                } else {
+                    socket.write_all(body.as_bytes()).await.unwrap();
+                }
+            }
+        });
+        let client = Client::builder().base_url(&url).build().unwrap();
+        let market = client.create_market("ETHCLP");
+        let start = chrono::NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+        let end = chrono::NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+        let mut trades = Box::pin(market.stream_trades(start, end));
+
+        let first = tokio::time::timeout(Duration::from_secs(5), trades.next())
+            .await
+            .expect("the first trade arrives before the rest of the page");
+        assert_eq!(first.unwrap().unwrap().tid, "a,]\"}");
+        resume.send(()).unwrap();
+        let rest: Vec<_> = trades.collect().await;
+        assert_eq!(rest.len(), 1);
+        assert_eq!(rest[0].as_ref().unwrap().tid, "2");
+    }
 }
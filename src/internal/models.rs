@@ -1,27 +1,39 @@
-use serde::{Deserialize, Serialize};
+use std::convert::TryFrom;
+use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use rust_decimal::{Decimal, RoundingStrategy};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use serde_json::Value;
 
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType};
+use crate::internal::timestamp;
+use crate::market::Side;
+
 ///
 /// The ticker is a high-level overview of the state of the market. It will show you
 /// the current bid and ask, as well as the latest market price. It also includes
 /// information such as the daily volume and how much the price has moved during the last day.
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Ticker {
     /// Highest price
-    pub high: String,
+    pub high: Decimal,
     /// Lowest price
-    pub low: String,
+    pub low: Decimal,
     /// Purchase price
-    pub ask: String,
+    pub ask: Decimal,
     /// Sale price
-    pub bid: String,
+    pub bid: Decimal,
     /// Last transaction price
-    pub last_price: String,
+    pub last_price: Decimal,
     /// Market volume
-    pub volume: String,
+    pub volume: Decimal,
     /// Market pair
-    pub timestamp: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
     /// Date of consultation
     pub market: String,
 }
@@ -30,32 +42,34 @@ pub struct Ticker {
 /// A market order corresponds to a purchase or sale request within the
 /// Exchange Market of CryptoMarket.
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Book {
     /// Order limit price
-    pub price: String,
+    pub price: Decimal,
     /// Order quantity
-    pub timestamp: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
     /// Creation date
-    pub amount: String,
+    pub amount: Decimal,
 }
 
 ///
 /// They correspond to transactions made in CryptoMarket.
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Trade {
     /// Transaction Type. buy or sell
     pub market_taker: String,
     /// Price at which the transaction was made
-    pub price: String,
+    pub price: Decimal,
     /// Amount of the transaction
-    pub amount: String,
+    pub amount: Decimal,
     /// ID of the transaction
     #[serde(default)]
     pub tid: String,
     /// Date of the transaction
-    pub timestamp: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
     /// Market pair where the transaction was made
     pub market: String,
 }
@@ -63,24 +77,24 @@ pub struct Trade {
 ///
 /// Amount
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Amount {
     /// Original quantity of the order
     #[serde(default)]
-    pub original: String,
+    pub original: Decimal,
     /// Remaining amount of the order. Only in active orders
     #[serde(default)]
-    pub remaining: String,
+    pub remaining: Decimal,
     /// Quantity executed of the order. Only in executed orders
     #[serde(default)]
-    pub executed: String,
+    pub executed: Decimal,
 }
 
 ///
 /// A market order corresponds to a purchase or sale request within the
 /// Exchange Market of CryptoMarket.
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Order {
     /// Order ID
     #[serde(default)]
@@ -93,78 +107,214 @@ pub struct Order {
     pub order_type: String,
     /// Order limit price
     #[serde(default)]
-    pub price: String,
+    pub price: Decimal,
     /// Ammount
     pub amount: Amount,
-    /// Execution price
+    /// Execution price, `None` until the order is executed
     #[serde(default)]
-    pub execution_price: Value, // null / String
+    pub execution_price: Option<Decimal>,
     /// Average weighted execution price. 0 if it is not executed.
     #[serde(default)]
-    pub avg_execution_price: String,
+    pub avg_execution_price: Decimal,
+    /// Fee charged for the execution. Only in executed orders
+    #[serde(default)]
+    pub fee: Decimal,
     /// Market pair
     #[serde(default)]
     pub market: String,
     /// Creation date
-    #[serde(default)]
-    pub created_at: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub created_at: DateTime<Utc>,
     /// Update date. Only in active orders
-    #[serde(default)]
-    pub updated_at: String,
+    #[serde(default, deserialize_with = "timestamp::deserialize_option")]
+    pub updated_at: Option<DateTime<Utc>>,
     /// Date of execution Only in executed orders
+    #[serde(default, deserialize_with = "timestamp::deserialize_option")]
+    pub executed_at: Option<DateTime<Utc>>,
+}
+
+impl Order {
+    ///
+    /// Side of the order, `None` if the exchange sent an unknown type
+    ///
+    pub fn side(&self) -> Option<Side> {
+        self.order_type.parse().ok()
+    }
+}
+
+///
+/// Candle of the price history of a market
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Candle {
+    /// Opening price
+    #[serde(rename = "open_price")]
+    pub open: Decimal,
+    /// Highest price
+    #[serde(rename = "hight_price", alias = "high_price")]
+    pub high: Decimal,
+    /// Lowest price
+    #[serde(rename = "low_price")]
+    pub low: Decimal,
+    /// Closing price
+    #[serde(rename = "close_price")]
+    pub close: Decimal,
+    /// Traded volume
+    #[serde(rename = "volume_sum")]
+    pub volume: Decimal,
+    /// Opening date of the candle
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub candle_date: DateTime<Utc>,
+}
+
+///
+/// Candles of the purchase (ask) and sale (bid) prices of a market
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct Prices {
+    /// Purchase price candles
     #[serde(default)]
-    pub executed_at: String,
+    pub ask: Vec<Candle>,
+    /// Sale price candles
+    #[serde(default)]
+    pub bid: Vec<Candle>,
+}
+
+///
+/// Trading rules of a market: orders with more decimals than these are
+/// rejected by the exchange
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct MarketLimits {
+    /// Market pair
+    pub market: String,
+    /// Smallest step of a price
+    pub tick_size: Decimal,
+    /// Smallest step of an order quantity
+    pub lot_size: Decimal,
+    /// Smallest order quantity
+    pub min_amount: Decimal,
+    /// `working`, or `suspended` when orders aren't accepted
+    pub status: String,
+}
+
+impl MarketLimits {
+    ///
+    /// Whether the market accepts orders
+    ///
+    pub fn is_active(&self) -> bool {
+        self.status == "working"
+    }
+
+    ///
+    /// Decimals of the prices
+    ///
+    pub fn price_precision(&self) -> u32 {
+        self.tick_size.normalize().scale()
+    }
+
+    ///
+    /// Decimals of the order quantities
+    ///
+    pub fn amount_precision(&self) -> u32 {
+        self.lot_size.normalize().scale()
+    }
+
+    ///
+    /// `price` rounded to the nearest multiple of the tick size
+    ///
+    pub fn round_price(&self, price: Decimal) -> Decimal {
+        round_to_step(price, self.tick_size, RoundingStrategy::MidpointAwayFromZero)
+    }
+
+    ///
+    /// `amount` rounded down to a multiple of the lot size, so an order never
+    /// exceeds the quantity asked
+    ///
+    pub fn round_amount(&self, amount: Decimal) -> Decimal {
+        round_to_step(amount, self.lot_size, RoundingStrategy::ToZero)
+    }
+
+    ///
+    /// Whether an order of `amount` at `price` follows the rules of the
+    /// market, as is
+    ///
+    pub fn accepts(&self, amount: Decimal, price: Decimal) -> bool {
+        self.is_active()
+            && amount >= self.min_amount
+            && self.round_amount(amount) == amount
+            && self.round_price(price) == price
+    }
+}
+
+///
+/// Redondea `value` a un múltiplo de `step`; sin redondeo si `step` no es positivo
+///
+pub(crate) fn round_to_step(value: Decimal, step: Decimal, strategy: RoundingStrategy) -> Decimal {
+    if step <= Decimal::ZERO {
+        return value;
+    }
+    (value / step).round_dp_with_strategy(0, strategy) * step
 }
 
 ///
 /// An instant order corresponds to a purchase or sale request within the Instant
 /// Exchange of CryptoMarket.
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct OrdersInstant {
     /// If it is a purchase purchase request, it corresponds to the amount of
     /// cryptocurrency to receive if the purchase was made. If it is a sell sell
     /// request, it corresponds to the amount of local currency to be received if the sale is made.
     #[serde(default)]
-    pub obtained: String,
+    pub obtained: Decimal,
     /// If it is a buy type request, it corresponds to the amount of local currency that you want
     /// to use to make the purchase. If type is sell, it corresponds to the amount of cryptocurrency
     /// that you want to use for the sale. Amount less than or equal to the amount requested. Modified
     /// by market liquidity.
     #[serde(default)]
-    pub required: String,
+    pub required: Decimal,
 }
 
 ///
 /// A balance corresponds to the status of your cryptocurrency and local wallets
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Balance {
     /// Wallet at CryptoMarket
     pub wallet: String,
     /// Balance available
-    pub available: String,
+    pub available: Decimal,
     /// Countable balance
-    pub balance: String,
+    pub balance: Decimal,
+}
+
+impl Balance {
+    ///
+    /// Currency of the wallet
+    ///
+    pub fn currency(&self) -> Currency {
+        Currency::from(self.wallet.as_str())
+    }
 }
 
 ///
 /// A balance corresponds to the status of your cryptocurrency and local wallets
 ///
-#[derive(Serialize, Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Payment {
     /// Internal ID of the payment order
     pub id: i32,
     /// External ID
     pub external_id: String,
-    /// State of the payment order. See below
-    pub status: String,
+    /// State of the payment order
+    pub status: PaymentStatus,
     /// Amount of the payment order
-    pub to_receive: String,
+    pub to_receive: Decimal,
     /// Type of currency to be received for the payment order
     pub to_receive_currency: String,
     /// Amount waiting for the order to be accepted
-    pub expected_amount: String,
+    pub expected_amount: Decimal,
     /// Type of currency waiting for the order to be accepted
     pub expected_currency: String,
     /// Payment order address
@@ -184,7 +334,536 @@ pub struct Payment {
     /// Payment order voucher url
     pub payment_url: String,
     /// Creation date of the payment order
-    pub created_at: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub created_at: DateTime<Utc>,
     /// Date of update of the payment order
-    pub updated_at: String,
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Payment {
+    ///
+    /// Whether the payment order reached a state it won't leave, see
+    /// `PaymentStatus::is_final`
+    ///
+    pub fn is_final(&self) -> bool {
+        self.status.is_final()
+    }
+
+    ///
+    /// Whether the payment order was paid successfully
+    ///
+    pub fn is_paid(&self) -> bool {
+        self.status == PaymentStatus::Paid
+    }
+}
+
+///
+/// State of a payment order. The exchange sends it as a numeric code, as a
+/// number or as a string
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PaymentStatus {
+    /// `-4`: the order received more than one payment
+    MultiplePayments,
+    /// `-3`: the amount paid doesn't match the expected amount
+    AmountMismatch,
+    /// `-2`: the conversion to the receiving currency failed
+    ConversionFailed,
+    /// `-1`: the order expired before being paid
+    Expired,
+    /// `0`: waiting for the payment
+    WaitingPayment,
+    /// `1`: payment received, waiting for the block
+    WaitingBlock,
+    /// `2`: payment confirmed, waiting to be processed
+    WaitingProcessing,
+    /// `3`: paid successfully
+    Paid,
+    /// A code this version doesn't know about
+    Unknown(i32),
+}
+
+impl PaymentStatus {
+    ///
+    /// Status of a numeric code
+    ///
+    pub fn from_code(code: i32) -> Self {
+        match code {
+            -4 => PaymentStatus::MultiplePayments,
+            -3 => PaymentStatus::AmountMismatch,
+            -2 => PaymentStatus::ConversionFailed,
+            -1 => PaymentStatus::Expired,
+            0 => PaymentStatus::WaitingPayment,
+            1 => PaymentStatus::WaitingBlock,
+            2 => PaymentStatus::WaitingProcessing,
+            3 => PaymentStatus::Paid,
+            code => PaymentStatus::Unknown(code),
+        }
+    }
+
+    ///
+    /// Numeric code sent by the exchange
+    ///
+    pub fn code(&self) -> i32 {
+        match self {
+            PaymentStatus::MultiplePayments => -4,
+            PaymentStatus::AmountMismatch => -3,
+            PaymentStatus::ConversionFailed => -2,
+            PaymentStatus::Expired => -1,
+            PaymentStatus::WaitingPayment => 0,
+            PaymentStatus::WaitingBlock => 1,
+            PaymentStatus::WaitingProcessing => 2,
+            PaymentStatus::Paid => 3,
+            PaymentStatus::Unknown(code) => *code,
+        }
+    }
+
+    ///
+    /// Whether the order won't change state anymore: it was paid, or it failed.
+    /// Unknown codes are not considered final
+    ///
+    pub fn is_final(&self) -> bool {
+        !matches!(
+            self,
+            PaymentStatus::WaitingPayment
+                | PaymentStatus::WaitingBlock
+                | PaymentStatus::WaitingProcessing
+                | PaymentStatus::Unknown(_)
+        )
+    }
+
+    ///
+    /// Whether the order failed: it expired or the payment couldn't be accepted
+    ///
+    pub fn is_failed(&self) -> bool {
+        self.is_final() && *self != PaymentStatus::Paid
+    }
+}
+
+impl From<i32> for PaymentStatus {
+    fn from(code: i32) -> Self {
+        PaymentStatus::from_code(code)
+    }
+}
+
+impl Serialize for PaymentStatus {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_i32(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for PaymentStatus {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let code = match Value::deserialize(deserializer)? {
+            Value::Number(n) => n.as_i64().and_then(|n| i32::try_from(n).ok()),
+            Value::String(s) => s.trim().parse().ok(),
+            _ => None,
+        };
+        code.map(PaymentStatus::from_code)
+            .ok_or_else(|| D::Error::custom("invalid payment status"))
+    }
+}
+
+///
+/// Account of the owner of the API key
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Account {
+    /// Name of the owner
+    pub name: String,
+    /// Email of the owner
+    pub email: String,
+    /// Fees charged on trades
+    pub rate: Rate,
+    /// Bank accounts registered for deposits and withdrawals
+    #[serde(default)]
+    pub bank_accounts: Vec<BankAccount>,
+}
+
+///
+/// Fees charged on trades, as a fraction of the traded amount
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct Rate {
+    /// Fee of orders resting on the book (maker)
+    pub market_maker: Decimal,
+    /// Fee of orders executed against the book (taker)
+    pub market_taker: Decimal,
+}
+
+///
+/// Bank account registered in CryptoMarket
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct BankAccount {
+    /// ID of the bank account, used to request withdrawals
+    pub id: i64,
+    /// Name of the bank
+    pub bank: String,
+    /// Description
+    #[serde(default)]
+    pub description: String,
+    /// Country code of the bank, e.g. `CL`
+    pub country: String,
+    /// Account number
+    pub number: String,
+    /// Check digit, when the country uses one
+    #[serde(default)]
+    pub dv: Option<String>,
+    /// Branch of the bank, when the country uses one
+    #[serde(default)]
+    pub agency: Option<String>,
+    /// CLABE number of Mexican accounts
+    #[serde(default)]
+    pub clabe: Option<String>,
+}
+
+///
+/// Deposit or withdrawal of funds
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Transaction {
+    /// ID of the transaction
+    pub id: String,
+    /// Currency moved, e.g. `ETH` or `CLP`
+    pub currency: String,
+    /// Amount moved, before fees
+    pub amount: Decimal,
+    /// Fee charged by CryptoMarket
+    #[serde(default)]
+    pub fee: Decimal,
+    /// State of the transaction, e.g. `pending` or `completed`
+    pub status: String,
+    /// Wallet address or bank account on the other side, when known
+    #[serde(default)]
+    pub address: Option<String>,
+    /// Hash of the blockchain transaction, for cryptocurrencies
+    #[serde(default)]
+    pub hash: Option<String>,
+    /// Date of the transaction
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub date: DateTime<Utc>,
+}
+
+///
+/// Market crossed by a currency conversion, see `Client::convert`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct ConversionStep {
+    /// Market traded
+    pub market: MarketPair,
+    /// `Sell` when the base currency of the market is converted into its quote
+    /// currency, `Buy` the other way around
+    pub side: Side,
+    /// Price of the step: the best bid when selling, the best ask when buying
+    pub price: Decimal,
+}
+
+///
+/// Estimated currency conversion, see `Client::convert`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Conversion {
+    /// Converted amount, in the target currency
+    pub amount: Decimal,
+    /// Markets crossed, in order. Empty when both currencies are the same
+    pub route: Vec<ConversionStep>,
+}
+
+///
+/// Operations allowed for an API key
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct KeyPermissions {
+    /// The key can read private account data (balances, orders)
+    pub read: bool,
+    /// The key can create and cancel orders
+    pub trade: bool,
+}
+
+///
+/// Price level of an order book
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct PriceLevel {
+    /// Level price
+    pub price: Decimal,
+    /// Amount available at this price
+    pub amount: Decimal,
+}
+
+///
+/// Order book of a market with both sides sorted from the best price:
+/// bids from highest to lowest and asks from lowest to highest.
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct OrderBook {
+    /// Market pair
+    pub market: String,
+    /// Purchase orders, highest price first
+    pub bids: Vec<PriceLevel>,
+    /// Sale orders, lowest price first
+    pub asks: Vec<PriceLevel>,
+    /// Date of the last update
+    pub timestamp: DateTime<Utc>,
+}
+
+impl OrderBook {
+    ///
+    /// Highest purchase price
+    ///
+    pub fn best_bid(&self) -> Option<&PriceLevel> {
+        self.bids.first()
+    }
+
+    ///
+    /// Lowest sale price
+    ///
+    pub fn best_ask(&self) -> Option<&PriceLevel> {
+        self.asks.first()
+    }
+
+    ///
+    /// Lowest sale price minus highest purchase price, or `None` when a side
+    /// is empty
+    ///
+    pub fn spread(&self) -> Option<Decimal> {
+        Some(self.best_ask()?.price - self.best_bid()?.price)
+    }
+
+    ///
+    /// Aplica niveles actualizados a un lado del libro: reemplaza el nivel del
+    /// mismo precio, elimina los de cantidad cero y mantiene el orden
+    ///
+    /// Argumentos
+    ///     levels: Lado del libro a actualizar
+    ///     updates: Niveles recibidos
+    ///     descending: Indica si el lado se ordena de mayor a menor precio (bids)
+    ///
+    pub(crate) fn merge_levels(levels: &mut Vec<PriceLevel>, updates: Vec<PriceLevel>, descending: bool) {
+        for update in updates {
+            levels.retain(|l| l.price != update.price);
+            if !update.amount.is_zero() {
+                levels.push(update);
+            }
+        }
+        levels.sort_by(|a, b| {
+            let ord = a.price.cmp(&b.price);
+            if descending {
+                ord.reverse()
+            } else {
+                ord
+            }
+        });
+    }
+}
+
+///
+/// Currency traded or accepted in CryptoMarket. Codes are case insensitive;
+/// currencies this version doesn't know about are kept in `Other`, in uppercase
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum Currency {
+    /// Chilean peso
+    Clp,
+    /// Argentine peso
+    Ars,
+    /// Brazilian real
+    Brl,
+    /// Euro
+    Eur,
+    /// Mexican peso
+    Mxn,
+    /// Bitcoin
+    Btc,
+    /// Ether
+    Eth,
+    /// EOS
+    Eos,
+    /// Stellar lumen
+    Xlm,
+    /// Any other currency code
+    Other(String),
+}
+
+impl Currency {
+    /// Currencies known by this version
+    pub const KNOWN: [Currency; 9] = [
+        Currency::Clp,
+        Currency::Ars,
+        Currency::Brl,
+        Currency::Eur,
+        Currency::Mxn,
+        Currency::Btc,
+        Currency::Eth,
+        Currency::Eos,
+        Currency::Xlm,
+    ];
+
+    ///
+    /// Code of the currency, as sent to the exchange (e.g. `CLP`)
+    ///
+    pub fn code(&self) -> &str {
+        match self {
+            Currency::Clp => "CLP",
+            Currency::Ars => "ARS",
+            Currency::Brl => "BRL",
+            Currency::Eur => "EUR",
+            Currency::Mxn => "MXN",
+            Currency::Btc => "BTC",
+            Currency::Eth => "ETH",
+            Currency::Eos => "EOS",
+            Currency::Xlm => "XLM",
+            Currency::Other(code) => code,
+        }
+    }
+
+    ///
+    /// Whether it's a local (fiat) currency. `Other` currencies are not
+    ///
+    pub fn is_fiat(&self) -> bool {
+        matches!(
+            self,
+            Currency::Clp | Currency::Ars | Currency::Brl | Currency::Eur | Currency::Mxn
+        )
+    }
+}
+
+impl From<&str> for Currency {
+    fn from(code: &str) -> Self {
+        let code = code.trim().to_uppercase();
+        Currency::KNOWN
+            .iter()
+            .find(|currency| currency.code() == code)
+            .cloned()
+            .unwrap_or(Currency::Other(code))
+    }
+}
+
+impl FromStr for Currency {
+    type Err = CryptoMktError;
+
+    fn from_str(code: &str) -> Result<Self, Self::Err> {
+        Ok(Currency::from(code))
+    }
+}
+
+impl Display for Currency {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.code())
+    }
+}
+
+impl Serialize for Currency {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.code())
+    }
+}
+
+impl<'de> Deserialize<'de> for Currency {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(Currency::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+///
+/// Market as a pair of currencies: `base` is bought and sold, prices are in
+/// `quote`. Written as the concatenation of both codes, e.g. `ETHCLP`
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MarketPair {
+    /// Currency bought and sold
+    pub base: Currency,
+    /// Currency the prices are expressed in
+    pub quote: Currency,
+}
+
+impl MarketPair {
+    /// Ether in Chilean pesos
+    pub const ETHCLP: MarketPair = MarketPair::new(Currency::Eth, Currency::Clp);
+    /// Ether in Argentine pesos
+    pub const ETHARS: MarketPair = MarketPair::new(Currency::Eth, Currency::Ars);
+    /// Ether in Brazilian reais
+    pub const ETHBRL: MarketPair = MarketPair::new(Currency::Eth, Currency::Brl);
+    /// Ether in euros
+    pub const ETHEUR: MarketPair = MarketPair::new(Currency::Eth, Currency::Eur);
+    /// Ether in Mexican pesos
+    pub const ETHMXN: MarketPair = MarketPair::new(Currency::Eth, Currency::Mxn);
+    /// Bitcoin in Chilean pesos
+    pub const BTCCLP: MarketPair = MarketPair::new(Currency::Btc, Currency::Clp);
+    /// Bitcoin in Argentine pesos
+    pub const BTCARS: MarketPair = MarketPair::new(Currency::Btc, Currency::Ars);
+    /// Bitcoin in Brazilian reais
+    pub const BTCBRL: MarketPair = MarketPair::new(Currency::Btc, Currency::Brl);
+    /// Bitcoin in euros
+    pub const BTCEUR: MarketPair = MarketPair::new(Currency::Btc, Currency::Eur);
+    /// Bitcoin in Mexican pesos
+    pub const BTCMXN: MarketPair = MarketPair::new(Currency::Btc, Currency::Mxn);
+    /// EOS in Chilean pesos
+    pub const EOSCLP: MarketPair = MarketPair::new(Currency::Eos, Currency::Clp);
+    /// Stellar lumens in Chilean pesos
+    pub const XLMCLP: MarketPair = MarketPair::new(Currency::Xlm, Currency::Clp);
+    /// Ether in bitcoin
+    pub const ETHBTC: MarketPair = MarketPair::new(Currency::Eth, Currency::Btc);
+
+    ///
+    /// Create a pair from its currencies
+    ///
+    pub const fn new(base: Currency, quote: Currency) -> Self {
+        MarketPair { base, quote }
+    }
+}
+
+impl FromStr for MarketPair {
+    type Err = CryptoMktError;
+
+    ///
+    /// Splits a market name such as `ethclp` in its currencies. One of them
+    /// must be known by this version, otherwise it fails with `UnknownMarket`;
+    /// use `MarketPair::new` with `Currency::Other` for those
+    ///
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        let name = name.trim().to_uppercase();
+        let split = |at: usize| {
+            (at > 0 && at < name.len() && name.is_char_boundary(at)).then(|| {
+                MarketPair::new(Currency::from(&name[..at]), Currency::from(&name[at..]))
+            })
+        };
+        let by_quote = Currency::KNOWN
+            .iter()
+            .filter(|quote| name.ends_with(quote.code()))
+            .find_map(|quote| split(name.len() - quote.code().len()));
+        let by_base = || {
+            Currency::KNOWN
+                .iter()
+                .filter(|base| name.starts_with(base.code()))
+                .find_map(|base| split(base.code().len()))
+        };
+        by_quote
+            .or_else(by_base)
+            .ok_or_else(|| CryptoMktErrorType::UnknownMarket(name.clone()).into())
+    }
+}
+
+impl Display for MarketPair {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "{}{}", self.base, self.quote)
+    }
+}
+
+impl Serialize for MarketPair {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for MarketPair {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer)?
+            .parse()
+            .map_err(D::Error::custom)
+    }
 }
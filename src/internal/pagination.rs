@@ -0,0 +1,181 @@
+//!
+//! Recorrido automático de los endpoints paginados
+//!
+
+use std::collections::HashMap;
+use std::future::Future;
+
+use futures::stream::{self, Stream, StreamExt};
+use tokio_util::sync::CancellationToken;
+
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::response::CryptoMktResponse;
+use crate::internal::streaming::PageEvent;
+
+///
+/// Page of a list endpoint to request. Unset values are left to the exchange,
+/// which starts at page 0 and usually returns 20 items per page
+///
+/// ```
+/// use cryptomkt::Page;
+///
+/// let page = Page::default().number(2).limit(100);
+/// assert_eq!((page.number, page.limit), (Some(2), Some(100)));
+/// ```
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Page {
+    /// Number of the page, starting at 0
+    pub number: Option<u32>,
+    /// Maximum number of items in the page
+    pub limit: Option<u32>,
+}
+
+impl Page {
+    ///
+    /// Request the page `number`, starting at 0
+    ///
+    pub fn number(mut self, number: u32) -> Self {
+        self.number = Some(number);
+        self
+    }
+
+    ///
+    /// Request at most `limit` items
+    ///
+    pub fn limit(mut self, limit: u32) -> Self {
+        self.limit = Some(limit);
+        self
+    }
+
+    ///
+    /// Agrega `page` y `limit` a los parámetros de la petición, si se indicaron
+    ///
+    /// Argumentos
+    ///     params: Parámetros de la petición
+    ///
+    pub(crate) fn add_to(&self, params: &mut HashMap<String, String>) {
+        if let Some(number) = self.number {
+            params.insert("page".to_string(), number.to_string());
+        }
+        if let Some(limit) = self.limit {
+            params.insert("limit".to_string(), limit.to_string());
+        }
+    }
+}
+
+///
+/// Recorre todas las páginas de un endpoint acumulando los elementos
+///
+/// El token se consulta antes de cada página y compite con la petición en curso;
+/// si se cancela, se devuelven los elementos acumulados hasta ese momento.
+///
+/// Argumentos
+///     cancel: Token de cancelación
+///     fetch: Función que obtiene la página indicada
+///
+pub async fn collect_pages<T, F, Fut>(
+    cancel: &CancellationToken,
+    mut fetch: F,
+) -> CryptoMktResult<Vec<T>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = CryptoMktResult<CryptoMktResponse<Vec<T>>>>,
+{
+    let mut items = Vec::new();
+    let mut page = 0;
+    loop {
+        let resp = tokio::select! {
+            biased;
+            _ = cancel.cancelled() => break,
+            resp = fetch(page) => resp?,
+        };
+        items.extend(resp.data);
+        match resp.pagination.next_page() {
+            // Evita ciclos si el servidor repite la página actual
+            Some(next) if next > page => page = next,
+            _ => break,
+        }
+    }
+    Ok(items)
+}
+
+///
+/// Recorre las páginas de un endpoint bajo demanda, entregando los elementos
+/// de uno en uno. Cada página se pide cuando se consumen los de la anterior;
+/// si una petición falla se entrega el error y el stream termina
+///
+/// Argumentos
+///     fetch: Función que obtiene la página indicada
+///
+pub fn stream_pages<T, F, Fut>(fetch: F) -> impl Stream<Item = CryptoMktResult<T>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = CryptoMktResult<CryptoMktResponse<Vec<T>>>>,
+{
+    stream::unfold((fetch, Some(0)), |(mut fetch, page)| async move {
+        let page = page?;
+        let (items, next) = match fetch(page).await {
+            Ok(resp) => {
+                // Evita ciclos si el servidor repite la página actual
+                let next = resp.pagination.next_page().filter(|next| *next > page);
+                (resp.data.into_iter().map(Ok).collect::<Vec<_>>(), next)
+            }
+            Err(e) => (vec![Err(e)], None),
+        };
+        Some((stream::iter(items), (fetch, next)))
+    })
+    .flatten()
+}
+
+///
+/// Como `stream_pages`, para páginas que entregan sus elementos a medida que
+/// llega el cuerpo de la respuesta. La siguiente página se pide al terminar la
+/// anterior, cuando se conoce su información de paginación
+///
+/// Argumentos
+///     fetch: Función que abre la página indicada
+///
+pub(crate) fn stream_incremental_pages<T, F, Fut, S>(
+    fetch: F,
+) -> impl Stream<Item = CryptoMktResult<T>>
+where
+    F: FnMut(u32) -> Fut,
+    Fut: Future<Output = CryptoMktResult<S>>,
+    S: Stream<Item = CryptoMktResult<PageEvent<T>>> + Unpin,
+{
+    stream::unfold((fetch, Walk::Open(0)), |(mut fetch, mut walk)| async move {
+        loop {
+            walk = match walk {
+                Walk::Done => return None,
+                Walk::Open(page) => match fetch(page).await {
+                    Ok(events) => Walk::Reading(page, events),
+                    Err(e) => return Some((Err(e), (fetch, Walk::Done))),
+                },
+                Walk::Reading(page, mut events) => match events.next().await {
+                    Some(Ok(PageEvent::Item(item))) => {
+                        return Some((Ok(item), (fetch, Walk::Reading(page, events))))
+                    }
+                    // Evita ciclos si el servidor repite la página actual
+                    Some(Ok(PageEvent::End(pagination))) => match pagination.next_page() {
+                        Some(next) if next > page => Walk::Open(next),
+                        _ => Walk::Done,
+                    },
+                    Some(Err(e)) => return Some((Err(e), (fetch, Walk::Done))),
+                    None => Walk::Done,
+                },
+            }
+        }
+    })
+}
+
+///
+/// Estado del recorrido de `stream_incremental_pages`
+///
+enum Walk<S> {
+    /// Falta pedir la página
+    Open(u32),
+    /// Leyendo los elementos de la página
+    Reading(u32, S),
+    Done,
+}
@@ -0,0 +1,372 @@
+//!
+//! Simulador de órdenes para el modo paper trading
+//!
+//! `PaperTransport` envuelve el transporte real: las peticiones que crean,
+//! cancelan o consultan órdenes se resuelven localmente, ejecutando las órdenes
+//! contra el libro de órdenes real del mercado, y el resto de lecturas se envía
+//! al exchange. Cualquier otro POST o DELETE se rechaza, para que un cliente
+//! simulado nunca mueva fondos reales.
+//! Las órdenes que quedan activas se vuelven a cruzar con el libro del momento
+//! cada vez que se consultan
+//!
+
+use std::collections::{HashMap, HashSet};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use chrono::Utc;
+use reqwest::header::HeaderMap;
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde::Serialize;
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::{Amount, Book, Order};
+use crate::internal::request::{ConnectionStatus, HttpRequest, Transport, TransportStats};
+use crate::internal::response::BookResponse;
+
+/// Prefijo de los IDs de las órdenes simuladas
+const PAPER_ID_PREFIX: &str = "PAPER-";
+/// Niveles del libro consultados para ejecutar una orden
+const BOOK_DEPTH: u32 = 100;
+
+///
+/// Transporte que simula las órdenes y envía el resto de peticiones al exchange
+///
+#[derive(Debug, Clone)]
+pub(crate) struct PaperTransport {
+    inner: Transport,
+    orders: Arc<Mutex<Vec<Order>>>,
+    next_id: Arc<AtomicU64>,
+}
+
+/// Respuesta con el formato del exchange
+#[derive(Serialize)]
+struct Envelope<T> {
+    status: &'static str,
+    data: T,
+}
+
+impl PaperTransport {
+    ///
+    /// Crea el simulador sobre el transporte real
+    ///
+    /// Argumentos
+    ///     inner: Transporte usado para los endpoints de lectura
+    ///
+    pub(crate) fn new(inner: Transport) -> Self {
+        PaperTransport {
+            inner,
+            orders: Arc::new(Mutex::new(Vec::new())),
+            next_id: Arc::new(AtomicU64::new(1)),
+        }
+    }
+
+    ///
+    /// Crea una orden límite y la ejecuta contra el libro real: se consumen los
+    /// niveles del lado contrario que cruzan el precio límite. Lo que no se
+    /// ejecuta queda activo hasta que se cancela
+    ///
+    async fn create_order(&self, url: &Url, params: &HashMap<String, String>) -> CryptoMktResult<String> {
+        let field = |name: &str| params.get(name).cloned().unwrap_or_default();
+        let parse = |name: &str| -> CryptoMktResult<Decimal> {
+            field(name).parse().map_err(|_| {
+                CryptoMktError::http(
+                    CryptoMktErrorType::BadRequest,
+                    400,
+                    url.path(),
+                    format!("invalid {}", name),
+                )
+            })
+        };
+        let market = field("market");
+        let side = field("type");
+        let amount = parse("amount")?;
+        let price = parse("price")?;
+
+        let book = self.book(url, &market, opposite(&side)).await?;
+        let now = Utc::now();
+        let mut order = Order {
+            id: format!(
+                "{}{}",
+                PAPER_ID_PREFIX,
+                self.next_id.fetch_add(1, Ordering::Relaxed)
+            ),
+            status: "active".to_string(),
+            order_type: side,
+            price,
+            amount: Amount {
+                original: amount,
+                remaining: amount,
+                executed: Decimal::ZERO,
+            },
+            execution_price: None,
+            avg_execution_price: Decimal::ZERO,
+            fee: Decimal::ZERO,
+            market,
+            created_at: now,
+            updated_at: Some(now),
+            executed_at: None,
+        };
+        execute(&mut order, &book);
+        self.orders.lock().unwrap().push(order.clone());
+        respond(order)
+    }
+
+    ///
+    /// Vuelve a cruzar con el libro actual las órdenes activas que cumplen
+    /// `matches`, consultando una vez cada lado de cada mercado
+    ///
+    /// Argumentos
+    ///     url: URL de la petición original, de la que se toma el dominio
+    ///     matches: Órdenes a revisar
+    ///
+    async fn match_resting<F>(&self, url: &Url, matches: F) -> CryptoMktResult<()>
+    where
+        F: Fn(&Order) -> bool,
+    {
+        let resting = {
+            let orders = self.orders.lock().unwrap();
+            orders
+                .iter()
+                .filter(|o| o.status == "active" && matches(o))
+                .map(|o| (o.market.clone(), o.order_type.clone()))
+                .collect::<HashSet<_>>()
+        };
+        for (market, side) in resting {
+            let book = self.book(url, &market, opposite(&side)).await?;
+            let mut orders = self.orders.lock().unwrap();
+            orders
+                .iter_mut()
+                .filter(|o| o.status == "active" && o.market == market && o.order_type == side)
+                .filter(|o| matches(o))
+                .for_each(|order| execute(order, &book));
+        }
+        Ok(())
+    }
+
+    ///
+    /// Cancela una orden activa simulada
+    ///
+    fn cancel_order(&self, url: &Url, params: &HashMap<String, String>) -> CryptoMktResult<String> {
+        let id = params.get("id").cloned().unwrap_or_default();
+        let mut orders = self.orders.lock().unwrap();
+        match orders.iter_mut().find(|o| o.id == id && o.status == "active") {
+            Some(order) => {
+                order.status = "cancelled".to_string();
+                order.updated_at = Some(Utc::now());
+                respond(order.clone())
+            }
+            None => Err(not_found(url)),
+        }
+    }
+
+    ///
+    /// Órdenes simuladas de un mercado con el estado indicado
+    ///
+    fn list_orders(&self, market: &str, status: &str) -> CryptoMktResult<String> {
+        let orders = self.orders.lock().unwrap();
+        let matching = orders
+            .iter()
+            .filter(|o| o.status == status && o.market.eq_ignore_ascii_case(market))
+            .cloned()
+            .collect::<Vec<_>>();
+        respond(matching)
+    }
+
+    ///
+    /// Lado del libro real de un mercado
+    ///
+    /// Argumentos
+    ///     url: URL de la petición original, de la que se toma el dominio
+    ///     market: Mercado
+    ///     side: `buy` o `sell`
+    ///
+    async fn book(&self, url: &Url, market: &str, side: &str) -> CryptoMktResult<Vec<Book>> {
+        let mut book_url = url.join("../book").map_err(|_| not_found(url))?;
+        book_url
+            .query_pairs_mut()
+            .clear()
+            .append_pair("market", market)
+            .append_pair("type", side)
+            .append_pair("page", "0")
+            .append_pair("limit", &BOOK_DEPTH.to_string());
+        let body = self.inner.get(book_url.clone(), HeaderMap::new()).await?;
+        match serde_json::from_str::<BookResponse>(&body) {
+            Ok(resp) => Ok(resp.data),
+            Err(e) => Err(CryptoMktError::malformed(book_url.path(), body, &e)),
+        }
+    }
+}
+
+///
+/// Lado del libro contra el que se ejecuta una orden de compra o venta
+///
+fn opposite(side: &str) -> &'static str {
+    if side == "buy" {
+        "sell"
+    } else {
+        "buy"
+    }
+}
+
+///
+/// Ejecuta lo que quede de una orden activa contra el libro, acumulando el
+/// precio promedio de ejecución. Si se completa pasa a `executed`
+///
+/// Argumentos
+///     order: Orden activa
+///     book: Lado contrario del libro
+///
+fn execute(order: &mut Order, book: &[Book]) {
+    let is_buy = order.order_type == "buy";
+    let (executed, cost) = fill(book, is_buy, order.amount.remaining, order.price);
+    if executed.is_zero() {
+        return;
+    }
+    let previous = order.execution_price.unwrap_or_default() * order.amount.executed;
+    let now = Utc::now();
+    order.amount.executed += executed;
+    order.amount.remaining -= executed;
+    let execution_price = ((previous + cost) / order.amount.executed).normalize();
+    order.execution_price = Some(execution_price);
+    order.avg_execution_price = execution_price;
+    order.updated_at = Some(now);
+    if order.amount.remaining.is_zero() {
+        order.status = "executed".to_string();
+        order.executed_at = Some(now);
+    }
+}
+
+///
+/// Cantidad ejecutada y su coste al cruzar una orden con el libro
+///
+/// Argumentos
+///     book: Lado contrario del libro
+///     is_buy: Indica si la orden es de compra
+///     amount: Cantidad de la orden
+///     limit: Precio límite
+///
+fn fill(book: &[Book], is_buy: bool, amount: Decimal, limit: Decimal) -> (Decimal, Decimal) {
+    let mut levels = book.iter().collect::<Vec<_>>();
+    levels.sort_by(|a, b| {
+        if is_buy {
+            a.price.cmp(&b.price)
+        } else {
+            b.price.cmp(&a.price)
+        }
+    });
+
+    let mut executed = Decimal::ZERO;
+    let mut cost = Decimal::ZERO;
+    for level in levels {
+        let crosses = if is_buy {
+            level.price <= limit
+        } else {
+            level.price >= limit
+        };
+        if !crosses || executed == amount {
+            break;
+        }
+        let quantity = level.amount.min(amount - executed);
+        executed += quantity;
+        cost += quantity * level.price;
+    }
+    (executed, cost)
+}
+
+fn respond<T: Serialize>(data: T) -> CryptoMktResult<String> {
+    let body = Envelope {
+        status: "success",
+        data,
+    };
+    Ok(serde_json::to_string(&body).expect("orders serialize to JSON"))
+}
+
+///
+/// Error de las peticiones que cambian algo en el exchange y no se simulan,
+/// como los retiros, las órdenes instantáneas o las órdenes de pago
+///
+fn unsimulated(url: &Url) -> CryptoMktError {
+    CryptoMktErrorType::InvalidConfiguration(format!(
+        "{} can't be simulated in paper trading",
+        url.path()
+    ))
+    .into()
+}
+
+fn not_found(url: &Url) -> CryptoMktError {
+    CryptoMktError::http(
+        CryptoMktErrorType::RequestNotFound,
+        404,
+        url.path(),
+        String::new(),
+    )
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpRequest for PaperTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        let params: HashMap<String, String> = url.query_pairs().into_owned().collect();
+        let market = params.get("market").cloned().unwrap_or_default();
+        let path = url.path();
+        if path.ends_with("/orders/active") || path.ends_with("/orders/executed") {
+            self.match_resting(&url, |o| o.market.eq_ignore_ascii_case(&market))
+                .await?;
+            let status = if path.ends_with("/orders/active") {
+                "active"
+            } else {
+                "executed"
+            };
+            return self.list_orders(&market, status);
+        }
+        if path.ends_with("/orders/status") {
+            let id = params.get("id").cloned().unwrap_or_default();
+            if id.starts_with(PAPER_ID_PREFIX) {
+                self.match_resting(&url, |o| o.id == id).await?;
+                let orders = self.orders.lock().unwrap();
+                return match orders.iter().find(|o| o.id == id) {
+                    Some(order) => respond(order.clone()),
+                    None => Err(not_found(&url)),
+                };
+            }
+        }
+        self.inner.get(url, headers).await
+    }
+
+    async fn post(
+        &self,
+        url: Url,
+        _headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> Self::Result {
+        let path = url.path();
+        if path.ends_with("/orders/create") {
+            return self.create_order(&url, &payload).await;
+        }
+        if path.ends_with("/orders/cancel") {
+            return self.cancel_order(&url, &payload);
+        }
+        Err(unsimulated(&url))
+    }
+
+    async fn delete(&self, url: Url, _headers: HeaderMap) -> CryptoMktResult<String> {
+        Err(unsimulated(&url))
+    }
+
+    async fn server_time(&self, url: Url) -> CryptoMktResult<Option<std::time::SystemTime>> {
+        self.inner.server_time(url).await
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        self.inner.connection_status()
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.inner.stats()
+    }
+}
@@ -0,0 +1,97 @@
+//!
+//! Códigos QR de las órdenes de pago
+//!
+//! Con la feature `qr` una `Payment` dibuja el código QR de su `payment_url`,
+//! la página donde el cliente paga, como PNG o SVG, para mostrarlo en el
+//! checkout sin depender de la imagen alojada en el exchange (`Payment::qr`).
+//! Ambos formatos llevan el margen de 4 módulos que exige el estándar.
+//!
+
+use png::{BitDepth, ColorType, Encoder};
+use qrcode::render::svg;
+use qrcode::{Color, QrCode};
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::Payment;
+
+/// Módulos claros alrededor del código
+const QUIET_ZONE: u32 = 4;
+/// Pixeles de la imagen PNG más grande, de 8192 x 8192
+const MAX_PNG_PIXELS: usize = 1 << 26;
+
+impl Payment {
+    ///
+    /// QR code of `payment_url` as a PNG image in grayscale. The image is the
+    /// largest multiple of the code's size that fits in `size` pixels, or one
+    /// pixel per module when `size` is smaller than that. Images wider than
+    /// 8192 pixels fail with `QrCode`
+    ///
+    pub fn qr_png(&self, size: u32) -> CryptoMktResult<Vec<u8>> {
+        let code = self.qr_code()?;
+        let modules = code.width() as u32 + 2 * QUIET_ZONE;
+        let scale = (size / modules).max(1);
+        let side = modules * scale;
+
+        let len = (side as usize)
+            .checked_mul(side as usize)
+            .filter(|len| *len <= MAX_PNG_PIXELS)
+            .ok_or_else(|| qr_error(format!("{} pixels wide image is too large", side)))?;
+        let mut pixels = Vec::with_capacity(len);
+        for y in 0..side {
+            for x in 0..side {
+                let dark = module(&code, x / scale, y / scale) == Color::Dark;
+                pixels.push(if dark { 0 } else { 255 });
+            }
+        }
+
+        let mut png = Vec::new();
+        let mut encoder = Encoder::new(&mut png, side, side);
+        encoder.set_color(ColorType::Grayscale);
+        encoder.set_depth(BitDepth::Eight);
+        encoder
+            .write_header()
+            .and_then(|mut writer| writer.write_image_data(&pixels))
+            .map_err(qr_error)?;
+        Ok(png)
+    }
+
+    ///
+    /// QR code of `payment_url` as an SVG document at least `size` pixels wide
+    ///
+    pub fn qr_svg(&self, size: u32) -> CryptoMktResult<String> {
+        Ok(self
+            .qr_code()?
+            .render::<svg::Color>()
+            .min_dimensions(size, size)
+            .build())
+    }
+
+    ///
+    /// Código QR de `payment_url`
+    ///
+    fn qr_code(&self) -> CryptoMktResult<QrCode> {
+        QrCode::new(self.payment_url.as_bytes()).map_err(qr_error)
+    }
+}
+
+///
+/// Color del módulo en la columna `x` y la fila `y` contando el margen
+///
+/// Argumentos
+///     code: Código QR
+///     x: Columna, desde el borde del margen
+///     y: Fila, desde el borde del margen
+///
+fn module(code: &QrCode, x: u32, y: u32) -> Color {
+    let width = code.width() as u32;
+    let inside = |n: u32| n >= QUIET_ZONE && n < QUIET_ZONE + width;
+    if inside(x) && inside(y) {
+        code[((x - QUIET_ZONE) as usize, (y - QUIET_ZONE) as usize)]
+    } else {
+        Color::Light
+    }
+}
+
+fn qr_error<E: std::fmt::Display>(e: E) -> CryptoMktError {
+    CryptoMktErrorType::QrCode(e.to_string()).into()
+}
@@ -0,0 +1,170 @@
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType};
+use crate::internal::rt::{self, Instant};
+
+/// Espera antes de reintentar cuando la respuesta 429 no trae `Retry-After`
+const DEFAULT_RETRY_AFTER: Duration = Duration::from_secs(1);
+
+///
+/// Request quota: at most `requests` every `per`, allowing bursts of up to
+/// `requests` calls
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RateLimit {
+    /// Requests allowed in each period
+    pub requests: u32,
+    /// Length of the period
+    pub per: Duration,
+}
+
+impl RateLimit {
+    ///
+    /// At most `requests` every `per`
+    ///
+    pub fn new(requests: u32, per: Duration) -> Self {
+        RateLimit { requests, per }
+    }
+}
+
+///
+/// What to do when the exchange answers `429 Too Many Requests`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum RateLimitBehavior {
+    /// Return `RequestTooManyRequests` right away. The wait asked by the
+    /// exchange is available through `CryptoMktError::retry_after`
+    #[default]
+    Fail,
+    /// Wait as long as the `Retry-After` header asks (one second when it's
+    /// missing) and send the request again
+    Retry {
+        /// Retries before giving up and returning the error
+        max_retries: u32,
+        /// Longest wait accepted; a longer `Retry-After` returns the error instead
+        max_wait: Duration,
+    },
+}
+
+///
+/// Token bucket: se recarga de forma continua a razón de `requests` por `per`
+///
+#[derive(Debug)]
+pub(crate) struct TokenBucket {
+    limit: RateLimit,
+    capacity: f64,
+    /// Tokens que se recargan por segundo
+    rate: f64,
+    /// Tokens disponibles y momento de la última recarga
+    state: Mutex<(f64, Instant)>,
+}
+
+impl TokenBucket {
+    pub(crate) fn new(limit: RateLimit) -> Self {
+        let capacity = f64::from(limit.requests.max(1));
+        TokenBucket {
+            limit,
+            capacity,
+            rate: capacity / limit.per.as_secs_f64().max(f64::EPSILON),
+            state: Mutex::new((capacity, Instant::now())),
+        }
+    }
+
+    ///
+    /// Cuota con la que se creó
+    ///
+    pub(crate) fn limit(&self) -> RateLimit {
+        self.limit
+    }
+
+    ///
+    /// Espera hasta que haya un token disponible y lo consume
+    ///
+    pub(crate) async fn acquire(&self) {
+        loop {
+            let wait = {
+                let mut state = self.state.lock().unwrap();
+                let (tokens, last) = &mut *state;
+                let now = Instant::now();
+                *tokens = (*tokens + now.duration_since(*last).as_secs_f64() * self.rate)
+                    .min(self.capacity);
+                *last = now;
+                if *tokens >= 1.0 {
+                    *tokens -= 1.0;
+                    return;
+                }
+                Duration::from_secs_f64((1.0 - *tokens) / self.rate)
+            };
+            rt::sleep(wait).await;
+        }
+    }
+}
+
+///
+/// Límites de peticiones por tipo de endpoint. Los clones comparten las cuotas
+///
+#[derive(Debug, Default, Clone)]
+pub(crate) struct RateLimiter {
+    /// Endpoints públicos
+    pub(crate) public: Option<Arc<TokenBucket>>,
+    /// Endpoints autenticados
+    pub(crate) private: Option<Arc<TokenBucket>>,
+    /// Respuesta ante un 429 del exchange
+    pub(crate) behavior: RateLimitBehavior,
+}
+
+impl RateLimiter {
+    ///
+    /// Espera el turno de una petición
+    ///
+    /// Argumentos
+    ///     is_public: Indica si el endpoint es público
+    ///
+    pub(crate) async fn acquire(&self, is_public: bool) {
+        let bucket = if is_public { &self.public } else { &self.private };
+        if let Some(bucket) = bucket {
+            bucket.acquire().await;
+        }
+    }
+
+    ///
+    /// Espera antes de reintentar una petición rechazada, o `None` si el error
+    /// debe devolverse
+    ///
+    /// Argumentos
+    ///     error: Error de la petición
+    ///     retries: Reintentos ya realizados
+    ///
+    pub(crate) fn retry_delay(&self, error: &CryptoMktError, retries: u32) -> Option<Duration> {
+        self.behavior.retry_delay(error, retries)
+    }
+}
+
+impl RateLimitBehavior {
+    ///
+    /// Espera antes de reintentar una petición rechazada según este
+    /// comportamiento, o `None` si el error debe devolverse
+    ///
+    /// Argumentos
+    ///     error: Error de la petición
+    ///     retries: Reintentos ya realizados
+    ///
+    pub(crate) fn retry_delay(&self, error: &CryptoMktError, retries: u32) -> Option<Duration> {
+        let (max_retries, max_wait) = match *self {
+            RateLimitBehavior::Fail => return None,
+            RateLimitBehavior::Retry {
+                max_retries,
+                max_wait,
+            } => (max_retries, max_wait),
+        };
+        if *error.kind() != CryptoMktErrorType::RequestTooManyRequests || retries >= max_retries {
+            return None;
+        }
+        let wait = error.retry_after().unwrap_or(DEFAULT_RETRY_AFTER);
+        if wait > max_wait {
+            return None;
+        }
+        Some(wait)
+    }
+}
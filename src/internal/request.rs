@@ -1,15 +1,30 @@
-use reqwest::{header::HeaderMap, Client, StatusCode, Url};
-use std::collections::HashMap;
+use reqwest::{header::{HeaderMap, DATE, RETRY_AFTER}, Client, RequestBuilder, StatusCode, Url};
+#[cfg(not(target_arch = "wasm32"))]
+use reqwest::Proxy;
+use std::collections::{HashMap, HashSet};
+use std::fmt::{self, Debug, Formatter};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicU32, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, SystemTime};
 use async_trait::async_trait;
-use log::error;
+use futures::TryStreamExt;
 
-use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::middleware::{HttpResponse, MiddlewareStack};
+use crate::internal::rt::{self, Instant};
+use crate::internal::streaming::ByteStream;
+use crate::internal::trace::error;
 
 ///
 /// Definición que deben cumplir para poder extaer datos mediante HTTP
 ///
-#[async_trait]
-pub trait HttpRequest {
+/// En wasm32 los futuros de `reqwest` no son `Send`, así que ahí las
+/// implementaciones usan `#[async_trait(?Send)]`
+///
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait HttpRequest: Send + Sync {
     ///
     /// Result
     ///
@@ -32,14 +47,431 @@ pub trait HttpRequest {
         headers: HeaderMap,
         payload: HashMap<String, String>,
     ) -> Self::Result;
+    ///
+    /// Petición DELETE, usada por las versiones 2 y 3 del API. Por defecto el
+    /// transporte no la soporta
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn delete(&self, _url: Url, _headers: HeaderMap) -> CryptoMktResult<String> {
+        Err(CryptoMktErrorType::RequestMethodNotAllowed.into())
+    }
+    ///
+    /// Como `get`, devolviendo además el estado, las cabeceras y la latencia de
+    /// la respuesta. Por defecto el transporte no los conoce: devuelve `None`
+    /// sin enviar nada y la petición se hace con `get`
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn get_with_meta(
+        &self,
+        _url: Url,
+        _headers: HeaderMap,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        Ok(None)
+    }
+    ///
+    /// Como `post`, devolviendo además el estado, las cabeceras y la latencia
+    /// de la respuesta. Por defecto el transporte no los conoce: devuelve
+    /// `None` sin enviar nada y la petición se hace con `post`
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///     payload: Datos a enviar a la URL especificada
+    ///
+    async fn post_with_meta(
+        &self,
+        _url: Url,
+        _headers: HeaderMap,
+        _payload: HashMap<String, String>,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        Ok(None)
+    }
+    ///
+    /// Como `get`, entregando el cuerpo por fragmentos a medida que llega, para
+    /// leer páginas grandes sin guardarlas enteras. Los middlewares ven la
+    /// petición pero no el cuerpo de la respuesta. Por defecto el transporte no
+    /// lo permite: devuelve `None` sin enviar nada y la petición se hace con `get`
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn get_stream(
+        &self,
+        _url: Url,
+        _headers: HeaderMap,
+    ) -> CryptoMktResult<Option<ByteStream>> {
+        Ok(None)
+    }
+    ///
+    /// Devuelve la hora del servidor según la cabecera `Date` de su respuesta,
+    /// o `None` si el transporte no puede conocerla
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///
+    async fn server_time(&self, _url: Url) -> CryptoMktResult<Option<SystemTime>> {
+        Ok(None)
+    }
+    ///
+    /// Estado de la conexión con el servidor
+    ///
+    fn connection_status(&self) -> ConnectionStatus {
+        ConnectionStatus::Healthy
+    }
+    ///
+    /// Estadísticas de las peticiones enviadas. Por defecto el transporte no
+    /// lleva la cuenta y devuelve todo a cero
+    ///
+    fn stats(&self) -> TransportStats {
+        TransportStats::default()
+    }
+}
+
+///
+/// Transporte de cualquier tipo, usado por `CryptoMktApi` para poder sustituir
+/// `CryptoMktRequest` por un transporte simulado
+///
+#[derive(Clone)]
+pub(crate) struct Transport(Arc<dyn HttpRequest<Result = CryptoMktResult<String>>>);
+
+impl Transport {
+    pub(crate) fn new<R>(transport: R) -> Self
+    where
+        R: HttpRequest<Result = CryptoMktResult<String>> + 'static,
+    {
+        Transport(Arc::new(transport))
+    }
+}
+
+impl Debug for Transport {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_tuple("Transport").finish()
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpRequest for Transport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        self.0.get(url, headers).await
+    }
+
+    async fn post(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> Self::Result {
+        self.0.post(url, headers, payload).await
+    }
+
+    async fn delete(&self, url: Url, headers: HeaderMap) -> CryptoMktResult<String> {
+        self.0.delete(url, headers).await
+    }
+
+    async fn get_with_meta(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        self.0.get_with_meta(url, headers).await
+    }
+
+    async fn post_with_meta(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        self.0.post_with_meta(url, headers, payload).await
+    }
+
+    async fn get_stream(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<Option<ByteStream>> {
+        self.0.get_stream(url, headers).await
+    }
+
+    async fn server_time(&self, url: Url) -> CryptoMktResult<Option<SystemTime>> {
+        self.0.server_time(url).await
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        self.0.connection_status()
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.0.stats()
+    }
+}
+
+/// Fallos de transporte consecutivos tras los que se reconstruye el cliente HTTP
+const RECONNECT_AFTER_FAILURES: u32 = 3;
+/// Conexiones recordadas para distinguir las nuevas de las reutilizadas
+#[cfg(not(target_arch = "wasm32"))]
+const KNOWN_CONNECTIONS: usize = 256;
+
+///
+/// Health of the connection with the exchange
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum ConnectionStatus {
+    /// The last request reached the exchange
+    Healthy,
+    /// The last requests failed at the transport level (connect, DNS, timeout)
+    Degraded {
+        /// Transport failures in a row
+        consecutive_failures: u32,
+    },
+    /// Transport failures kept happening, so the connection pool has been rebuilt
+    /// and is rebuilt again every few failures until a request gets through
+    Reconnecting {
+        /// Transport failures in a row
+        consecutive_failures: u32,
+    },
+}
+
+///
+/// Metadata of a successful response of the exchange, returned along with the
+/// data by `CryptoMktApi::call_with_meta`. Transports given to
+/// `ClientBuilder::http_transport` that don't implement
+/// `HttpRequest::get_with_meta` report a `200 OK` without headers
+///
+#[derive(Debug, Clone)]
+pub struct ResponseMeta {
+    /// Status code
+    pub status: StatusCode,
+    /// Headers, e.g. the rate limit ones or `Date`
+    pub headers: HeaderMap,
+    /// Time from sending the request to reading the whole answer
+    pub latency: Duration,
+}
+
+impl ResponseMeta {
+    ///
+    /// Metadatos de una respuesta de un transporte que no los conoce
+    ///
+    /// Argumentos:
+    ///     latency: Duración de la petición
+    ///
+    pub(crate) fn unknown(latency: Duration) -> Self {
+        ResponseMeta {
+            status: StatusCode::OK,
+            headers: HeaderMap::new(),
+            latency,
+        }
+    }
+
+    ///
+    /// Time of the exchange according to the `Date` header, if any
+    ///
+    pub fn server_time(&self) -> Option<SystemTime> {
+        let date = self.headers.get(DATE)?.to_str().ok()?;
+        httpdate::parse_http_date(date).ok()
+    }
+
+    ///
+    /// Value of the header `name`, when present and visible ASCII
+    ///
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name)?.to_str().ok()
+    }
+}
+
+///
+/// Snapshot of the requests sent by the HTTP transport, see
+/// `Client::transport_stats`
+///
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct TransportStats {
+    /// Requests sent, answered or not
+    pub requests: u64,
+    /// Requests that got no answer from the exchange (connect, DNS, timeout)
+    pub failures: u64,
+    /// Times the HTTP client and its connection pool were rebuilt after
+    /// repeated failures. The connections of the pool are reused until then
+    pub pool_rebuilds: u64,
+    /// Answered requests that opened a new connection
+    pub connections_opened: u64,
+    /// Answered requests sent over a connection of the pool already used by
+    /// an earlier one. On wasm32 `fetch` doesn't tell, and both counters stay
+    /// at zero
+    pub connections_reused: u64,
+    /// Mean time from sending a request to reading its whole answer, over the
+    /// answered requests. `None` until a request is answered
+    pub average_latency: Option<Duration>,
+}
+
+///
+/// Contadores con los que se construye `TransportStats`
+///
+#[derive(Debug, Default)]
+struct StatsCounters {
+    requests: AtomicU64,
+    failures: AtomicU64,
+    pool_rebuilds: AtomicU64,
+    connections_opened: AtomicU64,
+    connections_reused: AtomicU64,
+    /// Suma de las latencias de las peticiones respondidas, en microsegundos
+    latency_micros: AtomicU64,
+    /// Direcciones locales de las conexiones ya usadas: cada conexión TCP
+    /// tiene su propio puerto local
+    connections: Mutex<HashSet<SocketAddr>>,
+}
+
+impl StatsCounters {
+    fn snapshot(&self) -> TransportStats {
+        let requests = self.requests.load(Ordering::Relaxed);
+        let failures = self.failures.load(Ordering::Relaxed);
+        let answered = requests.saturating_sub(failures);
+        TransportStats {
+            requests,
+            failures,
+            pool_rebuilds: self.pool_rebuilds.load(Ordering::Relaxed),
+            connections_opened: self.connections_opened.load(Ordering::Relaxed),
+            connections_reused: self.connections_reused.load(Ordering::Relaxed),
+            average_latency: match answered {
+                0 => None,
+                n => Some(Duration::from_micros(
+                    self.latency_micros.load(Ordering::Relaxed) / n,
+                )),
+            },
+        }
+    }
+}
+
+///
+/// Configuración de los clientes HTTP del transporte
+///
+/// En wasm32 las peticiones las hace el `fetch` del navegador, que gestiona
+/// sus propias conexiones: ahí se ignoran `timeout`, `proxy`, las opciones
+/// del pool y `http2`. El plazo de cada petición sigue aplicándose mediante
+/// `RequestOptions`
+///
+#[derive(Debug, Clone, Default)]
+pub struct TransportConfig {
+    /// Tiempo máximo de cada petición
+    pub timeout: Option<Duration>,
+    /// URL del proxy por el que pasan todas las peticiones
+    pub proxy: Option<String>,
+    /// Cabecera User-Agent
+    pub user_agent: Option<String>,
+    /// Tiempo que una conexión sin uso se mantiene abierta en el pool
+    pub pool_idle_timeout: Option<Duration>,
+    /// Máximo de conexiones sin uso guardadas por host
+    pub pool_max_idle_per_host: Option<usize>,
+    /// `Some(true)` usa HTTP/2 sin negociarlo, `Some(false)` solo HTTP/1.1 y
+    /// `None` lo negocia mediante ALPN
+    pub http2: Option<bool>,
+    /// Pide las respuestas comprimidas con gzip o brotli. Activado por defecto
+    #[cfg(feature = "compression")]
+    pub compression: Option<bool>,
+    /// Cliente HTTP proporcionado por el usuario. Se usa tal cual, ignorando el
+    /// resto de opciones, y no se reconstruye tras fallos de transporte
+    pub client: Option<Client>,
+    /// Middlewares que observan las peticiones y respuestas
+    pub(crate) middleware: MiddlewareStack,
+}
+
+impl TransportConfig {
+    ///
+    /// Construye un cliente HTTP con esta configuración
+    ///
+    pub fn build_client(&self) -> CryptoMktResult<Client> {
+        if let Some(client) = &self.client {
+            return Ok(client.clone());
+        }
+        let mut builder = Client::builder();
+        if let Some(user_agent) = &self.user_agent {
+            builder = builder.user_agent(user_agent.as_str());
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        {
+            builder = self.connection_options(builder)?;
+        }
+        builder.build().map_err(config_error)
+    }
+
+    ///
+    /// Aplica las opciones de conexión, que solo existen fuera de wasm32
+    ///
+    /// Argumentos
+    ///     builder: Constructor del cliente HTTP
+    ///
+    #[cfg(not(target_arch = "wasm32"))]
+    fn connection_options(
+        &self,
+        mut builder: reqwest::ClientBuilder,
+    ) -> CryptoMktResult<reqwest::ClientBuilder> {
+        if let Some(timeout) = self.timeout {
+            builder = builder.timeout(timeout);
+        }
+        if let Some(proxy) = &self.proxy {
+            builder = builder.proxy(Proxy::all(proxy.as_str()).map_err(config_error)?);
+        }
+        if let Some(timeout) = self.pool_idle_timeout {
+            builder = builder.pool_idle_timeout(timeout);
+        }
+        if let Some(max) = self.pool_max_idle_per_host {
+            builder = builder.pool_max_idle_per_host(max);
+        }
+        #[cfg(feature = "compression")]
+        if let Some(enabled) = self.compression {
+            builder = builder.gzip(enabled).brotli(enabled);
+        }
+        Ok(match self.http2 {
+            Some(true) => builder.http2_prior_knowledge(),
+            Some(false) => builder.http1_only(),
+            None => builder,
+        })
+    }
+}
+
+///
+/// Espera pedida por la cabecera `Retry-After`, en segundos o como fecha HTTP
+///
+/// Argumentos
+///     headers: Cabeceras de la respuesta
+///
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+    let date = httpdate::parse_http_date(value).ok()?;
+    Some(date.duration_since(rt::system_now()).unwrap_or_default())
+}
+
+fn config_error(e: reqwest::Error) -> CryptoMktError {
+    error!(target: "cryptomkt", "HTTP client: {:?}", e);
+    CryptoMktErrorType::InvalidConfiguration(e.to_string()).into()
 }
 
 ///
 /// CryptoMktRequest
 ///
+/// Tras `RECONNECT_AFTER_FAILURES` fallos de transporte consecutivos se descarta
+/// el cliente HTTP (y su pool de conexiones) y se crea uno nuevo, de forma que un
+/// proceso de larga duración sobrevive a cortes de red o cambios de DNS
+///
 #[derive(Debug, Clone)]
 pub struct CryptoMktRequest {
-    client: Box<Client>,
+    client: Arc<RwLock<Client>>,
+    failures: Arc<AtomicU32>,
+    config: Arc<TransportConfig>,
+    stats: Arc<StatsCounters>,
 }
 
 impl CryptoMktRequest {
@@ -48,67 +480,285 @@ impl CryptoMktRequest {
     ///
     pub fn new() -> Self {
         CryptoMktRequest {
-            client: Box::new(Client::new()),
+            client: Arc::new(RwLock::new(Client::new())),
+            failures: Arc::new(AtomicU32::new(0)),
+            config: Arc::new(TransportConfig::default()),
+            stats: Arc::new(StatsCounters::default()),
         }
     }
+
     ///
-    /// Traspasa los errores del StatusCode para CryptoMktErrorType
+    /// Devuelve una nueva instancia cuyos clientes HTTP usan `config`
+    ///
+    /// Argumentos
+    ///     config: Configuración de los clientes HTTP
+    ///
+    pub fn with_config(config: TransportConfig) -> CryptoMktResult<Self> {
+        Ok(CryptoMktRequest {
+            client: Arc::new(RwLock::new(config.build_client()?)),
+            failures: Arc::new(AtomicU32::new(0)),
+            config: Arc::new(config),
+            stats: Arc::new(StatsCounters::default()),
+        })
+    }
+
+    ///
+    /// Cliente HTTP vigente
+    ///
+    fn client(&self) -> Client {
+        self.client.read().unwrap().clone()
+    }
+
+    ///
+    /// Registra el resultado a nivel de transporte de una petición, reconstruyendo
+    /// el cliente HTTP cuando se alcanza el umbral de fallos consecutivos
     ///
     /// Argumentos:
-    ///     prefix: Cadena de texto adiciona al log de errores
-    ///     status: Estado de la petición
+    ///     reached_server: Indica si la petición obtuvo respuesta del servidor
     ///
-    pub fn translate_errors<'c>(&self, prefix: &'c str, status: StatusCode) -> CryptoMktErrorType {
-        match status {
-            StatusCode::UNAUTHORIZED => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::UNAUTHORIZED);
-                CryptoMktErrorType::RequestUnauthorized
+    fn record_transport_result(&self, reached_server: bool) {
+        self.stats.requests.fetch_add(1, Ordering::Relaxed);
+        if reached_server {
+            self.failures.store(0, Ordering::Relaxed);
+            return;
+        }
+        self.stats.failures.fetch_add(1, Ordering::Relaxed);
+        let failures = self.failures.fetch_add(1, Ordering::Relaxed) + 1;
+        if failures.is_multiple_of(RECONNECT_AFTER_FAILURES) {
+            error!(target: "cryptomkt", "{} transport failures in a row, rebuilding HTTP client", failures);
+            // La configuración ya se validó al crear el transporte
+            if let Ok(client) = self.config.build_client() {
+                *self.client.write().unwrap() = client;
+                self.stats.pool_rebuilds.fetch_add(1, Ordering::Relaxed);
+                self.stats.connections.lock().unwrap().clear();
             }
+        }
+    }
 
-            StatusCode::FORBIDDEN => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::FORBIDDEN);
-                CryptoMktErrorType::RequestForbidden
-            }
-            StatusCode::NOT_FOUND => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::NOT_FOUND);
-                CryptoMktErrorType::RequestNotFound
-            }
-            StatusCode::METHOD_NOT_ALLOWED => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::METHOD_NOT_ALLOWED);
-                CryptoMktErrorType::RequestMethodNotAllowed
+    ///
+    /// Cuenta si la respuesta llegó por una conexión nueva o por una del pool
+    /// ya usada, según su dirección local
+    ///
+    /// Argumentos:
+    ///     resp: Respuesta recibida
+    ///
+    #[cfg(not(target_arch = "wasm32"))]
+    fn record_connection(&self, resp: &reqwest::Response) {
+        let info = match resp.extensions().get::<hyper::client::connect::HttpInfo>() {
+            Some(info) => info,
+            None => return,
+        };
+        let mut connections = self.stats.connections.lock().unwrap();
+        if connections.contains(&info.local_addr()) {
+            self.stats.connections_reused.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        // Las conexiones cerradas no avisan: se olvidan todas de vez en cuando
+        if connections.len() >= KNOWN_CONNECTIONS {
+            connections.clear();
+        }
+        connections.insert(info.local_addr());
+        self.stats.connections_opened.fetch_add(1, Ordering::Relaxed);
+    }
+
+    #[cfg(target_arch = "wasm32")]
+    fn record_connection(&self, _resp: &reqwest::Response) {}
+
+    ///
+    /// Envía la petición pasando por los middlewares y devuelve el cuerpo de
+    /// la respuesta, o el error correspondiente a su código HTTP
+    ///
+    /// Argumentos:
+    ///     prefix: Método, usado en el log de errores
+    ///     builder: Petición a enviar
+    ///
+    async fn send(&self, prefix: &str, builder: RequestBuilder) -> CryptoMktResult<String> {
+        self.send_with_meta(prefix, builder).await.map(|(body, _)| body)
+    }
+
+    ///
+    /// Como `send`, devolviendo además los metadatos de la respuesta
+    ///
+    /// Argumentos:
+    ///     prefix: Método, usado en el log de errores
+    ///     builder: Petición a enviar
+    ///
+    async fn send_with_meta(
+        &self,
+        prefix: &str,
+        builder: RequestBuilder,
+    ) -> CryptoMktResult<(String, ResponseMeta)> {
+        let mut request = match builder.build() {
+            Ok(request) => request,
+            Err(e) => {
+                error!(target: "cryptomkt", "{} {:?}", prefix, e);
+                return Err(CryptoMktErrorType::BadRequest.into());
             }
-            StatusCode::NOT_ACCEPTABLE => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::NOT_ACCEPTABLE);
-                CryptoMktErrorType::RequestNotAcceptable
+        };
+        self.config.middleware.on_request(&mut request);
+        let url = request.url().clone();
+        let endpoint = url.path().to_string();
+
+        let started = Instant::now();
+        let result = self.client().execute(request).await;
+        self.record_transport_result(result.is_ok());
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!(target: "cryptomkt", "{} {:?}", prefix, e);
+                return Err(CryptoMktErrorType::BadRequest.into());
             }
-            StatusCode::GONE => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::GONE);
-                CryptoMktErrorType::RequestGone
+        };
+        self.record_connection(&resp);
+        let status = resp.status();
+        let headers = resp.headers().clone();
+        let body = match resp.text().await {
+            Ok(txt) => txt,
+            Err(e) if status == StatusCode::OK => {
+                error!(target: "cryptomkt", "{}: Response Details: {:?}", prefix, e);
+                return Err(CryptoMktError::http(
+                    CryptoMktErrorType::MalformedResource,
+                    status.as_u16(),
+                    &endpoint,
+                    String::new(),
+                ));
             }
-            StatusCode::TOO_MANY_REQUESTS => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::TOO_MANY_REQUESTS);
-                CryptoMktErrorType::RequestTooManyRequests
+            Err(_) => String::new(),
+        };
+        let latency = self.record_latency(started);
+
+        let mut response = HttpResponse {
+            status,
+            headers,
+            body,
+        };
+        self.config.middleware.on_response(&url, &mut response);
+        match response.status {
+            StatusCode::OK => {
+                let meta = ResponseMeta {
+                    status: response.status,
+                    headers: response.headers,
+                    latency,
+                };
+                Ok((response.body, meta))
             }
-            StatusCode::INTERNAL_SERVER_ERROR => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::INTERNAL_SERVER_ERROR);
-                CryptoMktErrorType::RequestInternalServerError
+            status => {
+                let kind = self.translate_errors(prefix, status);
+                Err(CryptoMktError::http(kind, status.as_u16(), &endpoint, response.body)
+                    .with_retry_after(retry_after(&response.headers)))
             }
-            StatusCode::SERVICE_UNAVAILABLE => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?}", prefix, StatusCode::SERVICE_UNAVAILABLE);
-                CryptoMktErrorType::RequestServiceUnavailable
+        }
+    }
+
+    ///
+    /// Como `send`, entregando el cuerpo por fragmentos cuando la respuesta es
+    /// `200 OK`. Las respuestas de error se leen enteras para el error. La
+    /// latencia registrada es la de las cabeceras
+    ///
+    /// Argumentos:
+    ///     prefix: Método, usado en el log de errores
+    ///     builder: Petición a enviar
+    ///
+    async fn send_streaming(
+        &self,
+        prefix: &str,
+        builder: RequestBuilder,
+    ) -> CryptoMktResult<ByteStream> {
+        let mut request = match builder.build() {
+            Ok(request) => request,
+            Err(e) => {
+                error!(target: "cryptomkt", "{} {:?}", prefix, e);
+                return Err(CryptoMktErrorType::BadRequest.into());
             }
-            status => {
-                error!(target: "cryptomkt", "{}: StatusCode: {:?} Code({:?})", prefix, status, status.as_u16());
-                if status.as_u16() == 418 {
-                    CryptoMktErrorType::RequestTeapot
-                } else {
-                    CryptoMktErrorType::BadRequest
-                }
+        };
+        self.config.middleware.on_request(&mut request);
+        let endpoint = request.url().path().to_string();
+
+        let started = Instant::now();
+        let result = self.client().execute(request).await;
+        self.record_transport_result(result.is_ok());
+        let resp = match result {
+            Ok(resp) => resp,
+            Err(e) => {
+                error!(target: "cryptomkt", "{} {:?}", prefix, e);
+                return Err(CryptoMktErrorType::BadRequest.into());
             }
+        };
+        self.record_connection(&resp);
+        self.record_latency(started);
+        let status = resp.status();
+        if status != StatusCode::OK {
+            let headers = resp.headers().clone();
+            let body = resp.text().await.unwrap_or_default();
+            let kind = self.translate_errors(prefix, status);
+            return Err(CryptoMktError::http(kind, status.as_u16(), &endpoint, body)
+                .with_retry_after(retry_after(&headers)));
         }
+        let prefix = prefix.to_string();
+        let chunks = resp.bytes_stream().map_err(move |e| {
+            error!(target: "cryptomkt", "{}: Response Details: {:?}", prefix, e);
+            CryptoMktError::http(
+                CryptoMktErrorType::MalformedResource,
+                status.as_u16(),
+                &endpoint,
+                String::new(),
+            )
+        });
+        Ok(Box::pin(chunks))
+    }
+
+    ///
+    /// Suma a las estadísticas la latencia de una petición respondida y la
+    /// devuelve
+    ///
+    /// Argumentos:
+    ///     started: Momento en que se envió la petición
+    ///
+    fn record_latency(&self, started: Instant) -> Duration {
+        let latency = started.elapsed();
+        self.stats
+            .latency_micros
+            .fetch_add(latency.as_micros() as u64, Ordering::Relaxed);
+        latency
+    }
+
+    ///
+    /// Traspasa los errores del StatusCode para CryptoMktErrorType
+    ///
+    /// Argumentos:
+    ///     prefix: Cadena de texto adiciona al log de errores
+    ///     status: Estado de la petición
+    ///
+    pub fn translate_errors(&self, prefix: &str, status: StatusCode) -> CryptoMktErrorType {
+        error!(target: "cryptomkt", "{}: StatusCode: {:?} Code({:?})", prefix, status, status.as_u16());
+        status_kind(status)
     }
 }
-#[async_trait]
+
+///
+/// Tipo de error correspondiente a un código HTTP de error
+///
+/// Argumentos:
+///     status: Estado de la respuesta
+///
+pub(crate) fn status_kind(status: StatusCode) -> CryptoMktErrorType {
+    match status {
+        StatusCode::UNAUTHORIZED => CryptoMktErrorType::RequestUnauthorized,
+        StatusCode::FORBIDDEN => CryptoMktErrorType::RequestForbidden,
+        StatusCode::NOT_FOUND => CryptoMktErrorType::RequestNotFound,
+        StatusCode::METHOD_NOT_ALLOWED => CryptoMktErrorType::RequestMethodNotAllowed,
+        StatusCode::NOT_ACCEPTABLE => CryptoMktErrorType::RequestNotAcceptable,
+        StatusCode::GONE => CryptoMktErrorType::RequestGone,
+        StatusCode::IM_A_TEAPOT => CryptoMktErrorType::RequestTeapot,
+        StatusCode::TOO_MANY_REQUESTS => CryptoMktErrorType::RequestTooManyRequests,
+        StatusCode::INTERNAL_SERVER_ERROR => CryptoMktErrorType::RequestInternalServerError,
+        StatusCode::SERVICE_UNAVAILABLE => CryptoMktErrorType::RequestServiceUnavailable,
+        _ => CryptoMktErrorType::BadRequest,
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
 impl HttpRequest for CryptoMktRequest {
 
     type Result = CryptoMktResult<String>;
@@ -119,23 +769,7 @@ impl HttpRequest for CryptoMktRequest {
     ///     headers: HeaderMap
     ///
     async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
-        let result = self.client.get(url).headers(headers).send().await;
-        match result {
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => match resp.text().await {
-                    Ok(txt) => Ok(txt),
-                    Err(e) => {
-                        error!(target: "cryptomkt", "GET: Request Text details: {:?}", e);
-                        Err(CryptoMktErrorType::MalformedResource)
-                    }
-                },
-                status => Err(self.translate_errors("GET", status)),
-            },
-            Err(e) => {
-                error!(target: "cryptomkt", "GET {:?}", e);
-                Err(CryptoMktErrorType::BadRequest)
-            }
-        }
+        self.send("GET", self.client().get(url).headers(headers)).await
     }
     ///
     ///  Argumentos:
@@ -149,23 +783,98 @@ impl HttpRequest for CryptoMktRequest {
         headers: HeaderMap,
         payload: HashMap<String, String>,
     ) -> Self::Result {
-        let result = self.client.post(url).headers(headers).form(&payload).send().await;
-
+        self.send("POST", self.client().post(url).headers(headers).form(&payload))
+            .await
+    }
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn delete(&self, url: Url, headers: HeaderMap) -> CryptoMktResult<String> {
+        self.send("DELETE", self.client().delete(url).headers(headers)).await
+    }
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn get_with_meta(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        let builder = self.client().get(url).headers(headers);
+        self.send_with_meta("GET", builder).await.map(Some)
+    }
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///     payload: Datos a enviar a la URL especificada
+    ///
+    async fn post_with_meta(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> CryptoMktResult<Option<(String, ResponseMeta)>> {
+        let builder = self.client().post(url).headers(headers).form(&payload);
+        self.send_with_meta("POST", builder).await.map(Some)
+    }
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///     headers: HeaderMap
+    ///
+    async fn get_stream(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+    ) -> CryptoMktResult<Option<ByteStream>> {
+        let builder = self.client().get(url).headers(headers);
+        self.send_streaming("GET", builder).await.map(Some)
+    }
+    ///
+    ///  Argumentos:
+    ///     url: Url
+    ///
+    async fn server_time(&self, url: Url) -> CryptoMktResult<Option<SystemTime>> {
+        let started = Instant::now();
+        let result = self.client().head(url).send().await;
+        self.record_transport_result(result.is_ok());
         match result {
-            Ok(resp) => match resp.status() {
-                StatusCode::OK => match resp.text().await {
-                    Ok(txt) => Ok(txt),
-                    Err(e) => {
-                        error!(target: "cryptomkt", "POST: Response Details: {:?}", e);
-                        Err(CryptoMktErrorType::BadRequest)
-                    }
-                },
-                status => Err(self.translate_errors("POST", status)),
-            },
+            Ok(resp) => {
+                self.record_latency(started);
+                self.record_connection(&resp);
+                Ok(resp
+                    .headers()
+                    .get(DATE)
+                    .and_then(|date| date.to_str().ok())
+                    .and_then(|date| httpdate::parse_http_date(date).ok()))
+            }
             Err(e) => {
-                error!(target: "cryptomkt", "POST {:?}", e);
-                Err(CryptoMktErrorType::BadRequest)
+                error!(target: "cryptomkt", "HEAD {:?}", e);
+                Err(CryptoMktErrorType::BadRequest.into())
             }
         }
     }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        match self.failures.load(Ordering::Relaxed) {
+            0 => ConnectionStatus::Healthy,
+            n if n >= RECONNECT_AFTER_FAILURES => {
+                ConnectionStatus::Reconnecting {
+                    consecutive_failures: n,
+                }
+            }
+            n => ConnectionStatus::Degraded {
+                consecutive_failures: n,
+            },
+        }
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.stats.snapshot()
+    }
 }
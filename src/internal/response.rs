@@ -3,12 +3,18 @@
 //! servidor para los diferentes endpoints
 //!
 
-use crate::internal::models::{Balance, Book, Order, OrdersInstant, Payment, Ticker, Trade};
-use serde::Deserialize;
+use crate::internal::errors::{CryptoMktError, CryptoMktResult};
+use crate::internal::models::{
+    Account, Balance, Book, Order, OrdersInstant, Payment, Prices, Ticker, Trade, Transaction,
+};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
 use serde_json::Value;
+use std::convert::TryFrom;
+use std::ops::Deref;
 
 /// Información sobre la paginación
-#[derive(Deserialize, Debug, Clone)]
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
 pub struct Pagination {
     pub limit: i32,
     #[serde(default)]
@@ -17,6 +23,21 @@ pub struct Pagination {
     pub previous: Value, // String / i32
     pub next: Value, // String / i32
 }
+
+impl Pagination {
+    ///
+    /// Devuelve el número de la siguiente página, o `None` si esta es la última.
+    /// El servidor envía `next` como número, como cadena o como `"null"`
+    ///
+    pub fn next_page(&self) -> Option<u32> {
+        match &self.next {
+            Value::Number(n) => n.as_u64().and_then(|n| u32::try_from(n).ok()),
+            Value::String(s) => s.parse().ok(),
+            _ => None,
+        }
+    }
+}
+
 /// Timeout in seconds.
 impl Default for Pagination {
     fn default() -> Self {
@@ -38,6 +59,74 @@ pub struct CryptoMktResponse<T> {
     pub pagination: Pagination,
 }
 
+///
+/// Página de un listado: los elementos junto con la información de paginación,
+/// para saber si quedan más páginas. Se puede usar como un `Vec<T>`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Paginated<T> {
+    pub data: Vec<T>,
+    pub pagination: Pagination,
+}
+
+impl<T> Paginated<T> {
+    ///
+    /// Número de la siguiente página, o `None` si esta es la última
+    ///
+    pub fn next_page(&self) -> Option<u32> {
+        self.pagination.next_page()
+    }
+
+    ///
+    /// Indica si quedan más páginas
+    ///
+    pub fn has_next(&self) -> bool {
+        self.next_page().is_some()
+    }
+
+    ///
+    /// Descarta la información de paginación
+    ///
+    pub fn into_vec(self) -> Vec<T> {
+        self.data
+    }
+}
+
+impl<T> From<CryptoMktResponse<Vec<T>>> for Paginated<T> {
+    fn from(resp: CryptoMktResponse<Vec<T>>) -> Self {
+        Paginated {
+            data: resp.data,
+            pagination: resp.pagination,
+        }
+    }
+}
+
+impl<T> Deref for Paginated<T> {
+    type Target = Vec<T>;
+
+    fn deref(&self) -> &Vec<T> {
+        &self.data
+    }
+}
+
+impl<T> IntoIterator for Paginated<T> {
+    type Item = T;
+    type IntoIter = std::vec::IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.into_iter()
+    }
+}
+
+impl<'a, T> IntoIterator for &'a Paginated<T> {
+    type Item = &'a T;
+    type IntoIter = std::slice::Iter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.data.iter()
+    }
+}
+
 // ============ Market ==============
 
 /// Estructura de la respuesta asociada a los mercados, existentes
@@ -52,6 +141,9 @@ pub type BookResponse = CryptoMktResponse<Vec<Book>>;
 /// Retorna listado de trades realizados en CryptoMarket.
 pub type TradeResponse = CryptoMktResponse<Vec<Trade>>;
 
+/// Velas de precios de compra y venta de un mercado
+pub type PricesResponse = CryptoMktResponse<Prices>;
+
 /// Listados de Ordennes
 pub type OrderResponse = CryptoMktResponse<Vec<Order>>;
 
@@ -66,6 +158,24 @@ pub type EmptyResponse = CryptoMktResponse<String>;
 /// Obtener balances:
 pub type BalanceResponse = CryptoMktResponse<Vec<Balance>>;
 
+/// Cuenta del usuario:
+pub type AccountResponse = CryptoMktResponse<Account>;
+
+/// Depósitos y retiros:
+pub type TransactionResponse = CryptoMktResponse<Vec<Transaction>>;
+pub type SimpleTransactionResponse = CryptoMktResponse<Transaction>;
+
 /// Pagos:
 pub type PaymentResponse = CryptoMktResponse<Payment>;
 pub type PaymentListResponse = CryptoMktResponse<Vec<Payment>>;
+
+///
+/// Interpreta una respuesta sin sobre, como las de las versiones 2 y 3 del API
+///
+/// Argumentos
+///     endpoint: Ruta de la petición
+///     body: Cuerpo de la respuesta
+///
+pub(crate) fn parse_bare<T: DeserializeOwned>(endpoint: &str, body: String) -> CryptoMktResult<T> {
+    serde_json::from_str(&body).map_err(|e| CryptoMktError::malformed(endpoint, body, &e))
+}
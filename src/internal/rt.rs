@@ -0,0 +1,83 @@
+//!
+//! Relojes, temporizadores y tareas según la plataforma
+//!
+//! En wasm32-unknown-unknown no hay reloj de `std` ni temporizadores de Tokio:
+//! `Instant::now` y `tokio::time::sleep` fallan en tiempo de ejecución. Ahí se
+//! usan los de `wasmtimer`, que se apoyan en `performance.now` y `setTimeout`
+//! del navegador, y las tareas se lanzan con `spawn_local`. En el resto de
+//! plataformas son los de `std` y Tokio
+//!
+
+use std::future::Future;
+use std::time::SystemTime;
+#[cfg(target_arch = "wasm32")]
+use std::time::UNIX_EPOCH;
+
+#[cfg(not(target_arch = "wasm32"))]
+pub use std::time::Instant;
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use tokio::time::{interval, sleep, timeout, MissedTickBehavior};
+
+#[cfg(target_arch = "wasm32")]
+pub use wasmtimer::std::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use wasmtimer::tokio::{interval, sleep, timeout, timeout_at, MissedTickBehavior};
+
+///
+/// Hora actual del sistema
+///
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn system_now() -> SystemTime {
+    SystemTime::now()
+}
+
+///
+/// Hora actual del sistema, según el reloj del navegador
+///
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn system_now() -> SystemTime {
+    let since_epoch = wasmtimer::std::SystemTime::now()
+        .duration_since(wasmtimer::std::UNIX_EPOCH)
+        .unwrap_or_default();
+    UNIX_EPOCH + since_epoch
+}
+
+///
+/// Limita `future` hasta el instante `deadline`
+///
+/// Argumentos
+///     deadline: Instante a partir del cual se abandona el futuro
+///     future: Futuro a esperar
+///
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn timeout_at<F: Future>(deadline: Instant, future: F) -> tokio::time::Timeout<F> {
+    tokio::time::timeout_at(deadline.into(), future)
+}
+
+///
+/// Lanza una tarea en segundo plano en el runtime de Tokio
+///
+/// Argumentos
+///     task: Tarea a ejecutar
+///
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) fn spawn<F>(task: F)
+where
+    F: Future<Output = ()> + Send + 'static,
+{
+    tokio::spawn(task);
+}
+
+///
+/// Lanza una tarea en segundo plano en el bucle de eventos del navegador
+///
+/// Argumentos
+///     task: Tarea a ejecutar
+///
+#[cfg(target_arch = "wasm32")]
+pub(crate) fn spawn<F>(task: F)
+where
+    F: Future<Output = ()> + 'static,
+{
+    wasm_bindgen_futures::spawn_local(task);
+}
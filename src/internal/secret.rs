@@ -0,0 +1,71 @@
+//!
+//! Credenciales en memoria
+//!
+//! `SecretString` guarda una API key o un secret: se borra de la memoria al
+//! liberarse y nunca se muestra con `Debug` ni `Display`, así que un `{:?}` de
+//! un cliente o de un builder no deja las llaves en los logs.
+//!
+
+use std::fmt;
+
+use serde::{Deserialize, Deserializer};
+use zeroize::Zeroize;
+
+/// Lo que muestran `Debug` y `Display` en lugar del valor
+pub(crate) const REDACTED: &str = "<redacted>";
+
+///
+/// Cadena secreta que se borra al liberarse y no se muestra
+///
+#[derive(Clone, Default, PartialEq, Eq)]
+pub(crate) struct SecretString(String);
+
+impl SecretString {
+    ///
+    /// Copia `value` en un secreto
+    ///
+    pub(crate) fn new(value: &str) -> Self {
+        SecretString(value.to_string())
+    }
+
+    ///
+    /// Valor del secreto, solo para firmar o autenticar una petición
+    ///
+    pub(crate) fn expose(&self) -> &str {
+        &self.0
+    }
+
+    pub(crate) fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+impl Zeroize for SecretString {
+    fn zeroize(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl Drop for SecretString {
+    fn drop(&mut self) {
+        self.zeroize();
+    }
+}
+
+impl fmt::Debug for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl fmt::Display for SecretString {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(REDACTED)
+    }
+}
+
+impl<'de> Deserialize<'de> for SecretString {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(SecretString)
+    }
+}
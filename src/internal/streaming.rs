@@ -0,0 +1,259 @@
+//!
+//! Lectura incremental de las páginas de los listados
+//!
+//! Una página de `trades` se lee a medida que llegan los bytes: cada elemento
+//! de `data` se deserializa en cuanto se completa, sin guardar el cuerpo
+//! entero. El resto del sobre (`status`, `pagination`) se acumula aparte y se
+//! interpreta al terminar la respuesta
+//!
+
+use std::collections::VecDeque;
+use std::marker::PhantomData;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use bytes::Bytes;
+use futures::stream::{Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde_json::Value;
+
+use crate::internal::errors::{error_envelope, CryptoMktError, CryptoMktResult};
+use crate::internal::response::{CryptoMktResponse, Pagination};
+
+///
+/// Body of a response read in chunks as they arrive, see
+/// `HttpRequest::get_stream`
+///
+#[cfg(not(target_arch = "wasm32"))]
+pub type ByteStream = futures::stream::BoxStream<'static, CryptoMktResult<Bytes>>;
+
+///
+/// Body of a response read in chunks as they arrive, see
+/// `HttpRequest::get_stream`
+///
+#[cfg(target_arch = "wasm32")]
+pub type ByteStream = futures::stream::LocalBoxStream<'static, CryptoMktResult<Bytes>>;
+
+///
+/// Lo que entrega una página leída de forma incremental: sus elementos de
+/// uno en uno y, al terminar, la información de paginación
+///
+#[derive(Debug)]
+pub(crate) enum PageEvent<T> {
+    Item(T),
+    End(Pagination),
+}
+
+///
+/// Separa los elementos del arreglo `data` de un sobre
+/// `{"status", "data", "pagination"}` a medida que llegan los bytes
+///
+#[derive(Debug, Default)]
+pub(crate) struct DataSplitter {
+    /// Contenedores abiertos
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+    /// Dentro del arreglo `data`
+    in_data: bool,
+    /// Cuerpo sin los elementos de `data`
+    envelope: Vec<u8>,
+    /// Elemento de `data` en curso
+    element: Vec<u8>,
+    /// Última cadena del primer nivel, candidata a clave
+    last_string: Vec<u8>,
+    /// Clave cuyo valor se está leyendo en el primer nivel
+    key: Option<Vec<u8>>,
+}
+
+impl DataSplitter {
+    ///
+    /// Procesa un fragmento del cuerpo, dejando en `ready` los elementos de
+    /// `data` que se completan
+    ///
+    /// Argumentos
+    ///     chunk: Fragmento recibido
+    ///     ready: Elementos completos
+    ///
+    pub(crate) fn feed(&mut self, chunk: &[u8], ready: &mut VecDeque<Vec<u8>>) {
+        for &byte in chunk {
+            self.byte(byte, ready);
+        }
+    }
+
+    fn byte(&mut self, byte: u8, ready: &mut VecDeque<Vec<u8>>) {
+        if self.in_string {
+            if self.escaped {
+                self.escaped = false;
+            } else if byte == b'\\' {
+                self.escaped = true;
+            } else if byte == b'"' {
+                self.in_string = false;
+            }
+            if self.depth == 1 && self.in_string {
+                self.last_string.push(byte);
+            }
+            return self.push(byte);
+        }
+        if self.in_data && self.depth == 2 {
+            match byte {
+                b',' => return self.emit(ready),
+                b']' => {
+                    self.emit(ready);
+                    self.in_data = false;
+                    self.depth = 1;
+                    return self.envelope.push(byte);
+                }
+                b' ' | b'\t' | b'\r' | b'\n' if self.element.is_empty() => return,
+                _ => {}
+            }
+        }
+        match byte {
+            b'"' => {
+                self.in_string = true;
+                if self.depth == 1 {
+                    self.last_string.clear();
+                }
+            }
+            b':' if self.depth == 1 => self.key = Some(self.last_string.clone()),
+            b',' if self.depth == 1 => self.key = None,
+            b'[' if self.depth == 1 && self.key.as_deref() == Some(b"data") => {
+                self.envelope.push(byte);
+                self.depth = 2;
+                self.in_data = true;
+                return;
+            }
+            b'{' | b'[' => self.depth += 1,
+            b'}' | b']' => self.depth = self.depth.saturating_sub(1),
+            _ => {}
+        }
+        self.push(byte);
+    }
+
+    ///
+    /// Guarda un byte en el elemento en curso o en el sobre
+    ///
+    fn push(&mut self, byte: u8) {
+        if self.in_data && self.depth >= 2 {
+            self.element.push(byte);
+        } else {
+            self.envelope.push(byte);
+        }
+    }
+
+    ///
+    /// Entrega el elemento en curso, si lo hay
+    ///
+    fn emit(&mut self, ready: &mut VecDeque<Vec<u8>>) {
+        if !self.element.is_empty() {
+            ready.push_back(std::mem::take(&mut self.element));
+        }
+    }
+
+    ///
+    /// Interpreta el sobre una vez recibido todo el cuerpo, con el error del
+    /// exchange si lo hay
+    ///
+    /// Argumentos
+    ///     path: Ruta de la petición
+    ///
+    pub(crate) fn finish(self, path: &str) -> CryptoMktResult<Pagination> {
+        let body = String::from_utf8_lossy(&self.envelope);
+        if let Some(err) = error_envelope(path, &body) {
+            return Err(err);
+        }
+        serde_json::from_str::<CryptoMktResponse<Vec<Value>>>(&body)
+            .map(|resp| resp.pagination)
+            .map_err(|e| CryptoMktError::malformed(path, body.to_string(), &e))
+    }
+}
+
+///
+/// Página de un listado cuyos elementos se entregan de uno en uno: leídos
+/// de forma incremental del cuerpo de la respuesta o, si el transporte no
+/// lo permite, de la página ya interpretada
+///
+pub(crate) enum PageStream<T> {
+    Buffered(std::vec::IntoIter<T>, Option<Pagination>),
+    Incremental {
+        path: String,
+        bytes: ByteStream,
+        splitter: Option<DataSplitter>,
+        ready: VecDeque<Vec<u8>>,
+        _item: PhantomData<fn() -> T>,
+    },
+}
+
+impl<T: DeserializeOwned> PageStream<T> {
+    ///
+    /// Lee los elementos de `bytes` a medida que llegan
+    ///
+    /// Argumentos
+    ///     path: Ruta de la petición, para los errores
+    ///     bytes: Cuerpo de la respuesta
+    ///
+    pub(crate) fn incremental(path: &str, bytes: ByteStream) -> Self {
+        PageStream::Incremental {
+            path: path.to_string(),
+            bytes,
+            splitter: Some(DataSplitter::default()),
+            ready: VecDeque::new(),
+            _item: PhantomData,
+        }
+    }
+
+    ///
+    /// Entrega los elementos de una página ya interpretada
+    ///
+    /// Argumentos
+    ///     page: Página completa
+    ///
+    pub(crate) fn buffered(page: CryptoMktResponse<Vec<T>>) -> Self {
+        PageStream::Buffered(page.data.into_iter(), Some(page.pagination))
+    }
+}
+
+impl<T: DeserializeOwned + Unpin> Stream for PageStream<T> {
+    type Item = CryptoMktResult<PageEvent<T>>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        match self.get_mut() {
+            PageStream::Buffered(items, pagination) => Poll::Ready(match items.next() {
+                Some(item) => Some(Ok(PageEvent::Item(item))),
+                None => pagination.take().map(|pagination| Ok(PageEvent::End(pagination))),
+            }),
+            PageStream::Incremental {
+                path,
+                bytes,
+                splitter,
+                ready,
+                ..
+            } => loop {
+                if let Some(raw) = ready.pop_front() {
+                    let item = serde_json::from_slice(&raw).map_err(|e| {
+                        // Tras un elemento ilegible la página termina
+                        ready.clear();
+                        *splitter = None;
+                        CryptoMktError::malformed(path, String::from_utf8_lossy(&raw).into(), &e)
+                    });
+                    return Poll::Ready(Some(item.map(PageEvent::Item)));
+                }
+                let current = match splitter {
+                    Some(current) => current,
+                    None => return Poll::Ready(None),
+                };
+                match futures::ready!(bytes.poll_next_unpin(cx)) {
+                    Some(Ok(chunk)) => current.feed(&chunk, ready),
+                    Some(Err(e)) => {
+                        *splitter = None;
+                        return Poll::Ready(Some(Err(e)));
+                    }
+                    None => {
+                        let pagination = splitter.take().map(|done| done.finish(path));
+                        return Poll::Ready(pagination.map(|p| p.map(PageEvent::End)));
+                    }
+                }
+            },
+        }
+    }
+}
@@ -0,0 +1,78 @@
+use chrono::{DateTime, NaiveDate, NaiveDateTime, TimeZone, Utc};
+use serde::de::Error;
+use serde::{Deserialize, Deserializer};
+use serde_json::Value;
+
+/// Formato de las fechas de los parámetros `start`, `end`, `start_date` y `end_date`
+const DATE_FORMAT: &str = "%Y-%m-%d";
+
+///
+/// Interpreta una fecha enviada por el exchange, que puede venir como RFC 3339,
+/// como ISO 8601 sin zona horaria (se asume UTC) o como milisegundos desde epoch.
+/// Las respuestas antiguas de `ticker` pegan la hora a la fecha, sin la `T`:
+/// `2017-08-2915:44:17.267526`
+///
+/// Argumentos
+///     value: Valor JSON con la fecha
+///
+pub(crate) fn parse(value: &Value) -> Option<DateTime<Utc>> {
+    match value {
+        Value::Number(millis) => millis.as_i64().and_then(from_millis),
+        Value::String(date) => parse_str(date),
+        _ => None,
+    }
+}
+
+fn parse_str(date: &str) -> Option<DateTime<Utc>> {
+    if let Ok(millis) = date.parse::<i64>() {
+        return from_millis(millis);
+    }
+    DateTime::parse_from_rfc3339(date)
+        .map(|date| date.with_timezone(&Utc))
+        .ok()
+        .or_else(|| {
+            NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S%.f")
+                .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M:%S%.f"))
+                .or_else(|_| NaiveDateTime::parse_from_str(date, "%Y-%m-%d%H:%M:%S%.f"))
+                .map(|date| date.and_utc())
+                .ok()
+        })
+}
+
+fn from_millis(millis: i64) -> Option<DateTime<Utc>> {
+    Utc.timestamp_millis_opt(millis).single()
+}
+
+///
+/// Deserializa una fecha obligatoria, ver `parse`
+///
+pub(crate) fn deserialize<'de, D>(deserializer: D) -> Result<DateTime<Utc>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let value = Value::deserialize(deserializer)?;
+    parse(&value).ok_or_else(|| D::Error::custom(format!("invalid date: {}", value)))
+}
+
+///
+/// Deserializa una fecha opcional: `null` o una cadena vacía se leen como `None`
+///
+pub(crate) fn deserialize_option<'de, D>(deserializer: D) -> Result<Option<DateTime<Utc>>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match Value::deserialize(deserializer)? {
+        Value::Null => Ok(None),
+        Value::String(date) if date.is_empty() => Ok(None),
+        value => parse(&value)
+            .map(Some)
+            .ok_or_else(|| D::Error::custom(format!("invalid date: {}", value))),
+    }
+}
+
+///
+/// Formatea una fecha para los parámetros de la API
+///
+pub(crate) fn format_date(date: NaiveDate) -> String {
+    date.format(DATE_FORMAT).to_string()
+}
@@ -0,0 +1,78 @@
+//!
+//! Registro de eventos
+//!
+//! Sin la feature `tracing` los eventos se emiten con `log`. Con ella se emiten
+//! con `tracing` y cada petición al exchange se ejecuta dentro de un span
+//! `request` con el método, el endpoint, el código HTTP y la duración. Las
+//! cabeceras nunca se registran, por lo que la API key y la firma no aparecen
+//!
+
+use std::future::Future;
+use crate::internal::rt::Instant;
+
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::metrics;
+
+#[cfg(not(feature = "tracing"))]
+pub(crate) use log::{debug, error, warn};
+#[cfg(feature = "tracing")]
+pub(crate) use tracing::{debug, error, warn};
+
+///
+/// Ejecuta una petición dentro de su span y registra sus métricas
+///
+/// Argumentos
+///     method: `GET` o `POST`
+///     endpoint: Ruta de la petición, sin parámetros
+///     request: Petición al transporte
+///
+#[cfg(feature = "tracing")]
+pub(crate) async fn instrument<F, T>(method: &'static str, endpoint: &str, request: F) -> CryptoMktResult<T>
+where
+    F: Future<Output = CryptoMktResult<T>>,
+{
+    use tracing::field::Empty;
+    use tracing::Instrument;
+
+    let span = tracing::debug_span!(
+        target: "cryptomkt",
+        "request",
+        method,
+        endpoint,
+        status = Empty,
+        duration_ms = Empty,
+    );
+    let start = Instant::now();
+    let result = request.instrument(span.clone()).await;
+    let status = match &result {
+        Ok(_) => Some(200),
+        Err(e) => e.status(),
+    };
+    if let Some(status) = status {
+        span.record("status", status);
+    }
+    let elapsed = start.elapsed();
+    metrics::record(method, endpoint, &result, elapsed);
+    span.record("duration_ms", elapsed.as_millis() as u64);
+    span.in_scope(|| tracing::debug!(target: "cryptomkt", ok = result.is_ok(), "request finished"));
+    result
+}
+
+///
+/// Ejecuta una petición dentro de su span y registra sus métricas
+///
+/// Argumentos
+///     method: `GET` o `POST`
+///     endpoint: Ruta de la petición, sin parámetros
+///     request: Petición al transporte
+///
+#[cfg(not(feature = "tracing"))]
+pub(crate) async fn instrument<F, T>(method: &'static str, endpoint: &str, request: F) -> CryptoMktResult<T>
+where
+    F: Future<Output = CryptoMktResult<T>>,
+{
+    let start = Instant::now();
+    let result = request.await;
+    metrics::record(method, endpoint, &result, start.elapsed());
+    result
+}
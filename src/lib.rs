@@ -15,7 +15,13 @@
 //!     let client = Client::new(API_KEY, API_SECRET);
 //!
 //!     // Get the markets available in the exchange
-//!     let markets = client.get_markets().await;
+//!     let markets = match client.get_markets().await {
+//!         Ok(markets) => markets,
+//!         Err(e) => {
+//!             println!("{:?}", e);
+//!             return;
+//!         }
+//!     };
 //!     for m in markets.iter() {
 //!         println!("{}", m.get_name());
 //!
@@ -31,15 +37,109 @@
 //!     }
 //! }
 //! ```
+//!
+//! ## TLS
+//!
+//! HTTPS and WebSocket connections use `rustls` by default, so no OpenSSL is
+//! needed, e.g. for static musl binaries. To use the platform's TLS library
+//! instead, disable the default features and enable `native-tls`, along with a
+//! signing backend:
+//!
+//! ```toml
+//! cryptomkt = { version = "0.3", default-features = false, features = ["ring", "native-tls"] }
+//! ```
+//!
+//! ## Compression
+//!
+//! The `compression` feature negotiates gzip and brotli compressed responses
+//! with the exchange, cutting the bandwidth of large `trades` and `prices`
+//! pages. It can be turned off per client with `ClientBuilder::compression`.
+//!
+//! ## Metrics
+//!
+//! With the `metrics` feature every request to the exchange is recorded through
+//! the [`metrics`](https://docs.rs/metrics) facade, so any installed recorder
+//! (e.g. `metrics-exporter-prometheus`) exports them. Labels are `method` and
+//! `endpoint`, the path without query parameters:
+//!
+//! - `cryptomkt_requests_total`: finished requests, with `outcome` `ok` or `error`
+//! - `cryptomkt_request_errors_total`: failed requests, with the error `kind`
+//! - `cryptomkt_request_duration_seconds`: histogram of the request latencies
+//! - `cryptomkt_rate_limit_hits_total`: `429 Too Many Requests` answers
+//!
+//! ## QR codes
+//!
+//! The `qr` feature renders the payment URL of a payment order as a QR code,
+//! with `Payment::qr_png` or `Payment::qr_svg`, so a checkout can show it
+//! without fetching the image hosted by the exchange.
+//!
+//! ## WebAssembly
+//!
+//! The crate builds for `wasm32-unknown-unknown`, e.g. for browser dashboards
+//! on top of the public market data. There reqwest sends the requests with the
+//! browser's `fetch`, timers and clocks come from the JS host and requests are
+//! signed with the pure-Rust `hmac` + `sha2` backend, since `ring` doesn't
+//! build. The connection options of `ClientBuilder` (timeout, proxy, pool and
+//! HTTP version) are left to the browser. The `ws`, `webhook-server`,
+//! `blocking` and `cli` features need sockets or threads and are not available.
+//! Transports implementing `HttpRequest` on wasm32 use `#[async_trait(?Send)]`,
+//! and deadlines are given as `cryptomkt::Instant`, the browser clock.
 
 
+pub mod accounts;
+pub mod analytics;
 mod api;
+#[cfg(feature = "blocking")]
+pub mod blocking;
 mod client;
+#[cfg(feature = "arrow")]
+pub mod columnar;
+pub mod conditional;
+pub mod cost;
+pub mod execution;
+#[cfg(feature = "csv")]
+pub mod export;
+pub mod history;
 mod internal;
 mod market;
+pub mod mirror;
+pub mod mock;
+pub mod replay;
+#[cfg(feature = "strategy")]
+pub mod strategy;
+pub mod tickers;
+pub mod tracker;
+pub mod v2;
+pub mod v3;
+pub mod webhooks;
+#[cfg(feature = "ws")]
+pub mod ws;
+
+#[cfg(all(
+    target_arch = "wasm32",
+    any(feature = "ws", feature = "webhook-server", feature = "blocking", feature = "cli")
+))]
+compile_error!(
+    "the `ws`, `webhook-server`, `blocking` and `cli` features are not available on wasm32"
+);
 
-pub use crate::api::{CryptoMktApi, RequestMethod};
-pub use crate::client::Client;
+pub use crate::api::{with_cancellation, CryptoMktApi, RequestMethod, RequestOptions};
+pub use crate::client::{Client, ClientBuilder, PaymentOrderBuilder, WithdrawalDestination};
+pub use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
 pub use crate::internal::models;
+pub use crate::internal::models::{
+    Conversion, ConversionStep, Currency, MarketLimits, MarketPair,
+};
+pub use crate::internal::middleware::{DebugLogger, HttpResponse, Middleware};
+pub use crate::internal::pagination::Page;
+pub use crate::internal::ratelimit::{RateLimit, RateLimitBehavior};
+pub use crate::internal::rt::Instant;
+pub use crate::internal::streaming::ByteStream;
+pub use crate::internal::request::{ConnectionStatus, HttpRequest, ResponseMeta, TransportStats};
 pub use crate::internal::response;
-pub use crate::market::{Market, OrderType};
+pub use crate::internal::response::Paginated;
+pub use crate::market::{
+    BookSide, Market, OrderRequest, OrderState, OrderType, ReportedSide, Side, Timeframe,
+};
+pub use chrono::NaiveDate;
+pub use rust_decimal::Decimal;
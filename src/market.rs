@@ -1,31 +1,324 @@
-use crate::api::{CryptoMktApi, RequestMethod};
-use crate::internal::errors::CryptoMktResult;
-use crate::internal::models::{Book, Order, OrdersInstant, Ticker, Trade};
+use crate::api::{CryptoMktApi, PriorSubmission, RequestMethod};
+use crate::cost::{CostEstimate, Liquidity, TradeCostCalculator};
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::{
+    Book, MarketLimits, MarketPair, Order, OrderBook, OrdersInstant, PriceLevel, Prices, Ticker,
+    Trade,
+};
 use crate::internal::response::{
-    BookResponse, EmptyResponse, OrderResponse, OrdersInstantResponse, SimpleOrderResponse,
-    TickerResponse, TradeResponse,
+    AccountResponse, BookResponse, EmptyResponse, OrderResponse, OrdersInstantResponse, Paginated,
+    PricesResponse, SimpleOrderResponse, TickerResponse, TradeResponse,
 };
-use std::collections::HashMap;
+use crate::internal::pagination::{collect_pages, stream_incremental_pages, Page};
+use crate::internal::ratelimit::RateLimitBehavior;
+use crate::internal::streaming::PageStream;
+use crate::internal::timestamp;
+use crate::v3;
+use chrono::{DateTime, NaiveDate, Utc};
+use reqwest::Url;
+use futures::stream::{self, Stream, StreamExt};
+use rust_decimal::Decimal;
+use std::collections::{BTreeMap, HashMap};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use std::fmt::{self, Display, Formatter};
+use std::str::FromStr;
+use tokio_util::sync::CancellationToken;
+
+/// Largest page size accepted by the `trades` endpoint
+pub(crate) const TRADES_PAGE_LIMIT: u32 = 100;
+/// Orders submitted at the same time by `Market::create_orders`
+const ORDER_BATCH_CONCURRENCY: usize = 4;
+/// Órdenes revisadas al reconciliar un envío de `Market::create_order_with_id`
+const SUBMISSION_LOOKUP_LIMIT: u32 = 100;
+/// Segundos de desfase tolerados entre el reloj local y el del exchange
+const SUBMISSION_CLOCK_TOLERANCE: i64 = 5;
+/// Órdenes pedidas por lado para conocer el mejor precio y su cantidad total
+const TOP_OF_BOOK_DEPTH: u32 = 10;
 
 ///
-/// Order Type
+/// Side of an order: buying or selling. Written in lowercase (`buy`, `sell`)
+/// as the exchange does, and parsed in any case
 ///
-#[derive(Debug)]
-pub enum OrderType {
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum Side {
     Buy,
     Sell,
 }
 
-impl Display for OrderType {
+///
+/// Former name of `Side`
+///
+pub type OrderType = Side;
+
+impl Side {
+    ///
+    /// Value sent to the exchange
+    ///
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Side::Buy => "buy",
+            Side::Sell => "sell",
+        }
+    }
+
+    ///
+    /// The other side
+    ///
+    pub fn opposite(self) -> Side {
+        match self {
+            Side::Buy => Side::Sell,
+            Side::Sell => Side::Buy,
+        }
+    }
+}
+
+impl Display for Side {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for Side {
+    type Err = CryptoMktError;
+
+    fn from_str(side: &str) -> Result<Self, Self::Err> {
+        match side.trim().to_lowercase().as_str() {
+            "buy" => Ok(Side::Buy),
+            "sell" => Ok(Side::Sell),
+            _ => Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "invalid side: {:?}",
+                side
+            ))
+            .into()),
+        }
+    }
+}
+
+///
+/// Side of an order or trade as reported by the exchange. Sides this version
+/// doesn't know about are kept in `Unknown`, as sent, instead of failing to
+/// parse the whole response
+///
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum ReportedSide {
+    /// `buy` or `sell`
+    Known(Side),
+    /// Any other value
+    Unknown(String),
+}
+
+impl ReportedSide {
+    ///
+    /// The side, `None` if this version doesn't know it
+    ///
+    pub fn side(&self) -> Option<Side> {
+        match self {
+            ReportedSide::Known(side) => Some(*side),
+            ReportedSide::Unknown(_) => None,
+        }
+    }
+
+    ///
+    /// Value sent by the exchange
+    ///
+    pub fn as_str(&self) -> &str {
+        match self {
+            ReportedSide::Known(side) => side.as_str(),
+            ReportedSide::Unknown(side) => side,
+        }
+    }
+}
+
+impl From<&str> for ReportedSide {
+    fn from(side: &str) -> Self {
+        side.parse()
+            .map(ReportedSide::Known)
+            .unwrap_or_else(|_| ReportedSide::Unknown(side.to_string()))
+    }
+}
+
+impl From<Side> for ReportedSide {
+    fn from(side: Side) -> Self {
+        ReportedSide::Known(side)
+    }
+}
+
+impl PartialEq<Side> for ReportedSide {
+    fn eq(&self, other: &Side) -> bool {
+        self.side() == Some(*other)
+    }
+}
+
+impl Display for ReportedSide {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl Serialize for ReportedSide {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+impl<'de> Deserialize<'de> for ReportedSide {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Ok(ReportedSide::from(String::deserialize(deserializer)?.as_str()))
+    }
+}
+
+///
+/// Side of the order book: the bids are the buy orders and the asks the sell
+/// orders. Written as `bids` and `asks`
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+#[non_exhaustive]
+pub enum BookSide {
+    Bids,
+    Asks,
+}
+
+impl BookSide {
+    ///
+    /// Side of the orders resting on this side of the book
+    ///
+    pub fn side(self) -> Side {
+        match self {
+            BookSide::Bids => Side::Buy,
+            BookSide::Asks => Side::Sell,
+        }
+    }
+
+    ///
+    /// Side of the book an order of `side` rests on
+    ///
+    pub fn of(side: Side) -> BookSide {
+        match side {
+            Side::Buy => BookSide::Bids,
+            Side::Sell => BookSide::Asks,
+        }
+    }
+}
+
+impl From<Side> for BookSide {
+    fn from(side: Side) -> Self {
+        BookSide::of(side)
+    }
+}
+
+impl Display for BookSide {
     fn fmt(&self, f: &mut Formatter) -> fmt::Result {
-        write!(f, "{:?}", self)
+        f.write_str(match self {
+            BookSide::Bids => "bids",
+            BookSide::Asks => "asks",
+        })
+    }
+}
+
+impl FromStr for BookSide {
+    type Err = CryptoMktError;
+
+    fn from_str(side: &str) -> Result<Self, Self::Err> {
+        match side.trim().to_lowercase().as_str() {
+            "bids" | "bid" => Ok(BookSide::Bids),
+            "asks" | "ask" => Ok(BookSide::Asks),
+            _ => Err(CryptoMktErrorType::InvalidConfiguration(format!(
+                "invalid book side: {:?}",
+                side
+            ))
+            .into()),
+        }
+    }
+}
+
+///
+/// Duration of the candles returned by `Market::get_prices`
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+#[non_exhaustive]
+pub enum Timeframe {
+    OneMinute,
+    FiveMinutes,
+    FifteenMinutes,
+    OneHour,
+    FourHours,
+    OneDay,
+    OneWeek,
+}
+
+impl Timeframe {
+    ///
+    /// Duration of the candles in minutes, as sent to the exchange
+    ///
+    pub fn minutes(self) -> u32 {
+        match self {
+            Timeframe::OneMinute => 1,
+            Timeframe::FiveMinutes => 5,
+            Timeframe::FifteenMinutes => 15,
+            Timeframe::OneHour => 60,
+            Timeframe::FourHours => 240,
+            Timeframe::OneDay => 1440,
+            Timeframe::OneWeek => 10080,
+        }
+    }
+}
+
+///
+/// Limit order to be placed through `Market::create_orders`
+///
+#[derive(Debug, Clone)]
+pub struct OrderRequest {
+    /// Side of the order
+    pub order_type: Side,
+    /// Order quantity
+    pub amount: Decimal,
+    /// Order limit price
+    pub price: Decimal,
+}
+
+///
+/// Indica si el exchange rechazó una orden, que por lo tanto no quedó
+/// registrada: un `{"status": "error"}` o un 4xx de petición inválida, de
+/// permisos o de límite de peticiones
+///
+/// Argumentos
+///     e: Error del envío
+///
+pub(crate) fn is_rejection(e: &CryptoMktError) -> bool {
+    let client_error = e.status().is_some_and(|status| (400..500).contains(&status));
+    match e.kind() {
+        CryptoMktErrorType::RequestRejected => true,
+        CryptoMktErrorType::BadRequest
+        | CryptoMktErrorType::RequestForbidden
+        | CryptoMktErrorType::RequestUnauthorized
+        | CryptoMktErrorType::RequestTooManyRequests => client_error,
+        _ => false,
+    }
+}
+
+///
+/// Envío en curso de `Market::create_order_with_id`. Al soltarse deja de estar
+/// en curso, sin cambiar lo que se sabe de la orden
+///
+struct InFlight<'a> {
+    api: &'a CryptoMktApi,
+    client_order_id: &'a str,
+}
+
+impl Drop for InFlight<'_> {
+    fn drop(&mut self) {
+        self.api.settle_submission(self.client_order_id, None);
     }
 }
 
 ///
 /// Order State
 ///
+#[non_exhaustive]
 pub enum OrderState {
     Active,
     Executed,
@@ -36,7 +329,9 @@ pub enum OrderState {
 ///
 /// Through this class you access the functionalities offered by the market,
 /// either create purchase order, choose the state of the market, etc ...
+/// Like `Client`, it's `Send + Sync` and cheap to clone.
 ///
+#[derive(Debug, Clone)]
 pub struct Market {
     api: CryptoMktApi,
     name: String,
@@ -46,9 +341,9 @@ impl Market {
     ///
     /// Create new instance
     ///
-    pub fn new<'m>(api: CryptoMktApi, market_name: &'m str) -> Self {
+    pub fn new(api: CryptoMktApi, market_name: &str) -> Self {
         Market {
-            api: api,
+            api,
             name: market_name.to_string(),
         }
     }
@@ -60,10 +355,34 @@ impl Market {
         self.name.clone()
     }
 
+    ///
+    /// Base and quote currencies of the market. Fails with `UnknownMarket` when
+    /// neither currency of the name is known by this version
+    ///
+    pub fn pair(&self) -> CryptoMktResult<MarketPair> {
+        self.name.parse()
+    }
+
+    ///
+    /// Fails with `UnknownMarket` when the exchange doesn't list this market.
+    /// Every method sending the `market` parameter goes through here.
+    ///
+    async fn validate(&self) -> CryptoMktResult<()> {
+        self.api.validate_market(&self.name).await
+    }
+
+    ///
+    /// Respuesta del cliente ante un 429 del exchange
+    ///
+    pub(crate) fn rate_limit_behavior(&self) -> RateLimitBehavior {
+        self.api.rate_limit_behavior()
+    }
+
     ///
     /// Get the current ticker
     ///
     pub async fn get_current_ticker(&self) -> CryptoMktResult<Ticker> {
+        self.validate().await?;
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
         let resp = self
@@ -76,68 +395,315 @@ impl Market {
     }
 
     ///
-    /// Get the order books
+    /// Trading rules of the market: tick size, lot size, minimum quantity and
+    /// whether it accepts orders. The v1 API doesn't publish them, so they are
+    /// read from the public symbol endpoint of the v3 API, see
+    /// `ClientBuilder::v3_base_url`. Cached as the `symbol` endpoint, see
+    /// `ClientBuilder::cache_ttl`
     ///
-    pub async fn get_orders_book(
+    pub async fn get_limits(&self) -> CryptoMktResult<MarketLimits> {
+        let url = Url::parse(&self.api.v3_domain()?)
+            .and_then(|url| url.join(&format!("public/symbol/{}", self.name.to_uppercase())))
+            .map_err(|e| CryptoMktErrorType::InvalidConfiguration(e.to_string()))?;
+        let symbol: v3::Symbol = self.api.get_external(url, "symbol").await?;
+        Ok(symbol.limits(&self.name))
+    }
+
+    ///
+    /// Cost of an order of `amount` at `price`, with the fee of the account:
+    /// the maker fee if the order would rest on the book, the taker fee if it
+    /// crosses the spread. The price and amount are rounded to the tick and lot
+    /// sizes of the market, see `Market::get_limits`. See `cryptomkt::cost` to
+    /// estimate several orders without fetching the fees each time
+    ///
+    pub async fn estimate_cost(
         &self,
-        orders_type: OrderType,
-        page: u32,
-        limit: u32,
-    ) -> CryptoMktResult<Vec<Book>> {
+        side: Side,
+        amount: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<CostEstimate> {
+        let account =
+            self.api
+                .call::<AccountResponse>(RequestMethod::Get(false), "account", HashMap::new());
+        let (account, ticker, limits) =
+            futures::try_join!(account, self.get_current_ticker(), self.get_limits())?;
+        let liquidity = Liquidity::of(side, price, ticker.bid, ticker.ask);
+        let calculator = TradeCostCalculator::new(account.data.rate).limits(&limits);
+        Ok(calculator.estimate(side, amount, price, liquidity))
+    }
+
+    ///
+    /// Get one side of the order book, `BookSide::Bids` or `BookSide::Asks`.
+    /// A `Side` is taken as the side of the book its orders rest on
+    ///
+    pub async fn get_orders_book<B: Into<BookSide>>(
+        &self,
+        book_side: B,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Book>> {
+        self.validate().await?;
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
-        params.insert("type".to_string(), orders_type.to_string().to_lowercase());
-        params.insert("page".to_string(), format!("{}", page));
-        params.insert("limit".to_string(), format!("{}", limit));
+        params.insert("type".to_string(), book_side.into().side().to_string());
+        page.add_to(&mut params);
 
         let resp = self
             .api
             .call::<BookResponse>(RequestMethod::Get(true), "book", params);
         match resp.await {
-            Ok(value) => Ok(value.data),
+            Ok(value) => Ok(value.into()),
             Err(e) => Err(e),
         }
     }
 
     ///
-    /// Get Trades
+    /// Get both sides of the order book at once, with up to `depth` orders per
+    /// side. Orders at the same price are added up into a single level, and
+    /// `timestamp` is the moment the snapshot was taken
+    ///
+    pub async fn get_order_book_snapshot(&self, depth: u32) -> CryptoMktResult<OrderBook> {
+        let (bids, asks) = futures::try_join!(
+            self.get_orders_book(BookSide::Bids, Page::default().number(0).limit(depth)),
+            self.get_orders_book(BookSide::Asks, Page::default().number(0).limit(depth)),
+        )?;
+        Ok(OrderBook {
+            market: self.name.clone(),
+            bids: price_levels(bids.into_vec(), true),
+            asks: price_levels(asks.into_vec(), false),
+            timestamp: Utc::now(),
+        })
+    }
+
+    ///
+    /// Highest purchase price and the amount offered at it, or `None` when
+    /// nobody is buying. Only the first orders of the book are fetched, so a
+    /// price with many orders may report less than its full amount
+    ///
+    pub async fn best_bid(&self) -> CryptoMktResult<Option<PriceLevel>> {
+        self.best_level(BookSide::Bids).await
+    }
+
+    ///
+    /// Lowest sale price and the amount offered at it, or `None` when nobody
+    /// is selling, see `best_bid`
+    ///
+    pub async fn best_ask(&self) -> CryptoMktResult<Option<PriceLevel>> {
+        self.best_level(BookSide::Asks).await
+    }
+
+    ///
+    /// Difference between the lowest sale price and the highest purchase
+    /// price, or `None` when a side of the book is empty
+    ///
+    pub async fn spread(&self) -> CryptoMktResult<Option<Decimal>> {
+        Ok(self.get_order_book_snapshot(TOP_OF_BOOK_DEPTH).await?.spread())
+    }
+
+    ///
+    /// Mejor nivel de un lado del libro
+    ///
+    /// Argumentos
+    ///     side: Lado del libro
+    ///
+    async fn best_level(&self, side: BookSide) -> CryptoMktResult<Option<PriceLevel>> {
+        let orders = self
+            .get_orders_book(side, Page::default().number(0).limit(TOP_OF_BOOK_DEPTH))
+            .await?;
+        let levels = price_levels(orders.into_vec(), side == BookSide::Bids);
+        Ok(levels.into_iter().next())
+    }
+
+    ///
+    /// Get the price history as candles (open, high, low, close and volume) of
+    /// the purchase and sale prices, newest first
     ///
-    pub async  fn get_trades<'m>(
+    pub async fn get_prices(
         &self,
-        start: &'m str,
-        end: &'m str,
-        page: u32,
-        limit: u32,
-    ) -> CryptoMktResult<Vec<Trade>> {
+        timeframe: Timeframe,
+        page: Page,
+    ) -> CryptoMktResult<Prices> {
+        self.validate().await?;
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
-        params.insert("start".to_string(), start.to_string());
-        params.insert("end".to_string(), end.to_string());
-        params.insert("page".to_string(), format!("{}", page));
-        params.insert("limit".to_string(), format!("{}", limit));
+        params.insert("timeframe".to_string(), timeframe.minutes().to_string());
+        page.add_to(&mut params);
 
         let resp = self
             .api
-            .call::<TradeResponse>(RequestMethod::Get(true), "trades", params);
+            .call::<PricesResponse>(RequestMethod::Get(true), "prices", params);
         match resp.await {
             Ok(value) => Ok(value.data),
             Err(e) => Err(e),
         }
     }
 
+    ///
+    /// Get Trades
+    ///
+    pub async fn get_trades(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Trade>> {
+        let resp = self.trades_query(Some(start), Some(end), page);
+        match resp.await {
+            Ok(value) => Ok(value.into()),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///
+    /// Get Trades as an Arrow `RecordBatch`, see `cryptomkt::columnar` for the schema
+    ///
+    #[cfg(feature = "arrow")]
+    pub async fn get_trades_batch(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        page: Page,
+    ) -> CryptoMktResult<arrow_array::RecordBatch> {
+        let trades = self.get_trades(start, end, page).await?;
+        Ok(crate::columnar::trades_to_record_batch(&trades))
+    }
+
+    ///
+    /// Get every trade between `start` and `end`, fetching page after page
+    /// until the last one.
+    ///
+    /// `cancel` is checked between pages and raced against the in-flight request.
+    /// When it fires, the trades collected so far are returned as `Ok`, so a
+    /// cancelled backfill yields a possibly empty prefix of the full history
+    /// rather than an error. Dropping the returned future also stops any further
+    /// fetches.
+    ///
+    pub async fn get_all_trades(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        cancel: &CancellationToken,
+    ) -> CryptoMktResult<Vec<Trade>> {
+        collect_pages(cancel, |page| self.trades_page(start, end, page)).await
+    }
+
+    ///
+    /// Every trade between `start` and `end` as a stream. Pages are fetched
+    /// lazily, as the trades of the previous one are consumed; a failed
+    /// request yields its error and ends the stream. Each page is parsed as
+    /// its body arrives, so trades are handed out before the whole page is
+    /// downloaded and the page is never buffered. The cache of
+    /// `ClientBuilder::cache_ttl` is not used
+    ///
+    /// ```no_run
+    /// use cryptomkt::{Client, NaiveDate};
+    /// use futures::StreamExt;
+    ///
+    /// # async fn run() {
+    /// let client = Client::new("<API_KEY>", "<API SECRET>");
+    /// let market = client.create_market("ETHCLP");
+    /// let start = NaiveDate::from_ymd_opt(2018, 5, 1).unwrap();
+    /// let end = NaiveDate::from_ymd_opt(2018, 5, 31).unwrap();
+    ///
+    /// let mut trades = Box::pin(market.stream_trades(start, end));
+    /// while let Some(trade) = trades.next().await {
+    ///     println!("{:?}", trade);
+    /// }
+    /// # }
+    /// ```
+    ///
+    pub fn stream_trades(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+    ) -> impl Stream<Item = CryptoMktResult<Trade>> + '_ {
+        stream_incremental_pages(move |page| self.trades_page_stream(start, end, page))
+    }
+
+    ///
+    /// Página `page` de los trades entre `start` y `end`, de `TRADES_PAGE_LIMIT`
+    /// trades, como la piden los recorridos de todas las páginas
+    ///
+    pub(crate) async fn trades_page(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        page: u32,
+    ) -> CryptoMktResult<TradeResponse> {
+        let page = Page::default().number(page).limit(TRADES_PAGE_LIMIT);
+        self.trades_query(Some(start), Some(end), page).await
+    }
+
+    ///
+    /// Pide una página de trades enviando solo los parámetros indicados; el
+    /// exchange aplica sus valores por defecto al resto
+    ///
+    pub(crate) async fn trades_query(
+        &self,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        page: Page,
+    ) -> CryptoMktResult<TradeResponse> {
+        self.validate().await?;
+        let params = self.trades_params(start, end, page);
+        self.api
+            .call::<TradeResponse>(RequestMethod::Get(true), "trades", params)
+            .await
+    }
+
+    ///
+    /// Pide una página de trades entre `start` y `end`, entregando los trades
+    /// a medida que llega el cuerpo de la respuesta
+    ///
+    /// Argumentos
+    ///     start: Fecha inicial
+    ///     end: Fecha final
+    ///     page: Número de página
+    ///
+    pub(crate) async fn trades_page_stream(
+        &self,
+        start: NaiveDate,
+        end: NaiveDate,
+        page: u32,
+    ) -> CryptoMktResult<PageStream<Trade>> {
+        self.validate().await?;
+        let page = Page::default().number(page).limit(TRADES_PAGE_LIMIT);
+        let params = self.trades_params(Some(start), Some(end), page);
+        self.api.get_page_stream("trades", &params).await
+    }
+
+    ///
+    /// Parámetros de una petición de trades
+    ///
+    fn trades_params(
+        &self,
+        start: Option<NaiveDate>,
+        end: Option<NaiveDate>,
+        page: Page,
+    ) -> HashMap<String, String> {
+        let mut params = HashMap::new();
+        params.insert("market".to_string(), self.name.clone());
+        if let Some(start) = start {
+            params.insert("start".to_string(), timestamp::format_date(start));
+        }
+        if let Some(end) = end {
+            params.insert("end".to_string(), timestamp::format_date(end));
+        }
+        page.add_to(&mut params);
+        params
+    }
+
     ///
     /// Get user orders by state
     ///
     pub async fn get_user_orders_by_state(
         &self,
         state: OrderState,
-        page: u32,
-        limit: u32,
-    ) -> CryptoMktResult<Vec<Order>> {
+        page: Page,
+    ) -> CryptoMktResult<Paginated<Order>> {
+        self.validate().await?;
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
-        params.insert("page".to_string(), format!("{}", page));
-        params.insert("limit".to_string(), format!("{}", limit));
+        page.add_to(&mut params);
 
         let endpoint = match state {
             OrderState::Active => "orders/active",
@@ -148,28 +714,182 @@ impl Market {
             .api
             .call::<OrderResponse>(RequestMethod::Get(false), endpoint, params);
         match resp.await {
-            Ok(value) => Ok(value.data),
+            Ok(value) => Ok(value.into()),
             Err(e) => Err(e),
         }
     }
     ///
-    /// Create order
+    /// Get my active orders in this market, see `Market::cancel_order` to
+    /// cancel them
+    ///
+    pub async fn get_active_orders(&self, page: Page) -> CryptoMktResult<Paginated<Order>> {
+        self.get_user_orders_by_state(OrderState::Active, page).await
+    }
+
+    ///
+    /// Get my executed orders in this market, with the executed amount, the fee
+    /// and the execution price of each one
+    ///
+    pub async fn get_executed_orders(&self, page: Page) -> CryptoMktResult<Paginated<Order>> {
+        self.get_user_orders_by_state(OrderState::Executed, page).await
+    }
+
+    ///
+    /// Create a limit order, returning the order as registered by the exchange
+    ///
+    pub async fn create_order(
+        &self,
+        order_type: Side,
+        amount: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        self.place_order(&OrderRequest {
+            order_type,
+            amount,
+            price,
+        })
+        .await
+    }
+
+    ///
+    /// Create several orders at once.
+    ///
+    /// The exchange has no batch endpoint, so orders are submitted individually
+    /// with at most `ORDER_BATCH_CONCURRENCY` requests in flight. The result
+    /// vector matches `orders` position by position, so a partially successful
+    /// batch can be reconciled. Failed placements are never retried: a timed out
+    /// request may still have reached the exchange.
+    ///
+    pub async fn create_orders(&self, orders: &[OrderRequest]) -> Vec<CryptoMktResult<Order>> {
+        // Los futuros se crean antes del stream: un closure dentro de él impide
+        // que el futuro resultante sea `Send` para cualquier lifetime, y por lo
+        // tanto usarlo desde un `async_trait`
+        let placements: Vec<_> = orders.iter().map(|order| self.place_order(order)).collect();
+        stream::iter(placements)
+            .buffered(ORDER_BATCH_CONCURRENCY)
+            .collect()
+            .await
+    }
+
+    ///
+    /// Create a limit order identified by `client_order_id`, which is safe to
+    /// call again when a previous call failed, e.g. with a timeout.
+    ///
+    /// The v1 API has no client order IDs, so they are kept by this client and
+    /// shared by its clones. A retry of an order the exchange registered
+    /// returns that order instead of placing it again. When it's unknown
+    /// whether the previous request reached the exchange, the active and
+    /// executed orders of the market are looked up first: an order with the
+    /// same side, amount and price, created since the first attempt and not
+    /// claimed by another ID, is taken as this one. Only if there's none the
+    /// order is placed again.
+    ///
+    /// Fails with `InvalidConfiguration` when `client_order_id` was used for a
+    /// different order, or while another call is submitting it. See
+    /// `CryptoMktApi::forget_submission` to release the ID
+    ///
+    pub async fn create_order_with_id(
+        &self,
+        client_order_id: &str,
+        order_type: Side,
+        amount: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        self.validate().await?;
+        let request = OrderRequest {
+            order_type,
+            amount,
+            price,
+        };
+        let prior = self
+            .api
+            .begin_submission(client_order_id, &self.name, &request)?;
+        // Si el futuro se descarta a medio camino, el envío queda sin confirmar
+        let _in_flight = InFlight {
+            api: &self.api,
+            client_order_id,
+        };
+        if let PriorSubmission::Placed(order_id) = prior {
+            return self.get_order_status(&order_id).await;
+        }
+        if let PriorSubmission::Unknown(since) = prior {
+            if let Some(order) = self.find_submitted(&request, since).await? {
+                self.api.settle_submission(client_order_id, Some(&order.id));
+                return Ok(order);
+            }
+        }
+        let result = self.place_order(&request).await;
+        match &result {
+            Ok(order) => self.api.settle_submission(client_order_id, Some(&order.id)),
+            // El exchange rechazó la orden, así que no quedó registrada
+            Err(e) if is_rejection(e) => {
+                self.api.forget_submission(client_order_id);
+            }
+            // Un 200 ilegible, un 408 o un 5xx pueden haberla registrado
+            Err(_) => (),
+        }
+        result
+    }
+
+    ///
+    /// Registra un envío de `client_order_id` hecho por otro proceso, ver
+    /// `CryptoMktApi::restore_submission`
+    ///
+    /// Argumentos
+    ///     client_order_id: Identificador del cliente
+    ///     request: Orden enviada
+    ///     submitted_at: Fecha local del primer envío
+    ///
+    pub(crate) fn restore_submission(
+        &self,
+        client_order_id: &str,
+        request: &OrderRequest,
+        submitted_at: DateTime<Utc>,
+    ) {
+        self.api
+            .restore_submission(client_order_id, &self.name, request, submitted_at);
+    }
+
+    ///
+    /// Busca entre las órdenes activas y ejecutadas una orden igual a `request`
+    /// creada desde `since`, que no tenga ya un identificador del cliente. Si
+    /// hay varias, la más antigua
     ///
-    pub async  fn create_order(
+    async fn find_submitted(
         &self,
-        order_type: OrderType,
-        amount: f32,
-        price: f32,
-    ) -> CryptoMktResult<Vec<Order>> {
+        request: &OrderRequest,
+        since: DateTime<Utc>,
+    ) -> CryptoMktResult<Option<Order>> {
+        let (active, executed) = futures::try_join!(
+            self.get_active_orders(Page::default().number(0).limit(SUBMISSION_LOOKUP_LIMIT)),
+            self.get_executed_orders(Page::default().number(0).limit(SUBMISSION_LOOKUP_LIMIT)),
+        )?;
+        let claimed = self.api.submitted_order_ids();
+        let since = since - chrono::Duration::seconds(SUBMISSION_CLOCK_TOLERANCE);
+        Ok(active
+            .into_iter()
+            .chain(executed)
+            .filter(|order| {
+                order.side() == Some(request.order_type)
+                    && order.amount.original == request.amount
+                    && order.price == request.price
+                    && order.created_at >= since
+                    && !claimed.contains(&order.id)
+            })
+            .min_by_key(|order| order.created_at))
+    }
+
+    async fn place_order(&self, order: &OrderRequest) -> CryptoMktResult<Order> {
+        self.validate().await?;
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
-        params.insert("amount".to_string(), format!("{}", amount));
-        params.insert("price".to_string(), format!("{}", price));
-        params.insert("type".to_string(), order_type.to_string().to_lowercase());
+        params.insert("amount".to_string(), format!("{}", order.amount));
+        params.insert("price".to_string(), format!("{}", order.price));
+        params.insert("type".to_string(), order.order_type.to_string());
 
         let resp = self
             .api
-            .call::<OrderResponse>(RequestMethod::Post, "orders/create", params);
+            .call::<SimpleOrderResponse>(RequestMethod::Post, "orders/create", params);
         match resp.await {
             Ok(value) => Ok(value.data),
             Err(e) => Err(e),
@@ -179,7 +899,7 @@ impl Market {
     ///
     /// Get Order status
     ///
-    pub async fn get_order_status<'m>(&self, order_id: &'m str) -> CryptoMktResult<Order> {
+    pub async fn get_order_status(&self, order_id: &str) -> CryptoMktResult<Order> {
         let mut params = HashMap::new();
         params.insert("id".to_string(), order_id.to_string());
 
@@ -194,10 +914,26 @@ impl Market {
         }
     }
 
+    ///
+    /// Cancel several orders at once.
+    ///
+    /// Like `Market::create_orders`, each order is cancelled with its own
+    /// request, at most `ORDER_BATCH_CONCURRENCY` at a time, and the result
+    /// vector matches `order_ids` position by position: an order that couldn't
+    /// be cancelled reports its error without affecting the others.
+    ///
+    pub async fn cancel_orders(&self, order_ids: &[&str]) -> Vec<CryptoMktResult<Order>> {
+        let cancellations: Vec<_> = order_ids.iter().map(|id| self.cancel_order(id)).collect();
+        stream::iter(cancellations)
+            .buffered(ORDER_BATCH_CONCURRENCY)
+            .collect()
+            .await
+    }
+
     ///
     /// Cancel Order
     ///
-    pub async fn cancel_order<'m>(&self, order_id: &'m str) -> CryptoMktResult<Order> {
+    pub async fn cancel_order(&self, order_id: &str) -> CryptoMktResult<Order> {
         let mut params = HashMap::new();
         params.insert("id".to_string(), order_id.to_string());
 
@@ -211,25 +947,22 @@ impl Market {
     }
 
     ///
-    /// Get order instant
+    /// Preview an instant order: how much would be obtained by buying or
+    /// selling `amount` right now in the Instant Exchange of CryptoMarket.
     ///
-    /// An instant order corresponds to a purchase or sale request within the
-    /// Instant Exchange of CryptoMarket.
+    /// For a purchase `amount` is the local currency to spend, for a sale the
+    /// cryptocurrency to sell.
     ///
-    pub async fn get_order_instant(
+    pub async fn quote_instant(
         &self,
-        order_type: OrderType,
-        amount: f32,
+        side: Side,
+        amount: Decimal,
     ) -> CryptoMktResult<OrdersInstant> {
-        let mut params = HashMap::new();
-        params.insert("market".to_string(), self.name.clone());
-        params.insert("amount".to_string(), format!("{}", amount));
-        params.insert("type".to_string(), order_type.to_string().to_lowercase());
-
+        self.validate().await?;
         let resp = self.api.call::<OrdersInstantResponse>(
             RequestMethod::Get(false),
             "orders/instant/get",
-            params,
+            self.instant_params(side, amount),
         );
         match resp.await {
             Ok(value) => Ok(value.data),
@@ -237,25 +970,72 @@ impl Market {
         }
     }
 
+    ///
+    /// Buy or sell `amount` right now at the market price through the Instant
+    /// Exchange of CryptoMarket, see `Market::quote_instant` to preview it
+    ///
+    pub async fn execute_instant(&self, side: Side, amount: Decimal) -> CryptoMktResult<String> {
+        self.validate().await?;
+        let resp = self.api.call::<EmptyResponse>(
+            RequestMethod::Post,
+            "orders/instant/create",
+            self.instant_params(side, amount),
+        );
+        match resp.await {
+            Ok(value) => Ok(value.data),
+            Err(e) => Err(e),
+        }
+    }
+
+    ///
+    /// Get order instant
+    ///
+    #[deprecated(note = "use `Market::quote_instant`")]
+    pub async fn get_order_instant(
+        &self,
+        order_type: Side,
+        amount: Decimal,
+    ) -> CryptoMktResult<OrdersInstant> {
+        self.quote_instant(order_type, amount).await
+    }
+
     ///
     /// Create an instant order in the Instant Exchange of CryptoMarket
     ///
+    #[deprecated(note = "use `Market::execute_instant`")]
     pub async fn create_order_instant(
         &self,
-        order_type: OrderType,
-        amount: f32,
+        order_type: Side,
+        amount: Decimal,
     ) -> CryptoMktResult<String> {
+        self.execute_instant(order_type, amount).await
+    }
+
+    fn instant_params(&self, side: Side, amount: Decimal) -> HashMap<String, String> {
         let mut params = HashMap::new();
         params.insert("market".to_string(), self.name.clone());
         params.insert("amount".to_string(), format!("{}", amount));
-        params.insert("type".to_string(), order_type.to_string().to_lowercase());
+        params.insert("type".to_string(), side.to_string());
+        params
+    }
+}
 
-        let resp =
-            self.api
-                .call::<EmptyResponse>(RequestMethod::Post, "orders/instant/create", params);
-        match resp.await {
-            Ok(value) => Ok(value.data),
-            Err(e) => Err(e),
-        }
+///
+/// Groups the orders of one side of the book by price, sorted from the best one
+///
+fn price_levels(orders: Vec<Book>, descending: bool) -> Vec<PriceLevel> {
+    let mut amounts = BTreeMap::new();
+    for order in orders {
+        *amounts.entry(order.price).or_insert_with(Decimal::default) += order.amount;
     }
+    let mut levels = Vec::new();
+    OrderBook::merge_levels(
+        &mut levels,
+        amounts
+            .into_iter()
+            .map(|(price, amount)| PriceLevel { price, amount })
+            .collect(),
+        descending,
+    );
+    levels
 }
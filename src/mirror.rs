@@ -0,0 +1,248 @@
+//!
+//! ## Order book mirror
+//!
+//! `OrderBookMirror` keeps a local copy of the order book of a market, built
+//! from REST snapshots taken with `Market::get_order_book_snapshot`. Each
+//! refresh compares the new snapshot with the previous one and reports the
+//! levels that changed as a `BookDiff`, so strategies can react to changes in
+//! the book without a WebSocket connection.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::mirror::OrderBookMirror;
+//! use cryptomkt::Client;
+//! use futures::StreamExt;
+//!
+//! # async fn run() {
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! let mirror = OrderBookMirror::new(client.create_market("ETHCLP"), 50);
+//!
+//! let reader = mirror.clone();
+//! tokio::spawn(async move {
+//!     let mut diffs = Box::pin(reader.diffs(Duration::from_secs(5)));
+//!     while let Some(diff) = diffs.next().await {
+//!         println!("{:?}", diff);
+//!     }
+//! });
+//!
+//! println!("spread: {:?}", mirror.spread());
+//! # }
+//! ```
+//!
+//! Snapshots only include the best `depth` orders of each side, so a level
+//! leaving that window is reported as removed.
+//!
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use futures::stream::{self, Stream};
+use rust_decimal::Decimal;
+use serde::{Deserialize, Serialize};
+
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::models::{OrderBook, PriceLevel};
+use crate::internal::rt;
+use crate::market::{BookSide, Market};
+
+///
+/// Change of the amount available at a price between two snapshots. An amount
+/// of zero means the level isn't in the book
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+pub struct LevelChange {
+    /// Price of the level
+    pub price: Decimal,
+    /// Amount in the previous snapshot
+    pub before: Decimal,
+    /// Amount in the new snapshot
+    pub after: Decimal,
+}
+
+impl LevelChange {
+    ///
+    /// Whether the level appeared in the new snapshot
+    ///
+    pub fn is_added(&self) -> bool {
+        self.before.is_zero() && !self.after.is_zero()
+    }
+
+    ///
+    /// Whether the level is gone from the new snapshot
+    ///
+    pub fn is_removed(&self) -> bool {
+        !self.before.is_zero() && self.after.is_zero()
+    }
+}
+
+///
+/// Levels that changed between two snapshots of an order book, best price
+/// first on each side
+///
+#[derive(Serialize, Deserialize, Debug, Clone, Default, PartialEq)]
+pub struct BookDiff {
+    /// Changes of the purchase side, highest price first
+    pub bids: Vec<LevelChange>,
+    /// Changes of the sale side, lowest price first
+    pub asks: Vec<LevelChange>,
+    /// Date of the new snapshot
+    pub timestamp: DateTime<Utc>,
+}
+
+impl BookDiff {
+    ///
+    /// Levels that changed from `before` to `after`
+    ///
+    pub fn between(before: &OrderBook, after: &OrderBook) -> Self {
+        let mut bids = side_changes(&before.bids, &after.bids);
+        bids.reverse();
+        BookDiff {
+            bids,
+            asks: side_changes(&before.asks, &after.asks),
+            timestamp: after.timestamp,
+        }
+    }
+
+    ///
+    /// Whether nothing changed
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.bids.is_empty() && self.asks.is_empty()
+    }
+
+    ///
+    /// Changes of one side of the book
+    ///
+    pub fn side(&self, side: BookSide) -> &[LevelChange] {
+        match side {
+            BookSide::Bids => &self.bids,
+            BookSide::Asks => &self.asks,
+        }
+    }
+}
+
+///
+/// Cambios de un lado del libro, ordenados de menor a mayor precio
+///
+fn side_changes(before: &[PriceLevel], after: &[PriceLevel]) -> Vec<LevelChange> {
+    let mut levels: BTreeMap<Decimal, (Decimal, Decimal)> = BTreeMap::new();
+    for level in before {
+        levels.entry(level.price).or_default().0 += level.amount;
+    }
+    for level in after {
+        levels.entry(level.price).or_default().1 += level.amount;
+    }
+    levels
+        .into_iter()
+        .filter(|(_, (before, after))| before != after)
+        .map(|(price, (before, after))| LevelChange {
+            price,
+            before,
+            after,
+        })
+        .collect()
+}
+
+///
+/// Local copy of the order book of a market, see the module docs. Clones
+/// share the book, so one clone can refresh it while others read it
+///
+#[derive(Debug, Clone)]
+pub struct OrderBookMirror {
+    market: Market,
+    depth: u32,
+    book: Arc<RwLock<Option<OrderBook>>>,
+}
+
+impl OrderBookMirror {
+    ///
+    /// Mirror of the best `depth` orders of each side of `market`. The book is
+    /// empty until the first refresh
+    ///
+    pub fn new(market: Market, depth: u32) -> Self {
+        OrderBookMirror {
+            market,
+            depth,
+            book: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    ///
+    /// Take a new snapshot and return what changed since the previous one. The
+    /// first refresh reports every level as added. On error the book is kept
+    ///
+    pub async fn refresh(&self) -> CryptoMktResult<BookDiff> {
+        let snapshot = self.market.get_order_book_snapshot(self.depth).await?;
+        let mut book = self.book.write().unwrap();
+        let empty = OrderBook::default();
+        let diff = BookDiff::between(book.as_ref().unwrap_or(&empty), &snapshot);
+        *book = Some(snapshot);
+        Ok(diff)
+    }
+
+    ///
+    /// Refresh the book every `interval`, yielding the diff of each refresh.
+    /// The first refresh happens right away. Failed refreshes yield their error
+    /// and the stream goes on; drop it to stop refreshing
+    ///
+    pub fn diffs(&self, interval: Duration) -> impl Stream<Item = CryptoMktResult<BookDiff>> + '_ {
+        let ticks = rt::interval(interval);
+        stream::unfold(ticks, move |mut ticks| async move {
+            ticks.tick().await;
+            Some((self.refresh().await, ticks))
+        })
+    }
+
+    ///
+    /// Copy of the last snapshot, `None` before the first refresh
+    ///
+    pub fn book(&self) -> Option<OrderBook> {
+        self.book.read().unwrap().clone()
+    }
+
+    ///
+    /// Highest purchase price of the last snapshot
+    ///
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.with_book(|book| book.best_bid().cloned())
+    }
+
+    ///
+    /// Lowest sale price of the last snapshot
+    ///
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.with_book(|book| book.best_ask().cloned())
+    }
+
+    ///
+    /// Difference between the best sale and purchase prices, `None` when a
+    /// side is empty. Both prices come from the same snapshot
+    ///
+    pub fn spread(&self) -> Option<Decimal> {
+        self.with_book(|book| book.spread())
+    }
+
+    ///
+    /// Amount offered at exactly `price`, on either side. Zero when there's no
+    /// level at that price
+    ///
+    pub fn depth_at(&self, price: Decimal) -> Decimal {
+        self.with_book(|book| {
+            let levels = book.bids.iter().chain(&book.asks);
+            Some(
+                levels
+                    .filter(|level| level.price == price)
+                    .map(|level| level.amount)
+                    .sum(),
+            )
+        })
+        .unwrap_or_default()
+    }
+
+    fn with_book<T>(&self, f: impl FnOnce(&OrderBook) -> Option<T>) -> Option<T> {
+        self.book.read().unwrap().as_ref().and_then(f)
+    }
+}
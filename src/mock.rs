@@ -0,0 +1,243 @@
+//!
+//! ## Offline testing
+//!
+//! `MockTransport` answers the requests of a `Client` with canned JSON bodies
+//! instead of calling the exchange, and records every request it gets, so
+//! trading logic can be unit tested without network access.
+//!
+//! ```
+//! use cryptomkt::mock::MockTransport;
+//! use cryptomkt::Client;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let transport = MockTransport::with_fixtures();
+//!     let client = Client::builder()
+//!         .credentials("<API_KEY>", "<API SECRET>")
+//!         .http_transport(transport.clone())
+//!         .build()
+//!         .unwrap();
+//!
+//!     let balance = client.get_balance().await.unwrap();
+//!     assert_eq!(balance[0].wallet, "CLP");
+//!     assert_eq!(transport.requests()[0].endpoint, "balance");
+//! }
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Url};
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::request::{status_kind, HttpRequest};
+
+///
+/// JSON bodies of typical successful responses of the exchange
+///
+pub mod fixtures {
+    /// `GET market`: ETHCLP, ETHARS and BTCCLP
+    pub const MARKETS: &str = include_str!("../fixtures/markets.json");
+    /// `GET ticker`: ETHCLP ticker
+    pub const TICKER: &str = include_str!("../fixtures/ticker.json");
+    /// `GET orders/active`: two active buy orders in ETHCLP
+    pub const ORDERS: &str = include_str!("../fixtures/orders.json");
+    /// `GET balance`: CLP and ETH wallets
+    pub const BALANCE: &str = include_str!("../fixtures/balance.json");
+}
+
+///
+/// Request received by a `MockTransport`
+///
+#[derive(Debug, Clone)]
+pub struct RecordedRequest {
+    /// `GET`, `POST` or `DELETE`
+    pub method: &'static str,
+    /// Endpoint, e.g. `orders/active`, or the whole path when no response
+    /// was registered for it
+    pub endpoint: String,
+    /// Query parameters of a GET, or form payload of a POST
+    pub params: HashMap<String, String>,
+    /// Headers, including the signature of private requests
+    pub headers: HeaderMap,
+}
+
+///
+/// HTTP transport answering from registered responses, see the module docs.
+///
+/// Responses are looked up by method and endpoint, e.g. `on_get("ticker", ...)`
+/// answers every ticker request whatever its parameters. Requests to endpoints
+/// without a response fail with `RequestNotFound`. Clones share responses and
+/// recorded requests, so a clone can be kept for assertions after handing the
+/// transport to `ClientBuilder::http_transport`.
+///
+#[derive(Debug, Clone, Default)]
+pub struct MockTransport {
+    responses: Arc<Mutex<HashMap<(&'static str, String), Answer>>>,
+    requests: Arc<Mutex<Vec<RecordedRequest>>>,
+}
+
+///
+/// Respuesta registrada para un endpoint: un cuerpo, un estado HTTP de error o
+/// un fallo de conexión (`None`)
+///
+#[derive(Debug, Clone)]
+enum Answer {
+    Body(String),
+    Failure(Option<u16>),
+}
+
+impl MockTransport {
+    ///
+    /// Create a transport without responses
+    ///
+    pub fn new() -> Self {
+        MockTransport::default()
+    }
+
+    ///
+    /// Create a transport answering `market`, `ticker`, `orders/active` and
+    /// `balance` with the bodies in `fixtures`
+    ///
+    pub fn with_fixtures() -> Self {
+        MockTransport::new()
+            .on_get("market", fixtures::MARKETS)
+            .on_get("ticker", fixtures::TICKER)
+            .on_get("orders/active", fixtures::ORDERS)
+            .on_get("balance", fixtures::BALANCE)
+    }
+
+    ///
+    /// Answer GET requests to `endpoint` with `body`
+    ///
+    pub fn on_get(self, endpoint: &str, body: &str) -> Self {
+        self.respond("GET", endpoint, body)
+    }
+
+    ///
+    /// Answer POST requests to `endpoint` with `body`
+    ///
+    pub fn on_post(self, endpoint: &str, body: &str) -> Self {
+        self.respond("POST", endpoint, body)
+    }
+
+    ///
+    /// Answer DELETE requests to `endpoint` with `body`
+    ///
+    pub fn on_delete(self, endpoint: &str, body: &str) -> Self {
+        self.respond("DELETE", endpoint, body)
+    }
+
+    ///
+    /// Fail GET requests to `endpoint` like the HTTP transport does: with the
+    /// error kind of `status`, e.g. `RequestForbidden` for 403, or with a
+    /// `BadRequest` without status when `status` is `None`, as if the exchange
+    /// couldn't be reached
+    ///
+    pub fn fail_get(self, endpoint: &str, status: Option<u16>) -> Self {
+        self.register("GET", endpoint, Answer::Failure(status))
+    }
+
+    ///
+    /// Fail POST requests to `endpoint`, see `MockTransport::fail_get`
+    ///
+    pub fn fail_post(self, endpoint: &str, status: Option<u16>) -> Self {
+        self.register("POST", endpoint, Answer::Failure(status))
+    }
+
+    fn respond(self, method: &'static str, endpoint: &str, body: &str) -> Self {
+        self.register(method, endpoint, Answer::Body(body.to_string()))
+    }
+
+    fn register(self, method: &'static str, endpoint: &str, answer: Answer) -> Self {
+        self.responses
+            .lock()
+            .unwrap()
+            .insert((method, endpoint.trim_matches('/').to_string()), answer);
+        self
+    }
+
+    ///
+    /// Requests received so far, oldest first
+    ///
+    pub fn requests(&self) -> Vec<RecordedRequest> {
+        self.requests.lock().unwrap().clone()
+    }
+
+    ///
+    /// Registra la petición y devuelve la respuesta del endpoint más largo que
+    /// coincide con el final de la ruta
+    ///
+    fn answer(
+        &self,
+        method: &'static str,
+        url: &Url,
+        headers: HeaderMap,
+        params: HashMap<String, String>,
+    ) -> CryptoMktResult<String> {
+        let path = url.path();
+        let responses = self.responses.lock().unwrap();
+        let matched = responses
+            .iter()
+            .filter(|((m, endpoint), _)| {
+                *m == method && path.ends_with(&format!("/{}", endpoint))
+            })
+            .max_by_key(|((_, endpoint), _)| endpoint.len());
+
+        let endpoint = match matched {
+            Some(((_, endpoint), _)) => endpoint.clone(),
+            None => path.trim_start_matches('/').to_string(),
+        };
+        self.requests.lock().unwrap().push(RecordedRequest {
+            method,
+            endpoint,
+            params,
+            headers,
+        });
+
+        match matched {
+            Some((_, Answer::Body(body))) => Ok(body.clone()),
+            Some((_, Answer::Failure(Some(status)))) => {
+                let kind = StatusCode::from_u16(*status)
+                    .map(status_kind)
+                    .unwrap_or(CryptoMktErrorType::BadRequest);
+                Err(CryptoMktError::http(kind, *status, path, String::new()))
+            }
+            Some((_, Answer::Failure(None))) => Err(CryptoMktErrorType::BadRequest.into()),
+            None => Err(CryptoMktError::http(
+                CryptoMktErrorType::RequestNotFound,
+                404,
+                path,
+                String::new(),
+            )),
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpRequest for MockTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        let params = url.query_pairs().into_owned().collect();
+        self.answer("GET", &url, headers, params)
+    }
+
+    async fn post(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> Self::Result {
+        self.answer("POST", &url, headers, payload)
+    }
+
+    async fn delete(&self, url: Url, headers: HeaderMap) -> CryptoMktResult<String> {
+        let params = url.query_pairs().into_owned().collect();
+        self.answer("DELETE", &url, headers, params)
+    }
+}
@@ -0,0 +1,327 @@
+//!
+//! ## Record and replay
+//!
+//! `RecordingTransport` sends the requests of a `Client` to the exchange and
+//! saves every answer to a JSON *cassette* file. `ReplayTransport` serves the
+//! answers of a cassette back without network access, so the trading logic
+//! that produced them can be tested against real responses, deterministically.
+//!
+//! ```no_run
+//! use cryptomkt::replay::{RecordingTransport, ReplayTransport};
+//! use cryptomkt::Client;
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! // Once, against the exchange
+//! let client = Client::builder()
+//!     .credentials("<API_KEY>", "<API SECRET>")
+//!     .http_transport(RecordingTransport::new("tests/cassettes/balance.json"))
+//!     .build()?;
+//! client.get_balance().await?;
+//!
+//! // In the tests
+//! let client = Client::builder()
+//!     .credentials("<API_KEY>", "<API SECRET>")
+//!     .http_transport(ReplayTransport::from_file("tests/cassettes/balance.json")?)
+//!     .build()?;
+//! let balance = client.get_balance().await?;
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! Requests are matched by method, path and parameters; headers, which carry
+//! the signature and its timestamp, are ignored. Each recorded answer is served
+//! once, in the recorded order, so a sequence of calls to the same endpoint
+//! replays the same sequence of answers. Error responses of the exchange are
+//! recorded too; failures without a response (timeouts, connection errors) are
+//! not.
+//!
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use reqwest::header::HeaderMap;
+use reqwest::{StatusCode, Url};
+use serde::{Deserialize, Serialize};
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::request::{
+    status_kind, ConnectionStatus, CryptoMktRequest, HttpRequest, Transport, TransportStats,
+};
+use crate::internal::trace::error;
+
+///
+/// Petición y respuesta guardadas en un cassette
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Interaction {
+    method: String,
+    path: String,
+    /// Parámetros de la query, o el formulario de un POST
+    params: BTreeMap<String, String>,
+    status: u16,
+    body: String,
+}
+
+impl Interaction {
+    fn matches(&self, method: &str, path: &str, params: &BTreeMap<String, String>) -> bool {
+        self.method == method && self.path == path && self.params == *params
+    }
+
+    ///
+    /// Respuesta tal como la devolvería el transporte
+    ///
+    fn response(&self) -> CryptoMktResult<String> {
+        match StatusCode::from_u16(self.status) {
+            Ok(StatusCode::OK) => Ok(self.body.clone()),
+            Ok(status) => Err(CryptoMktError::http(
+                status_kind(status),
+                self.status,
+                &self.path,
+                self.body.clone(),
+            )),
+            Err(_) => Err(CryptoMktError::http(
+                CryptoMktErrorType::BadRequest,
+                self.status,
+                &self.path,
+                self.body.clone(),
+            )),
+        }
+    }
+}
+
+fn query(url: &Url) -> BTreeMap<String, String> {
+    url.query_pairs().into_owned().collect()
+}
+
+fn io_error(path: &Path, e: impl std::fmt::Display) -> CryptoMktError {
+    CryptoMktErrorType::InvalidConfiguration(format!("{}: {}", path.display(), e)).into()
+}
+
+///
+/// HTTP transport saving every answer of the exchange to a cassette file,
+/// see the module docs. The file is rewritten after each request, so it's
+/// complete even if the process is interrupted. Clones share the cassette.
+///
+/// Like any transport given to `ClientBuilder::http_transport`, the
+/// `timeout`, `proxy` and `middleware` options of the builder don't apply.
+///
+#[derive(Clone)]
+pub struct RecordingTransport {
+    inner: Transport,
+    path: Arc<PathBuf>,
+    interactions: Arc<Mutex<Vec<Interaction>>>,
+}
+
+impl RecordingTransport {
+    ///
+    /// Record the answers of the exchange to the file at `path`
+    ///
+    pub fn new<P: AsRef<Path>>(path: P) -> Self {
+        RecordingTransport::wrap(CryptoMktRequest::new(), path)
+    }
+
+    ///
+    /// Record the answers of `transport` to the file at `path`
+    ///
+    pub fn wrap<R, P>(transport: R, path: P) -> Self
+    where
+        R: HttpRequest<Result = CryptoMktResult<String>> + 'static,
+        P: AsRef<Path>,
+    {
+        RecordingTransport {
+            inner: Transport::new(transport),
+            path: Arc::new(path.as_ref().to_path_buf()),
+            interactions: Arc::new(Mutex::new(Vec::new())),
+        }
+    }
+
+    ///
+    /// Number of answers recorded so far
+    ///
+    pub fn len(&self) -> usize {
+        self.interactions.lock().unwrap().len()
+    }
+
+    ///
+    /// Whether nothing was recorded yet
+    ///
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    ///
+    /// Write the cassette to its file
+    ///
+    pub fn save(&self) -> CryptoMktResult<()> {
+        let json = serde_json::to_string_pretty(&*self.interactions.lock().unwrap())
+            .map_err(|e| io_error(&self.path, e))?;
+        fs::write(&*self.path, json).map_err(|e| io_error(&self.path, e))
+    }
+
+    ///
+    /// Guarda la respuesta de una petición, si el exchange respondió
+    ///
+    fn record(
+        &self,
+        method: &str,
+        url: &Url,
+        params: BTreeMap<String, String>,
+        result: &CryptoMktResult<String>,
+    ) {
+        let (status, body) = match result {
+            Ok(body) => (StatusCode::OK.as_u16(), body.clone()),
+            Err(CryptoMktError::Http { status, body, .. }) => (*status, body.clone()),
+            Err(_) => return,
+        };
+        self.interactions.lock().unwrap().push(Interaction {
+            method: method.to_string(),
+            path: url.path().to_string(),
+            params,
+            status,
+            body,
+        });
+        if let Err(e) = self.save() {
+            error!(target: "cryptomkt", "Recording: {}", e);
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpRequest for RecordingTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        let result = self.inner.get(url.clone(), headers).await;
+        self.record("GET", &url, query(&url), &result);
+        result
+    }
+
+    async fn post(
+        &self,
+        url: Url,
+        headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> Self::Result {
+        let params = payload.clone().into_iter().collect();
+        let result = self.inner.post(url.clone(), headers, payload).await;
+        self.record("POST", &url, params, &result);
+        result
+    }
+
+    async fn delete(&self, url: Url, headers: HeaderMap) -> CryptoMktResult<String> {
+        let result = self.inner.delete(url.clone(), headers).await;
+        self.record("DELETE", &url, query(&url), &result);
+        result
+    }
+
+    fn connection_status(&self) -> ConnectionStatus {
+        self.inner.connection_status()
+    }
+
+    fn stats(&self) -> TransportStats {
+        self.inner.stats()
+    }
+}
+
+///
+/// HTTP transport answering from a cassette written by `RecordingTransport`,
+/// see the module docs. Requests without a pending recorded answer fail with
+/// `RequestNotFound`. Clones share the cassette and the answers already served.
+///
+#[derive(Debug, Clone)]
+pub struct ReplayTransport {
+    interactions: Arc<Vec<Interaction>>,
+    served: Arc<Mutex<Vec<bool>>>,
+}
+
+impl ReplayTransport {
+    ///
+    /// Load the cassette at `path`. Fails with `InvalidConfiguration` when it
+    /// can't be read or isn't a cassette
+    ///
+    pub fn from_file<P: AsRef<Path>>(path: P) -> CryptoMktResult<Self> {
+        let path = path.as_ref();
+        let json = fs::read_to_string(path).map_err(|e| io_error(path, e))?;
+        ReplayTransport::from_json(&json).map_err(|e| match e.kind() {
+            CryptoMktErrorType::InvalidConfiguration(msg) => io_error(path, msg),
+            _ => e,
+        })
+    }
+
+    ///
+    /// Load a cassette from its contents
+    ///
+    pub fn from_json(json: &str) -> CryptoMktResult<Self> {
+        let interactions: Vec<Interaction> = serde_json::from_str(json).map_err(|e| {
+            CryptoMktError::from(CryptoMktErrorType::InvalidConfiguration(e.to_string()))
+        })?;
+        Ok(ReplayTransport {
+            served: Arc::new(Mutex::new(vec![false; interactions.len()])),
+            interactions: Arc::new(interactions),
+        })
+    }
+
+    ///
+    /// Number of recorded answers not served yet
+    ///
+    pub fn remaining(&self) -> usize {
+        self.served.lock().unwrap().iter().filter(|served| !**served).count()
+    }
+
+    ///
+    /// Sirve la primera respuesta pendiente que coincide con la petición
+    ///
+    fn answer(
+        &self,
+        method: &str,
+        url: &Url,
+        params: BTreeMap<String, String>,
+    ) -> CryptoMktResult<String> {
+        let mut served = self.served.lock().unwrap();
+        let found = self.interactions.iter().enumerate().find(|(i, interaction)| {
+            !served[*i] && interaction.matches(method, url.path(), &params)
+        });
+        match found {
+            Some((i, interaction)) => {
+                served[i] = true;
+                interaction.response()
+            }
+            None => {
+                error!(target: "cryptomkt", "Replay: no recorded answer for {} {}", method, url);
+                Err(CryptoMktError::http(
+                    CryptoMktErrorType::RequestNotFound,
+                    404,
+                    url.path(),
+                    String::new(),
+                ))
+            }
+        }
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl HttpRequest for ReplayTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, _headers: HeaderMap) -> Self::Result {
+        self.answer("GET", &url, query(&url))
+    }
+
+    async fn post(
+        &self,
+        url: Url,
+        _headers: HeaderMap,
+        payload: HashMap<String, String>,
+    ) -> Self::Result {
+        self.answer("POST", &url, payload.into_iter().collect())
+    }
+
+    async fn delete(&self, url: Url, _headers: HeaderMap) -> CryptoMktResult<String> {
+        self.answer("DELETE", &url, query(&url))
+    }
+}
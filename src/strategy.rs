@@ -0,0 +1,225 @@
+//!
+//! ## Strategy runner
+//!
+//! Skeleton of a trading bot (feature `strategy`): a `Runner` calls a
+//! `Strategy` once per tick until it's asked to stop, then gives it a last
+//! chance to clean up, e.g. to cancel its open orders. With `dry_run` the
+//! orders are simulated against the live market instead of being sent, see
+//! `ClientBuilder::paper_trading`.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use async_trait::async_trait;
+//! use cryptomkt::strategy::{Context, Runner, Strategy};
+//! use cryptomkt::{Client, CryptoMktResult};
+//!
+//! struct PrintTicker;
+//!
+//! #[async_trait]
+//! impl Strategy for PrintTicker {
+//!     async fn on_tick(&mut self, ctx: &Context) -> CryptoMktResult<()> {
+//!         let ticker = ctx.client().create_market("ETHCLP").get_current_ticker().await?;
+//!         println!("{} {}", ctx.tick(), ticker.last_price);
+//!         Ok(())
+//!     }
+//! }
+//!
+//! # async fn run() -> CryptoMktResult<()> {
+//! let runner = Runner::new(Client::builder().credentials("<API_KEY>", "<API SECRET>"))
+//!     .interval(Duration::from_secs(10))
+//!     .dry_run(true);
+//! let shutdown = runner.shutdown_token();
+//! tokio::spawn(async move {
+//!     tokio::signal::ctrl_c().await.ok();
+//!     shutdown.cancel();
+//! });
+//! runner.run(PrintTicker).await
+//! # }
+//! ```
+//!
+//! A tick is never interrupted: once the runner is asked to stop, the tick in
+//! progress finishes, `Strategy::on_shutdown` runs and `Runner::run` returns.
+//! See `examples/market_maker.rs` for a complete strategy.
+//!
+
+use std::time::Duration;
+
+use async_trait::async_trait;
+use tokio::time::MissedTickBehavior;
+use tokio_util::sync::CancellationToken;
+
+use crate::client::{Client, ClientBuilder};
+use crate::internal::errors::CryptoMktResult;
+use crate::internal::trace::{error, warn};
+
+/// Time between ticks, unless `Runner::interval` says otherwise
+pub const DEFAULT_TICK_INTERVAL: Duration = Duration::from_secs(5);
+/// Failed ticks in a row after which the runner stops, unless
+/// `Runner::max_consecutive_errors` says otherwise
+pub const DEFAULT_MAX_CONSECUTIVE_ERRORS: u32 = 5;
+
+///
+/// Trading logic driven by a `Runner`
+///
+#[async_trait]
+pub trait Strategy: Send {
+    ///
+    /// Called once per tick. An error is logged and the next tick runs as
+    /// usual, unless too many ticks failed in a row
+    ///
+    async fn on_tick(&mut self, ctx: &Context) -> CryptoMktResult<()>;
+
+    ///
+    /// Called once when the runner stops, whatever the reason. Does nothing
+    /// by default
+    ///
+    async fn on_shutdown(&mut self, _ctx: &Context) -> CryptoMktResult<()> {
+        Ok(())
+    }
+}
+
+///
+/// What a `Strategy` gets to see at each tick
+///
+#[derive(Debug)]
+pub struct Context {
+    client: Client,
+    dry_run: bool,
+    tick: u64,
+    shutdown: CancellationToken,
+}
+
+impl Context {
+    ///
+    /// Client to query the exchange and place orders with. Orders are
+    /// simulated when the runner is in dry-run mode
+    ///
+    pub fn client(&self) -> &Client {
+        &self.client
+    }
+
+    ///
+    /// Whether orders are simulated instead of sent to the exchange
+    ///
+    pub fn is_dry_run(&self) -> bool {
+        self.dry_run
+    }
+
+    ///
+    /// Number of the current tick, starting at 1. Still the number of the last
+    /// tick in `Strategy::on_shutdown`
+    ///
+    pub fn tick(&self) -> u64 {
+        self.tick
+    }
+
+    ///
+    /// Ask the runner to stop after the current tick
+    ///
+    pub fn shutdown(&self) {
+        self.shutdown.cancel();
+    }
+}
+
+///
+/// Tick loop running a `Strategy`, see the module docs
+///
+#[derive(Debug)]
+pub struct Runner {
+    builder: ClientBuilder,
+    dry_run: bool,
+    interval: Duration,
+    max_consecutive_errors: u32,
+    shutdown: CancellationToken,
+}
+
+impl Runner {
+    ///
+    /// Runner whose strategy uses a client built from `builder`
+    ///
+    pub fn new(builder: ClientBuilder) -> Self {
+        Runner {
+            builder,
+            dry_run: false,
+            interval: DEFAULT_TICK_INTERVAL,
+            max_consecutive_errors: DEFAULT_MAX_CONSECUTIVE_ERRORS,
+            shutdown: CancellationToken::new(),
+        }
+    }
+
+    ///
+    /// Simulate the orders of the strategy instead of sending them
+    ///
+    pub fn dry_run(mut self, enabled: bool) -> Self {
+        self.dry_run = enabled;
+        self
+    }
+
+    ///
+    /// Time between the start of two ticks. A tick longer than that delays
+    /// the next one instead of making ticks pile up
+    ///
+    pub fn interval(mut self, interval: Duration) -> Self {
+        self.interval = interval;
+        self
+    }
+
+    ///
+    /// Stop, returning the last error, once `max` ticks failed in a row.
+    /// `0` never stops on errors
+    ///
+    pub fn max_consecutive_errors(mut self, max: u32) -> Self {
+        self.max_consecutive_errors = max;
+        self
+    }
+
+    ///
+    /// Token that stops the runner when cancelled, e.g. from a signal handler
+    ///
+    pub fn shutdown_token(&self) -> CancellationToken {
+        self.shutdown.clone()
+    }
+
+    ///
+    /// Run `strategy` until the shutdown token is cancelled or too many ticks
+    /// fail in a row. Fails right away if the client can't be built; the error
+    /// of `Strategy::on_shutdown` is returned when there is no earlier one
+    ///
+    pub async fn run<S: Strategy>(self, mut strategy: S) -> CryptoMktResult<()> {
+        let mut ctx = Context {
+            client: self.builder.paper_trading(self.dry_run).build()?,
+            dry_run: self.dry_run,
+            tick: 0,
+            shutdown: self.shutdown,
+        };
+        let mut ticks = tokio::time::interval(self.interval);
+        ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+        let mut failures = 0;
+        let mut result = Ok(());
+
+        loop {
+            tokio::select! {
+                biased;
+                _ = ctx.shutdown.cancelled() => break,
+                _ = ticks.tick() => {}
+            }
+            ctx.tick += 1;
+            match strategy.on_tick(&ctx).await {
+                Ok(()) => failures = 0,
+                Err(e) => {
+                    failures += 1;
+                    warn!(target: "cryptomkt", "Strategy: tick {} failed: {}", ctx.tick, e);
+                    if failures == self.max_consecutive_errors {
+                        error!(target: "cryptomkt", "Strategy: {} failed ticks, stopping", failures);
+                        result = Err(e);
+                        break;
+                    }
+                }
+            }
+        }
+
+        let shutdown = strategy.on_shutdown(&ctx).await;
+        result.and(shutdown)
+    }
+}
@@ -0,0 +1,182 @@
+//!
+//! ## Ticker cache
+//!
+//! `TickerCache` keeps the latest ticker of a set of markets up to date from a
+//! background task, polling the REST API or listening to the ticker channel
+//! of the WebSocket (feature `ws`). Reads are synchronous and cheap, and come
+//! with the `Instant` the ticker arrived at, so a strategy can refuse to act
+//! on old prices:
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::tickers::TickerCache;
+//! use cryptomkt::Client;
+//!
+//! # async fn run() {
+//! let tickers = TickerCache::poll(Client::public(), &["ETHCLP", "BTCCLP"], Duration::from_secs(2));
+//!
+//! // ... later, in the strategy
+//! match tickers.fresh("ETHCLP", Duration::from_secs(10)) {
+//!     Some(ticker) => println!("bid {} ask {}", ticker.bid, ticker.ask),
+//!     None => println!("no recent ETHCLP ticker, skipping"),
+//! }
+//! # }
+//! ```
+//!
+//! The task stops when `TickerCache::stop` is called or when every clone of
+//! the cache is dropped. A failed poll is logged and leaves the previous
+//! tickers in place, so they just grow older.
+//!
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio_util::sync::{CancellationToken, DropGuard};
+
+use crate::client::Client;
+use crate::internal::models::Ticker;
+use crate::internal::rt::{self, Instant, MissedTickBehavior};
+use crate::internal::trace::warn;
+#[cfg(feature = "ws")]
+use crate::internal::errors::CryptoMktResult;
+#[cfg(feature = "ws")]
+use crate::ws::{WsClient, WsEvent};
+
+/// Último ticker de cada mercado y momento en que llegó
+type Latest = Arc<RwLock<HashMap<String, (Ticker, Instant)>>>;
+
+///
+/// Latest tickers of a set of markets, kept up to date in the background, see
+/// the module docs. Clones share the tickers and the task
+///
+#[derive(Debug, Clone)]
+pub struct TickerCache {
+    tickers: Latest,
+    shutdown: CancellationToken,
+    _guard: Arc<DropGuard>,
+}
+
+impl TickerCache {
+    ///
+    /// Poll the tickers of `markets` every `interval`, starting right away.
+    /// Every poll is a single request for all the tickers of the exchange.
+    /// Must be called within a Tokio runtime
+    ///
+    pub fn poll(client: Client, markets: &[&str], interval: Duration) -> Self {
+        let cache = TickerCache::empty();
+        let (tickers, shutdown) = (cache.tickers.clone(), cache.shutdown.clone());
+        let markets: Vec<String> = markets.iter().map(|m| m.to_uppercase()).collect();
+        rt::spawn(async move {
+            let mut ticks = rt::interval(interval);
+            ticks.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                let result = tokio::select! {
+                    biased;
+                    _ = shutdown.cancelled() => break,
+                    _ = ticks.tick() => tokio::select! {
+                        biased;
+                        _ = shutdown.cancelled() => break,
+                        result = client.get_all_tickers() => result,
+                    },
+                };
+                match result {
+                    Ok(mut all) => {
+                        let now = Instant::now();
+                        let mut tickers = tickers.write().unwrap();
+                        for market in &markets {
+                            if let Some(ticker) = all.remove(market) {
+                                tickers.insert(market.clone(), (ticker, now));
+                            }
+                        }
+                    }
+                    Err(e) => warn!(target: "cryptomkt", "TickerCache: poll failed: {}", e),
+                }
+            }
+        });
+        cache
+    }
+
+    ///
+    /// Keep the tickers of `markets` pushed by the exchange through `client`,
+    /// once per second. Fails when a subscription is rejected
+    ///
+    #[cfg(feature = "ws")]
+    pub async fn subscribe(client: &WsClient, markets: &[&str]) -> CryptoMktResult<Self> {
+        use futures::StreamExt;
+
+        let cache = TickerCache::empty();
+        for market in markets {
+            let market = market.to_uppercase();
+            let mut subscription = client.subscribe_ticker(&market).await?;
+            let (tickers, shutdown) = (cache.tickers.clone(), cache.shutdown.clone());
+            rt::spawn(async move {
+                loop {
+                    let event = tokio::select! {
+                        biased;
+                        _ = shutdown.cancelled() => break,
+                        event = subscription.next() => event,
+                    };
+                    match event {
+                        Some(WsEvent::Data(ticker)) => {
+                            let entry = (ticker, Instant::now());
+                            tickers.write().unwrap().insert(market.clone(), entry);
+                        }
+                        Some(WsEvent::Reconnected) => {}
+                        None => break,
+                    }
+                }
+            });
+        }
+        Ok(cache)
+    }
+
+    ///
+    /// Cache without tickers whose tasks stop with the last clone
+    ///
+    fn empty() -> Self {
+        let shutdown = CancellationToken::new();
+        TickerCache {
+            tickers: Arc::new(RwLock::new(HashMap::new())),
+            _guard: Arc::new(shutdown.clone().drop_guard()),
+            shutdown,
+        }
+    }
+
+    ///
+    /// Latest ticker of `market` and when it arrived, `None` until the first
+    /// one does or when the market isn't followed
+    ///
+    pub fn latest(&self, market: &str) -> Option<(Ticker, Instant)> {
+        self.tickers
+            .read()
+            .unwrap()
+            .get(&market.to_uppercase())
+            .cloned()
+    }
+
+    ///
+    /// Latest ticker of `market` if it arrived less than `max_age` ago
+    ///
+    pub fn fresh(&self, market: &str, max_age: Duration) -> Option<Ticker> {
+        self.latest(market)
+            .filter(|(_, received)| received.elapsed() <= max_age)
+            .map(|(ticker, _)| ticker)
+    }
+
+    ///
+    /// Whether there is no ticker of `market` younger than `max_age`
+    ///
+    pub fn is_stale(&self, market: &str, max_age: Duration) -> bool {
+        self.fresh(market, max_age).is_none()
+    }
+
+    ///
+    /// Stop updating the tickers, also for the clones. The tickers already
+    /// kept stay readable
+    ///
+    pub fn stop(&self) {
+        self.shutdown.cancel();
+    }
+}
@@ -0,0 +1,368 @@
+//!
+//! ## Order tracking
+//!
+//! `OrderTracker` follows the lifecycle of submitted orders, polling their
+//! status and reporting each transition as a `TrackerEvent`:
+//!
+//! ```text
+//! Accepted ──> PartiallyFilled ──> Filled
+//!     │               │
+//!     └───────────────┴──────────> Cancelled
+//! ```
+//!
+//! A partially filled order reports `PartiallyFilled` again every time more
+//! of it is executed. Orders are polled until they are filled or cancelled.
+//!
+//! ```no_run
+//! use std::time::Duration;
+//!
+//! use cryptomkt::tracker::{OrderTracker, TrackerEvent};
+//! use cryptomkt::{Client, Decimal, Side};
+//! use futures::StreamExt;
+//!
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! let client = Client::new("<API_KEY>", "<API SECRET>");
+//! let order = client
+//!     .create_market("ETHCLP")
+//!     .create_order(Side::Buy, Decimal::new(5, 1), Decimal::new(180000, 0))
+//!     .await?;
+//!
+//! let tracker = OrderTracker::new(client);
+//! tracker.track(&order.id);
+//! let mut events = Box::pin(tracker.events(Duration::from_secs(5)));
+//! while let Some(event) = events.next().await {
+//!     if let TrackerEvent::Filled(order) = event? {
+//!         println!("{} filled at {}", order.id, order.avg_execution_price);
+//!         break;
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+
+use std::collections::HashMap;
+use std::fmt::{self, Display, Formatter};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use futures::stream::{self, Stream, StreamExt};
+use rust_decimal::Decimal;
+
+use crate::client::Client;
+use crate::internal::errors::CryptoMktError;
+use crate::internal::models::Order;
+use crate::internal::rt;
+use crate::internal::trace::warn;
+
+///
+/// State of an order in its lifecycle, see the module docs
+///
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum OrderStatus {
+    /// Active, nothing executed yet
+    Accepted,
+    /// Active, with part of its amount executed
+    PartiallyFilled,
+    /// Fully executed
+    Filled,
+    /// Cancelled, maybe after being partially executed
+    Cancelled,
+}
+
+impl OrderStatus {
+    ///
+    /// State of an order as reported by the exchange
+    ///
+    pub fn of(order: &Order) -> Self {
+        match order.status.as_str() {
+            "executed" | "filled" => OrderStatus::Filled,
+            "cancelled" | "canceled" => OrderStatus::Cancelled,
+            _ if filled_amount(order).is_zero() => OrderStatus::Accepted,
+            _ => OrderStatus::PartiallyFilled,
+        }
+    }
+
+    ///
+    /// Whether the order won't change anymore
+    ///
+    pub fn is_final(self) -> bool {
+        matches!(self, OrderStatus::Filled | OrderStatus::Cancelled)
+    }
+
+    ///
+    /// Whether an order in this state can move to `next`. Staying in
+    /// `PartiallyFilled` is allowed, since it is reported for each new fill
+    ///
+    pub fn can_move_to(self, next: OrderStatus) -> bool {
+        match self {
+            OrderStatus::Accepted => next != OrderStatus::Accepted,
+            OrderStatus::PartiallyFilled => next != OrderStatus::Accepted,
+            OrderStatus::Filled | OrderStatus::Cancelled => false,
+        }
+    }
+}
+
+///
+/// Transition of a tracked order, with the order as last reported
+///
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum TrackerEvent {
+    /// The order is on the book, nothing executed yet
+    Accepted(Order),
+    /// Part of the order was executed; reported again for each new fill
+    PartiallyFilled(Order),
+    /// The order was fully executed
+    Filled(Order),
+    /// The order was cancelled
+    Cancelled(Order),
+}
+
+impl TrackerEvent {
+    fn new(status: OrderStatus, order: Order) -> Self {
+        match status {
+            OrderStatus::Accepted => TrackerEvent::Accepted(order),
+            OrderStatus::PartiallyFilled => TrackerEvent::PartiallyFilled(order),
+            OrderStatus::Filled => TrackerEvent::Filled(order),
+            OrderStatus::Cancelled => TrackerEvent::Cancelled(order),
+        }
+    }
+
+    ///
+    /// The order of the event
+    ///
+    pub fn order(&self) -> &Order {
+        match self {
+            TrackerEvent::Accepted(order)
+            | TrackerEvent::PartiallyFilled(order)
+            | TrackerEvent::Filled(order)
+            | TrackerEvent::Cancelled(order) => order,
+        }
+    }
+
+    ///
+    /// State the order moved to
+    ///
+    pub fn status(&self) -> OrderStatus {
+        match self {
+            TrackerEvent::Accepted(_) => OrderStatus::Accepted,
+            TrackerEvent::PartiallyFilled(_) => OrderStatus::PartiallyFilled,
+            TrackerEvent::Filled(_) => OrderStatus::Filled,
+            TrackerEvent::Cancelled(_) => OrderStatus::Cancelled,
+        }
+    }
+}
+
+///
+/// Failed status query of a tracked order. The order stays tracked, with the
+/// state it had, and is queried again by the next poll
+///
+#[derive(Debug)]
+pub struct PollError {
+    /// ID of the order
+    pub order_id: String,
+    /// Error of the query
+    pub error: CryptoMktError,
+}
+
+impl Display for PollError {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        write!(f, "order {}: {}", self.order_id, self.error)
+    }
+}
+
+impl std::error::Error for PollError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+impl From<PollError> for CryptoMktError {
+    fn from(e: PollError) -> Self {
+        e.error
+    }
+}
+
+///
+/// Cantidad ejecutada de una orden. Las órdenes activas informan a veces solo
+/// lo que les queda por ejecutar
+///
+pub(crate) fn filled_amount(order: &Order) -> Decimal {
+    let amount = &order.amount;
+    if amount.executed.is_zero() && !amount.remaining.is_zero() {
+        amount.original - amount.remaining
+    } else {
+        amount.executed
+    }
+}
+
+///
+/// Estado conocido de una orden seguida
+///
+#[derive(Debug, Clone, Copy, Default)]
+struct Tracked {
+    /// `None` hasta la primera consulta
+    status: Option<OrderStatus>,
+    filled: Decimal,
+}
+
+///
+/// Follows the lifecycle of orders by polling their status, see the module
+/// docs. Clones share the tracked orders
+///
+#[derive(Debug, Clone)]
+pub struct OrderTracker {
+    client: Client,
+    orders: Arc<Mutex<HashMap<String, Tracked>>>,
+}
+
+impl OrderTracker {
+    ///
+    /// Tracker polling the orders through `client`
+    ///
+    pub fn new(client: Client) -> Self {
+        OrderTracker {
+            client,
+            orders: Arc::new(Mutex::new(HashMap::new())),
+        }
+    }
+
+    ///
+    /// Start tracking the order `id`. Its state is unknown until the next poll
+    ///
+    pub fn track(&self, id: &str) {
+        self.orders
+            .lock()
+            .unwrap()
+            .entry(id.to_string())
+            .or_default();
+    }
+
+    ///
+    /// Stop tracking the order `id`
+    ///
+    pub fn untrack(&self, id: &str) {
+        self.orders.lock().unwrap().remove(id);
+    }
+
+    ///
+    /// Current state of the order `id`, `None` if it isn't tracked or wasn't
+    /// polled yet
+    ///
+    pub fn status(&self, id: &str) -> Option<OrderStatus> {
+        self.orders
+            .lock()
+            .unwrap()
+            .get(id)
+            .and_then(|order| order.status)
+    }
+
+    ///
+    /// Current state of every tracked order that was polled
+    ///
+    pub fn statuses(&self) -> HashMap<String, OrderStatus> {
+        let orders = self.orders.lock().unwrap();
+        orders
+            .iter()
+            .filter_map(|(id, order)| Some((id.clone(), order.status?)))
+            .collect()
+    }
+
+    ///
+    /// Whether some tracked order isn't filled or cancelled yet
+    ///
+    pub fn has_pending(&self) -> bool {
+        let orders = self.orders.lock().unwrap();
+        orders
+            .values()
+            .any(|order| !order.status.is_some_and(OrderStatus::is_final))
+    }
+
+    ///
+    /// Query the status of every pending order once and return their
+    /// transitions, followed by the queries that failed. The requests are
+    /// sent concurrently, at most `CryptoMktApi::concurrency` at once. A
+    /// failed query only leaves its own order unchanged
+    ///
+    pub async fn poll(&self) -> Vec<Result<TrackerEvent, PollError>> {
+        let pending: Vec<String> = {
+            let orders = self.orders.lock().unwrap();
+            orders
+                .iter()
+                .filter(|(_, order)| !order.status.is_some_and(OrderStatus::is_final))
+                .map(|(id, _)| id.clone())
+                .collect()
+        };
+        let results: Vec<_> = stream::iter(&pending)
+            .map(|id| self.client.get_order_status(id))
+            .buffered(self.client.api.concurrency())
+            .collect()
+            .await;
+        let mut events = Vec::new();
+        let mut failures = Vec::new();
+        for (id, result) in pending.into_iter().zip(results) {
+            match result {
+                Ok(order) => events.extend(self.apply(&id, order).map(Ok)),
+                Err(error) => {
+                    warn!(target: "cryptomkt", "Order {}: status query failed: {}", id, error);
+                    failures.push(Err(PollError {
+                        order_id: id,
+                        error,
+                    }));
+                }
+            }
+        }
+        events.extend(failures);
+        events
+    }
+
+    ///
+    /// Poll the pending orders every `interval`, yielding their transitions.
+    /// The first poll happens right away. Failed queries yield their error
+    /// and the stream goes on; drop it to stop polling
+    ///
+    pub fn events(
+        &self,
+        interval: Duration,
+    ) -> impl Stream<Item = Result<TrackerEvent, PollError>> + '_ {
+        let ticks = rt::interval(interval);
+        stream::unfold(ticks, move |mut ticks| async move {
+            ticks.tick().await;
+            Some((stream::iter(self.poll().await), ticks))
+        })
+        .flatten()
+    }
+
+    ///
+    /// Registra el último estado de una orden, devolviendo la transición si la hubo
+    ///
+    fn apply(&self, id: &str, order: Order) -> Option<TrackerEvent> {
+        let mut orders = self.orders.lock().unwrap();
+        // Pudo dejar de seguirse mientras se consultaba
+        let tracked = orders.get_mut(id)?;
+        let status = OrderStatus::of(&order);
+        let filled = filled_amount(&order);
+        let changed = match tracked.status {
+            None => true,
+            Some(OrderStatus::PartiallyFilled) if status == OrderStatus::PartiallyFilled => {
+                filled > tracked.filled
+            }
+            Some(current) if current == status => false,
+            Some(current) if current.can_move_to(status) => true,
+            Some(current) => {
+                warn!(target: "cryptomkt", "Order {}: ignoring {:?} after {:?}", id, status, current);
+                false
+            }
+        };
+        if !changed {
+            return None;
+        }
+        *tracked = Tracked {
+            status: Some(status),
+            filled,
+        };
+        Some(TrackerEvent::new(status, order))
+    }
+}
@@ -0,0 +1,388 @@
+//!
+//! ## API v2
+//!
+//! Client for version 2 of the CryptoMarket API, hosted at
+//! `https://api.exchange.cryptomkt.com/api/2/`. Version 1, used by
+//! `cryptomkt::Client`, keeps working unchanged, so code can move to v2 one
+//! call at a time.
+//!
+//! Version 2 changes the endpoints, identifies markets as *symbols*, answers
+//! with bare JSON instead of the `{"status", "data", "pagination"}` envelope and
+//! authenticates with HTTP basic auth instead of signed headers. Errors of the
+//! exchange (`{"error": {"code", "message", "description"}}`) are reported
+//! through `CryptoMktError::message`.
+//!
+//! ```no_run
+//! use cryptomkt::v2;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let client = v2::Client::new("<API_KEY>", "<API SECRET>");
+//!     let ticker = client.get_ticker("ETHCLP").await.unwrap();
+//!     println!("{:?}", ticker.last);
+//! }
+//! ```
+//!
+//! The options of `ClientBuilder` (timeout, proxy, middleware, transport, ...)
+//! apply to v2 too by building with `ClientBuilder::build_v2`.
+//!
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::internal::api::basic_auth;
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::request::{CryptoMktRequest, HttpRequest, Transport};
+use crate::internal::response::parse_bare;
+use crate::internal::timestamp;
+use crate::internal::trace::instrument;
+use crate::market::{ReportedSide, Side};
+
+/// Base URL of the v2 API
+pub const DEFAULT_URL: &str = "https://api.exchange.cryptomkt.com/api/2/";
+
+///
+/// Market of the exchange
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Symbol {
+    /// Symbol name, e.g. `ETHCLP`
+    pub id: String,
+    /// Currency bought and sold
+    pub base_currency: String,
+    /// Currency the prices are expressed in
+    pub quote_currency: String,
+    /// Smallest step of an order quantity
+    pub quantity_increment: Decimal,
+    /// Smallest step of a price
+    pub tick_size: Decimal,
+    /// Fee of orders taking liquidity
+    pub take_liquidity_rate: Decimal,
+    /// Fee of orders providing liquidity
+    pub provide_liquidity_rate: Decimal,
+    /// Currency fees are charged in
+    pub fee_currency: String,
+}
+
+///
+/// State of a market over the last 24 hours. Prices are `None` when there
+/// were no trades
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Ticker {
+    /// Symbol name
+    pub symbol: String,
+    /// Best sell price
+    pub ask: Option<Decimal>,
+    /// Best buy price
+    pub bid: Option<Decimal>,
+    /// Last trade price
+    pub last: Option<Decimal>,
+    /// Price 24 hours ago
+    pub open: Option<Decimal>,
+    /// Lowest price
+    pub low: Option<Decimal>,
+    /// Highest price
+    pub high: Option<Decimal>,
+    /// Volume in the base currency
+    pub volume: Decimal,
+    /// Volume in the quote currency
+    pub volume_quote: Decimal,
+    /// Time of the last update
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Price level of the order book
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Level {
+    /// Price
+    pub price: Decimal,
+    /// Quantity offered at the price
+    pub size: Decimal,
+}
+
+///
+/// Order book of a symbol, best prices first
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderBook {
+    /// Sell side
+    pub ask: Vec<Level>,
+    /// Buy side
+    pub bid: Vec<Level>,
+    /// Time of the snapshot
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Public trade
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    /// Trade ID
+    pub id: i64,
+    /// Price
+    pub price: Decimal,
+    /// Quantity
+    pub quantity: Decimal,
+    /// `buy` or `sell`, the side of the taker
+    pub side: ReportedSide,
+    /// Time of the trade
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Balance of a currency in the trading account
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Balance {
+    /// Currency
+    pub currency: String,
+    /// Amount available to trade
+    pub available: Decimal,
+    /// Amount reserved by active orders
+    pub reserved: Decimal,
+}
+
+///
+/// Order of the user
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(rename_all = "camelCase")]
+pub struct Order {
+    /// Order ID assigned by the exchange
+    pub id: i64,
+    /// Order ID assigned by the client, used to cancel it
+    pub client_order_id: String,
+    /// Symbol name
+    pub symbol: String,
+    /// `buy` or `sell`
+    pub side: ReportedSide,
+    /// `new`, `suspended`, `partiallyFilled`, `filled`, `canceled` or `expired`
+    pub status: String,
+    /// `limit`, `market`, `stopLimit` or `stopMarket`
+    #[serde(rename = "type")]
+    pub order_type: String,
+    /// `GTC`, `IOC`, `FOK`, `Day` or `GTD`
+    pub time_in_force: String,
+    /// Quantity
+    pub quantity: Decimal,
+    /// Limit price
+    #[serde(default)]
+    pub price: Option<Decimal>,
+    /// Quantity executed
+    pub cum_quantity: Decimal,
+    /// Creation time
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub created_at: DateTime<Utc>,
+    /// Time of the last change
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub updated_at: DateTime<Utc>,
+}
+
+///
+/// Client of the v2 API. Cheap to clone, clones share the connection pool
+///
+#[derive(Debug, Clone)]
+pub struct Client {
+    transport: Transport,
+    base_url: Url,
+    auth: HeaderValue,
+    default_headers: HeaderMap,
+}
+
+impl Client {
+    ///
+    /// Create the new Client instance
+    ///
+    pub fn new(api_key: &str, secret_key: &str) -> Self {
+        Client::with_transport(
+            api_key,
+            secret_key,
+            None,
+            Transport::new(CryptoMktRequest::new()),
+            HeaderMap::new(),
+        )
+        .expect("the default configuration is valid")
+    }
+
+    ///
+    /// Create a client over an already configured transport, see
+    /// `ClientBuilder::build_v2`
+    ///
+    pub(crate) fn with_transport(
+        api_key: &str,
+        secret_key: &str,
+        base_url: Option<&str>,
+        transport: Transport,
+        default_headers: HeaderMap,
+    ) -> CryptoMktResult<Self> {
+        let mut base_url = base_url.unwrap_or(DEFAULT_URL).to_string();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        let base_url = Url::parse(&base_url).map_err(|e| {
+            CryptoMktError::from(CryptoMktErrorType::InvalidConfiguration(format!(
+                "{}: {}",
+                base_url, e
+            )))
+        })?;
+        Ok(Client {
+            transport,
+            base_url,
+            default_headers,
+            auth: basic_auth(api_key, secret_key),
+        })
+    }
+
+    ///
+    /// Get every symbol of the exchange
+    ///
+    pub async fn get_symbols(&self) -> CryptoMktResult<Vec<Symbol>> {
+        self.get(&["public", "symbol"], &[]).await
+    }
+
+    ///
+    /// Get the ticker of a symbol
+    ///
+    pub async fn get_ticker(&self, symbol: &str) -> CryptoMktResult<Ticker> {
+        self.get(&["public", "ticker", symbol], &[]).await
+    }
+
+    ///
+    /// Get the ticker of every symbol
+    ///
+    pub async fn get_tickers(&self) -> CryptoMktResult<Vec<Ticker>> {
+        self.get(&["public", "ticker"], &[]).await
+    }
+
+    ///
+    /// Get the order book of a symbol, with up to `limit` levels per side
+    /// (0 for the whole book)
+    ///
+    pub async fn get_order_book(&self, symbol: &str, limit: u32) -> CryptoMktResult<OrderBook> {
+        self.get(
+            &["public", "orderbook", symbol],
+            &[("limit", limit.to_string())],
+        )
+        .await
+    }
+
+    ///
+    /// Get the last `limit` trades of a symbol, newest first
+    ///
+    pub async fn get_trades(&self, symbol: &str, limit: u32) -> CryptoMktResult<Vec<Trade>> {
+        self.get(
+            &["public", "trades", symbol],
+            &[("limit", limit.to_string()), ("sort", "DESC".to_string())],
+        )
+        .await
+    }
+
+    ///
+    /// Get the balances of the trading account
+    ///
+    pub async fn get_balance(&self) -> CryptoMktResult<Vec<Balance>> {
+        self.get(&["trading", "balance"], &[]).await
+    }
+
+    ///
+    /// Get my active orders, in every symbol or only in `symbol`
+    ///
+    pub async fn get_active_orders(&self, symbol: Option<&str>) -> CryptoMktResult<Vec<Order>> {
+        let params = symbol
+            .map(|symbol| vec![("symbol", symbol.to_string())])
+            .unwrap_or_default();
+        self.get(&["order"], &params).await
+    }
+
+    ///
+    /// Create a limit order
+    ///
+    pub async fn create_order(
+        &self,
+        symbol: &str,
+        side: Side,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        let mut params = HashMap::new();
+        params.insert("symbol".to_string(), symbol.to_string());
+        params.insert("side".to_string(), side.to_string());
+        params.insert("type".to_string(), "limit".to_string());
+        params.insert("quantity".to_string(), quantity.to_string());
+        params.insert("price".to_string(), price.to_string());
+
+        let url = self.url(&["order"], &[]);
+        let body = instrument(
+            "POST",
+            url.path(),
+            self.transport.post(url.clone(), self.headers(), params),
+        )
+        .await?;
+        parse_bare(url.path(), body)
+    }
+
+    ///
+    /// Cancel an active order by its `client_order_id`
+    ///
+    pub async fn cancel_order(&self, client_order_id: &str) -> CryptoMktResult<Order> {
+        let url = self.url(&["order", client_order_id], &[]);
+        let body = instrument(
+            "DELETE",
+            url.path(),
+            self.transport.delete(url.clone(), self.headers()),
+        )
+        .await?;
+        parse_bare(url.path(), body)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        params: &[(&str, String)],
+    ) -> CryptoMktResult<T> {
+        let url = self.url(segments, params);
+        let body = instrument(
+            "GET",
+            url.path(),
+            self.transport.get(url.clone(), self.headers()),
+        )
+        .await?;
+        parse_bare(url.path(), body)
+    }
+
+    ///
+    /// URL del endpoint, con cada segmento codificado
+    ///
+    fn url(&self, segments: &[&str], params: &[(&str, String)]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the base URL is http(s)")
+            .pop_if_empty()
+            .extend(segments);
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        url
+    }
+
+    fn headers(&self) -> HeaderMap {
+        let mut headers = self.default_headers.clone();
+        headers.insert(AUTHORIZATION, self.auth.clone());
+        headers
+    }
+}
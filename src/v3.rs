@@ -0,0 +1,523 @@
+//!
+//! ## API v3
+//!
+//! Client for version 3 of the CryptoMarket API, hosted at
+//! `https://api.exchange.cryptomkt.com/api/3/`, with spot trading, wallet and
+//! sub-account endpoints.
+//!
+//! Requests are authenticated with the HS256 scheme of v3: the `Authorization`
+//! header carries the API key, a timestamp and the HMAC-SHA256 of the request
+//! made with the secret key, so the secret never leaves the process. Like v2,
+//! responses are bare JSON, and errors of the exchange are reported through
+//! `CryptoMktError::message`.
+//!
+//! ```no_run
+//! use cryptomkt::v3;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let client = v3::Client::new("<API_KEY>", "<API SECRET>");
+//!     for balance in client.get_spot_balance().await.unwrap() {
+//!         println!("{}: {}", balance.currency, balance.available);
+//!     }
+//! }
+//! ```
+//!
+//! The options of `ClientBuilder` apply to v3 too by building with
+//! `ClientBuilder::build_v3`.
+//!
+
+use std::collections::HashMap;
+use std::fmt::{self, Debug, Formatter};
+use std::sync::Arc;
+use std::time::UNIX_EPOCH;
+
+use chrono::{DateTime, Utc};
+use reqwest::header::{HeaderMap, AUTHORIZATION};
+use reqwest::Url;
+use rust_decimal::Decimal;
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+
+use crate::internal::api::hs256_auth;
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::models::MarketLimits;
+use crate::internal::request::{CryptoMktRequest, HttpRequest, Transport};
+use crate::internal::response::parse_bare;
+use crate::internal::rt;
+use crate::internal::secret::SecretString;
+use crate::internal::timestamp;
+use crate::internal::trace::instrument;
+use crate::market::{ReportedSide, Side};
+
+/// Base URL of the v3 API
+pub const DEFAULT_URL: &str = "https://api.exchange.cryptomkt.com/api/3/";
+
+///
+/// Market of the exchange
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Symbol {
+    /// `spot`
+    #[serde(rename = "type")]
+    pub symbol_type: String,
+    /// Currency bought and sold
+    pub base_currency: String,
+    /// Currency the prices are expressed in
+    pub quote_currency: String,
+    /// `working` or `suspended`
+    pub status: String,
+    /// Smallest step of an order quantity
+    pub quantity_increment: Decimal,
+    /// Smallest step of a price
+    pub tick_size: Decimal,
+    /// Fee of orders taking liquidity
+    pub take_rate: Decimal,
+    /// Fee of orders providing liquidity
+    pub make_rate: Decimal,
+    /// Currency fees are charged in
+    pub fee_currency: String,
+}
+
+impl Symbol {
+    ///
+    /// Trading rules of the symbol, named `market`
+    ///
+    pub fn limits(&self, market: &str) -> MarketLimits {
+        MarketLimits {
+            market: market.to_string(),
+            tick_size: self.tick_size,
+            lot_size: self.quantity_increment,
+            min_amount: self.quantity_increment,
+            status: self.status.clone(),
+        }
+    }
+}
+
+///
+/// State of a market over the last 24 hours. Prices are `None` when there
+/// were no trades
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Ticker {
+    /// Best sell price
+    pub ask: Option<Decimal>,
+    /// Best buy price
+    pub bid: Option<Decimal>,
+    /// Last trade price
+    pub last: Option<Decimal>,
+    /// Price 24 hours ago
+    pub open: Option<Decimal>,
+    /// Lowest price
+    pub low: Option<Decimal>,
+    /// Highest price
+    pub high: Option<Decimal>,
+    /// Volume in the base currency
+    pub volume: Decimal,
+    /// Volume in the quote currency
+    pub volume_quote: Decimal,
+    /// Time of the last update
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Price level of the order book, sent by the exchange as `[price, size]`
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+#[serde(from = "(Decimal, Decimal)")]
+pub struct Level {
+    /// Price
+    pub price: Decimal,
+    /// Quantity offered at the price
+    pub size: Decimal,
+}
+
+impl From<(Decimal, Decimal)> for Level {
+    fn from((price, size): (Decimal, Decimal)) -> Self {
+        Level { price, size }
+    }
+}
+
+///
+/// Order book of a symbol, best prices first
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct OrderBook {
+    /// Sell side
+    pub ask: Vec<Level>,
+    /// Buy side
+    pub bid: Vec<Level>,
+    /// Time of the snapshot
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Public trade
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Trade {
+    /// Trade ID
+    pub id: i64,
+    /// Price
+    pub price: Decimal,
+    /// Quantity
+    pub qty: Decimal,
+    /// `buy` or `sell`, the side of the taker
+    pub side: ReportedSide,
+    /// Time of the trade
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub timestamp: DateTime<Utc>,
+}
+
+///
+/// Balance of a currency in the spot or wallet account
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Balance {
+    /// Currency
+    pub currency: String,
+    /// Amount available
+    pub available: Decimal,
+    /// Amount reserved by active orders or pending withdrawals
+    pub reserved: Decimal,
+}
+
+///
+/// Spot order of the user
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct Order {
+    /// Order ID assigned by the exchange
+    pub id: i64,
+    /// Order ID assigned by the client, used to cancel it
+    pub client_order_id: String,
+    /// Symbol name
+    pub symbol: String,
+    /// `buy` or `sell`
+    pub side: ReportedSide,
+    /// `new`, `suspended`, `partiallyFilled`, `filled`, `canceled` or `expired`
+    pub status: String,
+    /// `limit`, `market`, `stopLimit`, `stopMarket`, ...
+    #[serde(rename = "type")]
+    pub order_type: String,
+    /// `GTC`, `IOC`, `FOK`, `Day` or `GTD`
+    pub time_in_force: String,
+    /// Quantity
+    pub quantity: Decimal,
+    /// Limit price
+    #[serde(default)]
+    pub price: Option<Decimal>,
+    /// Quantity executed
+    pub quantity_cumulative: Decimal,
+    /// Creation time
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub created_at: DateTime<Utc>,
+    /// Time of the last change
+    #[serde(deserialize_with = "timestamp::deserialize")]
+    pub updated_at: DateTime<Utc>,
+}
+
+///
+/// Sub-account of the user
+///
+#[derive(Serialize, Deserialize, Debug, Clone)]
+pub struct SubAccount {
+    /// Sub-account ID
+    pub sub_account_id: String,
+    /// Email of the sub-account
+    pub email: String,
+    /// `new`, `active` or `disable`
+    pub status: String,
+}
+
+///
+/// Address to deposit a cryptocurrency into the wallet
+///
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct DepositAddress {
+    /// Currency
+    pub currency: String,
+    /// Address to send the funds to
+    pub address: String,
+    /// Memo, tag or payment ID that must go along with the deposit, for the
+    /// currencies that share an address between accounts (XLM, EOS, ...)
+    #[serde(default, rename = "payment_id")]
+    pub memo: Option<String>,
+}
+
+/// Respuesta del listado de sub-cuentas
+#[derive(Deserialize)]
+struct SubAccounts {
+    result: Vec<SubAccount>,
+}
+
+///
+/// Client of the v3 API. Cheap to clone, clones share the connection pool
+///
+#[derive(Clone)]
+pub struct Client {
+    transport: Transport,
+    base_url: Url,
+    credentials: Arc<(SecretString, SecretString)>,
+    default_headers: HeaderMap,
+}
+
+impl Debug for Client {
+    fn fmt(&self, f: &mut Formatter) -> fmt::Result {
+        f.debug_struct("Client")
+            .field("base_url", &self.base_url.as_str())
+            .field("credentials", &"<redacted>")
+            .finish()
+    }
+}
+
+impl Client {
+    ///
+    /// Create the new Client instance
+    ///
+    pub fn new(api_key: &str, secret_key: &str) -> Self {
+        Client::with_transport(
+            api_key,
+            secret_key,
+            None,
+            Transport::new(CryptoMktRequest::new()),
+            HeaderMap::new(),
+        )
+        .expect("the default configuration is valid")
+    }
+
+    ///
+    /// Create a client over an already configured transport, see
+    /// `ClientBuilder::build_v3`
+    ///
+    pub(crate) fn with_transport(
+        api_key: &str,
+        secret_key: &str,
+        base_url: Option<&str>,
+        transport: Transport,
+        default_headers: HeaderMap,
+    ) -> CryptoMktResult<Self> {
+        let mut base_url = base_url.unwrap_or(DEFAULT_URL).to_string();
+        if !base_url.ends_with('/') {
+            base_url.push('/');
+        }
+        let base_url = Url::parse(&base_url).map_err(|e| {
+            CryptoMktError::from(CryptoMktErrorType::InvalidConfiguration(format!(
+                "{}: {}",
+                base_url, e
+            )))
+        })?;
+        Ok(Client {
+            transport,
+            base_url,
+            default_headers,
+            credentials: Arc::new((SecretString::new(api_key), SecretString::new(secret_key))),
+        })
+    }
+
+    ///
+    /// Get every symbol of the exchange, by name
+    ///
+    pub async fn get_symbols(&self) -> CryptoMktResult<HashMap<String, Symbol>> {
+        self.get(&["public", "symbol"], &[]).await
+    }
+
+    ///
+    /// Get one symbol, e.g. `ETHCLP`
+    ///
+    pub async fn get_symbol(&self, symbol: &str) -> CryptoMktResult<Symbol> {
+        self.get(&["public", "symbol", symbol], &[]).await
+    }
+
+    ///
+    /// Get the ticker of a symbol
+    ///
+    pub async fn get_ticker(&self, symbol: &str) -> CryptoMktResult<Ticker> {
+        self.get(&["public", "ticker", symbol], &[]).await
+    }
+
+    ///
+    /// Get the order book of a symbol, with up to `depth` levels per side
+    /// (0 for the whole book)
+    ///
+    pub async fn get_order_book(&self, symbol: &str, depth: u32) -> CryptoMktResult<OrderBook> {
+        self.get(
+            &["public", "orderbook", symbol],
+            &[("depth", depth.to_string())],
+        )
+        .await
+    }
+
+    ///
+    /// Get the last `limit` trades of a symbol, newest first
+    ///
+    pub async fn get_trades(&self, symbol: &str, limit: u32) -> CryptoMktResult<Vec<Trade>> {
+        self.get(
+            &["public", "trades", symbol],
+            &[("limit", limit.to_string()), ("sort", "DESC".to_string())],
+        )
+        .await
+    }
+
+    ///
+    /// Get the balances of the spot trading account
+    ///
+    pub async fn get_spot_balance(&self) -> CryptoMktResult<Vec<Balance>> {
+        self.get(&["spot", "balance"], &[]).await
+    }
+
+    ///
+    /// Get the balances of the wallet, used for deposits and withdrawals
+    ///
+    pub async fn get_wallet_balance(&self) -> CryptoMktResult<Vec<Balance>> {
+        self.get(&["wallet", "balance"], &[]).await
+    }
+
+    ///
+    /// Get my active spot orders, in every symbol or only in `symbol`
+    ///
+    pub async fn get_active_orders(&self, symbol: Option<&str>) -> CryptoMktResult<Vec<Order>> {
+        let params = symbol
+            .map(|symbol| vec![("symbol", symbol.to_string())])
+            .unwrap_or_default();
+        self.get(&["spot", "order"], &params).await
+    }
+
+    ///
+    /// Create a spot limit order
+    ///
+    pub async fn create_order(
+        &self,
+        symbol: &str,
+        side: Side,
+        quantity: Decimal,
+        price: Decimal,
+    ) -> CryptoMktResult<Order> {
+        let mut params = HashMap::new();
+        params.insert("symbol".to_string(), symbol.to_string());
+        params.insert("side".to_string(), side.to_string());
+        params.insert("type".to_string(), "limit".to_string());
+        params.insert("quantity".to_string(), quantity.to_string());
+        params.insert("price".to_string(), price.to_string());
+
+        self.post(&["spot", "order"], params).await
+    }
+
+    ///
+    /// Cancel an active spot order by its `client_order_id`
+    ///
+    pub async fn cancel_order(&self, client_order_id: &str) -> CryptoMktResult<Order> {
+        let url = self.url(&["spot", "order", client_order_id], &[]);
+        let headers = self.headers("DELETE", &url, "");
+        let resp = instrument(
+            "DELETE",
+            url.path(),
+            self.transport.delete(url.clone(), headers),
+        )
+        .await?;
+        parse_bare(url.path(), resp)
+    }
+
+    ///
+    /// Get the address to deposit `currency` into the wallet, creating one
+    /// when the account has none yet
+    ///
+    pub async fn get_deposit_address(&self, currency: &str) -> CryptoMktResult<DepositAddress> {
+        let params = [("currency", currency.to_string())];
+        let addresses: Vec<DepositAddress> =
+            self.get(&["wallet", "crypto", "address"], &params).await?;
+        match addresses.into_iter().next() {
+            Some(address) => Ok(address),
+            None => self.create_deposit_address(currency).await,
+        }
+    }
+
+    ///
+    /// Create a new address to deposit `currency` into the wallet. Previous
+    /// addresses keep receiving deposits
+    ///
+    pub async fn create_deposit_address(&self, currency: &str) -> CryptoMktResult<DepositAddress> {
+        let mut params = HashMap::new();
+        params.insert("currency".to_string(), currency.to_string());
+        self.post(&["wallet", "crypto", "address"], params).await
+    }
+
+    ///
+    /// Get the sub-accounts of the user
+    ///
+    pub async fn get_sub_accounts(&self) -> CryptoMktResult<Vec<SubAccount>> {
+        let resp: SubAccounts = self.get(&["sub-account"], &[]).await?;
+        Ok(resp.result)
+    }
+
+    async fn get<T: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        params: &[(&str, String)],
+    ) -> CryptoMktResult<T> {
+        let url = self.url(segments, params);
+        let headers = self.headers("GET", &url, "");
+        let resp = instrument("GET", url.path(), self.transport.get(url.clone(), headers)).await?;
+        parse_bare(url.path(), resp)
+    }
+
+    ///
+    /// Envía un formulario firmado por POST
+    ///
+    /// Argumentos
+    ///     segments: Segmentos de la ruta del endpoint
+    ///     params: Formulario
+    ///
+    async fn post<T: DeserializeOwned>(
+        &self,
+        segments: &[&str],
+        params: HashMap<String, String>,
+    ) -> CryptoMktResult<T> {
+        let url = self.url(segments, &[]);
+        // El transporte envía el formulario en el orden del HashMap, el mismo
+        // con el que se firma aquí
+        let body = serde_urlencoded::to_string(&params).unwrap_or_default();
+        let headers = self.headers("POST", &url, &body);
+        let resp = instrument(
+            "POST",
+            url.path(),
+            self.transport.post(url.clone(), headers, params),
+        )
+        .await?;
+        parse_bare(url.path(), resp)
+    }
+
+    ///
+    /// URL del endpoint, con cada segmento codificado
+    ///
+    fn url(&self, segments: &[&str], params: &[(&str, String)]) -> Url {
+        let mut url = self.base_url.clone();
+        url.path_segments_mut()
+            .expect("the base URL is http(s)")
+            .pop_if_empty()
+            .extend(segments);
+        if !params.is_empty() {
+            url.query_pairs_mut().extend_pairs(params);
+        }
+        url
+    }
+
+    ///
+    /// Cabeceras con la firma HS256 de la petición
+    ///
+    fn headers(&self, method: &str, url: &Url, body: &str) -> HeaderMap {
+        let timestamp = rt::system_now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_millis() as i64;
+        let (api_key, secret_key) = &*self.credentials;
+        let mut headers = self.default_headers.clone();
+        headers.insert(
+            AUTHORIZATION,
+            hs256_auth(api_key.expose(), secret_key.expose(), method, url, body, timestamp),
+        );
+        headers
+    }
+}
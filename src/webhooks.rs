@@ -0,0 +1,348 @@
+//!
+//! ## Payment callbacks
+//!
+//! When a payment order is created with a `callback_url`, the exchange sends a
+//! POST to that URL every time the order changes state. The notification is
+//! signed with the API secret: `signature` is the hex HMAC-SHA384 of the `id`
+//! and `status` fields concatenated. `verify_callback` checks the signature and
+//! parses the notification, so a handler only has to pass the request through.
+//!
+//! Only `id` and `status` are signed: anyone who saw a valid callback can send
+//! it again with other amounts or addresses. `to_receive`, `expected_amount`,
+//! `deposit_address` and the rest of the fields must be fetched again with
+//! `Client::payment_order_status` before being trusted.
+//!
+//! ```
+//! use cryptomkt::webhooks::verify_callback;
+//! use reqwest::header::HeaderMap;
+//!
+//! let body = "id=52&external_id=ORD-1&status=1&to_receive=5000&to_receive_currency=CLP\
+//!             &signature=9f16e1b0b3b1c6fd6b1c19f2e3d8b1b1";
+//! // The signature above wasn't made with this secret
+//! assert!(verify_callback(&HeaderMap::new(), body.as_bytes(), "<API SECRET>").is_err());
+//! ```
+//!
+//! With the `webhook-server` feature, `PaymentWebhookServer` listens for the
+//! callbacks itself and yields the verified notifications, for merchants
+//! without a web server of their own:
+//!
+//! ```no_run
+//! # #[cfg(feature = "webhook-server")]
+//! # async fn run() -> cryptomkt::CryptoMktResult<()> {
+//! use cryptomkt::webhooks::PaymentWebhookServer;
+//! use futures::StreamExt;
+//!
+//! let mut callbacks = PaymentWebhookServer::bind("0.0.0.0:8080", "<API SECRET>").await?;
+//! while let Some(notification) = callbacks.next().await {
+//!     if notification.status.is_final() {
+//!         println!("order {} is done: {:?}", notification.external_id, notification.status);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+
+use reqwest::header::{HeaderMap, CONTENT_TYPE};
+use rust_decimal::Decimal;
+use serde::Deserialize;
+
+use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+use crate::internal::hmac::hmac_sha384;
+use crate::internal::models::PaymentStatus;
+
+#[cfg(feature = "webhook-server")]
+pub use self::server::PaymentWebhookServer;
+
+///
+/// State change of a payment order, as sent to its `callback_url`
+///
+#[derive(Deserialize, Debug, Clone)]
+pub struct PaymentNotification {
+    /// Internal ID of the payment order
+    #[serde(deserialize_with = "lenient::i32")]
+    pub id: i32,
+    /// External ID given when the order was created
+    #[serde(default)]
+    pub external_id: String,
+    /// New state of the payment order
+    pub status: PaymentStatus,
+    /// Amount of the payment order, not covered by the signature
+    #[serde(default)]
+    pub to_receive: Option<Decimal>,
+    /// Currency of the amount of the payment order
+    #[serde(default)]
+    pub to_receive_currency: Option<String>,
+    /// Amount expected to be paid, not covered by the signature
+    #[serde(default)]
+    pub expected_amount: Option<Decimal>,
+    /// Currency expected to be paid
+    #[serde(default)]
+    pub expected_currency: Option<String>,
+    /// Address the payment is sent to, not covered by the signature
+    #[serde(default)]
+    pub deposit_address: Option<String>,
+    /// Hex HMAC-SHA384 of `id` and `status`
+    pub signature: String,
+}
+
+///
+/// Verify the signature of a payment callback and parse it.
+///
+/// `body` is the raw body of the POST, form-encoded unless the `Content-Type`
+/// header says it's JSON. `secret` is the API secret of the account that
+/// created the order. Fails with `InvalidSignature` when the signature doesn't
+/// match, and with `MalformedResource` when the body isn't a notification.
+///
+pub fn verify_callback(
+    headers: &HeaderMap,
+    body: &[u8],
+    secret: &str,
+) -> CryptoMktResult<PaymentNotification> {
+    let notification = parse(headers, body)?;
+    let msg = format!("{}{}", notification.id, notification.status.code());
+    let expected = hmac_sha384(secret.as_bytes(), msg.as_bytes());
+    match decode_hex(&notification.signature) {
+        Some(signature) if constant_time_eq(&signature, &expected) => Ok(notification),
+        _ => Err(CryptoMktErrorType::InvalidSignature.into()),
+    }
+}
+
+///
+/// Interpreta el cuerpo según la cabecera `Content-Type`
+///
+fn parse(headers: &HeaderMap, body: &[u8]) -> CryptoMktResult<PaymentNotification> {
+    let is_json = headers
+        .get(CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.contains("json"));
+    let parsed = if is_json {
+        serde_json::from_slice(body).ok()
+    } else {
+        serde_urlencoded::from_bytes(body).ok()
+    };
+    parsed.ok_or_else(|| CryptoMktErrorType::MalformedResource.into())
+}
+
+///
+/// Decodifica una cadena hexadecimal
+///
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+///
+/// Compara dos firmas en tiempo constante para no filtrar cuántos bytes coinciden
+///
+fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    a.len() == b.len() && a.iter().zip(b).fold(0, |acc, (x, y)| acc | (x ^ y)) == 0
+}
+
+///
+/// Los formularios envían todos los campos como texto y el JSON como número,
+/// este deserializador acepta ambos
+///
+mod lenient {
+    use std::convert::TryFrom;
+
+    use serde::{de::Error, Deserialize, Deserializer};
+    use serde_json::Value;
+
+    pub fn i32<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i32, D::Error> {
+        match Value::deserialize(deserializer)? {
+            Value::Number(n) => n
+                .as_i64()
+                .and_then(|n| i32::try_from(n).ok())
+                .ok_or_else(|| D::Error::custom("invalid id")),
+            Value::String(s) => s.parse().map_err(|_| D::Error::custom("invalid id")),
+            _ => Err(D::Error::custom("invalid id")),
+        }
+    }
+}
+
+#[cfg(feature = "webhook-server")]
+mod server {
+    use std::convert::Infallible;
+    use std::net::SocketAddr;
+    use std::pin::Pin;
+    use std::sync::Arc;
+    use std::task::{Context, Poll};
+    use std::time::Duration;
+
+    use futures::Stream;
+    use hyper::body::HttpBody;
+    use hyper::server::conn::Http;
+    use hyper::service::service_fn;
+    use hyper::{Body, Method, Request, Response, StatusCode};
+    use tokio::net::{TcpListener, ToSocketAddrs};
+    use tokio::sync::mpsc;
+    use tokio_util::sync::{CancellationToken, DropGuard};
+
+    use super::{verify_callback, PaymentNotification};
+    use crate::internal::errors::{CryptoMktErrorType, CryptoMktResult};
+    use crate::internal::secret::SecretString;
+    use crate::internal::trace::warn;
+
+    /// Tamaño máximo del cuerpo de una notificación
+    const MAX_BODY: usize = 64 * 1024;
+    /// Notificaciones verificadas que esperan a ser leídas del stream
+    const MAX_PENDING: usize = 256;
+    /// Plazo para recibir las cabeceras de una petición
+    const HEADER_READ_TIMEOUT: Duration = Duration::from_secs(10);
+
+    ///
+    /// HTTP listener for the payment callbacks, see the module docs. Answers
+    /// `200 OK` to every POST with a valid signature, whatever its path, and
+    /// yields its notification. Requests with a wrong signature get a
+    /// `401 Unauthorized`, other malformed ones a `400 Bad Request`, and are
+    /// only logged.
+    ///
+    /// At most 256 notifications wait to be read from the stream;
+    /// beyond that callbacks get a `503 Service Unavailable`, so the exchange
+    /// sends them again later. Connections that don't send their headers
+    /// within 10 seconds are closed.
+    ///
+    /// Dropping the server stops listening.
+    ///
+    pub struct PaymentWebhookServer {
+        local_addr: SocketAddr,
+        notifications: mpsc::Receiver<PaymentNotification>,
+        _guard: DropGuard,
+    }
+
+    impl PaymentWebhookServer {
+        ///
+        /// Listen on `addr` for callbacks signed with `secret`, the API secret
+        /// of the account that creates the orders. Must be called within a
+        /// Tokio runtime. Fails with `InvalidConfiguration` when the address
+        /// can't be bound
+        ///
+        pub async fn bind<A: ToSocketAddrs>(addr: A, secret: &str) -> CryptoMktResult<Self> {
+            let listener = TcpListener::bind(addr).await.map_err(bind_error)?;
+            let local_addr = listener.local_addr().map_err(bind_error)?;
+            let (tx, notifications) = mpsc::channel(MAX_PENDING);
+            let shutdown = CancellationToken::new();
+            let secret = Arc::new(SecretString::new(secret));
+
+            let stop = shutdown.clone();
+            tokio::spawn(async move {
+                loop {
+                    let (stream, _) = tokio::select! {
+                        biased;
+                        _ = stop.cancelled() => break,
+                        accepted = listener.accept() => match accepted {
+                            Ok(accepted) => accepted,
+                            Err(e) => {
+                                warn!(target: "cryptomkt", "Webhook server: {}", e);
+                                continue;
+                            }
+                        },
+                    };
+                    let (tx, secret, stop) = (tx.clone(), secret.clone(), stop.clone());
+                    tokio::spawn(async move {
+                        let service = service_fn(move |request| {
+                            let (tx, secret) = (tx.clone(), secret.clone());
+                            async move { Ok::<_, Infallible>(handle(request, &tx, &secret).await) }
+                        });
+                        let mut http = Http::new();
+                        http.http1_only(true)
+                            .http1_header_read_timeout(HEADER_READ_TIMEOUT);
+                        let connection = http.serve_connection(stream, service);
+                        tokio::select! {
+                            _ = stop.cancelled() => {}
+                            _ = connection => {}
+                        }
+                    });
+                }
+            });
+
+            Ok(PaymentWebhookServer {
+                local_addr,
+                notifications,
+                _guard: shutdown.drop_guard(),
+            })
+        }
+
+        ///
+        /// Address the server listens on, e.g. to learn the port picked when
+        /// binding to port 0
+        ///
+        pub fn local_addr(&self) -> SocketAddr {
+            self.local_addr
+        }
+    }
+
+    impl Stream for PaymentWebhookServer {
+        type Item = PaymentNotification;
+
+        fn poll_next(
+            self: Pin<&mut Self>,
+            cx: &mut Context<'_>,
+        ) -> Poll<Option<PaymentNotification>> {
+            self.get_mut().notifications.poll_recv(cx)
+        }
+    }
+
+    ///
+    /// Verifica una notificación y la entrega al stream
+    ///
+    /// Argumentos
+    ///     request: Petición del exchange
+    ///     tx: Canal de las notificaciones verificadas
+    ///     secret: Secret con el que se firman
+    ///
+    async fn handle(
+        request: Request<Body>,
+        tx: &mpsc::Sender<PaymentNotification>,
+        secret: &SecretString,
+    ) -> Response<Body> {
+        if request.method() != Method::POST {
+            return status(StatusCode::METHOD_NOT_ALLOWED);
+        }
+        let (parts, mut body) = request.into_parts();
+        let mut bytes = Vec::new();
+        while let Some(chunk) = body.data().await {
+            match chunk {
+                Ok(chunk) if bytes.len() + chunk.len() <= MAX_BODY => {
+                    bytes.extend_from_slice(&chunk)
+                }
+                Ok(_) => return status(StatusCode::PAYLOAD_TOO_LARGE),
+                Err(_) => return status(StatusCode::BAD_REQUEST),
+            }
+        }
+        match verify_callback(&parts.headers, &bytes, secret.expose()) {
+            // Si la cola está llena o ya nadie escucha, el exchange la reintenta
+            Ok(notification) => match tx.try_send(notification) {
+                Ok(()) => status(StatusCode::OK),
+                Err(_) => {
+                    warn!(target: "cryptomkt", "Webhook server: queue full, answering 503");
+                    status(StatusCode::SERVICE_UNAVAILABLE)
+                }
+            },
+            Err(e) => {
+                let path = parts.uri.path();
+                warn!(target: "cryptomkt", "Webhook server: rejected callback to {}: {}", path, e);
+                match e.kind() {
+                    CryptoMktErrorType::InvalidSignature => status(StatusCode::UNAUTHORIZED),
+                    _ => status(StatusCode::BAD_REQUEST),
+                }
+            }
+        }
+    }
+
+    fn status(status: StatusCode) -> Response<Body> {
+        let mut response = Response::new(Body::empty());
+        *response.status_mut() = status;
+        response
+    }
+
+    fn bind_error(e: std::io::Error) -> crate::internal::errors::CryptoMktError {
+        CryptoMktErrorType::InvalidConfiguration(format!("webhook server: {}", e)).into()
+    }
+}
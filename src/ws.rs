@@ -0,0 +1,1245 @@
+//!
+//! ## WebSocket Client
+//!
+//! Real-time market data and account updates pushed by CryptoMarket's socket
+//! API, available with the `ws` feature. A single connection is shared by every
+//! subscription made through the same `WsClient`.
+//!
+//! Market data is served by the public socket (`WsClient::connect`), while
+//! order and balance updates require an authenticated connection to the
+//! trading socket (`WsClient::connect_trading`).
+//!
+//! If the connection drops, it is re-established following the client's
+//! `ReconnectPolicy` and every subscription is made again. Streams yield
+//! `WsEvent::Reconnected` at that point instead of ending, since updates may
+//! have been missed meanwhile.
+//!
+//! ```no_run
+//! use cryptomkt::ws::{WsClient, WsEvent};
+//! use futures::StreamExt;
+//!
+//! #[tokio::main]
+//! async fn main() {
+//!     let client = WsClient::connect().await.unwrap();
+//!     let mut tickers = client.subscribe_ticker("ETHCLP").await.unwrap();
+//!
+//!     while let Some(event) = tickers.next().await {
+//!         match event {
+//!             WsEvent::Data(ticker) => println!("{:?}", ticker),
+//!             WsEvent::Reconnected => println!("reconnected"),
+//!             _ => {}
+//!         }
+//!     }
+//! }
+//! ```
+//!
+
+use std::pin::Pin;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, RwLock, Weak};
+use std::task::{Context, Poll};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use futures::stream::SplitSink;
+use futures::{SinkExt, Stream, StreamExt};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use tokio::net::TcpStream;
+use tokio::sync::{mpsc, oneshot};
+use tokio_tungstenite::tungstenite::Message;
+use tokio_tungstenite::{connect_async, MaybeTlsStream, WebSocketStream};
+
+use crate::internal::errors::{CryptoMktError, CryptoMktErrorType, CryptoMktResult};
+use crate::internal::hmac::hmac_sha256;
+use crate::internal::models::{OrderBook, PriceLevel, Ticker, Trade};
+use crate::internal::secret::SecretString;
+use crate::internal::timestamp;
+use crate::internal::trace::error;
+use crate::market::ReportedSide;
+use chrono::{DateTime, Utc};
+use rust_decimal::Decimal;
+
+/// Public market data socket of CryptoMarket
+pub const PUBLIC_WS_URL: &str = "wss://api.exchange.cryptomkt.com/api/3/ws/public";
+/// Authenticated trading socket of CryptoMarket
+pub const TRADING_WS_URL: &str = "wss://api.exchange.cryptomkt.com/api/3/ws/trading";
+
+type WsStream = WebSocketStream<MaybeTlsStream<TcpStream>>;
+
+///
+/// What the connection task hands to a subscriber
+///
+enum Notification<'a> {
+    /// Notificación de uno de sus canales
+    Message(&'a Value),
+    /// La conexión se restableció y la suscripción se volvió a enviar
+    Reconnected,
+}
+
+///
+/// Receives every notification of its channels. Returns `false` once the
+/// consumer dropped the subscription, so it can be discarded
+///
+type Deliver = Box<dyn FnMut(Notification) -> bool + Send>;
+
+///
+/// Subscription registered in the connection task. Public notifications are
+/// identified by their `ch` field and private ones by their `method`
+///
+struct Subscriber {
+    channels: Vec<String>,
+    deliver: Deliver,
+}
+
+///
+/// Requests sent from `WsClient` to the connection task
+///
+enum Command {
+    Request {
+        request: Value,
+        subscriber: Option<Subscriber>,
+        ack: oneshot::Sender<CryptoMktResult<()>>,
+    },
+    /// Construye una petición de login nueva para cada reconexión
+    Login(Box<dyn Fn() -> Value + Send>),
+    SetPolicy(ReconnectPolicy),
+}
+
+///
+/// How a dropped connection is re-established: the delay between attempts
+/// starts at `initial_delay` and doubles after every failed attempt up to
+/// `max_delay`.
+///
+/// The default policy retries forever, starting at 500ms and waiting at most
+/// 30 seconds between attempts.
+///
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ReconnectPolicy {
+    /// Delay before the first attempt
+    pub initial_delay: Duration,
+    /// Upper bound of the delay between attempts
+    pub max_delay: Duration,
+    /// Attempts before giving up and ending every stream, `None` for no limit
+    pub max_attempts: Option<u32>,
+}
+
+impl Default for ReconnectPolicy {
+    fn default() -> Self {
+        ReconnectPolicy {
+            initial_delay: Duration::from_millis(500),
+            max_delay: Duration::from_secs(30),
+            max_attempts: None,
+        }
+    }
+}
+
+///
+/// Item of a `Subscription`
+///
+#[derive(Debug, Clone, PartialEq)]
+#[non_exhaustive]
+pub enum WsEvent<T> {
+    /// Item pushed by the exchange
+    Data(T),
+    /// The connection dropped and was re-established, and the subscription was
+    /// made again. Items pushed in the meantime are lost
+    Reconnected,
+}
+
+///
+/// Stream of the items pushed by the exchange for one subscription.
+///
+/// The stream ends only when the connection is closed for good, that is when
+/// the `ReconnectPolicy` gives up. Dropping it unsubscribes locally; the
+/// exchange keeps sending until the connection is closed.
+///
+pub struct Subscription<T> {
+    rx: mpsc::UnboundedReceiver<WsEvent<T>>,
+    // Mantiene viva la conexión aunque se suelten todos los WsClient
+    _connection: mpsc::UnboundedSender<Command>,
+}
+
+impl<T> Stream for Subscription<T> {
+    type Item = WsEvent<T>;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<WsEvent<T>>> {
+        self.get_mut().rx.poll_recv(cx)
+    }
+}
+
+///
+/// WebSocket Client
+///
+/// Cloning is cheap, clones share the same connection.
+///
+#[derive(Debug, Clone)]
+pub struct WsClient {
+    commands: mpsc::UnboundedSender<Command>,
+    next_id: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for Command {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Command::Request { request, .. } => write!(f, "Request({})", request),
+            Command::Login(_) => write!(f, "Login"),
+            Command::SetPolicy(policy) => write!(f, "SetPolicy({:?})", policy),
+        }
+    }
+}
+
+impl WsClient {
+    ///
+    /// Connect to the public socket of CryptoMarket
+    ///
+    pub async fn connect() -> CryptoMktResult<Self> {
+        Self::connect_to(PUBLIC_WS_URL).await
+    }
+
+    ///
+    /// Connect to the socket at `url`, e.g. a local mock server
+    ///
+    pub async fn connect_to(url: &str) -> CryptoMktResult<Self> {
+        let (stream, _) = connect_async(url).await.map_err(ws_error)?;
+        let (commands, rx) = mpsc::unbounded_channel();
+        tokio::spawn(run(url.to_string(), stream, rx));
+        Ok(WsClient {
+            commands,
+            next_id: Arc::new(AtomicU64::new(1)),
+        })
+    }
+
+    ///
+    /// Replace the policy followed when the connection drops, see `ReconnectPolicy`
+    ///
+    pub fn set_reconnect_policy(&self, policy: ReconnectPolicy) -> CryptoMktResult<()> {
+        self.commands
+            .send(Command::SetPolicy(policy))
+            .map_err(|_| connection_closed())
+    }
+
+    ///
+    /// Connect to the trading socket of CryptoMarket and log in with the API
+    /// credentials, enabling `subscribe_orders` and `subscribe_balances`
+    ///
+    pub async fn connect_trading(api_key: &str, secret_key: &str) -> CryptoMktResult<Self> {
+        Self::connect_trading_to(TRADING_WS_URL, api_key, secret_key).await
+    }
+
+    ///
+    /// Connect to the trading socket at `url` and log in with the API credentials
+    ///
+    pub async fn connect_trading_to(
+        url: &str,
+        api_key: &str,
+        secret_key: &str,
+    ) -> CryptoMktResult<Self> {
+        let client = Self::connect_to(url).await?;
+        let (api_key, secret_key) = (SecretString::new(api_key), SecretString::new(secret_key));
+        let next_id = client.next_id.clone();
+        // La firma caduca, así que cada reconexión necesita un login nuevo
+        let login = move || login_request(&next_id, api_key.expose(), secret_key.expose());
+        let request = login();
+        client
+            .commands
+            .send(Command::Login(Box::new(login)))
+            .map_err(|_| connection_closed())?;
+        client.send(request, None).await?;
+        Ok(client)
+    }
+
+    ///
+    /// Subscribe to the ticker of `market`, pushed every second
+    ///
+    pub async fn subscribe_ticker(&self, market: &str) -> CryptoMktResult<Subscription<Ticker>> {
+        let market = market.to_uppercase();
+        let symbol = market.clone();
+        let request = self.market_request("ticker/1s", &market);
+        self.subscribe(request, &["ticker/1s"], move |message| {
+            match message["data"].get(&symbol).map(WsTicker::deserialize) {
+                Some(Ok(ticker)) => vec![ticker.into_ticker(&symbol)],
+                Some(Err(e)) => {
+                    error!(target: "cryptomkt", "WS ticker: {:?}", e);
+                    vec![]
+                }
+                None => vec![],
+            }
+        })
+        .await
+    }
+
+    ///
+    /// Subscribe to the trades of `market` as they are executed
+    ///
+    pub async fn subscribe_trades(&self, market: &str) -> CryptoMktResult<Subscription<Trade>> {
+        let market = market.to_uppercase();
+        let symbol = market.clone();
+        let request = self.market_request("trades", &market);
+        self.subscribe(request, &["trades"], move |message| {
+            let trades = message
+                .get("update")
+                .or_else(|| message.get("snapshot"))
+                .and_then(|data| data.get(&symbol))
+                .cloned()
+                .unwrap_or_default();
+            match Vec::<WsTrade>::deserialize(trades) {
+                Ok(trades) => trades.into_iter().map(|t| t.into_trade(&symbol)).collect(),
+                Err(e) => {
+                    error!(target: "cryptomkt", "WS trades: {:?}", e);
+                    vec![]
+                }
+            }
+        })
+        .await
+    }
+
+    ///
+    /// Subscribe to the full order book of `market`.
+    ///
+    /// The exchange sends a snapshot followed by incremental updates, which are
+    /// applied in the background to the returned `LiveOrderBook`. It can be
+    /// queried at any time without further requests. When an update is missed
+    /// the book subscribes again to get a new snapshot. After a missed update or
+    /// a reconnection the book is out of sync until that snapshot arrives.
+    ///
+    pub async fn subscribe_orderbook(&self, market: &str) -> CryptoMktResult<LiveOrderBook> {
+        let market = market.to_uppercase();
+        let state = Arc::new(RwLock::new(BookState {
+            book: OrderBook {
+                market: market.clone(),
+                ..OrderBook::default()
+            },
+            sequence: 0,
+            synced: false,
+            resyncing: false,
+        }));
+        let weak: Weak<RwLock<BookState>> = Arc::downgrade(&state);
+        let symbol = market.clone();
+        // Débil, para no mantener viva la conexión desde su propio suscriptor
+        let commands = self.commands.downgrade();
+        let next_id = self.next_id.clone();
+        let deliver = Box::new(move |notification: Notification| match weak.upgrade() {
+            Some(state) => {
+                let mut state = state.write().unwrap();
+                match notification {
+                    Notification::Message(message) => {
+                        if state.apply(message, &symbol) {
+                            // Al suscribirse otra vez el exchange envía un snapshot nuevo
+                            if let Some(commands) = commands.upgrade() {
+                                let (ack, _) = oneshot::channel();
+                                let request = market_request(&next_id, "orderbook/full", &symbol);
+                                let _ = commands.send(Command::Request {
+                                    request,
+                                    subscriber: None,
+                                    ack,
+                                });
+                            }
+                        }
+                    }
+                    Notification::Reconnected => state.synced = false,
+                }
+                true
+            }
+            None => false,
+        });
+
+        let request = self.market_request("orderbook/full", &market);
+        let subscriber = Subscriber {
+            channels: vec!["orderbook/full".to_string()],
+            deliver,
+        };
+        self.send(request, Some(subscriber)).await?;
+        Ok(LiveOrderBook {
+            state,
+            connection: self.commands.clone(),
+        })
+    }
+
+    ///
+    /// Subscribe to the reports of my orders: the active orders at subscription
+    /// time followed by every change (new, filled, cancelled, ...).
+    /// Requires a connection made with `connect_trading`.
+    ///
+    pub async fn subscribe_orders(&self) -> CryptoMktResult<Subscription<OrderEvent>> {
+        let request = self.request("spot_subscribe", json!({"params": {}}));
+        self.subscribe(request, &["spot_order", "spot_orders"], |message| {
+            forward_params(message, "orders")
+        })
+        .await
+    }
+
+    ///
+    /// Subscribe to the changes of my balances.
+    /// Requires a connection made with `connect_trading`.
+    ///
+    pub async fn subscribe_balances(&self) -> CryptoMktResult<Subscription<BalanceEvent>> {
+        let request = self.request("spot_balance_subscribe", json!({"params": {"mode": "updates"}}));
+        self.subscribe(request, &["spot_balance"], |message| {
+            forward_params(message, "balance")
+        })
+        .await
+    }
+
+    ///
+    /// Construye una petición con su identificador
+    ///
+    /// Argumentos
+    ///     method: Método de la petición
+    ///     fields: Objeto con el resto de campos (`ch`, `params`)
+    ///
+    fn request(&self, method: &str, mut fields: Value) -> Value {
+        fields["method"] = json!(method);
+        fields["id"] = json!(self.next_id.fetch_add(1, Ordering::Relaxed));
+        fields
+    }
+
+    ///
+    /// Petición de suscripción a un canal público de un mercado
+    ///
+    fn market_request(&self, ch: &str, market: &str) -> Value {
+        market_request(&self.next_id, ch, market)
+    }
+
+    ///
+    /// Registra una suscripción cuyas notificaciones se convierten y envían a un stream
+    ///
+    /// Argumentos
+    ///     request: Petición de suscripción
+    ///     channels: Canales de las notificaciones
+    ///     on_message: Convierte cada notificación en los elementos del stream
+    ///
+    async fn subscribe<T, F>(
+        &self,
+        request: Value,
+        channels: &[&str],
+        mut on_message: F,
+    ) -> CryptoMktResult<Subscription<T>>
+    where
+        T: Send + 'static,
+        F: FnMut(&Value) -> Vec<T> + Send + 'static,
+    {
+        let (tx, rx) = mpsc::unbounded_channel();
+        let deliver = move |notification: Notification| {
+            let events: Vec<WsEvent<T>> = match notification {
+                Notification::Message(message) => {
+                    on_message(message).into_iter().map(WsEvent::Data).collect()
+                }
+                Notification::Reconnected => vec![WsEvent::Reconnected],
+            };
+            events.into_iter().all(|event| tx.send(event).is_ok()) && !tx.is_closed()
+        };
+        let subscriber = Subscriber {
+            channels: channels.iter().map(|ch| ch.to_string()).collect(),
+            deliver: Box::new(deliver),
+        };
+        self.send(request, Some(subscriber)).await?;
+        Ok(Subscription {
+            rx,
+            _connection: self.commands.clone(),
+        })
+    }
+
+    ///
+    /// Envía una petición, registra su suscriptor y espera la confirmación del servidor
+    ///
+    /// Argumentos
+    ///     request: Petición
+    ///     subscriber: Receptor de las notificaciones, si la petición las genera
+    ///
+    async fn send(&self, request: Value, subscriber: Option<Subscriber>) -> CryptoMktResult<()> {
+        let (ack, ack_rx) = oneshot::channel();
+        self.commands
+            .send(Command::Request {
+                request,
+                subscriber,
+                ack,
+            })
+            .map_err(|_| connection_closed())?;
+        ack_rx.await.map_err(|_| connection_closed())?
+    }
+}
+
+///
+/// Petición de login al socket de trading, firmada con la hora actual
+///
+/// Argumentos
+///     next_id: Contador de identificadores del cliente
+///     api_key: API Key
+///     secret_key: Secret Key
+///
+fn login_request(next_id: &AtomicU64, api_key: &str, secret_key: &str) -> Value {
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default()
+        .as_millis() as u64;
+    let signature = hmac_sha256(secret_key.as_bytes(), timestamp.to_string().as_bytes())
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect::<String>();
+    json!({
+        "method": "login",
+        "params": {
+            "type": "HS256",
+            "api_key": api_key,
+            "timestamp": timestamp,
+            "signature": signature,
+        },
+        "id": next_id.fetch_add(1, Ordering::Relaxed),
+    })
+}
+
+///
+/// Petición de suscripción a un canal público de un mercado
+///
+/// Argumentos
+///     next_id: Contador de identificadores del cliente
+///     ch: Canal
+///     market: Mercado
+///
+fn market_request(next_id: &AtomicU64, ch: &str, market: &str) -> Value {
+    json!({
+        "method": "subscribe",
+        "ch": ch,
+        "params": {"symbols": [market]},
+        "id": next_id.fetch_add(1, Ordering::Relaxed),
+    })
+}
+
+///
+/// Devuelve los `params` de una notificación privada, que pueden ser un objeto
+/// o una lista de objetos
+///
+fn forward_params<T: DeserializeOwned>(message: &Value, what: &str) -> Vec<T> {
+    let items = match &message["params"] {
+        Value::Array(items) => items.clone(),
+        item => vec![item.clone()],
+    };
+    items
+        .into_iter()
+        .filter_map(|item| match serde_json::from_value(item) {
+            Ok(item) => Some(item),
+            Err(e) => {
+                error!(target: "cryptomkt", "WS {}: {:?}", what, e);
+                None
+            }
+        })
+        .collect()
+}
+
+///
+/// Order book maintained from the socket, see `WsClient::subscribe_orderbook`.
+///
+/// Cloning is cheap, clones share the same book.
+///
+#[derive(Clone)]
+pub struct LiveOrderBook {
+    state: Arc<RwLock<BookState>>,
+    // Mantiene viva la conexión aunque se suelten todos los WsClient
+    connection: mpsc::UnboundedSender<Command>,
+}
+
+impl LiveOrderBook {
+    ///
+    /// Copy of the current book
+    ///
+    pub fn snapshot(&self) -> OrderBook {
+        self.state.read().unwrap().book.clone()
+    }
+
+    ///
+    /// Highest purchase price
+    ///
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        self.state.read().unwrap().book.best_bid().cloned()
+    }
+
+    ///
+    /// Lowest sale price
+    ///
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        self.state.read().unwrap().book.best_ask().cloned()
+    }
+
+    ///
+    /// Whether the book mirrors the exchange. It is `false` until the first
+    /// snapshot arrives, after an update is missed and once the connection is
+    /// closed; the last known levels are still available in those cases.
+    ///
+    pub fn is_synced(&self) -> bool {
+        self.state.read().unwrap().synced && !self.connection.is_closed()
+    }
+}
+
+///
+/// Estado del libro local
+///
+struct BookState {
+    book: OrderBook,
+    /// Número de secuencia de la última notificación aplicada
+    sequence: u64,
+    synced: bool,
+    /// Se pidió un snapshot nuevo que aún no llega
+    resyncing: bool,
+}
+
+impl BookState {
+    ///
+    /// Aplica un snapshot o una actualización del canal `orderbook/full`.
+    /// Devuelve `true` si se perdió una actualización y hay que pedir un
+    /// snapshot nuevo
+    ///
+    fn apply(&mut self, message: &Value, market: &str) -> bool {
+        let (data, is_snapshot) = match (message.get("snapshot"), message.get("update")) {
+            (Some(snapshot), _) => (snapshot.get(market), true),
+            (None, Some(update)) => (update.get(market), false),
+            _ => return false,
+        };
+        // El canal también trae los libros de otros mercados suscritos
+        let data = match data {
+            Some(data) => data,
+            None => return false,
+        };
+        let update = match WsBook::deserialize(data) {
+            Ok(update) => update,
+            Err(e) => {
+                error!(target: "cryptomkt", "WS orderbook: {:?}", e);
+                return false;
+            }
+        };
+
+        let mut resync = false;
+        if is_snapshot {
+            self.book.bids.clear();
+            self.book.asks.clear();
+            self.synced = true;
+            self.resyncing = false;
+        } else if update.sequence <= self.sequence {
+            return false;
+        } else if update.sequence != self.sequence + 1 {
+            // Se perdió una actualización: el libro deja de ser fiable
+            self.synced = false;
+            resync = !self.resyncing;
+            self.resyncing = true;
+        }
+        self.sequence = update.sequence;
+        self.book.timestamp = update.timestamp;
+        OrderBook::merge_levels(&mut self.book.bids, levels(update.bids), true);
+        OrderBook::merge_levels(&mut self.book.asks, levels(update.asks), false);
+        resync
+    }
+}
+
+fn levels(levels: Vec<(Decimal, Decimal)>) -> Vec<PriceLevel> {
+    levels
+        .into_iter()
+        .map(|(price, amount)| PriceLevel { price, amount })
+        .collect()
+}
+
+///
+/// Estado de la conexión que sobrevive a las reconexiones
+///
+struct Session {
+    /// Suscripciones vigentes junto a la petición que las creó
+    subscribers: Vec<(Value, Subscriber)>,
+    login: Option<Box<dyn Fn() -> Value + Send>>,
+    policy: ReconnectPolicy,
+    /// Comandos recibidos mientras se reconectaba
+    queued: Vec<Command>,
+}
+
+type Pending = Vec<(Value, oneshot::Sender<CryptoMktResult<()>>)>;
+
+impl Session {
+    ///
+    /// Atiende un comando de `WsClient`. Devuelve `false` si la conexión se cayó
+    ///
+    async fn execute(
+        &mut self,
+        command: Command,
+        write: &mut SplitSink<WsStream, Message>,
+        pending: &mut Pending,
+    ) -> bool {
+        match command {
+            Command::Request { request, subscriber, ack } => {
+                if let Err(e) = write.send(Message::Text(request.to_string().into())).await {
+                    let _ = ack.send(Err(ws_error(e)));
+                    return false;
+                }
+                pending.push((request["id"].clone(), ack));
+                if let Some(subscriber) = subscriber {
+                    self.subscribers.push((request, subscriber));
+                }
+            }
+            Command::Login(login) => self.login = Some(login),
+            Command::SetPolicy(policy) => self.policy = policy,
+        }
+        true
+    }
+}
+
+///
+/// Motivo por el que terminó una conexión
+///
+enum Exit {
+    /// Todos los WsClient y suscripciones fueron soltados
+    Dropped,
+    Disconnected,
+}
+
+///
+/// Tarea que atiende la conexión, restableciéndola según la política de
+/// reconexión cada vez que se cae
+///
+async fn run(url: String, mut stream: WsStream, mut commands: mpsc::UnboundedReceiver<Command>) {
+    let mut session = Session {
+        subscribers: Vec::new(),
+        login: None,
+        policy: ReconnectPolicy::default(),
+        queued: Vec::new(),
+    };
+    loop {
+        if let Exit::Dropped = serve(stream, &mut commands, &mut session).await {
+            return;
+        }
+        stream = match reconnect(&url, &mut commands, &mut session).await {
+            Some(stream) => stream,
+            None => return,
+        };
+    }
+}
+
+///
+/// Envía las suscripciones y reparte las notificaciones entre los suscriptores
+/// de cada canal hasta que se cae la conexión
+///
+async fn serve(
+    stream: WsStream,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    session: &mut Session,
+) -> Exit {
+    let (mut write, mut read) = stream.split();
+    let mut pending: Pending = Vec::new();
+
+    let mut queued = std::mem::take(&mut session.queued).into_iter();
+    while let Some(command) = queued.next() {
+        if !session.execute(command, &mut write, &mut pending).await {
+            session.queued.extend(queued);
+            return Exit::Disconnected;
+        }
+    }
+
+    loop {
+        tokio::select! {
+            command = commands.recv() => match command {
+                Some(command) => {
+                    if !session.execute(command, &mut write, &mut pending).await {
+                        return Exit::Disconnected;
+                    }
+                }
+                None => return Exit::Dropped,
+            },
+            message = read.next() => match message {
+                Some(Ok(Message::Text(text))) => {
+                    let message: Value = match serde_json::from_str(&text) {
+                        Ok(message) => message,
+                        Err(e) => {
+                            error!(target: "cryptomkt", "WS: {:?}", e);
+                            continue;
+                        }
+                    };
+                    let channel = message["ch"].as_str().or_else(|| message["method"].as_str());
+                    if let Some(channel) = channel {
+                        session.subscribers.retain_mut(|(_, s)| {
+                            !s.channels.iter().any(|ch| ch == channel)
+                                || (s.deliver)(Notification::Message(&message))
+                        });
+                    } else if let Some(index) = pending.iter().position(|(id, _)| *id == message["id"]) {
+                        let (_, ack) = pending.swap_remove(index);
+                        let _ = ack.send(match message.get("error") {
+                            Some(error) => Err(CryptoMktErrorType::WebSocket(error.to_string()).into()),
+                            None => Ok(()),
+                        });
+                    } else if let Some(error) = message.get("error") {
+                        // Respuesta a una petición repetida tras reconectar
+                        error!(target: "cryptomkt", "WS: {}", error);
+                    }
+                }
+                Some(Ok(Message::Ping(payload))) => {
+                    let _ = write.send(Message::Pong(payload)).await;
+                }
+                Some(Ok(Message::Close(_))) | None => return Exit::Disconnected,
+                Some(Ok(_)) => {}
+                Some(Err(e)) => {
+                    error!(target: "cryptomkt", "WS: {:?}", e);
+                    return Exit::Disconnected;
+                }
+            },
+        }
+    }
+}
+
+///
+/// Restablece la conexión con espera exponencial entre intentos y repite el
+/// login y las suscripciones. Devuelve `None` si se agotan los intentos o si
+/// se soltaron todos los WsClient y suscripciones
+///
+async fn reconnect(
+    url: &str,
+    commands: &mut mpsc::UnboundedReceiver<Command>,
+    session: &mut Session,
+) -> Option<WsStream> {
+    let mut delay = session.policy.initial_delay;
+    let mut attempts = 0;
+    loop {
+        let sleep = tokio::time::sleep(delay);
+        tokio::pin!(sleep);
+        loop {
+            tokio::select! {
+                _ = &mut sleep => break,
+                command = commands.recv() => match command {
+                    Some(Command::Login(login)) => session.login = Some(login),
+                    Some(Command::SetPolicy(policy)) => session.policy = policy,
+                    Some(command) => session.queued.push(command),
+                    None => return None,
+                },
+            }
+        }
+
+        attempts += 1;
+        match connect_async(url).await {
+            Ok((mut stream, _)) => {
+                if resubscribe(&mut stream, session).await {
+                    return Some(stream);
+                }
+            }
+            Err(e) => error!(target: "cryptomkt", "WS reconnect #{}: {:?}", attempts, e),
+        }
+        if session.policy.max_attempts.is_some_and(|max| attempts >= max) {
+            error!(target: "cryptomkt", "WS: giving up after {} reconnection attempts", attempts);
+            return None;
+        }
+        delay = (delay * 2).min(session.policy.max_delay);
+    }
+}
+
+///
+/// Repite el login y las suscripciones vigentes en una conexión nueva y avisa
+/// a los suscriptores. Devuelve `false` si la conexión se cayó entretanto
+///
+async fn resubscribe(stream: &mut WsStream, session: &mut Session) -> bool {
+    let requests: Vec<Value> = session
+        .login
+        .iter()
+        .map(|login| login())
+        .chain(session.subscribers.iter().map(|(request, _)| request.clone()))
+        .collect();
+    for request in requests {
+        if let Err(e) = stream.send(Message::Text(request.to_string().into())).await {
+            error!(target: "cryptomkt", "WS: {:?}", e);
+            return false;
+        }
+    }
+    session
+        .subscribers
+        .retain_mut(|(_, s)| (s.deliver)(Notification::Reconnected));
+    true
+}
+
+///
+/// Ticker tal como lo envía el socket
+///
+#[derive(Deserialize)]
+struct WsTicker {
+    #[serde(rename = "t", deserialize_with = "timestamp::deserialize")]
+    timestamp: DateTime<Utc>,
+    #[serde(rename = "a", default)]
+    ask: Decimal,
+    #[serde(rename = "b", default)]
+    bid: Decimal,
+    #[serde(rename = "c", default)]
+    last_price: Decimal,
+    #[serde(rename = "h", default)]
+    high: Decimal,
+    #[serde(rename = "l", default)]
+    low: Decimal,
+    #[serde(rename = "v", default)]
+    volume: Decimal,
+}
+
+impl WsTicker {
+    fn into_ticker(self, market: &str) -> Ticker {
+        Ticker {
+            high: self.high,
+            low: self.low,
+            ask: self.ask,
+            bid: self.bid,
+            last_price: self.last_price,
+            volume: self.volume,
+            timestamp: self.timestamp,
+            market: market.to_string(),
+        }
+    }
+}
+
+///
+/// Trade tal como lo envía el socket
+///
+#[derive(Deserialize)]
+struct WsTrade {
+    #[serde(rename = "t", deserialize_with = "timestamp::deserialize")]
+    timestamp: DateTime<Utc>,
+    #[serde(rename = "i")]
+    id: Value,
+    #[serde(rename = "p")]
+    price: Decimal,
+    #[serde(rename = "q")]
+    quantity: Decimal,
+    #[serde(rename = "s")]
+    side: String,
+}
+
+impl WsTrade {
+    fn into_trade(self, market: &str) -> Trade {
+        Trade {
+            market_taker: self.side,
+            price: self.price,
+            amount: self.quantity,
+            tid: self.id.to_string(),
+            timestamp: self.timestamp,
+            market: market.to_string(),
+        }
+    }
+}
+
+///
+/// Report of one of my orders pushed by the trading socket
+///
+#[derive(Deserialize, Debug, Clone)]
+pub struct OrderEvent {
+    /// Order ID
+    pub id: Value,
+    /// Order ID chosen by the client
+    #[serde(default)]
+    pub client_order_id: String,
+    /// Market pair
+    #[serde(rename = "symbol")]
+    pub market: String,
+    /// Order side, buy o sell
+    pub side: ReportedSide,
+    /// Order Status: new, suspended, partiallyFilled, filled, canceled, expired
+    pub status: String,
+    /// Order Type: limit, market, ...
+    #[serde(rename = "type")]
+    pub order_type: String,
+    /// Order quantity
+    pub quantity: Decimal,
+    /// Order limit price
+    #[serde(default)]
+    pub price: Decimal,
+    /// Quantity executed so far
+    #[serde(default)]
+    pub quantity_cumulative: Decimal,
+    /// What triggered the report: status, new, canceled, trade, expired, ...
+    #[serde(default)]
+    pub report_type: String,
+    /// Creation date
+    #[serde(default, deserialize_with = "timestamp::deserialize_option")]
+    pub created_at: Option<DateTime<Utc>>,
+    /// Update date
+    #[serde(default, deserialize_with = "timestamp::deserialize_option")]
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+///
+/// Balance change of one of my wallets pushed by the trading socket
+///
+#[derive(Deserialize, Debug, Clone)]
+pub struct BalanceEvent {
+    /// Wallet currency
+    pub currency: String,
+    /// Balance available
+    pub available: Decimal,
+    /// Balance reserved by active orders or withdrawals
+    pub reserved: Decimal,
+}
+
+///
+/// Libro tal como lo envía el socket, con niveles `[precio, cantidad]`
+///
+#[derive(Deserialize)]
+struct WsBook {
+    #[serde(rename = "t", deserialize_with = "timestamp::deserialize")]
+    timestamp: DateTime<Utc>,
+    #[serde(rename = "s")]
+    sequence: u64,
+    #[serde(rename = "b", default)]
+    bids: Vec<(Decimal, Decimal)>,
+    #[serde(rename = "a", default)]
+    asks: Vec<(Decimal, Decimal)>,
+}
+
+fn ws_error<E: std::fmt::Debug>(e: E) -> CryptoMktError {
+    error!(target: "cryptomkt", "WS: {:?}", e);
+    CryptoMktErrorType::WebSocket(format!("{:?}", e)).into()
+}
+
+fn connection_closed() -> CryptoMktError {
+    CryptoMktErrorType::WebSocket("connection closed".to_string()).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::net::TcpListener;
+    use tokio_tungstenite::accept_async;
+
+    ///
+    /// Servidor simulado: confirma cada suscripción y responde con `notifications`
+    ///
+    async fn mock_server(notifications: Vec<Value>) -> String {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(socket).await.unwrap();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let ack = json!({"result": {"ch": request["ch"], "subscriptions": request["params"]["symbols"]}, "id": request["id"]});
+                ws.send(Message::Text(ack.to_string().into())).await.unwrap();
+                for notification in &notifications {
+                    ws.send(Message::Text(notification.to_string().into())).await.unwrap();
+                }
+            }
+        });
+        format!("ws://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn subscribe_ticker_streams_tickers_of_the_market() {
+        let url = mock_server(vec![json!({
+            "ch": "ticker/1s",
+            "data": {
+                "ETHCLP": {"t": 1614815872000u64, "a": "6887", "b": "6416", "c": "6610", "h": "6888", "l": "6303", "v": "13.03"}
+            }
+        })])
+        .await;
+
+        let client = WsClient::connect_to(&url).await.unwrap();
+        let mut tickers = client.subscribe_ticker("ethclp").await.unwrap();
+        let ticker = match tickers.next().await.unwrap() {
+            WsEvent::Data(ticker) => ticker,
+            event => panic!("unexpected {:?}", event),
+        };
+
+        assert_eq!(ticker.market, "ETHCLP");
+        assert_eq!(ticker.ask.to_string(), "6887");
+        assert_eq!(ticker.last_price.to_string(), "6610");
+        assert_eq!(ticker.timestamp.timestamp_millis(), 1614815872000);
+    }
+
+    #[tokio::test]
+    async fn subscribe_trades_streams_snapshot_and_updates() {
+        let url = mock_server(vec![
+            json!({"ch": "trades", "snapshot": {"ETHCLP": [
+                {"t": 1626861109494u64, "i": 1555634969, "p": "2000", "q": "0.5", "s": "buy"}]}}),
+            json!({"ch": "trades", "update": {"ETHCLP": [
+                {"t": 1626861123552u64, "i": 1555634970, "p": "2001", "q": "0.25", "s": "sell"}]}}),
+        ])
+        .await;
+
+        let client = WsClient::connect_to(&url).await.unwrap();
+        let trades: Vec<_> = client
+            .subscribe_trades("ethclp")
+            .await
+            .unwrap()
+            .take(2)
+            .collect()
+            .await;
+
+        match &trades[..] {
+            [WsEvent::Data(first), WsEvent::Data(second)] => {
+                assert_eq!(first.market, "ETHCLP");
+                assert_eq!(first.tid, "1555634969");
+                assert_eq!(first.market_taker, "buy");
+                assert_eq!(second.price.to_string(), "2001");
+                assert_eq!(second.amount.to_string(), "0.25");
+                assert_eq!(second.timestamp.timestamp_millis(), 1626861123552);
+            }
+            events => panic!("unexpected {:?}", events),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscribe_orderbook_applies_snapshot_and_updates() {
+        let url = mock_server(vec![
+            json!({"ch": "orderbook/full", "snapshot": {"ETHCLP": {"t": 1, "s": 10,
+                "b": [["100", "1"], ["99", "2"]],
+                "a": [["101", "1"], ["102", "3"]]}}}),
+            json!({"ch": "orderbook/full", "update": {"ETHCLP": {"t": 2, "s": 11,
+                "b": [["100", "0"], ["99.5", "4"]],
+                "a": [["100.5", "2"]]}}}),
+        ])
+        .await;
+
+        let client = WsClient::connect_to(&url).await.unwrap();
+        let book = client.subscribe_orderbook("ETHCLP").await.unwrap();
+        for _ in 0..100 {
+            if book.snapshot().timestamp.timestamp_millis() == 2 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid().unwrap().price.to_string(), "99.5");
+        assert_eq!(book.best_ask().unwrap().price.to_string(), "100.5");
+        let snapshot = book.snapshot();
+        assert_eq!(snapshot.bids.len(), 2);
+        assert_eq!(snapshot.asks.len(), 3);
+    }
+
+    #[tokio::test]
+    async fn subscribe_orderbook_asks_for_a_snapshot_after_a_gap() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        let (requests_tx, mut requests) = mpsc::unbounded_channel();
+        tokio::spawn(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let mut ws = accept_async(socket).await.unwrap();
+            let mut answers = vec![
+                // Libro de otro mercado, un snapshot y una actualización tras un salto
+                vec![
+                    json!({"ch": "orderbook/full", "update": {"BTCCLP": {"t": 1, "s": 5}}}),
+                    json!({"ch": "orderbook/full", "snapshot": {"ETHCLP": {"t": 1, "s": 10,
+                        "b": [["100", "1"]], "a": [["101", "1"]]}}}),
+                    json!({"ch": "orderbook/full", "update": {"ETHCLP": {"t": 2, "s": 12,
+                        "b": [["99", "1"]]}}}),
+                    json!({"ch": "orderbook/full", "update": {"ETHCLP": {"t": 3, "s": 13,
+                        "b": [["98", "1"]]}}}),
+                ],
+                vec![json!({"ch": "orderbook/full", "snapshot": {"ETHCLP": {"t": 4, "s": 20,
+                    "b": [["97", "1"]], "a": [["103", "1"]]}}})],
+            ]
+            .into_iter();
+            while let Some(Ok(Message::Text(text))) = ws.next().await {
+                let request: Value = serde_json::from_str(&text).unwrap();
+                let ack = json!({"result": true, "id": request["id"]});
+                ws.send(Message::Text(ack.to_string().into())).await.unwrap();
+                requests_tx.send(request).unwrap();
+                for notification in answers.next().unwrap_or_default() {
+                    ws.send(Message::Text(notification.to_string().into())).await.unwrap();
+                }
+            }
+        });
+
+        let client = WsClient::connect_to(&url).await.unwrap();
+        let book = client.subscribe_orderbook("ETHCLP").await.unwrap();
+        let first = requests.recv().await.unwrap();
+        // Un solo pedido de snapshot, aunque sigan llegando actualizaciones
+        let second = requests.recv().await.unwrap();
+        assert_eq!(second["ch"], "orderbook/full");
+        assert_eq!(second["params"], first["params"]);
+        for _ in 0..100 {
+            if book.snapshot().timestamp.timestamp_millis() == 4 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+
+        assert!(book.is_synced());
+        assert_eq!(book.best_bid().unwrap().price.to_string(), "97");
+        assert_eq!(book.best_ask().unwrap().price.to_string(), "103");
+        assert!(requests.try_recv().is_err());
+    }
+
+    #[test]
+    fn book_state_ignores_other_markets() {
+        let mut state = BookState {
+            book: OrderBook::default(),
+            sequence: 0,
+            synced: false,
+            resyncing: false,
+        };
+        let other = json!({"ch": "orderbook/full", "snapshot": {"BTCCLP": {"t": 1, "s": 1}}});
+        assert!(!state.apply(&other, "ETHCLP"));
+        assert!(!state.synced);
+        assert_eq!(state.sequence, 0);
+    }
+
+    #[tokio::test]
+    async fn trading_socket_streams_order_and_balance_events() {
+        let url = mock_server(vec![
+            json!({"jsonrpc": "2.0", "method": "spot_order", "params": {
+                "id": 584244931496u64, "client_order_id": "b5acd79c0a854b01b558665bcf379456",
+                "symbol": "ETHCLP", "side": "buy", "status": "new", "type": "limit",
+                "quantity": "0.3", "price": "10000", "quantity_cumulative": "0",
+                "report_type": "new", "created_at": "2021-05-20T11:36:02.131Z", "updated_at": "2021-05-20T11:36:02.131Z"}}),
+            json!({"jsonrpc": "2.0", "method": "spot_balance", "params": [
+                {"currency": "CLP", "available": "120347", "reserved": "3000"}]}),
+        ])
+        .await;
+
+        let client = WsClient::connect_trading_to(&url, "APK", "SK").await.unwrap();
+        let mut orders = client.subscribe_orders().await.unwrap();
+        match orders.next().await.unwrap() {
+            WsEvent::Data(order) => {
+                assert_eq!(order.market, "ETHCLP");
+                assert_eq!(order.status, "new");
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+
+        let mut balances = client.subscribe_balances().await.unwrap();
+        match balances.next().await.unwrap() {
+            WsEvent::Data(balance) => {
+                assert_eq!(balance.currency, "CLP");
+                assert_eq!(balance.reserved.to_string(), "3000");
+            }
+            event => panic!("unexpected {:?}", event),
+        }
+    }
+
+    #[tokio::test]
+    async fn subscriptions_are_restored_after_the_connection_drops() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("ws://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move {
+            // Cada conexión confirma la suscripción, envía un ticker y se cierra
+            for price in &["6610", "6620"] {
+                let (socket, _) = listener.accept().await.unwrap();
+                let mut ws = accept_async(socket).await.unwrap();
+                if let Some(Ok(Message::Text(text))) = ws.next().await {
+                    let request: Value = serde_json::from_str(&text).unwrap();
+                    let ack = json!({"result": true, "id": request["id"]});
+                    ws.send(Message::Text(ack.to_string().into())).await.unwrap();
+                    let ticker = json!({"ch": "ticker/1s", "data": {"ETHCLP": {"t": 1, "c": price}}});
+                    ws.send(Message::Text(ticker.to_string().into())).await.unwrap();
+                }
+                let _ = ws.close(None).await;
+            }
+        });
+
+        let client = WsClient::connect_to(&url).await.unwrap();
+        client
+            .set_reconnect_policy(ReconnectPolicy {
+                initial_delay: Duration::from_millis(10),
+                max_delay: Duration::from_millis(50),
+                max_attempts: Some(5),
+            })
+            .unwrap();
+        let mut tickers = client.subscribe_ticker("ETHCLP").await.unwrap();
+
+        let prices: Vec<_> = tickers
+            .by_ref()
+            .take(3)
+            .map(|event| match event {
+                WsEvent::Data(ticker) => ticker.last_price.to_string(),
+                WsEvent::Reconnected => "reconnected".to_string(),
+            })
+            .collect()
+            .await;
+        assert_eq!(prices, vec!["6610", "reconnected", "6620"]);
+    }
+}
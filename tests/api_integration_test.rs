@@ -1,8 +1,2128 @@
-use cryptomkt::Client;
+use async_trait::async_trait;
+use cryptomkt::conditional::{ConditionalOrder, ConditionalOrders, Trigger};
+use cryptomkt::cost::Liquidity;
+use cryptomkt::execution::{Twap, TwapState};
+use cryptomkt::history::download_trades;
+use cryptomkt::mirror::OrderBookMirror;
+use cryptomkt::mock::MockTransport;
+use cryptomkt::models::PaymentStatus;
+use cryptomkt::replay::{RecordingTransport, ReplayTransport};
+use cryptomkt::tracker::{OrderStatus, OrderTracker, TrackerEvent};
+use cryptomkt::webhooks::verify_callback;
+use cryptomkt::{
+    Client, CryptoMktErrorType, CryptoMktResult, Currency, Decimal, MarketPair, HttpRequest, NaiveDate, OrderRequest, OrderState, OrderType, Page, RateLimitBehavior, RequestMethod, RequestOptions, Side,
+    WithdrawalDestination, with_cancellation,
+};
+use futures::StreamExt;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::Url;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_util::sync::CancellationToken;
+
+fn client(transport: &MockTransport) -> Client {
+    Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .build()
+        .unwrap()
+}
 
 #[tokio::test]
 async fn test_api_get_markets() {
-    let api = Client::new("APK", "SK");
-    let markets = api.get_markets();
-    assert!(markets.await.len() > 1);
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let markets = api.get_markets().await.unwrap();
+    assert!(markets.len() > 1);
+}
+
+#[tokio::test]
+async fn test_api_get_ticker() {
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+    let ticker = market.get_current_ticker().await.unwrap();
+    assert_eq!(ticker.market, "ETHCLP");
+
+    let requests = transport.requests();
+    let ticker_request = requests.iter().find(|r| r.endpoint == "ticker").unwrap();
+    assert_eq!(ticker_request.params["market"], "ETHCLP");
+}
+
+#[tokio::test]
+async fn test_api_get_balance_is_signed() {
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let balance = api.get_balance().await.unwrap();
+    assert_eq!(balance.len(), 2);
+
+    let request = &transport.requests()[0];
+    assert_eq!(request.endpoint, "balance");
+    assert_eq!(request.headers["X-MKT-APIKEY"], "APK");
+    assert!(request.headers.contains_key("X-MKT-SIGNATURE"));
+}
+
+#[tokio::test]
+async fn test_api_credentials_are_redacted() {
+    let transport = MockTransport::with_fixtures();
+    let builder = Client::builder().credentials("APK", "SK-SECRET");
+    assert!(!format!("{:?}", builder).contains("SK-SECRET"));
+    let api = builder.http_transport(transport.clone()).build().unwrap();
+    assert!(!format!("{:?}", api).contains("SK-SECRET"));
+
+    api.get_balance().await.unwrap();
+    let request = &transport.requests()[0];
+    assert!(request.headers["X-MKT-APIKEY"].is_sensitive());
+    assert!(request.headers["X-MKT-SIGNATURE"].is_sensitive());
+    let signature = request.headers["X-MKT-SIGNATURE"].to_str().unwrap();
+    assert!(!format!("{:?}", request.headers).contains(signature));
+}
+
+#[tokio::test]
+async fn test_api_without_credentials_only_reaches_public_endpoints() {
+    let transport = MockTransport::with_fixtures();
+    let api = Client::builder()
+        .http_transport(transport.clone())
+        .build()
+        .unwrap();
+    let market = api.create_market(MarketPair::ETHCLP);
+    assert_eq!(market.pair().unwrap().quote, Currency::Clp);
+    let ticker = market.get_current_ticker().await.unwrap();
+    assert_eq!(ticker.market, "ETHCLP");
+    let sent = transport.requests().len();
+
+    let err = api.get_balance().await.unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::MissingCredentials);
+    assert_eq!(transport.requests().len(), sent);
+}
+
+#[tokio::test]
+async fn test_api_get_active_orders() {
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+    let orders = market
+        .get_user_orders_by_state(OrderState::Active, Page::default().limit(20))
+        .await
+        .unwrap();
+    assert_eq!(orders.len(), 2);
+}
+
+#[tokio::test]
+async fn test_api_unknown_endpoint_is_not_found() {
+    let transport = MockTransport::new().on_get("market", r#"{"status":"success","data":["ETHCLP"]}"#);
+    let api = client(&transport);
+    let err = api.get_balance().await.err().unwrap();
+    assert_eq!(err.status(), Some(404));
+}
+
+#[tokio::test]
+async fn test_api_get_all_tickers() {
+    let transport = MockTransport::new().on_get(
+        "ticker",
+        r#"{"status":"success","data":[{"high":"6888","volume":"13.03","low":"6303","ask":"6887","timestamp":"2017-08-29T15:44:17.267526","bid":"6416","last_price":"6610","market":"ETHARS"},{"high":"252300","volume":"40.1","low":"249800","ask":"252210","timestamp":"2017-08-29T15:44:17.267526","bid":"251000","last_price":"252000","market":"ETHCLP"}]}"#,
+    );
+    let api = client(&transport);
+    let tickers = api.get_all_tickers().await.unwrap();
+
+    assert_eq!(tickers.len(), 2);
+    assert_eq!(tickers["ETHCLP"].last_price.to_string(), "252000");
+    assert_eq!(tickers["ETHARS"].last_price.to_string(), "6610");
+    assert!(transport.requests()[0].params.is_empty());
+}
+
+/// Transport that takes a while to answer, counting the requests in flight
+#[derive(Clone, Default)]
+struct SlowTransport {
+    inner: MockTransport,
+    in_flight: Arc<AtomicUsize>,
+    max_in_flight: Arc<AtomicUsize>,
+}
+
+#[async_trait]
+impl HttpRequest for SlowTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        let now = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(now, Ordering::SeqCst);
+        tokio::time::sleep(Duration::from_millis(20)).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        self.inner.get(url, headers).await
+    }
+
+    async fn post(&self, url: Url, headers: HeaderMap, payload: HashMap<String, String>) -> Self::Result {
+        self.inner.post(url, headers, payload).await
+    }
+}
+
+#[tokio::test]
+async fn test_api_get_tickers() {
+    let transport = SlowTransport {
+        inner: MockTransport::with_fixtures(),
+        ..Default::default()
+    };
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .concurrency(2)
+        .build()
+        .unwrap();
+    api.get_markets().await.unwrap();
+
+    let tickers = api.get_tickers(&["ETHCLP", "ETHARS", "BTCCLP"]).await.unwrap();
+    assert_eq!(tickers.len(), 3);
+    assert!(tickers.contains_key("ETHARS"));
+    assert_eq!(transport.max_in_flight.load(Ordering::SeqCst), 2);
+    let tickers_requested = transport
+        .inner
+        .requests()
+        .iter()
+        .filter(|r| r.endpoint == "ticker")
+        .count();
+    assert_eq!(tickers_requested, 3);
+
+    let err = api.get_tickers(&["ETHCLP", "DOGECLP"]).await.unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::UnknownMarket("DOGECLP".to_string()));
+    assert!(api.get_tickers(&[]).await.unwrap().is_empty());
+}
+
+#[tokio::test]
+async fn test_api_get_order_book_snapshot() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "book",
+        r#"{"status":"success","pagination":{"previous":"null","limit":20,"page":0,"next":"null"},"data":[{"timestamp":"2017-08-31T12:31:58.782060","price":"252200","amount":"0.6729"},{"timestamp":"2017-08-31T10:14:58.466285","price":"252610","amount":"7.6226"},{"timestamp":"2017-08-30T18:15:54.757558","price":"252200","amount":"2.3271"}]}"#,
+    );
+    let api = client(&transport);
+    let book = api
+        .create_market("ETHCLP")
+        .get_order_book_snapshot(20)
+        .await
+        .unwrap();
+
+    let bids: Vec<String> = book.bids.iter().map(|l| l.price.to_string()).collect();
+    let asks: Vec<String> = book.asks.iter().map(|l| l.price.to_string()).collect();
+    assert_eq!(bids, ["252610", "252200"]);
+    assert_eq!(asks, ["252200", "252610"]);
+    assert_eq!(book.best_ask().unwrap().amount.to_string(), "3.0000");
+    assert_eq!(book.spread().unwrap().to_string(), "-410");
+
+    let mut sides: Vec<String> = transport
+        .requests()
+        .iter()
+        .filter(|r| r.endpoint == "book")
+        .map(|r| r.params["type"].clone())
+        .collect();
+    sides.sort();
+    assert_eq!(sides, ["buy", "sell"]);
+
+    let market = api.create_market("ETHCLP");
+    let bid = market.best_bid().await.unwrap().unwrap();
+    assert_eq!((bid.price.to_string(), bid.amount.to_string()), ("252610".into(), "7.6226".into()));
+    let ask = market.best_ask().await.unwrap().unwrap();
+    assert_eq!((ask.price.to_string(), ask.amount.to_string()), ("252200".into(), "3.0000".into()));
+    assert_eq!(market.spread().await.unwrap().unwrap().to_string(), "-410");
+}
+
+#[tokio::test]
+async fn test_api_instant_exchange() {
+    let transport = MockTransport::with_fixtures()
+        .on_get(
+            "orders/instant/get",
+            r#"{"status":"success","data":{"obtained":"1.38","required":"10000"}}"#,
+        )
+        .on_post("orders/instant/create", r#"{"status":"success","data":""}"#);
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+
+    let quote = market
+        .quote_instant(OrderType::Buy, Decimal::new(10000, 0))
+        .await
+        .unwrap();
+    assert_eq!(quote.obtained.to_string(), "1.38");
+    market
+        .execute_instant(OrderType::Buy, Decimal::new(10000, 0))
+        .await
+        .unwrap();
+
+    let requests = transport.requests();
+    let execute = requests.last().unwrap();
+    assert_eq!(execute.method, "POST");
+    assert_eq!(execute.endpoint, "orders/instant/create");
+    assert_eq!(execute.params["type"], "buy");
+    assert_eq!(execute.params["amount"], "10000");
+}
+
+#[tokio::test]
+async fn test_api_get_deposits_and_withdrawals() {
+    let transport = MockTransport::new()
+        .on_get(
+            "deposits",
+            r#"{"status":"success","pagination":{"previous":"null","limit":20,"page":0,"next":"null"},"data":[{"id":"D1001","currency":"ETH","amount":"1.5","fee":"0","status":"completed","address":"0x5f1c","hash":"0xab12","date":"2017-09-01T14:01:56.887272"}]}"#,
+        )
+        .on_get(
+            "withdrawals",
+            r#"{"status":"success","pagination":{"previous":"null","limit":20,"page":0,"next":"null"},"data":[{"id":"W2001","currency":"CLP","amount":"100000","fee":"300","status":"pending","date":"2017-09-02T10:00:00"}]}"#,
+        );
+    let api = client(&transport);
+
+    let deposits = api.get_deposits("eth", Page::default().number(0).limit(20)).await.unwrap();
+    assert_eq!(deposits[0].amount.to_string(), "1.5");
+    assert_eq!(deposits[0].hash.as_deref(), Some("0xab12"));
+
+    let withdrawals = api.get_withdrawals("CLP", Page::default().limit(20)).await.unwrap();
+    assert_eq!(withdrawals[0].fee.to_string(), "300");
+    assert_eq!(withdrawals[0].address, None);
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].params["currency"], "ETH");
+    assert_eq!(requests[1].endpoint, "withdrawals");
+}
+
+#[tokio::test]
+async fn test_api_request_withdrawal() {
+    let transport = MockTransport::new().on_post(
+        "withdrawal",
+        r#"{"status":"success","data":{"id":"W2002","currency":"ETH","amount":"0.5","fee":"0.005","status":"pending","address":"0x5f1c","date":"2017-09-02T10:00:00"}}"#,
+    );
+    let api = client(&transport);
+    let withdrawal = api
+        .request_withdrawal(
+            Decimal::new(5, 1),
+            "eth",
+            WithdrawalDestination::Address {
+                address: "0x5f1c".to_string(),
+                memo: None,
+            },
+        )
+        .await
+        .unwrap();
+    assert_eq!(withdrawal.status, "pending");
+
+    let request = &transport.requests()[0];
+    assert_eq!(request.method, "POST");
+    assert_eq!(request.params["amount"], "0.5");
+    assert_eq!(request.params["currency"], "ETH");
+    assert_eq!(request.params["address"], "0x5f1c");
+    assert!(request.headers.contains_key("X-MKT-SIGNATURE"));
+}
+
+#[tokio::test]
+async fn test_api_list_endpoints_keep_pagination() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "trades",
+        r#"{"status":"success","pagination":{"previous":"null","limit":1,"page":0,"next":1},"data":[{"market_taker":"buy","timestamp":"2017-05-29T22:14:00.419466","price":"155000","amount":"0.129","market":"ETHCLP"}]}"#,
+    );
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+    let day = NaiveDate::from_ymd_opt(2017, 5, 29).unwrap();
+
+    let trades = market.get_trades(day, day, Page::default().number(0).limit(1)).await.unwrap();
+    assert_eq!(trades.len(), 1);
+    assert!(trades.has_next());
+    assert_eq!(trades.next_page(), Some(1));
+
+    let orders = market.get_active_orders(Page::default().limit(20)).await.unwrap();
+    assert!(!orders.has_next());
+}
+
+#[tokio::test]
+async fn test_api_stream_trades() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "trades",
+        r#"{"status":"success","pagination":{"previous":"null","limit":1,"page":0,"next":1},"data":[{"market_taker":"buy","timestamp":"2017-05-29T22:14:00.419466","price":"155000","amount":"0.129","market":"ETHCLP"}]}"#,
+    );
+    let api = client(&transport);
+    let day = NaiveDate::from_ymd_opt(2017, 5, 29).unwrap();
+
+    // La segunda página repite `next`, así que el recorrido termina ahí
+    let trades: Vec<_> = api.stream_trades("ETHCLP", day, day).collect().await;
+    assert_eq!(trades.len(), 2);
+    assert!(trades.iter().all(|t| t.is_ok()));
+
+    let pages: Vec<String> = transport
+        .requests()
+        .iter()
+        .filter(|r| r.endpoint == "trades")
+        .map(|r| r.params["page"].clone())
+        .collect();
+    assert_eq!(pages, ["0", "1"]);
+}
+
+#[tokio::test]
+async fn test_api_client_get_trades_sends_only_given_params() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "trades",
+        r#"{"status":"success","pagination":{"previous":"null","limit":20,"page":0,"next":1},"data":[{"market_taker":"buy","timestamp":"2017-05-29T22:14:00.419466","price":"155000","amount":"0.129","market":"ETHCLP"}]}"#,
+    );
+    let api = client(&transport);
+    let start = NaiveDate::from_ymd_opt(2017, 5, 29).unwrap();
+
+    let trades = api
+        .get_trades("ETHCLP", Some(start), None, Page::default().limit(20))
+        .await
+        .unwrap();
+    assert_eq!(trades.len(), 1);
+    assert_eq!(trades.next_page(), Some(1));
+
+    let requests = transport.requests();
+    let params = &requests.iter().find(|r| r.endpoint == "trades").unwrap().params;
+    assert_eq!(params["market"], "ETHCLP");
+    assert_eq!(params["start"], "2017-05-29");
+    assert_eq!(params["limit"], "20");
+    assert!(!params.contains_key("end"));
+    assert!(!params.contains_key("page"));
+}
+
+#[tokio::test]
+async fn test_api_paper_trading_fills_against_the_book() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "book",
+        r#"{"status":"success","data":[{"timestamp":"2017-08-31T12:31:58.782060","price":"101","amount":"1"},{"timestamp":"2017-08-31T12:31:58.782060","price":"100","amount":"0.5"},{"timestamp":"2017-08-31T12:31:58.782060","price":"105","amount":"2"}]}"#,
+    );
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .paper_trading(true)
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+
+    let filled = market
+        .create_order(OrderType::Buy, Decimal::new(1, 0), Decimal::new(101, 0))
+        .await
+        .unwrap();
+    assert_eq!(filled.status, "executed");
+    assert_eq!(filled.execution_price.unwrap().to_string(), "100.5");
+
+    let resting = market
+        .create_order(OrderType::Buy, Decimal::new(5, 0), Decimal::new(101, 0))
+        .await
+        .unwrap();
+    assert_eq!(resting.status, "active");
+    assert_eq!(resting.amount.executed.to_string(), "1.5");
+    assert_eq!(resting.amount.remaining.to_string(), "3.5");
+
+    assert_eq!(market.get_active_orders(Page::default().limit(20)).await.unwrap().len(), 1);
+    assert_eq!(market.get_executed_orders(Page::default().limit(20)).await.unwrap().len(), 1);
+    let cancelled = market.cancel_order(&resting.id).await.unwrap();
+    assert_eq!(cancelled.status, "cancelled");
+    assert!(market.get_active_orders(Page::default().limit(20)).await.unwrap().is_empty());
+
+    // Nothing but reads reached the exchange
+    assert!(transport.requests().iter().all(|r| r.method == "GET"));
+}
+
+#[tokio::test]
+async fn test_api_paper_trading_fills_resting_orders_later() {
+    let book = |price: &str| {
+        format!(
+            r#"{{"status":"success","data":[{{"timestamp":"2017-08-31T12:31:58.782060","price":"{}","amount":"1"}}]}}"#,
+            price
+        )
+    };
+    let transport = MockTransport::with_fixtures().on_get("book", &book("105"));
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .paper_trading(true)
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+
+    let resting = market
+        .create_order(OrderType::Buy, Decimal::new(2, 0), Decimal::new(101, 0))
+        .await
+        .unwrap();
+    assert_eq!(resting.status, "active");
+    assert_eq!(resting.amount.executed, Decimal::ZERO);
+
+    // El libro baja y cruza la mitad de la orden
+    let transport = transport.on_get("book", &book("100"));
+    let order = market.get_order_status(&resting.id).await.unwrap();
+    assert_eq!(order.status, "active");
+    assert_eq!(order.amount.remaining.to_string(), "1");
+
+    transport.on_get("book", &book("101"));
+    assert!(market.get_active_orders(Page::default().limit(20)).await.unwrap().is_empty());
+    let executed = market.get_executed_orders(Page::default().limit(20)).await.unwrap();
+    assert_eq!(executed[0].id, resting.id);
+    assert_eq!(executed[0].execution_price.unwrap().to_string(), "100.5");
+    assert!(executed[0].executed_at.is_some());
+
+    let err = market.execute_instant(Side::Buy, Decimal::new(1, 0)).await.unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn test_api_paper_trading_never_moves_funds() {
+    let destination = || WithdrawalDestination::Address {
+        address: "0xd3adb33f".to_string(),
+        memo: None,
+    };
+    let err = Client::new_paper("APK", "SK")
+        .request_withdrawal(Decimal::new(1, 0), "ETH", destination())
+        .await
+        .unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+
+    let transport = MockTransport::with_fixtures().on_post("withdrawal", r#"{"status":"success","data":{}}"#);
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .paper_trading(true)
+        .build()
+        .unwrap();
+    let err = api.request_withdrawal(Decimal::new(1, 0), "ETH", destination()).await.unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+    assert!(api.cancel_payment_order("P1").await.is_err());
+    assert!(transport.requests().is_empty());
+
+    let err = Client::builder().paper_trading(true).build_v3().unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+}
+
+/// Transport whose server clock runs one hour ahead of the local one
+struct SkewedTransport(MockTransport);
+
+#[async_trait]
+impl HttpRequest for SkewedTransport {
+    type Result = CryptoMktResult<String>;
+
+    async fn get(&self, url: Url, headers: HeaderMap) -> Self::Result {
+        self.0.get(url, headers).await
+    }
+
+    async fn post(&self, url: Url, headers: HeaderMap, payload: HashMap<String, String>) -> Self::Result {
+        self.0.post(url, headers, payload).await
+    }
+
+    async fn server_time(&self, _url: Url) -> CryptoMktResult<Option<SystemTime>> {
+        Ok(Some(SystemTime::now() + Duration::from_secs(3600)))
+    }
+}
+
+#[tokio::test]
+async fn test_api_clock_sync_corrects_signature_timestamps() {
+    let transport = MockTransport::with_fixtures();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(SkewedTransport(transport.clone()))
+        .clock_sync(Duration::from_secs(60))
+        .build()
+        .unwrap();
+
+    api.get_balance().await.unwrap();
+
+    let offset = api.api.clock_offset();
+    assert!((offset - 3_600_000).abs() < 5_000, "{}", offset);
+    let timestamp: u64 = transport.requests()[0].headers["X-MKT-TIMESTAMP"]
+        .to_str()
+        .unwrap()
+        .parse()
+        .unwrap();
+    let expected = (SystemTime::now() + Duration::from_secs(3600))
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_millis() as u64;
+    assert!(timestamp.abs_diff(expected) <= 5_000, "{} {}", timestamp, expected);
+}
+
+#[tokio::test]
+async fn test_api_payment_order_builder() {
+    let transport = MockTransport::new().on_post(
+        "payment/new_order",
+        r#"{"status": "success", "data": {
+            "id": 52, "external_id": "ORD-1", "status": "0", "to_receive": "5000",
+            "to_receive_currency": "CLP", "expected_amount": "0.0013",
+            "expected_currency": "ETH", "deposit_address": "0xabc",
+            "refund_email": "", "qr": "", "obs": "",
+            "callback_url": "https://example.com/callback", "error_url": "",
+            "success_url": "", "payment_url": "https://example.com/pay",
+            "created_at": "2017-11-15T12:00:00.000000", "updated_at": "2017-11-15T12:00:00.000000"
+        }}"#,
+    );
+    let api = client(&transport);
+    let payment = api
+        .payment_order(Decimal::new(5000, 0), "CLP", "merchant@example.com")
+        .external_id("ORD-1")
+        .callback_url("https://example.com/callback")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(payment.id, 52);
+    assert_eq!(payment.status, PaymentStatus::WaitingPayment);
+    assert!(!payment.is_final());
+
+    let params = &transport.requests()[0].params;
+    assert_eq!(params["to_receive"], "5000");
+    assert_eq!(params["payment_receiver"], "merchant@example.com");
+    assert_eq!(params["callback_url"], "https://example.com/callback");
+    assert!(!params.contains_key("refund_email"));
+}
+
+#[tokio::test]
+async fn test_api_client_shared_across_tasks() {
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let tasks = (0..4)
+        .map(|_| {
+            let api = api.clone();
+            tokio::spawn(async move { api.get_balance().await })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        assert_eq!(task.await.unwrap().unwrap()[0].wallet, "CLP");
+    }
+    assert_eq!(transport.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn test_api_v2_client() {
+    let transport = MockTransport::new()
+        .on_get(
+            "public/ticker/ETHCLP",
+            r#"{"symbol": "ETHCLP", "ask": "190000", "bid": "189000", "last": "189500",
+                "open": null, "low": "185000", "high": "192000", "volume": "12.5",
+                "volumeQuote": "2368750", "timestamp": "2021-05-20T11:36:02.131Z"}"#,
+        )
+        .on_post(
+            "order",
+            r#"{"id": 840450210, "clientOrderId": "c1837634ef81472a9cd13c81e7b91401",
+                "symbol": "ETHCLP", "side": "buy", "status": "new", "type": "limit",
+                "timeInForce": "GTC", "quantity": "0.5", "price": "180000", "cumQuantity": "0",
+                "createdAt": "2021-05-20T11:36:02.131Z", "updatedAt": "2021-05-20T11:36:02.131Z"}"#,
+        )
+        .on_delete(
+            "order/c1837634ef81472a9cd13c81e7b91401",
+            r#"{"id": 840450210, "clientOrderId": "c1837634ef81472a9cd13c81e7b91401",
+                "symbol": "ETHCLP", "side": "buy", "status": "canceled", "type": "limit",
+                "timeInForce": "GTC", "quantity": "0.5", "price": "180000", "cumQuantity": "0",
+                "createdAt": "2021-05-20T11:36:02.131Z", "updatedAt": "2021-05-20T11:37:00.000Z"}"#,
+        );
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .build_v2()
+        .unwrap();
+
+    let ticker = api.get_ticker("ETHCLP").await.unwrap();
+    assert_eq!(ticker.last, Some(Decimal::new(189500, 0)));
+    assert_eq!(ticker.open, None);
+
+    let order = api
+        .create_order("ETHCLP", OrderType::Buy, Decimal::new(5, 1), Decimal::new(180000, 0))
+        .await
+        .unwrap();
+    let cancelled = api.cancel_order(&order.client_order_id).await.unwrap();
+    assert_eq!(cancelled.status, "canceled");
+
+    let requests = transport.requests();
+    assert_eq!(requests[1].params["side"], "buy");
+    assert_eq!(requests[1].params["quantity"], "0.5");
+    assert_eq!(requests[2].method, "DELETE");
+    // Autenticación básica con "APK:SK"
+    assert_eq!(requests[0].headers["authorization"], "Basic QVBLOlNL");
+}
+
+#[tokio::test]
+async fn test_api_v3_client() {
+    let transport = MockTransport::new()
+        .on_get(
+            "public/orderbook/ETHCLP",
+            r#"{"timestamp": "2021-05-20T11:36:02.131Z",
+                "ask": [["190000", "0.5"], ["191000", "1.2"]], "bid": [["189000", "0.3"]]}"#,
+        )
+        .on_get(
+            "spot/balance",
+            r#"[{"currency": "CLP", "available": "150000", "reserved": "0"}]"#,
+        )
+        .on_get(
+            "sub-account",
+            r#"{"result": [{"sub_account_id": "be2a3d", "email": "sub@example.com", "status": "active"}]}"#,
+        );
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .build_v3()
+        .unwrap();
+
+    let book = api.get_order_book("ETHCLP", 2).await.unwrap();
+    assert_eq!(book.ask.len(), 2);
+    assert_eq!(book.ask[0].price, Decimal::new(190000, 0));
+    assert_eq!(book.bid[0].size, Decimal::new(3, 1));
+
+    let balance = api.get_spot_balance().await.unwrap();
+    assert_eq!(balance[0].available, Decimal::new(150000, 0));
+
+    let accounts = api.get_sub_accounts().await.unwrap();
+    assert_eq!(accounts[0].status, "active");
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].params["depth"], "2");
+    let auth = &requests[1].headers["authorization"];
+    assert!(auth.to_str().unwrap().starts_with("HS256 "));
+}
+
+#[tokio::test]
+async fn test_api_record_and_replay() {
+    let cassette =
+        std::env::temp_dir().join(format!("cryptomkt-cassette-{}.json", std::process::id()));
+    let recorder = RecordingTransport::wrap(MockTransport::with_fixtures(), &cassette);
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(recorder.clone())
+        .build()
+        .unwrap();
+    let balance = api.get_balance().await.unwrap();
+    let orders = api.create_market("ETHCLP").get_active_orders(Page::default().limit(20)).await.unwrap();
+    assert!(api.get_account().await.is_err());
+    // market, orders/active, balance and the 404 of account
+    assert_eq!(recorder.len(), 4);
+
+    let replay = ReplayTransport::from_file(&cassette).unwrap();
+    std::fs::remove_file(&cassette).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    assert_eq!(api.get_balance().await.unwrap()[0].available, balance[0].available);
+    let replayed = api.create_market("ETHCLP").get_active_orders(Page::default().limit(20)).await.unwrap();
+    assert_eq!(replayed.len(), orders.len());
+    let err = api.get_account().await.unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::RequestNotFound);
+    assert_eq!(replay.remaining(), 0);
+
+    // Cada respuesta se sirve una sola vez
+    let err = api.get_balance().await.unwrap_err();
+    assert_eq!(err.status(), Some(404));
+}
+
+fn payment_body(status: &str) -> String {
+    format!(
+        r#"{{"status": "success", "data": {{
+            "id": 52, "external_id": "ORD-1", "status": "{}", "to_receive": "5000",
+            "to_receive_currency": "CLP", "expected_amount": "0.0013",
+            "expected_currency": "ETH", "deposit_address": "0xabc",
+            "refund_email": "", "qr": "", "obs": "", "callback_url": "", "error_url": "",
+            "success_url": "", "payment_url": "https://example.com/pay",
+            "created_at": "2017-11-15T12:00:00.000000", "updated_at": "2017-11-15T12:00:00.000000"
+        }}}}"#,
+        status
+    )
+}
+
+#[tokio::test]
+async fn test_api_wait_for_payment() {
+    let transport = MockTransport::new().on_get("payment/status", &payment_body("3"));
+    let api = client(&transport);
+    let payment = api
+        .wait_for_payment("52", Duration::from_millis(10), Duration::from_secs(1))
+        .await
+        .unwrap();
+    assert!(payment.is_paid());
+    assert_eq!(transport.requests().len(), 1);
+
+    let transport = MockTransport::new().on_get("payment/status", &payment_body("1"));
+    let api = client(&transport);
+    let err = api
+        .wait_for_payment("52", Duration::from_millis(10), Duration::from_millis(35))
+        .await
+        .unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::Timeout);
+    assert!(transport.requests().len() >= 2);
+}
+
+#[tokio::test]
+async fn test_api_wait_for_payment_polls_once_more_at_the_deadline() {
+    let status = |status: &str| {
+        serde_json::json!({
+            "method": "GET", "path": "/v1/payment/status", "params": {"id": "52"},
+            "status": 200, "body": payment_body(status),
+        })
+    };
+    let cassette = serde_json::json!([status("1"), status("3")]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+
+    // El intervalo supera el plazo: se espera solo lo que queda y se consulta otra vez
+    let started = std::time::Instant::now();
+    let payment = api
+        .wait_for_payment("52", Duration::from_secs(60), Duration::from_millis(50))
+        .await
+        .unwrap();
+    assert!(payment.is_paid());
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_list_payment_orders() {
+    let order: serde_json::Value = serde_json::from_str(&payment_body("0")).unwrap();
+    let body = serde_json::json!({
+        "status": "success",
+        "pagination": {"previous": "null", "limit": 1, "page": 0, "next": 1},
+        "data": [order["data"]],
+    });
+    let transport = MockTransport::new()
+        .on_get("payment/orders", &body.to_string())
+        .on_get("payment/status", &payment_body("3"));
+    let api = client(&transport);
+    let day = NaiveDate::from_ymd_opt(2017, 11, 15).unwrap();
+
+    let orders = api.get_payment_orders(day, day, Page::default().limit(1)).await.unwrap();
+    assert_eq!(orders.len(), 1);
+    assert_eq!(orders[0].status, PaymentStatus::WaitingPayment);
+    assert_eq!(orders.next_page(), Some(1));
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].endpoint, "payment/orders");
+    assert_eq!(requests[0].params["start_date"], "2017-11-15");
+    assert_eq!(requests[0].params["limit"], "1");
+
+    // La segunda página repite `next`, así que el recorrido termina ahí
+    let streamed: Vec<_> = api.stream_payment_orders(day, day).collect().await;
+    assert_eq!(streamed.len(), 2);
+    assert!(streamed.iter().all(|p| p.is_ok()));
+    assert!(transport.requests().iter().all(|r| r.endpoint == "payment/orders"));
+}
+
+#[tokio::test]
+async fn test_api_cancel_payment_order() {
+    let transport = MockTransport::new().on_post("payment/cancel", &payment_body("-1"));
+    let api = client(&transport);
+    let payment = api.cancel_payment_order("52").await.unwrap();
+    assert_eq!(payment.status, PaymentStatus::Expired);
+    assert!(payment.is_final());
+
+    let requests = transport.requests();
+    assert_eq!(requests[0].method, "POST");
+    assert_eq!(requests[0].params["id"], "52");
+    assert!(requests[0].headers.contains_key("x-mkt-signature"));
+}
+
+/// HMAC-SHA384 de "521" con el secret "SK"
+const CALLBACK_SIGNATURE: &str = "962fa5ff36da32dddca642097705d0f1478fb40d45efbc0fada77a3cacffc5945e1838daea26b8d212f8d0edafac7f26";
+
+#[test]
+fn test_verify_form_callback() {
+    let body = format!(
+        "id=52&external_id=ORD-1&status=1&to_receive=5000&to_receive_currency=CLP&signature={}",
+        CALLBACK_SIGNATURE
+    );
+    let notification = verify_callback(&HeaderMap::new(), body.as_bytes(), "SK").unwrap();
+    assert_eq!(notification.id, 52);
+    assert_eq!(notification.external_id, "ORD-1");
+    assert_eq!(notification.status, PaymentStatus::WaitingBlock);
+    assert!(!notification.status.is_final());
+    assert_eq!(notification.to_receive, Some(Decimal::new(5000, 0)));
+
+    let err = verify_callback(&HeaderMap::new(), body.as_bytes(), "OTHER").unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::InvalidSignature);
+}
+
+#[test]
+fn test_verify_json_callback() {
+    let mut headers = HeaderMap::new();
+    headers.insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    let body = format!(
+        r#"{{"id": 52, "status": 1, "signature": "{}"}}"#,
+        CALLBACK_SIGNATURE
+    );
+    let notification = verify_callback(&headers, body.as_bytes(), "SK").unwrap();
+    assert_eq!(notification.id, 52);
+    assert_eq!(notification.to_receive, None);
+
+    let err = verify_callback(&headers, b"id=52", "SK").unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::MalformedResource);
+
+    // 2^32 + 52 no se trunca a 52, que tendría una firma válida
+    let body = format!(
+        r#"{{"id": 4294967348, "status": 1, "signature": "{}"}}"#,
+        CALLBACK_SIGNATURE
+    );
+    let err = verify_callback(&headers, body.as_bytes(), "SK").unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::MalformedResource);
+}
+
+#[cfg(feature = "webhook-server")]
+#[tokio::test]
+async fn test_payment_webhook_server() {
+    use cryptomkt::webhooks::PaymentWebhookServer;
+
+    let mut server = PaymentWebhookServer::bind("127.0.0.1:0", "SK").await.unwrap();
+    let url = format!("http://{}/callback", server.local_addr());
+    let http = reqwest::Client::new();
+    let post = |signature: &str| {
+        let form = [
+            ("id", "52"),
+            ("external_id", "ORD-1"),
+            ("status", "1"),
+            ("signature", signature),
+        ];
+        http.post(&url).form(&form).send()
+    };
+
+    let resp = post("0123").await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::UNAUTHORIZED);
+    let resp = http.get(&url).send().await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::METHOD_NOT_ALLOWED);
+
+    let resp = post(CALLBACK_SIGNATURE).await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::OK);
+    // Solo llegan las notificaciones con firma válida
+    let notification = tokio::time::timeout(Duration::from_secs(1), server.next())
+        .await
+        .unwrap()
+        .unwrap();
+    assert_eq!(notification.id, 52);
+    assert_eq!(notification.external_id, "ORD-1");
+    assert_eq!(notification.status, PaymentStatus::WaitingBlock);
+
+    // Con la cola llena las notificaciones se rechazan para que se reintenten
+    for _ in 0..256 {
+        assert_eq!(post(CALLBACK_SIGNATURE).await.unwrap().status(), reqwest::StatusCode::OK);
+    }
+    let resp = post(CALLBACK_SIGNATURE).await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::SERVICE_UNAVAILABLE);
+    server.next().await.unwrap();
+    let resp = post(CALLBACK_SIGNATURE).await.unwrap();
+    assert_eq!(resp.status(), reqwest::StatusCode::OK);
+
+    drop(server);
+    tokio::time::sleep(Duration::from_millis(20)).await;
+    assert!(post(CALLBACK_SIGNATURE).await.is_err());
+}
+
+fn book_interaction(side: &str, levels: &[(&str, &str)]) -> serde_json::Value {
+    let data: Vec<_> = levels
+        .iter()
+        .map(|(price, amount)| {
+            serde_json::json!({
+                "timestamp": "2017-08-31T12:31:58.782060", "price": price, "amount": amount
+            })
+        })
+        .collect();
+    let body = serde_json::json!({
+        "status": "success",
+        "pagination": {"previous": 0, "limit": 20, "page": 0, "next": "null"},
+        "data": data,
+    });
+    serde_json::json!({
+        "method": "GET", "path": "/v1/book", "status": 200, "body": body.to_string(),
+        "params": {"market": "ETHCLP", "type": side, "page": "0", "limit": "20"},
+    })
+}
+
+#[tokio::test]
+async fn test_api_order_book_mirror() {
+    let market = serde_json::json!({
+        "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+        "body": r#"{"status":"success","data":["ETHCLP","ETHARS","BTCCLP"]}"#,
+    });
+    let cassette = serde_json::json!([
+        market,
+        book_interaction("buy", &[("252000", "1.5"), ("251000", "2")]),
+        book_interaction("sell", &[("253000", "0.5"), ("254000", "3")]),
+        book_interaction("buy", &[("252500", "1"), ("251000", "2")]),
+        book_interaction("sell", &[("253000", "0.75"), ("254000", "3")]),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let mirror = OrderBookMirror::new(api.create_market("ETHCLP"), 20);
+    assert_eq!(mirror.spread(), None);
+
+    let diff = mirror.refresh().await.unwrap();
+    assert_eq!(diff.bids.len(), 2);
+    assert!(diff.bids.iter().all(|change| change.is_added()));
+    assert_eq!(mirror.best_bid().unwrap().price, Decimal::new(252000, 0));
+    assert_eq!(mirror.best_ask().unwrap().price, Decimal::new(253000, 0));
+    assert_eq!(mirror.spread(), Some(Decimal::new(1000, 0)));
+    assert_eq!(mirror.depth_at(Decimal::new(251000, 0)), Decimal::new(2, 0));
+
+    let reader = mirror.clone();
+    let mut diffs = Box::pin(mirror.diffs(Duration::from_millis(10)));
+    let diff = diffs.next().await.unwrap().unwrap();
+    // El nivel de 252000 sale y aparece uno mejor en 252500
+    assert_eq!(diff.bids.len(), 2);
+    assert!(diff.bids[0].is_added() && diff.bids[0].price == Decimal::new(252500, 0));
+    assert!(diff.bids[1].is_removed());
+    assert_eq!(diff.asks.len(), 1);
+    assert_eq!(diff.asks[0].after, Decimal::new(75, 2));
+    assert_eq!(reader.spread(), Some(Decimal::new(500, 0)));
+    assert_eq!(reader.depth_at(Decimal::new(252000, 0)), Decimal::ZERO);
+    assert_eq!(replay.remaining(), 0);
+
+    // Un refresco fallido conserva el último libro
+    assert!(diffs.next().await.unwrap().is_err());
+    assert_eq!(reader.best_bid().unwrap().price, Decimal::new(252500, 0));
+}
+
+fn trades_interaction(page: u32, next: &str, status: u16, tids: &[&str]) -> serde_json::Value {
+    let data: Vec<_> = tids
+        .iter()
+        .map(|tid| {
+            serde_json::json!({
+                "market_taker": "buy", "price": "252000", "amount": "0.1", "tid": tid,
+                "timestamp": "2017-05-29T12:00:00.000000", "market": "ETHCLP"
+            })
+        })
+        .collect();
+    let body = serde_json::json!({
+        "status": "success",
+        "pagination": {"previous": 0, "limit": 100, "page": page, "next": next},
+        "data": data,
+    });
+    serde_json::json!({
+        "method": "GET", "path": "/v1/trades", "status": status, "body": body.to_string(),
+        "params": {
+            "market": "ETHCLP", "start": "2017-05-01", "end": "2017-06-01",
+            "page": page.to_string(), "limit": "100"
+        },
+    })
+}
+
+#[tokio::test]
+async fn test_api_download_trades() {
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        trades_interaction(0, "1", 200, &["5", "4", "3"]),
+        // Entre páginas llegó un trade nuevo y el 3 pasó a la segunda
+        trades_interaction(1, "null", 429, &[]),
+        trades_interaction(1, "null", 200, &["3", "2", "1"]),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let from = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+    let to = NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+
+    let trades = download_trades(&market, from, to).await.unwrap();
+    let tids: Vec<_> = trades.iter().map(|trade| trade.tid.as_str()).collect();
+    assert_eq!(tids, ["5", "4", "3", "2", "1"]);
+    assert_eq!(replay.remaining(), 0);
+
+    let err = download_trades(&market, from, to).await.unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::RequestNotFound);
+}
+
+#[tokio::test]
+async fn test_api_download_trades_leaves_retries_to_the_client() {
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        trades_interaction(0, "null", 429, &[]),
+        trades_interaction(0, "null", 429, &[]),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .rate_limit_behavior(RateLimitBehavior::Retry {
+            max_retries: 1,
+            max_wait: Duration::from_secs(5),
+        })
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let from = NaiveDate::from_ymd_opt(2017, 5, 1).unwrap();
+    let to = NaiveDate::from_ymd_opt(2017, 6, 1).unwrap();
+
+    // El cliente reintenta una vez y el downloader no suma sus propios reintentos
+    let err = download_trades(&market, from, to).await.unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::RequestTooManyRequests);
+    assert_eq!(replay.remaining(), 0);
+}
+
+fn order_status_interaction(id: &str, status: &str, executed: &str) -> serde_json::Value {
+    let body = serde_json::json!({
+        "status": "success",
+        "data": {
+            "status": status, "created_at": "2017-09-01T14:01:56.887272",
+            "amount": {"executed": executed, "original": "1.5"}, "avg_execution_price": "7120",
+            "price": "7120", "type": "buy", "id": id, "market": "ETHCLP",
+        },
+    });
+    serde_json::json!({
+        "method": "GET", "path": "/v1/orders/status", "params": {"id": id},
+        "status": 200, "body": body.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn test_api_order_tracker() {
+    let cassette = serde_json::json!([
+        order_status_interaction("M1", "active", "0"),
+        order_status_interaction("M2", "active", "0.5"),
+        order_status_interaction("M1", "active", "0"),
+        order_status_interaction("M2", "active", "1"),
+        order_status_interaction("M1", "cancelled", "0"),
+        order_status_interaction("M2", "executed", "1.5"),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let tracker = OrderTracker::new(api);
+    tracker.track("M1");
+    tracker.track("M2");
+    assert_eq!(tracker.status("M1"), None);
+
+    let statuses = |events: Vec<TrackerEvent>| {
+        let mut statuses: Vec<_> = events
+            .iter()
+            .map(|event| (event.order().id.clone(), event.status()))
+            .collect();
+        statuses.sort_by(|a, b| a.0.cmp(&b.0));
+        statuses
+    };
+    let events = tracker.poll().await.into_iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(
+        statuses(events),
+        [("M1".to_string(), OrderStatus::Accepted), ("M2".to_string(), OrderStatus::PartiallyFilled)]
+    );
+
+    // M1 sigue igual; M2 se ejecutó un poco más
+    let events = tracker.poll().await.into_iter().collect::<Result<_, _>>().unwrap();
+    assert_eq!(statuses(events), [("M2".to_string(), OrderStatus::PartiallyFilled)]);
+
+    let mut events = Box::pin(tracker.events(Duration::from_millis(10)));
+    let mut last = Vec::new();
+    last.push(events.next().await.unwrap().unwrap());
+    last.push(events.next().await.unwrap().unwrap());
+    assert_eq!(
+        statuses(last),
+        [("M1".to_string(), OrderStatus::Cancelled), ("M2".to_string(), OrderStatus::Filled)]
+    );
+    assert!(!tracker.has_pending());
+    assert_eq!(tracker.statuses()["M2"], OrderStatus::Filled);
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_order_tracker_reports_failures_per_order() {
+    let cassette = serde_json::json!([
+        order_status_interaction("M1", "active", "0"),
+        order_status_interaction("M1", "executed", "1.5"),
+        order_status_interaction("M2", "active", "0.5"),
+        order_status_interaction("M2", "active", "0.5"),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let tracker = OrderTracker::new(api);
+    tracker.track("M1");
+    tracker.track("M2");
+    tracker.track("M3");
+
+    // M3 no existe: su consulta falla sin impedir que se apliquen las demás
+    let results = tracker.poll().await;
+    assert_eq!(results.len(), 3);
+    let failed: Vec<_> = results.iter().filter_map(|result| result.as_ref().err()).collect();
+    assert_eq!(failed.len(), 1);
+    assert_eq!(failed[0].order_id, "M3");
+    assert_eq!(*failed[0].error.kind(), CryptoMktErrorType::RequestNotFound);
+    assert_eq!(tracker.status("M1"), Some(OrderStatus::Accepted));
+    assert_eq!(tracker.status("M2"), Some(OrderStatus::PartiallyFilled));
+    assert_eq!(tracker.status("M3"), None);
+
+    tracker.untrack("M3");
+    let results = tracker.poll().await;
+    assert!(results.iter().all(Result::is_ok));
+    assert_eq!(tracker.status("M1"), Some(OrderStatus::Filled));
+    assert_eq!(replay.remaining(), 0);
+}
+
+fn ticker_interaction(last_price: &str) -> serde_json::Value {
+    let body = serde_json::json!({
+        "status": "success",
+        "data": [{
+            "high": "200000", "volume": "40.1", "low": "170000", "ask": last_price,
+            "timestamp": "2017-08-29T15:44:17.267526", "bid": last_price,
+            "last_price": last_price, "market": "ETHCLP"
+        }],
+    });
+    serde_json::json!({
+        "method": "GET", "path": "/v1/ticker", "params": {"market": "ETHCLP"},
+        "status": 200, "body": body.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn test_api_conditional_orders() {
+    let created = serde_json::json!({
+        "status": "success",
+        "data": {
+            "status": "active", "created_at": "2017-09-01T14:01:56.887272",
+            "amount": {"original": "0.5", "remaining": "0.5"}, "price": "170000",
+            "type": "sell", "id": "M103970", "market": "ETHCLP",
+            "updated_at": "2017-09-01T14:01:56.887272"
+        },
+    });
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        ticker_interaction("180000"),
+        ticker_interaction("174000"),
+        {
+            "method": "POST", "path": "/v1/orders/create", "status": 200,
+            "params": {"market": "ETHCLP", "type": "sell", "amount": "0.5", "price": "170000"},
+            "body": created.to_string(),
+        },
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let conditional = ConditionalOrders::new(api.create_market("ETHCLP"));
+    let amount = Decimal::new(5, 1);
+    let stop = conditional.register(ConditionalOrder::stop_loss(
+        Side::Sell,
+        amount,
+        Decimal::new(175000, 0),
+        Decimal::new(170000, 0),
+    ));
+    let target = conditional.register(ConditionalOrder::take_profit(
+        Side::Sell,
+        amount,
+        Decimal::new(200000, 0),
+        Decimal::new(199000, 0),
+    ));
+    assert_eq!(
+        ConditionalOrder::take_profit(Side::Buy, amount, Decimal::ONE, Decimal::ONE).trigger,
+        Trigger::Below(Decimal::ONE)
+    );
+
+    // El primer precio no cruza ningún gatillo; el segundo, el stop loss
+    let mut triggered = Box::pin(conditional.watch(Duration::from_millis(10)));
+    let fired = triggered.next().await.unwrap().unwrap();
+    assert_eq!(fired.id, stop);
+    assert_eq!(fired.price, Decimal::new(174000, 0));
+    assert_eq!(fired.result.unwrap().id, "M103970");
+    assert_eq!(replay.remaining(), 0);
+
+    let pending = conditional.pending();
+    assert_eq!(pending.len(), 1);
+    assert_eq!(pending[0].0, target);
+    assert!(conditional.cancel(target).is_some());
+    assert!(conditional.cancel(stop).is_none());
+    assert!(conditional.on_price(Decimal::new(250000, 0)).await.is_empty());
+}
+
+fn symbol_interaction(quantity_increment: &str) -> serde_json::Value {
+    let body = serde_json::json!({
+        "type": "spot", "base_currency": "ETH", "quote_currency": "CLP", "status": "working",
+        "quantity_increment": quantity_increment, "tick_size": "10", "take_rate": "0.0068",
+        "make_rate": "0.0039", "fee_currency": "CLP",
+    });
+    serde_json::json!({
+        "method": "GET", "path": "/api/3/public/symbol/ETHCLP", "params": {},
+        "status": 200, "body": body.to_string(),
+    })
+}
+
+#[tokio::test]
+async fn test_api_twap_execution() {
+    let create = |amount: &str, id: &str| {
+        let body = serde_json::json!({
+            "status": "success",
+            "data": {
+                "status": "active", "created_at": "2017-09-01T14:01:56.887272",
+                "amount": {"original": amount, "remaining": amount}, "price": "180000",
+                "type": "sell", "id": id, "market": "ETHCLP"
+            },
+        });
+        serde_json::json!({
+            "method": "POST", "path": "/v1/orders/create", "status": 200,
+            "params": {"market": "ETHCLP", "type": "sell", "amount": amount, "price": "180000"},
+            "body": body.to_string(),
+        })
+    };
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        symbol_interaction("0.00000001"),
+        ticker_interaction("180000"),
+        create("0.33333333", "M1"),
+        // Las reglas del mercado se piden de nuevo al reanudar
+        symbol_interaction("0.00000001"),
+        ticker_interaction("180000"),
+        create("0.33333333", "M2"),
+        ticker_interaction("180000"),
+        create("0.33333334", "M3"),
+        order_status_interaction("M1", "executed", "0.33333333"),
+        order_status_interaction("M2", "executed", "0.33333333"),
+        order_status_interaction("M3", "active", "0.2"),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let duration = Duration::from_millis(30);
+    assert!(Twap::new(&market, Side::Sell, Decimal::ONE, duration, 0).is_err());
+
+    let mut twap = Twap::new(&market, Side::Sell, Decimal::ONE, duration, 3).unwrap();
+    twap.step().await.unwrap();
+    // Se reanuda desde el estado guardado, como tras reiniciar el proceso
+    let saved = serde_json::to_string(twap.state()).unwrap();
+    let state: TwapState = serde_json::from_str(&saved).unwrap();
+    assert!(Twap::resume(&api.create_market("BTCCLP"), state.clone()).is_err());
+
+    let mut twap = Twap::resume(&market, state).unwrap();
+    let mut persisted = 0;
+    let state = twap.run(|_| persisted += 1).await.unwrap();
+    // Antes y después de cada una de las dos porciones restantes, y al final
+    assert_eq!(persisted, 5);
+    assert!(state.is_complete());
+    assert_eq!(state.placed(), Decimal::ONE);
+    assert_eq!(state.children[2].order_id, "M3");
+    assert_eq!(state.filled(), Decimal::new(86666666, 8));
+    assert!(state.children[2].placed_at - state.started_at >= chrono::Duration::milliseconds(20));
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_twap_does_not_place_a_slice_twice() {
+    let created_at = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6f").to_string();
+    let active = serde_json::json!({
+        "status": "success", "pagination": {"previous": "null", "limit": 100, "page": 0, "next": "null"},
+        "data": [{
+            "status": "active", "created_at": created_at,
+            "amount": {"original": "0.5", "remaining": "0.5"}, "price": "180000",
+            "type": "sell", "id": "M1", "market": "ETHCLP",
+        }],
+    });
+    let lookup = |path: &str, body: &str| {
+        serde_json::json!({
+            "method": "GET", "path": path, "status": 200,
+            "params": {"market": "ETHCLP", "page": "0", "limit": "100"},
+            "body": body,
+        })
+    };
+    let executed = r#"{"status":"success","pagination":{"previous":"null","limit":100,"page":0,"next":"null"},"data":[]}"#;
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        symbol_interaction("0.00000001"),
+        ticker_interaction("180000"),
+        // El pedido llegó al exchange aunque la respuesta fue un 503
+        {
+            "method": "POST", "path": "/v1/orders/create", "status": 503,
+            "params": {"market": "ETHCLP", "type": "sell", "amount": "0.5", "price": "180000"},
+            "body": "",
+        },
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        lookup("/v1/orders/active", &active.to_string()),
+        lookup("/v1/orders/executed", executed),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let client = || {
+        Client::builder()
+            .credentials("APK", "SK")
+            .http_transport(replay.clone())
+            .build()
+            .unwrap()
+    };
+    let market = client().create_market("ETHCLP");
+    let duration = Duration::from_secs(60);
+    let mut twap = Twap::new(&market, Side::Sell, Decimal::ONE, duration, 2).unwrap();
+    let mut saved = Vec::new();
+    assert!(twap.run(|state| saved.push(state.clone())).await.is_err());
+    let state = saved.pop().unwrap();
+    assert_eq!(state.pending.as_ref().unwrap().slice, 0);
+    assert!(state.children.is_empty());
+
+    // Tras reiniciar, la porción pendiente se busca en vez de enviarse otra vez
+    let market = client().create_market("ETHCLP");
+    let mut twap = Twap::resume(&market, state).unwrap();
+    let child = twap.step().await.unwrap().unwrap();
+    assert_eq!(child.order_id, "M1");
+    assert_eq!(child.amount, Decimal::new(5, 1));
+    assert!(twap.state().pending.is_none());
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_twap_skips_slices_below_the_lot_size() {
+    let created = r#"{"status":"success","data":{"status":"active","created_at":"2017-09-01T14:01:56.887272","amount":{"original":"0.5","remaining":"0.5"},"price":"6416","type":"sell","id":"M1","market":"ETHCLP"}}"#;
+    let transport = MockTransport::with_fixtures()
+        .on_get(
+            "public/symbol/ETHCLP",
+            r#"{"type":"spot","base_currency":"ETH","quote_currency":"CLP","status":"working","quantity_increment":"0.5","tick_size":"10","take_rate":"0.0068","make_rate":"0.0039","fee_currency":"CLP"}"#,
+        )
+        .on_post("orders/create", created)
+        .on_get("orders/status", created);
+    let market = client(&transport).create_market("ETHCLP");
+    let duration = Duration::from_millis(10);
+    let mut twap = Twap::new(&market, Side::Sell, Decimal::ONE, duration, 3).unwrap();
+    let state = twap.run(|_| ()).await.unwrap();
+
+    // Un tercio no llega al lote: su cantidad pasa a las otras dos porciones
+    assert!(state.is_complete());
+    assert_eq!(state.skipped, 1);
+    let slices: Vec<_> = state.children.iter().map(|child| child.slice).collect();
+    assert_eq!(slices, [1, 2]);
+    let amounts: Vec<_> = transport
+        .requests()
+        .iter()
+        .filter(|r| r.endpoint == "orders/create")
+        .map(|r| r.params["amount"].clone())
+        .collect();
+    assert_eq!(amounts, ["0.5", "0.5"]);
+}
+
+#[tokio::test]
+async fn test_api_market_estimate_cost() {
+    let transport = MockTransport::with_fixtures()
+        .on_get(
+            "account",
+            r#"{"status":"success","data":{"name":"John Doe","email":"john.doe@gmail.com","rate":{"market_maker":"0.0039","market_taker":"0.0068"},"bank_accounts":[]}}"#,
+        )
+        .on_get(
+            "public/symbol/ETHCLP",
+            r#"{"type":"spot","base_currency":"ETH","quote_currency":"CLP","status":"working","quantity_increment":"0.0001","tick_size":"10","take_rate":"0.0068","make_rate":"0.0039","fee_currency":"CLP"}"#,
+        );
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+
+    // El ticker de ejemplo tiene bid 6416 y ask 6887
+    let taker = market
+        .estimate_cost(Side::Buy, Decimal::new(200005, 5), Decimal::new(7005, 0))
+        .await
+        .unwrap();
+    assert_eq!(taker.liquidity, Liquidity::Taker);
+    // Redondeados al lote de 0.0001 y al tick de 10
+    assert_eq!(taker.amount, Decimal::new(2, 0));
+    assert_eq!(taker.price, Decimal::new(7000, 0));
+    assert_eq!(taker.fee, Decimal::new(952, 1));
+    assert_eq!(taker.net, Decimal::new(140952, 1));
+
+    let maker = market
+        .estimate_cost(Side::Sell, Decimal::new(2, 0), Decimal::new(6995, 0))
+        .await
+        .unwrap();
+    assert_eq!(maker.liquidity, Liquidity::Maker);
+    assert_eq!(maker.price, Decimal::new(7000, 0));
+    assert_eq!(maker.net, Decimal::new(139454, 1));
+}
+
+#[tokio::test]
+async fn test_api_market_get_limits() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "public/symbol/ETHCLP",
+        r#"{"type":"spot","base_currency":"ETH","quote_currency":"CLP","status":"working","quantity_increment":"0.0001","tick_size":"10","take_rate":"0.0068","make_rate":"0.0039","fee_currency":"CLP"}"#,
+    );
+    let api = client(&transport);
+    let limits = api.create_market("ETHCLP").get_limits().await.unwrap();
+
+    assert_eq!(limits.market, "ETHCLP");
+    assert!(limits.is_active());
+    assert_eq!(limits.price_precision(), 0);
+    assert_eq!(limits.amount_precision(), 4);
+    assert_eq!(limits.round_price(Decimal::new(180005, 0)), Decimal::new(180010, 0));
+    assert_eq!(limits.round_amount(Decimal::new(123456, 5)), Decimal::new(12345, 4));
+    assert!(limits.accepts(Decimal::new(12345, 4), Decimal::new(180010, 0)));
+    assert!(!limits.accepts(Decimal::new(123456, 5), Decimal::new(180010, 0)));
+    assert!(!limits.accepts(Decimal::new(12345, 4), Decimal::new(180005, 0)));
+}
+
+#[tokio::test]
+async fn test_api_market_get_limits_with_custom_base_url() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "staging/api/3/public/symbol/ETHCLP",
+        r#"{"type":"spot","base_currency":"ETH","quote_currency":"CLP","status":"working","quantity_increment":"0.0001","tick_size":"10","take_rate":"0.0068","make_rate":"0.0039","fee_currency":"CLP"}"#,
+    );
+    let builder = || {
+        Client::builder()
+            .credentials("APK", "SK")
+            .base_url("https://staging.example.com/")
+            .http_transport(transport.clone())
+    };
+
+    // La v3 de CryptoMarket no corresponde a otro dominio
+    let api = builder().build().unwrap();
+    let err = api.create_market("ETHCLP").get_limits().await.unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+
+    let api = builder().v3_base_url("https://staging.example.com/staging/api/3").build().unwrap();
+    let limits = api.create_market("ETHCLP").get_limits().await.unwrap();
+    assert_eq!(limits.market, "ETHCLP");
+}
+
+#[tokio::test]
+async fn test_api_call_raw_and_call_as() {
+    #[derive(serde::Deserialize)]
+    struct OwnTicker {
+        market: String,
+        bid: Decimal,
+    }
+
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport).api;
+    let mut payload = HashMap::new();
+    payload.insert("market".to_string(), "ETHCLP".to_string());
+
+    let raw = api
+        .call_raw(RequestMethod::Get(true), "ticker", payload.clone())
+        .await
+        .unwrap();
+    assert_eq!(raw["status"], "success");
+    assert_eq!(raw["data"][0]["market"], "ETHCLP");
+
+    let tickers: Vec<OwnTicker> = api
+        .call_as(RequestMethod::Get(true), "ticker", payload)
+        .await
+        .unwrap();
+    assert_eq!(tickers[0].market, "ETHCLP");
+    assert_eq!(tickers[0].bid, Decimal::new(6416, 0));
+}
+
+#[tokio::test]
+async fn test_api_create_order_with_id() {
+    let order = |id: &str, delay: i64| {
+        let created_at = chrono::Utc::now() + chrono::Duration::seconds(delay);
+        serde_json::json!({
+            "status": "active", "created_at": created_at.format("%Y-%m-%dT%H:%M:%S%.6f").to_string(),
+            "amount": {"original": "1.5", "remaining": "1.5"}, "price": "7120",
+            "type": "buy", "id": id, "market": "ETHCLP",
+        })
+    };
+    let orders = |path: &str, data: serde_json::Value| {
+        let body = serde_json::json!({
+            "status": "success", "pagination": {"previous": "null", "limit": 100, "page": 0, "next": "null"},
+            "data": data,
+        });
+        serde_json::json!({
+            "method": "GET", "path": path, "status": 200,
+            "params": {"market": "ETHCLP", "page": "0", "limit": "100"},
+            "body": body.to_string(),
+        })
+    };
+    let create = serde_json::json!({
+        "method": "POST", "path": "/v1/orders/create", "status": 503,
+        "params": {"market": "ETHCLP", "type": "buy", "amount": "1.5", "price": "7120"},
+        "body": "",
+    });
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        create,
+        // El pedido sí llegó: el reintento toma la más antigua de las iguales
+        orders("/v1/orders/active", serde_json::json!([order("O2", 2), order("O1", 1)])),
+        orders("/v1/orders/executed", serde_json::json!([])),
+        order_status_interaction("O1", "active", "0"),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let (amount, price) = (Decimal::new(15, 1), Decimal::new(7120, 0));
+
+    assert!(market
+        .create_order_with_id("my-order", Side::Buy, amount, price)
+        .await
+        .is_err());
+    let err = market
+        .create_order_with_id("my-order", Side::Sell, amount, price)
+        .await
+        .unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+
+    let order = market
+        .create_order_with_id("my-order", Side::Buy, amount, price)
+        .await
+        .unwrap();
+    assert_eq!(order.id, "O1");
+    // Confirmada: no se vuelve a enviar ni a buscar
+    let order = market
+        .create_order_with_id("my-order", Side::Buy, amount, price)
+        .await
+        .unwrap();
+    assert_eq!(order.id, "O1");
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_create_order_with_id_after_malformed_answer() {
+    let created_at = chrono::Utc::now().format("%Y-%m-%dT%H:%M:%S%.6f").to_string();
+    let active = serde_json::json!({
+        "status": "success", "pagination": {"previous": "null", "limit": 100, "page": 0, "next": "null"},
+        "data": [{
+            "status": "active", "created_at": created_at,
+            "amount": {"original": "1.5", "remaining": "1.5"}, "price": "7120",
+            "type": "buy", "id": "O1", "market": "ETHCLP",
+        }],
+    });
+    let executed = r#"{"status":"success","pagination":{"previous":"null","limit":100,"page":0,"next":"null"},"data":[]}"#;
+    let transport = MockTransport::new()
+        .on_get("market", r#"{"status":"success","data":["ETHCLP"]}"#)
+        // Un 200 ilegible: la orden pudo quedar registrada
+        .on_post("orders/create", r#"{"status":"success","data":{"#)
+        .on_get("orders/active", &active.to_string())
+        .on_get("orders/executed", executed);
+    let market = client(&transport).create_market("ETHCLP");
+    let (amount, price) = (Decimal::new(15, 1), Decimal::new(7120, 0));
+
+    let err = market
+        .create_order_with_id("my-order", Side::Buy, amount, price)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::MalformedResource);
+
+    let order = market
+        .create_order_with_id("my-order", Side::Buy, amount, price)
+        .await
+        .unwrap();
+    assert_eq!(order.id, "O1");
+    let posts = transport.requests().iter().filter(|r| r.endpoint == "orders/create").count();
+    assert_eq!(posts, 1);
+}
+
+#[tokio::test]
+async fn test_api_key_permissions() {
+    let transport = MockTransport::with_fixtures().fail_post("orders/cancel", Some(403));
+    let permissions = client(&transport).key_permissions().await.unwrap();
+    assert!(permissions.read);
+    assert!(!permissions.trade);
+
+    // El exchange respondió a ambas sondas más allá de los permisos
+    let transport = MockTransport::new()
+        .fail_get("balance", Some(404))
+        .fail_post("orders/cancel", Some(400));
+    let permissions = client(&transport).key_permissions().await.unwrap();
+    assert!(permissions.read);
+    assert!(permissions.trade);
+
+    // Sin respuesta no se sabe nada del permiso
+    let transport = MockTransport::with_fixtures().fail_post("orders/cancel", None);
+    let err = client(&transport).key_permissions().await.unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::BadRequest);
+    assert_eq!(err.status(), None);
+}
+
+#[tokio::test]
+async fn test_api_call_with_options() {
+    let transport = SlowTransport {
+        inner: MockTransport::with_fixtures(),
+        ..Default::default()
+    };
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport)
+        .build()
+        .unwrap()
+        .api;
+    let call = |options: RequestOptions| {
+        let api = api.clone();
+        async move {
+            api.call_with::<serde_json::Value>(RequestMethod::Get(true), "market", HashMap::new(), options)
+                .await
+        }
+    };
+
+    // Cada petición del transporte tarda 20ms
+    let err = call(RequestOptions {
+        timeout: Some(Duration::from_millis(5)),
+        ..Default::default()
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::Timeout);
+
+    let err = call(RequestOptions {
+        deadline: Some(std::time::Instant::now() + Duration::from_millis(5)),
+        ..Default::default()
+    })
+    .await
+    .unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::Timeout);
+
+    let markets = call(RequestOptions {
+        timeout: Some(Duration::from_secs(5)),
+        deadline: Some(std::time::Instant::now() + Duration::from_secs(5)),
+        ..Default::default()
+    })
+    .await
+    .unwrap();
+    assert_eq!(markets["status"], "success");
+}
+
+#[tokio::test]
+async fn test_api_cancellation() {
+    let transport = SlowTransport {
+        inner: MockTransport::with_fixtures(),
+        ..Default::default()
+    };
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport)
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let cancel = CancellationToken::new();
+
+    let canceller = cancel.clone();
+    tokio::spawn(async move {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+        canceller.cancel();
+    });
+    let err = with_cancellation(&cancel, market.get_current_ticker()).await.unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::Cancelled);
+    let options = RequestOptions {
+        cancel: Some(cancel),
+        ..Default::default()
+    };
+    let err = api
+        .api
+        .call_with::<serde_json::Value>(RequestMethod::Get(true), "ticker", HashMap::new(), options)
+        .await
+        .unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::Cancelled);
+    // El cliente sigue disponible tras descartar las peticiones
+    assert!(market.get_current_ticker().await.is_ok());
+}
+
+#[tokio::test]
+async fn test_api_batch_orders_report_each_result() {
+    let order = |id: &str, status: &str, amount: &str| {
+        serde_json::json!({
+            "status": "success",
+            "data": {
+                "status": status, "created_at": "2017-09-01T14:01:56.887272",
+                "amount": {"original": amount, "remaining": amount}, "price": "7120",
+                "type": "buy", "id": id, "market": "ETHCLP",
+            },
+        })
+        .to_string()
+    };
+    let create = |amount: &str, status: u16, body: String| {
+        serde_json::json!({
+            "method": "POST", "path": "/v1/orders/create", "status": status,
+            "params": {"market": "ETHCLP", "type": "buy", "amount": amount, "price": "7120"},
+            "body": body,
+        })
+    };
+    let cancel = |id: &str, status: u16, body: String| {
+        serde_json::json!({
+            "method": "POST", "path": "/v1/orders/cancel", "status": status,
+            "params": {"id": id}, "body": body,
+        })
+    };
+    let rejected = r#"{"status":"error","message":"insufficient funds"}"#.to_string();
+    let cassette = serde_json::json!([
+        {
+            "method": "GET", "path": "/v1/market", "params": {}, "status": 200,
+            "body": r#"{"status":"success","data":["ETHCLP"]}"#,
+        },
+        create("1", 200, order("O1", "active", "1")),
+        create("2", 200, rejected),
+        create("3", 200, order("O3", "active", "3")),
+        cancel("O1", 200, order("O1", "cancelled", "1")),
+        cancel("O2", 404, String::new()),
+        cancel("O3", 200, order("O3", "cancelled", "3")),
+    ]);
+    let replay = ReplayTransport::from_json(&cassette.to_string()).unwrap();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(replay.clone())
+        .build()
+        .unwrap();
+    let market = api.create_market("ETHCLP");
+    let request = |amount: i64| OrderRequest {
+        order_type: Side::Buy,
+        amount: Decimal::new(amount, 0),
+        price: Decimal::new(7120, 0),
+    };
+
+    let created = market.create_orders(&[request(1), request(2), request(3)]).await;
+    assert_eq!(created.len(), 3);
+    assert_eq!(created[0].as_ref().unwrap().id, "O1");
+    let err = created[1].as_ref().unwrap_err();
+    assert_eq!(err.kind(), &CryptoMktErrorType::RequestRejected);
+    assert_eq!(err.message(), Some("insufficient funds"));
+    assert_eq!(created[2].as_ref().unwrap().id, "O3");
+
+    let cancelled = market.cancel_orders(&["O1", "O2", "O3"]).await;
+    assert_eq!(cancelled[0].as_ref().unwrap().status, "cancelled");
+    assert_eq!(cancelled[1].as_ref().unwrap_err().kind(), &CryptoMktErrorType::RequestNotFound);
+    assert_eq!(cancelled[2].as_ref().unwrap().id, "O3");
+    assert_eq!(replay.remaining(), 0);
+}
+
+#[tokio::test]
+async fn test_api_response_cache() {
+    let transport = MockTransport::with_fixtures();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .cache_ttl("market", Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let count = |endpoint: &str| {
+        transport
+            .requests()
+            .iter()
+            .filter(|request| request.endpoint == endpoint)
+            .count()
+    };
+
+    assert_eq!(api.get_markets().await.unwrap().len(), api.get_markets().await.unwrap().len());
+    assert_eq!(count("market"), 1);
+    // Los endpoints sin vencimiento configurado no se guardan
+    let market = api.create_market("ETHCLP");
+    market.get_current_ticker().await.unwrap();
+    market.get_current_ticker().await.unwrap();
+    assert_eq!(count("ticker"), 2);
+
+    api.api.clear_cache();
+    api.get_markets().await.unwrap();
+    assert_eq!(count("market"), 2);
+}
+
+#[tokio::test]
+async fn test_api_response_cache_ignores_the_order_of_the_params() {
+    let transport = MockTransport::with_fixtures();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .cache_ttl("ticker", Duration::from_secs(60))
+        .build()
+        .unwrap();
+    let params = [("market", "ETHCLP"), ("page", "0"), ("limit", "20"), ("start", "2017-05-20")];
+    // Cada HashMap recorre sus claves en su propio orden
+    let payload = |params: &mut dyn Iterator<Item = &(&str, &str)>| {
+        params
+            .map(|(key, value)| (key.to_string(), value.to_string()))
+            .collect::<HashMap<_, _>>()
+    };
+
+    let first = payload(&mut params.iter());
+    let second = payload(&mut params.iter().rev());
+    api.api.call_raw(RequestMethod::Get(true), "ticker", first).await.unwrap();
+    api.api.call_raw(RequestMethod::Get(true), "ticker", second).await.unwrap();
+    let count = transport.requests().iter().filter(|r| r.endpoint == "ticker").count();
+    assert_eq!(count, 1);
+}
+
+#[tokio::test]
+async fn test_api_default_headers() {
+    let transport = MockTransport::with_fixtures();
+    let api = Client::builder()
+        .credentials("APK", "SK")
+        .http_transport(transport.clone())
+        .default_header("X-App", "my-bot/1.0")
+        .build()
+        .unwrap();
+
+    api.get_markets().await.unwrap();
+    api.get_balance().await.unwrap();
+    for request in transport.requests() {
+        assert_eq!(request.headers["x-app"], "my-bot/1.0");
+    }
+    assert!(transport.requests()[1].headers.contains_key("X-MKT-SIGNATURE"));
+
+    let err = Client::builder().default_header("X App", "1").build().err().unwrap();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+}
+
+/// Estrategia que cuenta los ticks y falla o se detiene en el indicado
+#[cfg(feature = "strategy")]
+#[derive(Default)]
+struct CountingStrategy {
+    stop_at: u64,
+    fail: bool,
+    ticks: Arc<AtomicUsize>,
+    shutdowns: Arc<AtomicUsize>,
+}
+
+#[cfg(feature = "strategy")]
+#[async_trait]
+impl cryptomkt::strategy::Strategy for CountingStrategy {
+    async fn on_tick(&mut self, ctx: &cryptomkt::strategy::Context) -> CryptoMktResult<()> {
+        assert!(ctx.is_dry_run());
+        ctx.client().create_market("ETHCLP").get_current_ticker().await?;
+        self.ticks.fetch_add(1, Ordering::SeqCst);
+        if self.fail {
+            return Err(CryptoMktErrorType::RequestServiceUnavailable.into());
+        }
+        if ctx.tick() == self.stop_at {
+            ctx.shutdown();
+        }
+        Ok(())
+    }
+
+    async fn on_shutdown(&mut self, _ctx: &cryptomkt::strategy::Context) -> CryptoMktResult<()> {
+        self.shutdowns.fetch_add(1, Ordering::SeqCst);
+        Ok(())
+    }
+}
+
+#[cfg(feature = "strategy")]
+#[tokio::test]
+async fn test_api_strategy_runner() {
+    use cryptomkt::strategy::Runner;
+
+    let runner = |transport: &MockTransport| {
+        let builder = Client::builder().http_transport(transport.clone());
+        Runner::new(builder).interval(Duration::from_millis(1)).dry_run(true)
+    };
+
+    // Se detiene cuando la estrategia lo pide, tras terminar el tick
+    let strategy = CountingStrategy {
+        stop_at: 3,
+        ..Default::default()
+    };
+    let (ticks, shutdowns) = (strategy.ticks.clone(), strategy.shutdowns.clone());
+    runner(&MockTransport::with_fixtures()).run(strategy).await.unwrap();
+    assert_eq!(ticks.load(Ordering::SeqCst), 3);
+    assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+    // Tras varios ticks fallidos seguidos devuelve el último error
+    let strategy = CountingStrategy {
+        fail: true,
+        ..Default::default()
+    };
+    let (ticks, shutdowns) = (strategy.ticks.clone(), strategy.shutdowns.clone());
+    let err = runner(&MockTransport::with_fixtures())
+        .max_consecutive_errors(2)
+        .run(strategy)
+        .await
+        .unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::RequestServiceUnavailable);
+    assert_eq!(ticks.load(Ordering::SeqCst), 2);
+    assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+
+    // Un token cancelado de antemano no deja correr ningún tick
+    let strategy = CountingStrategy::default();
+    let ticks = strategy.ticks.clone();
+    let runner = runner(&MockTransport::with_fixtures());
+    runner.shutdown_token().cancel();
+    runner.run(strategy).await.unwrap();
+    assert_eq!(ticks.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn test_api_convert() {
+    let transport = MockTransport::with_fixtures().on_get(
+        "ticker",
+        r#"{"status":"success","data":[
+            {"high":"6888","volume":"13.03","low":"6303","ask":"200000","timestamp":"2017-08-29T15:44:17.267526","bid":"190000","last_price":"195000","market":"ETHCLP"},
+            {"high":"6888","volume":"13.03","low":"6303","ask":"4000000","timestamp":"2017-08-29T15:44:17.267526","bid":"3800000","last_price":"3900000","market":"BTCCLP"},
+            {"high":"6888","volume":"13.03","low":"6303","ask":"40000","timestamp":"2017-08-29T15:44:17.267526","bid":"38000","last_price":"39000","market":"ETHARS"}
+        ]}"#,
+    );
+    let api = client(&transport);
+
+    // Directa: se vende ETH al mejor bid
+    let conversion = api.convert(Decimal::new(2, 0), Currency::Eth, Currency::Clp).await.unwrap();
+    assert_eq!(conversion.amount, Decimal::new(380000, 0));
+    assert_eq!(conversion.route.len(), 1);
+    assert_eq!((conversion.route[0].market.clone(), conversion.route[0].side), (MarketPair::ETHCLP, Side::Sell));
+
+    // Dos mercados: ETH -> CLP -> BTC, comprando BTC al mejor ask
+    let conversion = api.convert(Decimal::new(10, 0), Currency::Eth, Currency::Btc).await.unwrap();
+    assert_eq!(conversion.amount, Decimal::new(475, 3));
+    let markets: Vec<String> = conversion.route.iter().map(|step| step.market.to_string()).collect();
+    assert_eq!(markets, ["ETHCLP", "BTCCLP"]);
+    assert_eq!(conversion.route[1].side, Side::Buy);
+
+    let conversion = api.convert(Decimal::ONE, Currency::Eth, Currency::Eth).await.unwrap();
+    assert!(conversion.route.is_empty());
+
+    let err = api.convert(Decimal::ONE, Currency::Eth, Currency::Xlm).await.unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::UnknownMarket(_)));
+}
+
+#[tokio::test]
+async fn test_api_v3_deposit_address() {
+    let v3 = |transport: &MockTransport| {
+        Client::builder()
+            .credentials("APK", "SK")
+            .http_transport(transport.clone())
+            .build_v3()
+            .unwrap()
+    };
+
+    let transport = MockTransport::new().on_get(
+        "wallet/crypto/address",
+        r#"[{"currency": "XLM", "address": "GAHK7EEG2WWHVKDNT4CEQFZGKF2LGDSW2IVM4S5DP42RBW3K6BTODB4A", "payment_id": "1011"}]"#,
+    );
+    let address = v3(&transport).get_deposit_address("XLM").await.unwrap();
+    assert_eq!(address.memo.as_deref(), Some("1011"));
+    assert_eq!(transport.requests().len(), 1);
+    assert_eq!(transport.requests()[0].params["currency"], "XLM");
+
+    // Sin dirección previa se crea una
+    let transport = MockTransport::new()
+        .on_get("wallet/crypto/address", "[]")
+        .on_post(
+            "wallet/crypto/address",
+            r#"{"currency": "ETH", "address": "0x5Ab7d2E9D5F1C0d4C8a1f2aE1F9b3C7d8E6f4A21"}"#,
+        );
+    let address = v3(&transport).get_deposit_address("ETH").await.unwrap();
+    assert_eq!(address.currency, "ETH");
+    assert_eq!(address.memo, None);
+    let requests = transport.requests();
+    assert_eq!(requests[1].method, "POST");
+    assert_eq!(requests[1].params["currency"], "ETH");
+}
+
+#[tokio::test]
+async fn test_api_accounts() {
+    use cryptomkt::accounts::Accounts;
+    use cryptomkt::RateLimit;
+
+    let transport = MockTransport::with_fixtures();
+    let accounts = Accounts::builder(Client::builder().http_transport(transport.clone()))
+        .account("trading", "TRADING", "SK1")
+        .account("treasury", "TREASURY", "SK2")
+        .rate_limit("trading", RateLimit::new(1, Duration::from_millis(300)))
+        .build()
+        .unwrap();
+    assert_eq!(accounts.labels().collect::<Vec<_>>(), ["trading", "treasury"]);
+
+    // Cada cuenta firma con su API key, sobre el mismo transporte
+    accounts.get("treasury").unwrap().get_balance().await.unwrap();
+    accounts.get("trading").unwrap().get_balance().await.unwrap();
+    let requests = transport.requests();
+    assert_eq!(requests[0].headers["X-MKT-APIKEY"], "TREASURY");
+    assert_eq!(requests[1].headers["X-MKT-APIKEY"], "TRADING");
+
+    // La cuota de trading no frena a treasury
+    let start = tokio::time::Instant::now();
+    accounts.get("treasury").unwrap().get_balance().await.unwrap();
+    assert!(start.elapsed() < Duration::from_millis(200));
+    accounts.get("trading").unwrap().get_balance().await.unwrap();
+    assert!(start.elapsed() >= Duration::from_millis(200));
+
+    let err = accounts.get("savings").unwrap_err();
+    assert_eq!(*err.kind(), CryptoMktErrorType::UnknownAccount("savings".to_string()));
+
+    let err = Accounts::builder(Client::builder())
+        .account("trading", "APK", "SK")
+        .account("trading", "APK2", "SK2")
+        .build()
+        .unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+    let err = Accounts::builder(Client::builder())
+        .account("trading", "APK", "SK")
+        .rate_limit("savings", RateLimit::new(1, Duration::from_secs(1)))
+        .build()
+        .unwrap_err();
+    assert!(matches!(err.kind(), CryptoMktErrorType::InvalidConfiguration(_)));
+}
+
+#[tokio::test]
+async fn test_api_ticker_cache() {
+    use cryptomkt::tickers::TickerCache;
+
+    let transport = MockTransport::with_fixtures();
+    let tickers = TickerCache::poll(client(&transport), &["ethclp"], Duration::from_millis(20));
+    assert!(tickers.latest("ETHCLP").is_none());
+    assert!(tickers.is_stale("ETHCLP", Duration::from_secs(60)));
+
+    let deadline = tokio::time::Instant::now() + Duration::from_secs(2);
+    while tickers.latest("ETHCLP").is_none() && tokio::time::Instant::now() < deadline {
+        tokio::time::sleep(Duration::from_millis(5)).await;
+    }
+    let (ticker, received) = tickers.latest("ethclp").unwrap();
+    assert_eq!(ticker.market, "ETHCLP");
+    assert!(received.elapsed() < Duration::from_secs(2));
+    assert!(tickers.fresh("ETHCLP", Duration::from_secs(60)).is_some());
+    assert!(tickers.latest("BTCCLP").is_none());
+
+    // Sin actualizaciones el ticker envejece
+    tickers.stop();
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let polls = transport.requests().len();
+    tokio::time::sleep(Duration::from_millis(60)).await;
+    assert_eq!(transport.requests().len(), polls);
+    assert!(tickers.is_stale("ETHCLP", Duration::from_millis(40)));
+    assert!(tickers.latest("ETHCLP").is_some());
+}
+
+#[tokio::test]
+async fn test_api_models_round_trip_through_serde() {
+    use cryptomkt::models::{Balance, Order, Ticker};
+    use cryptomkt::Paginated;
+
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+    let market = api.create_market("ETHCLP");
+
+    let ticker = market.get_current_ticker().await.unwrap();
+    let json = serde_json::to_string(&ticker).unwrap();
+    assert_eq!(serde_json::from_str::<Ticker>(&json).unwrap(), ticker);
+
+    let balance = api.get_balance().await.unwrap();
+    let json = serde_json::to_string(&balance).unwrap();
+    assert_eq!(serde_json::from_str::<Vec<Balance>>(&json).unwrap(), balance);
+
+    let orders = market.get_active_orders(Page::default()).await.unwrap();
+    let json = serde_json::to_string(&orders).unwrap();
+    assert_eq!(serde_json::from_str::<Paginated<Order>>(&json).unwrap(), orders);
+}
+
+#[tokio::test]
+async fn test_api_call_with_meta_without_transport_metadata() {
+    let transport = MockTransport::with_fixtures();
+    let api = client(&transport);
+
+    let (balance, meta): (serde_json::Value, _) = api
+        .api
+        .call_with_meta(RequestMethod::Get(false), "balance", HashMap::new())
+        .await
+        .unwrap();
+    assert_eq!(balance["status"], "success");
+    assert_eq!(meta.status, reqwest::StatusCode::OK);
+    assert!(meta.headers.is_empty());
+    assert_eq!(meta.server_time(), None);
+
+    // Las respuestas con metadatos pasan por la misma firma
+    let requests = transport.requests();
+    assert!(requests[0].headers.contains_key("x-mkt-signature"));
 }